@@ -0,0 +1,67 @@
+//! Benchmarks of the language filters (`-l`, `--require-each-lang`), which
+//! check every translation unit of the input. Run with `cargo bench`; each
+//! check is compared with a nested scan of the texts for every requested
+//! language, as the filters did before [LangSet].
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use dgt_parser::functions::coerce_lang_codes;
+use dgt_parser::tmx_parser::{parse_tmx, TranslationUnit};
+use dgt_parser::types::{LangSet, RequestedLangs};
+
+const ITERATIONS: u32 = 1_000_000;
+
+const DGT_LANGS: [&str; 24] = [
+    "en", "pl", "de", "da", "el", "es", "fi", "fr", "it", "nl", "pt", "sv", "lv", "cs", "et", "hu",
+    "sl", "lt", "mt", "sk", "ro", "bg", "hr", "ga",
+];
+
+/// A translation unit with a text in each of the languages.
+fn translation_unit(langs: &[String]) -> TranslationUnit {
+    let tuvs: String = langs
+        .iter()
+        .map(|lang| format!(r#"<tuv lang="{}"><seg>Text</seg></tuv>"#, lang))
+        .collect();
+    let tmx = format!(
+        r#"<tmx><header></header><body><tu><prop type="Txt::Doc. No.">A</prop>{}</tu></body></tmx>"#,
+        tuvs
+    );
+    parse_tmx(tmx).unwrap().body.translation_units.remove(0)
+}
+
+/// Run the check on the translation unit and print the time per unit.
+fn bench(name: &str, tu: &TranslationUnit, check: impl Fn(&TranslationUnit) -> bool) {
+    let started = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(check(black_box(tu)));
+    }
+    let per_unit = started.elapsed().as_nanos() / ITERATIONS as u128;
+    println!("{:<40} {:>6} ns per unit", name, per_unit);
+}
+
+fn main() {
+    let all_langs = coerce_lang_codes(DGT_LANGS.iter().map(|lang| lang.to_string()).collect());
+    let tu = translation_unit(&all_langs);
+    // Half of the languages.
+    let lang_set: LangSet = all_langs.iter().step_by(2).cloned().collect();
+    let each = RequestedLangs::Each(lang_set.clone());
+    bench("contains_each_lang", &tu, |tu| tu.contains_each_lang(&each));
+    bench("contains_each_lang (nested scan)", &tu, |tu| {
+        lang_set
+            .iter()
+            .all(|lang| tu.segments.iter().any(|segment| &segment.lang == lang))
+    });
+
+    // As many languages, none of which occurs in the unit.
+    let missing: LangSet = (0..lang_set.len()).map(|i| format!("X{}-01", i)).collect();
+    let some = RequestedLangs::Some(missing.clone());
+    bench("contains_any_lang (no hit)", &tu, |tu| {
+        tu.contains_any_lang(&some)
+    });
+    bench("contains_any_lang (no hit, nested scan)", &tu, |tu| {
+        missing
+            .iter()
+            .any(|lang| tu.segments.iter().any(|segment| &segment.lang == lang))
+    });
+}
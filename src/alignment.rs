@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use crate::tmx_parser::{LangMatch, TranslationUnit};
+
+/// Probability of a target token which never occurred in training (or is not
+/// a translation of any source token).
+const MIN_PROBABILITY: f64 = 1e-7;
+
+/// Maximum number of pairs of a source and a target token whose expected
+/// counts are collected in an iteration of training, which bounds its memory
+/// (about 40 bytes per pair). Pairs first seen once the limit is reached are
+/// left out of the iteration.
+const MAX_PAIRS: usize = 50_000_000;
+
+/// Translation probabilities below this are dropped after each iteration, so
+/// that the pairs of tokens which merely co-occur make room for the others.
+const MIN_KEPT_PROBABILITY: f64 = 1e-4;
+
+/// ID of the empty (NULL) source token, which target tokens without a
+/// counterpart in the source text are aligned to.
+const NULL_TOKEN: u32 = 0;
+
+/// Collects the texts of a language pair for training an IBM Model 1
+/// lexical translation model (see [AlignmentTrainer::train]).
+///
+/// Only token IDs are kept in memory, so that the texts of a language pair of
+/// the whole DGT-TM can be collected in a first pass over the input.
+pub struct AlignmentTrainer {
+    source_lang: String,
+    target_lang: String,
+    source_vocab: HashMap<String, u32>,
+    target_vocab: HashMap<String, u32>,
+
+    /// Token IDs of all source (and target) texts, concatenated.
+    source_tokens: Vec<u32>,
+    target_tokens: Vec<u32>,
+
+    /// End of each source and target text in [Self::source_tokens] and
+    /// [Self::target_tokens].
+    text_ends: Vec<(usize, usize)>,
+
+    /// See [MAX_PAIRS].
+    max_pairs: usize,
+}
+
+/// An IBM Model 1 lexical translation model, which scores how likely the
+/// target text of a translation unit is a translation of its source text.
+pub struct AlignmentModel {
+    source_lang: String,
+    target_lang: String,
+    source_vocab: HashMap<String, u32>,
+    target_vocab: HashMap<String, u32>,
+
+    /// Probability that a source token (the first ID) is translated as a
+    /// target token (the second ID). Pairs which never co-occur are left out.
+    probabilities: HashMap<(u32, u32), f32>,
+}
+
+impl AlignmentTrainer {
+    pub fn new(source_lang: String, target_lang: String) -> AlignmentTrainer {
+        AlignmentTrainer {
+            source_lang,
+            target_lang,
+            source_vocab: HashMap::new(),
+            target_vocab: HashMap::new(),
+            source_tokens: Vec::new(),
+            target_tokens: Vec::new(),
+            text_ends: Vec::new(),
+            max_pairs: MAX_PAIRS,
+        }
+    }
+
+    /// Add the texts of the translation unit in the two languages, if it has
+    /// both.
+    pub fn add(&mut self, tu: &TranslationUnit) {
+        let (source, target) = match text_pair(tu, &self.source_lang, &self.target_lang) {
+            Some(texts) => texts,
+            None => return,
+        };
+        for token in tokens(source) {
+            let next_id = self.source_vocab.len() as u32 + 1;
+            let id = *self.source_vocab.entry(token).or_insert(next_id);
+            self.source_tokens.push(id);
+        }
+        for token in tokens(target) {
+            let next_id = self.target_vocab.len() as u32;
+            let id = *self.target_vocab.entry(token).or_insert(next_id);
+            self.target_tokens.push(id);
+        }
+        self.text_ends
+            .push((self.source_tokens.len(), self.target_tokens.len()));
+    }
+
+    /// Number of translation units added.
+    pub fn units(&self) -> usize {
+        self.text_ends.len()
+    }
+
+    /// Estimate the translation probabilities with the given number of
+    /// iterations of expectation maximization, starting from uniform
+    /// probabilities. At most [MAX_PAIRS] pairs of tokens are counted.
+    pub fn train(self, iterations: usize) -> AlignmentModel {
+        let mut probabilities: HashMap<(u32, u32), f32> = HashMap::new();
+        for iteration in 0..iterations {
+            let mut counts: HashMap<(u32, u32), f64> = HashMap::new();
+            let mut totals: HashMap<u32, f64> = HashMap::new();
+            let mut start = (0, 0);
+            for &end in &self.text_ends {
+                let source = &self.source_tokens[start.0..end.0];
+                let target = &self.target_tokens[start.1..end.1];
+                start = end;
+                let source_with_null = || std::iter::once(&NULL_TOKEN).chain(source);
+                for &target_token in target {
+                    let probability = |source_token: u32| match iteration {
+                        0 => 1.0,
+                        _ => probabilities
+                            .get(&(source_token, target_token))
+                            .copied()
+                            .unwrap_or_default() as f64,
+                    };
+                    let sum: f64 = source_with_null().map(|&s| probability(s)).sum();
+                    if sum == 0.0 {
+                        continue;
+                    }
+                    for &source_token in source_with_null() {
+                        let share = probability(source_token) / sum;
+                        let pair = (source_token, target_token);
+                        if share == 0.0
+                            || (counts.len() >= self.max_pairs && !counts.contains_key(&pair))
+                        {
+                            continue;
+                        }
+                        *counts.entry(pair).or_default() += share;
+                        *totals.entry(source_token).or_default() += share;
+                    }
+                }
+            }
+            probabilities = counts
+                .into_iter()
+                .map(|((source, target), count)| ((source, target), count / totals[&source]))
+                .filter(|(_, probability)| *probability >= MIN_KEPT_PROBABILITY)
+                .map(|(pair, probability)| (pair, probability as f32))
+                .collect();
+        }
+
+        AlignmentModel {
+            source_lang: self.source_lang,
+            target_lang: self.target_lang,
+            source_vocab: self.source_vocab,
+            target_vocab: self.target_vocab,
+            probabilities,
+        }
+    }
+}
+
+impl AlignmentModel {
+    /// The average log-probability of the tokens of the target text given the
+    /// source text (at most 0; the lower, the more likely the texts are
+    /// misaligned), or `None` if the translation unit lacks either text.
+    pub fn score(&self, tu: &TranslationUnit) -> Option<f64> {
+        let (source, target) = text_pair(tu, &self.source_lang, &self.target_lang)?;
+        let mut source_ids = vec![NULL_TOKEN];
+        source_ids
+            .extend(tokens(source).filter_map(|token| self.source_vocab.get(&token).copied()));
+        let source_len = tokens(source).count() + 1;
+        let mut log_probability = 0.0;
+        let mut target_len = 0;
+        for token in tokens(target) {
+            target_len += 1;
+            let sum: f64 = match self.target_vocab.get(&token) {
+                Some(&target_id) => source_ids
+                    .iter()
+                    .filter_map(|&source_id| self.probabilities.get(&(source_id, target_id)))
+                    .map(|&probability| probability as f64)
+                    .sum(),
+                None => 0.0,
+            };
+            log_probability += (sum / source_len as f64).max(MIN_PROBABILITY).ln();
+        }
+        match target_len {
+            0 => None,
+            _ => Some(log_probability / target_len as f64),
+        }
+    }
+}
+
+/// The first non-empty texts of the translation unit in the two languages.
+fn text_pair<'a>(
+    tu: &'a TranslationUnit,
+    source_lang: &str,
+    target_lang: &str,
+) -> Option<(&'a str, &'a str)> {
+    let text = |lang: &str| {
+        tu.get_lang(lang, LangMatch::Variant)
+            .map(|segment| segment.content.as_str())
+    };
+    Some((text(source_lang)?, text(target_lang)?))
+}
+
+/// Lowercased sequences of alphanumeric characters.
+fn tokens(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+}
+
+#[cfg(test)]
+mod test {
+    use super::AlignmentTrainer;
+    use crate::tmx_parser::{parse_tmx, TranslationUnit};
+
+    fn units(pairs: &[(&str, &str)]) -> Vec<TranslationUnit> {
+        let mut xml = String::from("<tmx><header></header><body>");
+        for (en, de) in pairs {
+            xml.push_str(&format!(
+                r#"<tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>{}</seg></tuv><tuv lang="DE-DE"><seg>{}</seg></tuv></tu>"#,
+                en, de
+            ));
+        }
+        xml.push_str("</body></tmx>");
+        parse_tmx(xml).unwrap().body.translation_units
+    }
+
+    #[test]
+    fn misaligned_units_score_lower() {
+        let corpus = units(&[
+            ("the house", "das Haus"),
+            ("the book", "das Buch"),
+            ("a book", "ein Buch"),
+            ("a house", "ein Haus"),
+        ]);
+        let mut trainer = AlignmentTrainer::new(String::from("EN-GB"), String::from("DE-DE"));
+        for tu in &corpus {
+            trainer.add(tu);
+        }
+        assert_eq!(trainer.units(), 4);
+        let model = trainer.train(5);
+
+        let aligned = model.score(&corpus[0]).unwrap();
+        let misaligned = model
+            .score(&units(&[("the house", "ein Buch")])[0])
+            .unwrap();
+        assert!(aligned <= 0.0);
+        assert!(aligned > misaligned, "{} <= {}", aligned, misaligned);
+        assert!(model.score(&units(&[("the house", "")])[0]).is_none());
+    }
+
+    #[test]
+    fn counted_pairs_are_bounded() {
+        let corpus = units(&[("the house", "das Haus"), ("a big book", "ein dickes Buch")]);
+        let mut trainer = AlignmentTrainer::new(String::from("EN-GB"), String::from("DE-DE"));
+        for tu in &corpus {
+            trainer.add(tu);
+        }
+        trainer.max_pairs = 4;
+        let model = trainer.train(3);
+
+        assert!(!model.probabilities.is_empty());
+        assert!(model.probabilities.len() <= 4);
+        assert!(model.score(&corpus[0]).is_some());
+    }
+}
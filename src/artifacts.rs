@@ -0,0 +1,78 @@
+use regex::Regex;
+
+/// Flags texts which are formatting artifacts rather than sentences, typical
+/// of legislative annexes and tables.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct ArtifactFlags {
+    /// The text is entirely uppercase, e.g. `ANNEX I`.
+    pub uppercase: bool,
+
+    /// The text consists of numbers only, e.g. `12,5 %` or `3.2.1`.
+    pub numeric: bool,
+
+    /// The text is a list marker (e.g. `(a)`, `2.`, `iv)`) or consists of
+    /// punctuation only, e.g. a dash in an empty table cell.
+    pub list_marker: bool,
+}
+
+pub struct ArtifactDetector {
+    list_marker: Regex,
+}
+
+impl Default for ArtifactDetector {
+    fn default() -> ArtifactDetector {
+        ArtifactDetector::new()
+    }
+}
+
+impl ArtifactDetector {
+    pub fn new() -> ArtifactDetector {
+        ArtifactDetector {
+            list_marker: Regex::new(
+                r"(?i)^(\(([0-9]{1,3}|[a-z]|[ivxlc]{1,6})\)|([0-9]{1,3}|[a-z]|[ivxlc]{1,6})[.)])$",
+            )
+            .unwrap(),
+        }
+    }
+
+    pub fn detect(&self, text: &str) -> ArtifactFlags {
+        let text = text.trim();
+        let letters = text.chars().filter(|c| c.is_alphabetic()).count();
+        let digits = text.chars().filter(|c| c.is_numeric()).count();
+        let list_marker = self.list_marker.is_match(text);
+
+        ArtifactFlags {
+            uppercase: letters > 1 && !list_marker && !text.chars().any(char::is_lowercase),
+            numeric: letters == 0 && digits > 0 && !list_marker,
+            list_marker: list_marker || (!text.is_empty() && letters == 0 && digits == 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ArtifactDetector, ArtifactFlags};
+
+    #[test]
+    fn artifacts_are_detected() {
+        let detector = ArtifactDetector::new();
+        let flags = |uppercase, numeric, list_marker| ArtifactFlags {
+            uppercase,
+            numeric,
+            list_marker,
+        };
+
+        assert_eq!(detector.detect("ANNEX I"), flags(true, false, false));
+        assert_eq!(detector.detect("ΠΑΡΑΡΤΗΜΑ"), flags(true, false, false));
+        assert_eq!(detector.detect("12,5 %"), flags(false, true, false));
+        assert_eq!(detector.detect("3.2.1"), flags(false, true, false));
+        assert_eq!(detector.detect("(a)"), flags(false, false, true));
+        assert_eq!(detector.detect("iv)"), flags(false, false, true));
+        assert_eq!(detector.detect("2."), flags(false, false, true));
+        assert_eq!(detector.detect("—"), flags(false, false, true));
+        assert_eq!(
+            detector.detect("This Decision shall enter into force."),
+            ArtifactFlags::default()
+        );
+    }
+}
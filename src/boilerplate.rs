@@ -0,0 +1,79 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::error::Result;
+use crate::tmx_parser::TranslationUnit;
+
+/// Formulaic sentences of each language (see `--boilerplate-file`), e.g.
+/// `This Decision shall enter into force on the day of its publication.`
+/// A translation unit is boilerplate if any of its texts is one of the
+/// phrases of its language. Texts are compared exactly, except that
+/// surrounding whitespace is ignored and runs of whitespace are treated as
+/// a single space.
+#[derive(Debug, Default)]
+pub struct Boilerplate {
+    phrases: HashMap<String, HashSet<String>>,
+}
+
+impl Boilerplate {
+    /// Add the phrases of a file, one per line, for a language (a DGT code,
+    /// e.g. `EN-GB`). Empty lines and lines starting with `#` are ignored.
+    pub fn add_file(&mut self, lang: &str, path: &Path) -> Result<()> {
+        let contents = std::fs::read_to_string(path)?;
+        let phrases = self.phrases.entry(lang.to_string()).or_default();
+        phrases.extend(
+            contents
+                .lines()
+                .filter(|line| !line.trim_start().starts_with('#'))
+                .map(normalize)
+                .filter(|phrase| !phrase.is_empty()),
+        );
+
+        Ok(())
+    }
+
+    pub fn is_boilerplate(&self, tu: &TranslationUnit) -> bool {
+        tu.segments.iter().any(|segment| {
+            self.phrases
+                .get(&segment.lang)
+                .is_some_and(|phrases| phrases.contains(&normalize(&segment.content)))
+        })
+    }
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::Boilerplate;
+    use crate::tmx_parser::{parse_tmx, TranslationUnit};
+
+    fn tu(lang: &str, text: &str) -> TranslationUnit {
+        let xml = format!(
+            r#"<tmx><header></header><body><tu><tuv lang="{}"><seg>{}</seg></tuv></tu></body></tmx>"#,
+            lang, text
+        );
+        parse_tmx(xml).unwrap().body.translation_units.remove(0)
+    }
+
+    #[test]
+    fn boilerplate_is_matched_in_its_language() {
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("boilerplate_en.txt");
+        std::fs::write(
+            &path,
+            "# Final provisions\nThis Decision shall enter into force.\n\n  Done at Brussels.  \n",
+        )
+        .unwrap();
+        let mut boilerplate = Boilerplate::default();
+        boilerplate.add_file("EN-GB", &path).unwrap();
+
+        assert!(boilerplate.is_boilerplate(&tu("EN-GB", "This Decision  shall enter into force.")));
+        assert!(boilerplate.is_boilerplate(&tu("EN-GB", "Done at Brussels.")));
+        assert!(!boilerplate.is_boilerplate(&tu("EN-GB", "This Decision shall apply.")));
+        assert!(!boilerplate.is_boilerplate(&tu("EN-GB", "# Final provisions")));
+        assert!(!boilerplate.is_boilerplate(&tu("FR-FR", "Done at Brussels.")));
+    }
+}
@@ -0,0 +1,93 @@
+use crate::error::Result;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+use crate::functions::{parse_tmx_file, to_hex, ParsedTmxFile};
+
+/// Must be incremented whenever the serialized representation of
+/// [ParsedTmxFile] changes, so that stale cache entries are not read.
+const CACHE_FORMAT_VERSION: u32 = 6;
+
+/// zstd compression level used for cache entries.
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Stores the parsed TMX files (translation units and warnings) as compressed binary
+/// files, keyed by the hash of the raw TMX contents. Decoding UTF-16 and
+/// parsing XML is the most expensive part of the pipeline, so subsequent runs
+/// over the same input (e.g. exporting to a different output format) can skip
+/// it entirely.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf) -> Result<Cache> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Cache { dir })
+    }
+
+    /// Parse a TMX file, reading it from the cache if the file has been
+    /// parsed before.
+    pub fn load_or_parse(&self, contents: Vec<u8>) -> Result<ParsedTmxFile> {
+        let path = self.entry_path(&contents);
+        if path.exists() {
+            // A corrupted or truncated entry is simply replaced.
+            if let Ok(parsed) = Cache::read_entry(&path) {
+                return Ok(parsed);
+            }
+        }
+
+        let parsed = parse_tmx_file(contents)?;
+        self.write_entry(&path, &parsed)?;
+
+        Ok(parsed)
+    }
+
+    fn entry_path(&self, contents: &[u8]) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(CACHE_FORMAT_VERSION.to_le_bytes());
+        hasher.update(contents);
+        self.dir
+            .join(format!("{}.bin.zst", to_hex(&hasher.finalize())))
+    }
+
+    fn read_entry(path: &PathBuf) -> Result<ParsedTmxFile> {
+        let decoder = zstd::Decoder::new(BufReader::new(File::open(path)?))?;
+        Ok(bincode::deserialize_from(decoder)?)
+    }
+
+    fn write_entry(&self, path: &PathBuf, parsed: &ParsedTmxFile) -> Result<()> {
+        // Written under a temporary name first, so that an interrupted run
+        // never leaves a partial entry behind.
+        let tmp_path = path.with_extension("tmp");
+        let writer = BufWriter::new(File::create(&tmp_path)?);
+        let mut encoder = zstd::Encoder::new(writer, COMPRESSION_LEVEL)?;
+        bincode::serialize_into(&mut encoder, parsed)?;
+        encoder.finish()?.flush()?;
+        std::fs::rename(tmp_path, path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Cache;
+    use crate::functions::parse_tmx_file;
+
+    #[test]
+    fn cached_translation_units_are_identical_to_parsed() {
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        let cache = Cache::new(tmp_dir.path().to_path_buf()).unwrap();
+        let contents = std::fs::read("./test_data/test_dgt_docs/22019D1360.tmx").unwrap();
+
+        let parsed = cache.load_or_parse(contents.clone()).unwrap();
+        assert_eq!(std::fs::read_dir(tmp_dir.path()).unwrap().count(), 1);
+        let cached = cache.load_or_parse(contents.clone()).unwrap();
+
+        assert_eq!(cached, parsed);
+        assert_eq!(cached, parse_tmx_file(contents).unwrap());
+    }
+}
@@ -1,5 +1,32 @@
-use clap::{Parser, Subcommand};
+use clap::builder::PossibleValuesParser;
+use clap::{ArgGroup, CommandFactory, FromArgMatches, Parser, Subcommand};
+use regex::Regex;
+use std::ffi::{OsStr, OsString};
 use std::path::PathBuf;
+use std::time::Duration;
+
+#[cfg(feature = "sqlite")]
+use crate::commands::align_phrases::Measure;
+#[cfg(feature = "sqlite")]
+use crate::commands::kwic::KwicFormat;
+use dgt_parser::config::DEFAULT_CONFIG_FILE;
+use dgt_parser::error::DgtError;
+#[cfg(feature = "sqlite")]
+use dgt_parser::functions::parse_pragma;
+use dgt_parser::functions::{
+    parse_boilerplate_file, parse_byte_size, parse_duration, parse_glob, parse_lang,
+    parse_merge_corpus, parse_prop_column, parse_regex, parse_tee_target, parse_threshold,
+    DEFAULT_MAX_OPEN_ARCHIVES, LANG_PRESETS,
+};
+use dgt_parser::handlers::all_pairs::PairFormat;
+use dgt_parser::handlers::classification::ClassificationFormat;
+#[cfg(feature = "sqlite")]
+use dgt_parser::handlers::sqlite_db::LangStorage;
+use dgt_parser::input::InputSource;
+use dgt_parser::types::{
+    FailOn, MissingAs, MultiDoc, NewlinePolicy, Progress, PropColumn, TeeTarget,
+};
+use glob::Pattern;
 
 #[derive(Parser)]
 #[clap(
@@ -10,36 +37,829 @@ use std::path::PathBuf;
 #[clap(author = "Paweł Malinowski")]
 #[clap(about = "Parse and transform the DGT-TM (translation memory).")]
 #[clap(propagate_version = true)]
+#[clap(group(ArgGroup::new("lang_filter").args(["langs", "langs_file", "langs_preset"]).multiple(true)))]
 pub struct Cli {
     #[clap(subcommand)]
     pub command: Commands,
 
-    /// Path to directory containing a flat collection of ZIP files.
-    #[clap(short, long)]
-    #[clap(display_order = 1)]
-    pub input_dir: PathBuf,
+    /// Path to directory containing the DGT-TM release files: ZIP, tar or
+    /// tar.gz archives (possibly nested), plain TMX files or subdirectories.
+    /// A single archive or TMX file, or `-` for the standard input, can be
+    /// given instead. Required by the subcommands which parse the
+    /// translation memory.
+    #[clap(short, long, value_parser = InputSource::parse)]
+    pub input_dir: Option<InputSource>,
+
+    /// Merge another corpus of TMX files, e.g. `europarl=./europarl_tmx`
+    /// (given as `--input-dir`), processed after the input. Each translation
+    /// unit is then tagged with the name of its corpus (`dgt` for the input)
+    /// in the `corpus` column. The language codes of the other corpora are
+    /// coerced to those of DGT-TM (e.g. `en` to `EN-GB`), their document
+    /// names are prefixed with the name of the corpus (e.g.
+    /// `europarl:ep-00-01-17`, also in `--docs-file`), and their units
+    /// without a document ID are assigned to a document named after the
+    /// corpus and the TMX file, e.g. `europarl:en-pl`. May be repeated.
+    #[clap(long, value_name = "NAME=INPUT", value_parser = parse_merge_corpus)]
+    pub merge_corpus: Vec<(String, InputSource)>,
 
     /// Languages that should be included in the output. All languages are
-    /// included by default.
-    #[clap(short)]
-    #[clap(display_order = 2)]
+    /// included by default. Unknown languages are rejected, with the closest
+    /// DGT language suggested.
+    #[clap(short, value_parser = parse_lang)]
     pub langs: Option<Vec<String>>,
 
+    /// File with languages that should be included in the output, one per
+    /// line (e.g. `en` or `EN-GB`). Empty lines and comments (starting with
+    /// `#`) are ignored. Combined with the languages specified with `-l`.
+    #[clap(long, value_name = "FILE")]
+    pub langs_file: Option<PathBuf>,
+
+    /// Include a named set of languages: `eu15` (the languages of the 15
+    /// member states before 2004), `eu24` (all DGT languages), `slavic` or
+    /// `romance`. Combined with the languages specified with `-l` and in
+    /// the `--langs-file`.
+    #[clap(long, value_name = "PRESET")]
+    #[clap(value_parser = PossibleValuesParser::new(LANG_PRESETS.map(|(name, _langs)| name)))]
+    pub langs_preset: Option<Vec<String>>,
+
     /// Only include translation units where each of the specified languages is
     /// present.
     #[clap(short, long)]
-    #[clap(display_order = 3)]
-    #[clap(requires = "langs")]
+    #[clap(requires = "lang_filter")]
     pub require_each_lang: bool,
+
+    /// Only include translation units with texts in at least this many
+    /// languages (counting the specified languages only, if any), e.g. 2 to
+    /// drop the units which are useless as parallel texts. By default, units
+    /// are kept even if all their texts are empty (see `--missing-as`).
+    #[clap(long, value_name = "N")]
+    pub min_langs: Option<usize>,
+
+    /// Only include translation units whose text in the language specified
+    /// with `--match-lang` matches this regular expression, e.g. `(?i)state
+    /// aid`, to build a topical sub-corpus.
+    #[clap(long, value_name = "REGEX", value_parser = parse_regex)]
+    #[clap(requires = "match_lang")]
+    pub match_regex: Option<Regex>,
+
+    /// Language of the texts matched against `--match-regex` (which need
+    /// not be among the languages included in the output).
+    #[clap(long, value_name = "LANG", value_parser = parse_lang)]
+    #[clap(requires = "match_regex")]
+    pub match_lang: Option<String>,
+
+    /// Drop entire documents in which a language has texts in fewer than
+    /// this share (between 0 and 1) of the translation units, e.g. 0.9 to
+    /// keep half-translated documents out of document-level corpora. The
+    /// specified languages are checked (or all languages found in a
+    /// document), within each TMX file.
+    #[clap(long, value_name = "SHARE", value_parser = parse_threshold)]
+    pub require_doc_coverage: Option<f64>,
+
+    /// Treat requested languages which do not occur in the input (e.g.
+    /// because of a typo) as errors rather than warnings, so that the exit
+    /// status reflects them (see `--fail-on`).
+    #[clap(long)]
+    #[clap(requires = "lang_filter")]
+    pub strict_langs: bool,
+
+    /// Pivot language, e.g. `en`. Only translation units containing a text in
+    /// this language are included, and it is treated as the source language
+    /// by the output formats (e.g. it is the first language column in SQLite).
+    #[clap(short, long, value_parser = parse_lang)]
+    pub source_lang: Option<String>,
+
+    /// Only process the TMX files in archives whose path within the archive
+    /// matches this glob pattern, e.g. `'*/EN_*.tmx'`.
+    #[clap(long, value_name = "PATTERN", value_parser = parse_glob)]
+    pub tmx_glob: Option<Pattern>,
+
+    /// File with the names (CELEX numbers) of documents to exclude, one per
+    /// line, e.g. corrigenda or documents known to be misaligned. Empty lines
+    /// and comments (starting with `#`) are ignored.
+    #[clap(long, value_name = "FILE")]
+    pub exclude_docs_file: Option<PathBuf>,
+
+    /// File with the names (CELEX numbers) of the only documents to process,
+    /// in the format of `--exclude-docs-file`, e.g. a curated set of
+    /// regulations. Listed documents which do not occur in the input are
+    /// reported as warnings.
+    #[clap(long, value_name = "FILE")]
+    pub docs_file: Option<PathBuf>,
+
+    /// File with the SHA-256 checksums of the input files, in the format of
+    /// `sha256sum` (e.g. the list published with a release). The listed files
+    /// are verified before processing, and the run fails on a mismatch (e.g. a
+    /// truncated download).
+    #[clap(long, value_name = "FILE")]
+    pub checksums: Option<PathBuf>,
+
+    /// How texts missing from a translation unit (or empty) are saved in
+    /// outputs with a column per language, e.g. SQLite.
+    #[clap(long, value_enum, default_value_t = MissingAs::Empty)]
+    pub missing_as: MissingAs,
+
+    /// What to do with translation units which name several documents:
+    /// assign them to the first one, assign them to the first one and link
+    /// them to all of them in the `unit_documents` table of the SQLite
+    /// output, or assign them to a document named after all of them (e.g.
+    /// `32019D0557;32019D0558`). Filters by document (e.g.
+    /// `--docs-file`) apply to the first document.
+    #[clap(long, value_enum, default_value_t = MultiDoc::First)]
+    pub multi_doc: MultiDoc,
+
+    /// Also save the position of each translation unit in its TMX file
+    /// (starting from 0), in the `index_in_file` column. The sequential
+    /// number is the position in the document, which may span several TMX
+    /// files.
+    #[clap(long)]
+    pub index_in_file: bool,
+
+    /// Save a prop of the translation units in a column of its own in the
+    /// tabular outputs (SQLite and XLSX), e.g. `"Txt::Doc. Type=doc_type"`.
+    /// The column holds text, unless the type `integer` is appended, e.g.
+    /// `"Txt::Year=year:integer"` (values which are not integers are left
+    /// out). Can be repeated.
+    #[clap(long, value_name = "KEY=COLUMN[:TYPE]", value_parser = parse_prop_column)]
+    pub prop_column: Vec<PropColumn>,
+
+    /// Save the props which are not mapped with `--prop-column` (other than
+    /// the document number) in the `props` column of the tabular outputs, as
+    /// a JSON object mapping their types to their values.
+    #[clap(long)]
+    pub unmapped_props_json: bool,
+
+    /// Cluster near-duplicate translation units and record the cluster ID.
+    /// Units are compared by their text in the source language (or, if not
+    /// selected, by their first text). The threshold (between 0 and 1) is the
+    /// minimum similarity of the texts' word trigrams.
+    #[clap(long, value_name = "THRESHOLD", value_parser = parse_threshold)]
+    #[clap(conflicts_with = "drop_near_duplicates")]
+    pub cluster_near_duplicates: Option<f64>,
+
+    /// Same as `--cluster-near-duplicates`, but only the first translation
+    /// unit of each cluster is included in the output.
+    #[clap(long, value_name = "THRESHOLD", value_parser = parse_threshold)]
+    pub drop_near_duplicates: Option<f64>,
+
+    /// Flag translation units whose text (in the source language or, if not
+    /// selected, the first text) is entirely uppercase, numeric or a list
+    /// marker, e.g. headings and table cells of annexes.
+    #[clap(long)]
+    pub flag_artifacts: bool,
+
+    /// Drop translation units with a text listed in a file of boilerplate
+    /// phrases of its language, e.g. `en=boilerplate_en.txt` (one phrase per
+    /// line, compared exactly except for whitespace). May be repeated for
+    /// several languages (or files).
+    #[clap(long, value_name = "LANG=FILE", value_parser = parse_boilerplate_file)]
+    pub boilerplate_file: Vec<(String, PathBuf)>,
+
+    /// Flag the translation units with boilerplate texts (see
+    /// `--boilerplate-file`) in the `is_boilerplate` column rather than
+    /// dropping them.
+    #[clap(long)]
+    #[clap(requires = "boilerplate_file")]
+    pub flag_boilerplate: bool,
+
+    /// Score how likely the text in the target language is a translation of
+    /// the text in the source language, e.g. `--score-alignment en pl`, to
+    /// find misaligned translation units. The texts are scored with an IBM
+    /// Model 1 lexical translation model trained on the input in an extra
+    /// pass, and the score (the average log-probability of the target
+    /// tokens, at most 0) is saved in the `alignment_score` column (SQLite)
+    /// or field (JSONL). The token IDs of the language pair are held in
+    /// memory during training.
+    #[clap(long, num_args = 2, value_names = ["SOURCE", "TARGET"], value_parser = parse_lang)]
+    pub score_alignment: Option<Vec<String>>,
+
+    /// Number of training iterations of the alignment model (see
+    /// `--score-alignment`).
+    #[clap(long, value_name = "N", default_value_t = 5)]
+    #[clap(requires = "score_alignment")]
+    pub alignment_iterations: usize,
+
+    /// Save at most this many texts in each language (i.e. translation units
+    /// containing the language). Texts are kept in the order of the input;
+    /// translation units left without texts (or without the text in the
+    /// source language) are not saved.
+    #[clap(long, value_name = "N")]
+    pub max_units_per_lang: Option<u64>,
+
+    /// Save at most this many characters of texts in each language, with the
+    /// same rules as `--max-units-per-lang`.
+    #[clap(long, value_name = "N")]
+    pub max_chars_per_lang: Option<u64>,
+
+    /// Save a record of each translation unit skipped by the filters (with
+    /// the reason, the TMX file, the document and the sequential number) in
+    /// a JSONL file, e.g. to audit the effect of the filters.
+    #[clap(long, value_name = "FILE")]
+    pub skip_log: Option<PathBuf>,
+
+    /// Record at most this many skipped translation units for each reason
+    /// (sampled from all of them), which bounds the size of `--skip-log`.
+    #[clap(long, value_name = "N")]
+    #[clap(requires = "skip_log")]
+    pub skip_log_limit: Option<u64>,
+
+    /// Directory where parsed TMX files are cached. Subsequent runs over the
+    /// same input skip decoding and parsing the cached files.
+    #[clap(long)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Catalog of the input saved by the `catalog` subcommand. The number of
+    /// TMX files is read from it rather than counted before parsing (see
+    /// `--progress`), and the files recorded in `--seen-files` are skipped
+    /// without being read. A catalog which does not match the input (or
+    /// `--tmx-glob`) is ignored for these purposes. The number of translation
+    /// units of each TMX file is checked against it in any case, with a
+    /// warning for files which may be truncated, and an error if none of the
+    /// files is listed in it.
+    #[clap(long, value_name = "FILE")]
+    pub catalog: Option<PathBuf>,
+
+    /// Configuration file (TOML), e.g. registering out-of-tree handlers for
+    /// the `external` subcommand, or with the input and the languages used
+    /// if they are not given on the command line (see the `init` subcommand).
+    #[clap(long, value_name = "FILE", default_value = DEFAULT_CONFIG_FILE)]
+    pub config: PathBuf,
+
+    /// File recording the TMX files already processed (by the hash of their
+    /// contents). Files listed in it are skipped, and the files processed by
+    /// a successful run are added to it, so that e.g. `sqlite --append` over
+    /// a synced mirror only processes new files. Created if it does not
+    /// exist.
+    #[clap(long, value_name = "FILE")]
+    pub seen_files: Option<PathBuf>,
+
+    /// Stop once the run has taken the given time, e.g. `2h`, `90m` or
+    /// `1h30m`. The limit is checked between TMX files: the file being
+    /// processed is finished, the output is saved and the remaining files
+    /// are left out, so that a later run with the same `--seen-files` (and
+    /// e.g. `sqlite --append`) continues where this one stopped.
+    #[clap(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub max_duration: Option<Duration>,
+
+    /// Stop once the given number of translation units has been saved,
+    /// like `--max-duration` (at the end of the TMX file in which the limit
+    /// is reached).
+    #[clap(long, value_name = "N")]
+    pub max_units: Option<u64>,
+
+    /// Cap the approximate memory used by the translation units held at
+    /// once (those of the TMX file being processed and those not yet
+    /// written by the output), e.g. `512M`: the SQLite output commits its
+    /// batch early whenever the cap is exceeded. The memory used is shown
+    /// in the progress line.
+    #[clap(long, value_name = "SIZE", value_parser = parse_byte_size)]
+    pub max_memory: Option<usize>,
+
+    /// Read and parse the next TMX file on another thread while the output
+    /// writes the translation units of the current one, e.g. while SQLite
+    /// commits a batch. Up to two more TMX files are held in memory at
+    /// once (not counted by `--max-memory`).
+    #[clap(long)]
+    pub read_ahead: bool,
+
+    /// Exit with status code 3 (after the output has been saved) if problems
+    /// with the input data were found: TMX files that had to be skipped
+    /// (errors) or translation units dropped because of invalid data
+    /// (warnings).
+    #[clap(long, value_enum, default_value_t = FailOn::Errors)]
+    pub fail_on: FailOn,
+
+    /// Maximum number of ZIP archives of each input kept open between the
+    /// passes over it (e.g. counting the TMX files for `--progress files`,
+    /// then processing them), the least recently used being closed first.
+    /// Lower it on systems with a low limit of open files; 0 closes each
+    /// archive once read.
+    #[clap(long, value_name = "N")]
+    #[clap(default_value_t = DEFAULT_MAX_OPEN_ARCHIVES)]
+    pub max_open_files: usize,
+
+    /// How progress is reported. Counting the TMX files (`files`) requires
+    /// opening every archive twice, which `bytes` avoids.
+    #[clap(long, value_enum, default_value_t = Progress::Files)]
+    pub progress: Progress,
+
+    /// Also report progress as newline-delimited JSON events on the standard
+    /// error (`started`, `file_started`, `file_done`, `batch_committed`,
+    /// `error`, `warning` and `finished`), for tools wrapping the command,
+    /// instead of the progress line and of the problems printed as text.
+    /// They are emitted with `--quiet` too.
+    #[clap(long)]
+    pub progress_json: bool,
+
+    /// After the run, save a JSON manifest at this path, listing the output
+    /// files with their sizes and SHA-256 checksums, the schema version and
+    /// the filters used.
+    #[clap(long, value_name = "FILE")]
+    pub manifest: Option<PathBuf>,
+
+    /// Reuse notice embedded in the outputs (a `license` table in SQLite, an
+    /// `ATTRIBUTION.txt` file in output directories, the footer of review
+    /// pages, the workbook properties and the manifest). Defaults to the
+    /// acknowledgement of the DGT-TM as the source; an empty value disables
+    /// it.
+    #[clap(long, value_name = "TEXT")]
+    pub attribution: Option<String>,
+
+    /// Also write each translation unit saved in the output to a secondary
+    /// output, `FORMAT:PATH`. The only format is `jsonl` (a JSON object per
+    /// line); the path `-` stands for the standard output (implies
+    /// `--quiet`), e.g. to follow the units live while a database is built.
+    #[clap(long, value_name = "FORMAT:PATH", value_parser = parse_tee_target)]
+    pub tee: Option<TeeTarget>,
+
+    /// Don’t report progress or problems with the input data.
+    #[clap(short, long)]
+    pub quiet: bool,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
-    #[clap(display_order = 1)]
+    /// Ask for the input, the languages and the output format, save the
+    /// input and the languages in the `--config` file (so that they can be
+    /// left out of later commands) and print the equivalent command.
+    Init,
+
+    /// Save the translation units in the format inferred from the extension
+    /// of the output file: `.db`, `.sqlite` or `.sqlite3` (as with the
+    /// `sqlite` subcommand), `.xlsx` (as with `xlsx`) or `.jsonl` (a JSON
+    /// object per line, as with `--tee jsonl:PATH`), with the default
+    /// options. Use the subcommands to set their options.
+    Convert {
+        /// Output file path.
+        #[clap(short, long = "output")]
+        output_file: PathBuf,
+    },
+
+    #[cfg(feature = "sqlite")]
     /// Save the translation units in an SQLite database.
     Sqlite {
+        /// Output file path, or `-` to write the database to the standard
+        /// output once finished (implies `--quiet`).
+        #[clap(short, long = "output")]
+        output_file: String,
+
+        /// Commit the current batch of translation units once their texts
+        /// exceed this size (e.g. `64M`), limiting memory usage when the
+        /// segments are very long.
+        #[clap(long, value_parser = parse_byte_size)]
+        max_batch_bytes: Option<usize>,
+
+        /// Save the creation and change dates of the texts (if present in the
+        /// input) in `<lang>_created` and `<lang>_changed` columns, in the
+        /// ISO 8601 format.
+        #[clap(long)]
+        dates: bool,
+
+        /// Save a copy of each text, lowercased and stripped of diacritics, in
+        /// a `<lang>_search` column (e.g. `pl_01_search`), for accent- and
+        /// case-insensitive `LIKE` queries.
+        #[clap(long)]
+        #[clap(conflicts_with = "metadata_only")]
+        search_columns: bool,
+
+        /// Save the language of the original text of each translation unit
+        /// (declared by the `srclang` attribute in the input, e.g. `EN-GB`)
+        /// in a `srclang` column.
+        #[clap(long)]
+        #[clap(conflicts_with = "metadata_only")]
+        srclang_column: bool,
+
+        /// How the texts are stored: a column per language, or a single
+        /// `texts` column with a JSON object mapping language codes to texts
+        /// (e.g. for ORMs, which expect a fixed schema).
+        #[clap(long, value_enum, default_value_t = LangStorage::Columns)]
+        #[clap(conflicts_with_all = ["metadata_only", "dates", "search_columns"])]
+        lang_storage: LangStorage,
+
+        /// Remove parenthetical suffixes (e.g. `(01)`) from the normalized
+        /// document names. The names found in the input are kept in the
+        /// `raw_name` column of the `documents` table.
+        #[clap(long)]
+        strip_doc_name_suffixes: bool,
+
+        /// Only save a catalog of the documents: the number of translation
+        /// units of each document (in the `documents` table) and the number
+        /// of texts in each language (in the `document_languages` table),
+        /// without the texts.
+        #[clap(long)]
+        #[clap(conflicts_with = "append")]
+        metadata_only: bool,
+
+        /// Number the documents in the order of their names (CELEX numbers)
+        /// rather than in the order in which they were found, so that the
+        /// document IDs are the same across runs over the same documents.
+        #[clap(long)]
+        #[clap(conflicts_with = "append")]
+        deterministic: bool,
+
+        /// Create STRICT tables, in which values of the wrong type are
+        /// rejected rather than stored (requires SQLite 3.37 or newer to read
+        /// the database).
+        #[clap(long)]
+        #[clap(conflicts_with = "append")]
+        strict: bool,
+
+        /// Add the translation units to an existing database (created by a
+        /// previous run) instead of creating a new one. Documents already in
+        /// the database keep their IDs.
+        #[clap(long)]
+        append: bool,
+
+        /// Like `--append`, but the translation units already in the
+        /// database (with the same document and sequential number) are
+        /// updated rather than added again: their texts which differ are
+        /// replaced, and each change is recorded in the `revisions` table
+        /// (the hash of the old text, the new text and the time), giving a
+        /// change history across releases.
+        #[clap(long)]
+        #[clap(conflicts_with_all = ["append", "metadata_only", "deterministic", "strict", "lang_storage"])]
+        update: bool,
+
+        /// Once all translation units have been saved, index the columns by
+        /// which the tables are joined (e.g. `document_id` and
+        /// `sequential_number`) and run `ANALYZE`, so that the database is
+        /// ready to be queried.
+        #[clap(long)]
+        with_indexes: bool,
+
+        /// Set a pragma on the database connection before the tables are
+        /// created, e.g. `--pragma page_size=8192` or `--pragma
+        /// journal_mode=WAL` (may be given more than once).
+        #[clap(long = "pragma", value_name = "NAME=VALUE", value_parser = parse_pragma)]
+        pragmas: Vec<(String, String)>,
+
+        /// Load an SQLite extension (a shared library) into the database
+        /// connection before the tables are created (may be given more than
+        /// once). Extensions are only loaded from the paths given here.
+        #[clap(long = "load-extension", value_name = "PATH")]
+        extensions: Vec<PathBuf>,
+    },
+
+    /// Save a language co-occurrence graph as a CSV edge list (lang A, lang B,
+    /// shared unit count, shared token count).
+    LangPairs {
         /// Output file path.
         #[clap(short, long = "output")]
         output_file: String,
     },
+
+    #[cfg(feature = "kv")]
+    /// Save the translation units in a sled key-value store, keyed by the
+    /// document name and the sequential number of the translation unit in the
+    /// document, for fast lookups of aligned texts.
+    Kv {
+        /// Output directory path (sled stores its data in a directory).
+        #[clap(short, long = "output")]
+        output_dir: PathBuf,
+    },
+
+    /// Save a parallel corpus for every pair of the languages specified with
+    /// `-l` (e.g. en-fr, en-de and fr-de) in a single pass, each in its own
+    /// `<OUTPUT_DIR>/<lang_a>-<lang_b>/` directory.
+    ExtractAllPairs {
+        /// Output directory path.
+        #[clap(short, long = "output")]
+        output_dir: PathBuf,
+
+        #[clap(long, value_enum, default_value_t = PairFormat::Moses)]
+        format: PairFormat,
+
+        /// How line breaks in the texts are written: replaced with spaces,
+        /// escaped as `\n`, or kept in quoted fields (RFC 4180, only with
+        /// `--format tsv`).
+        #[clap(long, value_enum, default_value_t = NewlinePolicy::Replace)]
+        newline_policy: NewlinePolicy,
+
+        /// Maximum number of output files open at the same time. Files are
+        /// closed and reopened as needed, so that extracting many pairs does
+        /// not exceed the limit of open files of the system.
+        #[clap(long)]
+        max_open_outputs: Option<usize>,
+    },
+
+    #[cfg(feature = "xlsx")]
+    /// Save the translation units in an Excel workbook, with a column per
+    /// language specified with `-l` and a worksheet per document, e.g. for
+    /// reviewers working in a spreadsheet.
+    Xlsx {
+        /// Output file path.
+        #[clap(short, long = "output")]
+        output_file: PathBuf,
+
+        /// Split the translation units into worksheets of this many rows
+        /// (with the document in the first column) instead of saving each
+        /// document in a worksheet of its own.
+        #[clap(long)]
+        rows_per_sheet: Option<u32>,
+    },
+
+    /// Save a static HTML page for each document, with the texts in the
+    /// languages specified with `-l` side by side and an anchor for each
+    /// sequential number, e.g. for reviewing translations.
+    HtmlReview {
+        /// Output directory path.
+        #[clap(short, long = "output")]
+        output_dir: PathBuf,
+    },
+
+    /// Save the texts as training data for text classifiers: lines labelled
+    /// with the language in the format of fastText, or pairs of aligned (and
+    /// mismatched) texts, e.g. for language identification or cross-lingual
+    /// classification.
+    Classification {
+        /// Output file path.
+        #[clap(short, long = "output")]
+        output_file: PathBuf,
+
+        #[clap(long, value_enum, default_value_t = ClassificationFormat::LangId)]
+        format: ClassificationFormat,
+
+        /// How line breaks in the texts are written: replaced with spaces,
+        /// escaped as `\n`, or kept in quoted fields (RFC 4180, only with
+        /// `--format pairs`).
+        #[clap(long, value_enum, default_value_t = NewlinePolicy::Replace)]
+        newline_policy: NewlinePolicy,
+    },
+
+    /// Check that, within each document, every language has as many texts as
+    /// the best-covered language, reporting documents with missing texts (a
+    /// symptom of partially corrupted TMX files) as warnings. Use with
+    /// `--fail-on warnings` to exit with a non-zero status code if any are
+    /// found.
+    Verify {
+        /// Minimum share (between 0 and 1) of the texts of the best-covered
+        /// language that each language must have.
+        #[clap(long, default_value_t = 0.75, value_parser = parse_threshold)]
+        min_coverage: f64,
+    },
+
+    /// Pass the translation units to an out-of-tree handler: a program
+    /// registered in a `[handlers.<NAME>]` table of the `--config` file,
+    /// which receives them as JSON lines on its standard input.
+    External {
+        /// Name of the handler in the configuration file.
+        name: String,
+    },
+
+    /// Save a catalog of the TMX files of the input (with the size, the
+    /// number of translation units and the hash of each file) as JSON, so
+    /// that later runs over the same input can report progress without
+    /// scanning the archives first, and skip the files they have already
+    /// processed without reading them (see `--catalog`).
+    Catalog {
+        /// Output file path.
+        #[clap(short, long = "output")]
+        output_file: PathBuf,
+    },
+
+    #[cfg(feature = "sqlite")]
+    /// Print keyword-in-context lines for a term found in an SQLite database
+    /// generated by the `sqlite` subcommand, with aligned translations.
+    Kwic {
+        /// Path to the database.
+        database: PathBuf,
+
+        /// The term to look for (case-insensitive).
+        #[clap(short, long)]
+        term: String,
+
+        /// Language of the term, e.g. `en`.
+        #[clap(short, long)]
+        lang: String,
+
+        /// Language of the translations displayed below each line.
+        #[clap(short = 'T', long)]
+        target_lang: Option<String>,
+
+        /// Number of characters of context displayed on each side of the
+        /// term.
+        #[clap(short, long, default_value_t = 40)]
+        window: usize,
+
+        /// Maximum number of matching translation units (0 for all of them).
+        #[clap(long, default_value_t = 100)]
+        limit: u32,
+
+        /// Number of matching translation units to leave out, to page through
+        /// the matches with `--limit`.
+        #[clap(long, default_value_t = 0)]
+        offset: u32,
+
+        /// Output format. With `jsonl` and `csv`, the occurrences are written
+        /// as they are found, so that all matches (`--limit 0`) can be
+        /// exported without being held in memory.
+        #[clap(long, value_enum, default_value_t = KwicFormat::Text)]
+        format: KwicFormat,
+    },
+
+    #[cfg(feature = "sqlite")]
+    /// (Experimental) Rank candidate pairs of source and target phrases by
+    /// their co-occurrence in the translation units of an SQLite database
+    /// generated by the `sqlite` subcommand.
+    AlignPhrases {
+        /// Path to the database.
+        database: PathBuf,
+
+        /// Source language, e.g. `en`.
+        #[clap(short, long)]
+        source_lang: String,
+
+        /// Target language, e.g. `pl`.
+        #[clap(short, long)]
+        target_lang: String,
+
+        /// Maximum length of a phrase (in tokens).
+        #[clap(long, default_value_t = 2)]
+        max_ngram: usize,
+
+        /// Minimum number of translation units a phrase, or a pair of phrases,
+        /// must occur in.
+        #[clap(long, default_value_t = 5)]
+        min_count: u32,
+
+        /// Association measure used to rank the pairs.
+        #[clap(long, value_enum, default_value_t = Measure::Dice)]
+        measure: Measure,
+
+        /// Number of candidate pairs printed.
+        #[clap(long, default_value_t = 100)]
+        limit: usize,
+    },
+
+    #[cfg(feature = "sqlite")]
+    /// Upgrade an SQLite database created by an older version of the tool to
+    /// the current schema.
+    Migrate {
+        /// Path to the database.
+        database: PathBuf,
+    },
+
+    #[cfg(feature = "sqlite")]
+    /// Save the translation units of an SQLite database generated by the
+    /// `sqlite` subcommand with another subcommand, e.g. `export db.sqlite
+    /// extract-all-pairs -o pairs`, without the release files. Only the
+    /// texts, the documents and the languages of the original texts are read
+    /// back (the units are numbered again, and their props and annotations
+    /// are left out). The options of the run (e.g. `-l`) are given before
+    /// `export`.
+    Export {
+        /// Path to the database.
+        database: PathBuf,
+
+        /// The subcommand saving the output, with its options.
+        #[clap(
+            value_name = "SUBCOMMAND",
+            required = true,
+            trailing_var_arg = true,
+            allow_hyphen_values = true
+        )]
+        output: Vec<OsString>,
+    },
+
+    /// Save TMX files with edge cases (empty texts, duplicate languages,
+    /// missing document IDs, inline markup, huge texts and invalid language
+    /// codes), e.g. to check how they are handled by an output format.
+    GenEdgeCases {
+        /// Output directory path.
+        #[clap(short, long = "output")]
+        output_dir: PathBuf,
+    },
+}
+
+/// Output formats of `convert`, by extension of the output file: the
+/// subcommand which saves the output, or `jsonl`.
+const CONVERT_FORMATS: &[(&str, &str)] = &[
+    #[cfg(feature = "sqlite")]
+    ("db", "sqlite"),
+    #[cfg(feature = "sqlite")]
+    ("sqlite", "sqlite"),
+    #[cfg(feature = "sqlite")]
+    ("sqlite3", "sqlite"),
+    #[cfg(feature = "xlsx")]
+    ("xlsx", "xlsx"),
+    ("jsonl", "jsonl"),
+];
+
+#[cfg(feature = "sqlite")]
+impl Cli {
+    /// Replace `export` with the subcommand saving the output, reading the
+    /// database as the input.
+    pub fn resolve_export(self) -> Cli {
+        let Commands::Export { database, output } = self.command else {
+            return self;
+        };
+        if self.input_dir.is_some() {
+            Cli::command()
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    "--input-dir cannot be used with export, which reads the database",
+                )
+                .exit();
+        }
+        let args = std::iter::once(OsString::from("dgt_parser")).chain(output);
+        let command = Cli::command()
+            .try_get_matches_from(args)
+            .and_then(|matches| Cli::from_arg_matches(&matches))
+            .unwrap_or_else(|error| error.exit())
+            .command;
+        if matches!(
+            command,
+            Commands::Init
+                | Commands::Catalog { .. }
+                | Commands::Kwic { .. }
+                | Commands::AlignPhrases { .. }
+                | Commands::Migrate { .. }
+                | Commands::Export { .. }
+                | Commands::GenEdgeCases { .. }
+        ) {
+            Cli::command()
+                .error(
+                    clap::error::ErrorKind::InvalidSubcommand,
+                    "export only accepts the subcommands which save translation units",
+                )
+                .exit();
+        }
+
+        Cli {
+            command,
+            input_dir: Some(InputSource::Database(database)),
+            ..self
+        }
+    }
+}
+
+impl Commands {
+    /// Replace `convert` with the subcommand inferred from the extension of
+    /// the output file, with its default options. `convert` is kept for the
+    /// JSONL output, which has no subcommand of its own.
+    pub fn resolve_convert(self) -> Result<Commands, DgtError> {
+        let Commands::Convert { output_file } = &self else {
+            return Ok(self);
+        };
+        let extension = output_file
+            .extension()
+            .and_then(OsStr::to_str)
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match CONVERT_FORMATS
+            .iter()
+            .find(|(ext, _format)| *ext == extension)
+        {
+            None => Err(DgtError::UnknownOutputFormat(output_file.clone())),
+            Some((_ext, "jsonl")) => Ok(self),
+            Some((_ext, subcommand)) => {
+                // The output is passed as `--output=<path>`, so that paths
+                // starting with `-` are not taken for options.
+                let mut output = OsString::from("--output=");
+                output.push(output_file);
+                let args = [OsStr::new("dgt_parser"), OsStr::new(subcommand), &output];
+                Cli::command()
+                    .try_get_matches_from(args)
+                    .and_then(|matches| Cli::from_arg_matches(&matches))
+                    .map(|cli| cli.command)
+                    .map_err(|_| {
+                        DgtError::Io(std::io::Error::new(
+                            std::io::ErrorKind::InvalidInput,
+                            format!("invalid output path: {}", output_file.display()),
+                        ))
+                    })
+            }
+        }
+    }
+
+    /// Path of the file or directory where the output of the subcommand is
+    /// saved, if any.
+    pub fn output_path(&self) -> Option<PathBuf> {
+        match self {
+            _ if self.writes_to_stdout() => None,
+            Commands::Convert { output_file } => Some(output_file.clone()),
+            #[cfg(feature = "sqlite")]
+            Commands::Sqlite { output_file, .. } => Some(PathBuf::from(output_file)),
+            Commands::LangPairs { output_file } => Some(PathBuf::from(output_file)),
+            #[cfg(feature = "kv")]
+            Commands::Kv { output_dir } => Some(output_dir.clone()),
+            Commands::ExtractAllPairs { output_dir, .. } | Commands::HtmlReview { output_dir } => {
+                Some(output_dir.clone())
+            }
+            #[cfg(feature = "xlsx")]
+            Commands::Xlsx { output_file, .. } => Some(output_file.clone()),
+            Commands::Classification { output_file, .. } => Some(output_file.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether the output is written to the standard output (`-o -`), in
+    /// which case nothing else may be printed there.
+    pub fn writes_to_stdout(&self) -> bool {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Commands::Sqlite { output_file, .. } => output_file == "-",
+            _ => false,
+        }
+    }
 }
@@ -0,0 +1,249 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use super::{lang_column, open_database};
+use dgt_parser::handlers::sqlite_db::quote_identifier;
+
+/// Options of the `align-phrases` subcommand.
+pub struct AlignPhrasesOptions {
+    pub source_lang: String,
+    pub target_lang: String,
+
+    /// Phrases of up to this many tokens are considered.
+    pub max_ngram: usize,
+
+    /// Phrases, and pairs of phrases, occurring in fewer translation units
+    /// are ignored.
+    pub min_count: u32,
+
+    pub measure: Measure,
+
+    /// Number of candidate pairs printed.
+    pub limit: usize,
+}
+
+/// Association measure used to rank the candidate pairs.
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum Measure {
+    Dice,
+    Pmi,
+}
+
+/// A candidate pair of phrases with its association scores.
+#[derive(Debug)]
+pub struct PhrasePair {
+    pub source: String,
+    pub target: String,
+    pub cooccurrences: u32,
+    pub dice: f64,
+    pub pmi: f64,
+}
+
+/// Rank pairs of source and target phrases by how strongly they co-occur in
+/// the translation units of a previously generated SQLite database, and print
+/// the top candidates as tab-separated values.
+pub fn run(database: &Path, options: AlignPhrasesOptions) -> Result<()> {
+    let conn = open_database(database)?;
+    let (_, source_column) = lang_column(&conn, &options.source_lang)?;
+    let (_, target_column) = lang_column(&conn, &options.target_lang)?;
+
+    let mut counter = PhraseCounter::new(options.max_ngram, options.min_count);
+    for_each_text_pair(&conn, &source_column, &target_column, |source, target| {
+        counter.count_phrases(source, target)
+    })?;
+    counter.prune();
+    for_each_text_pair(&conn, &source_column, &target_column, |source, target| {
+        counter.count_pairs(source, target)
+    })?;
+
+    let mut pairs = counter.scored_pairs();
+    pairs.sort_by(|a, b| {
+        let (a_score, b_score) = match options.measure {
+            Measure::Dice => (a.dice, b.dice),
+            Measure::Pmi => (a.pmi, b.pmi),
+        };
+        b_score
+            .total_cmp(&a_score)
+            .then(b.cooccurrences.cmp(&a.cooccurrences))
+    });
+
+    println!("source\ttarget\tcooccurrences\tdice\tpmi");
+    for pair in pairs.iter().take(options.limit) {
+        println!(
+            "{}\t{}\t{}\t{:.4}\t{:.4}",
+            pair.source, pair.target, pair.cooccurrences, pair.dice, pair.pmi
+        );
+    }
+
+    Ok(())
+}
+
+/// Stream every translation unit containing texts in both languages.
+fn for_each_text_pair<F>(
+    conn: &Connection,
+    source_column: &str,
+    target_column: &str,
+    mut callback: F,
+) -> Result<()>
+where
+    F: FnMut(&str, &str),
+{
+    let source_column = quote_identifier(source_column);
+    let target_column = quote_identifier(target_column);
+    let mut query = conn.prepare(&format!(
+        "SELECT {source_column}, {target_column} FROM translation_units
+        WHERE IFNULL({source_column}, '') <> '' AND IFNULL({target_column}, '') <> ''"
+    ))?;
+    let mut rows = query.query([])?;
+    while let Some(row) = rows.next()? {
+        let source: String = row.get(0)?;
+        let target: String = row.get(1)?;
+        callback(&source, &target);
+    }
+
+    Ok(())
+}
+
+/// Collects unit-level co-occurrence statistics in two passes: the first one
+/// counts the phrases on each side, the second one counts the pairs of
+/// phrases which are frequent enough (counting all pairs would not fit in
+/// memory for a corpus of the size of the DGT-TM).
+pub struct PhraseCounter {
+    max_ngram: usize,
+    min_count: u32,
+
+    /// Number of translation units seen in the first pass.
+    units: u32,
+    source_counts: HashMap<String, u32>,
+    target_counts: HashMap<String, u32>,
+    pair_counts: HashMap<(String, String), u32>,
+}
+
+impl PhraseCounter {
+    pub fn new(max_ngram: usize, min_count: u32) -> PhraseCounter {
+        PhraseCounter {
+            max_ngram,
+            min_count,
+            units: 0,
+            source_counts: HashMap::new(),
+            target_counts: HashMap::new(),
+            pair_counts: HashMap::new(),
+        }
+    }
+
+    pub fn count_phrases(&mut self, source: &str, target: &str) {
+        self.units += 1;
+        for phrase in phrases(source, self.max_ngram) {
+            *self.source_counts.entry(phrase).or_default() += 1;
+        }
+        for phrase in phrases(target, self.max_ngram) {
+            *self.target_counts.entry(phrase).or_default() += 1;
+        }
+    }
+
+    /// Forget the phrases which occur fewer than `min_count` times.
+    pub fn prune(&mut self) {
+        let min_count = self.min_count;
+        self.source_counts.retain(|_, count| *count >= min_count);
+        self.target_counts.retain(|_, count| *count >= min_count);
+    }
+
+    pub fn count_pairs(&mut self, source: &str, target: &str) {
+        let source_phrases: Vec<String> = phrases(source, self.max_ngram)
+            .into_iter()
+            .filter(|phrase| self.source_counts.contains_key(phrase))
+            .collect();
+        let target_phrases: Vec<String> = phrases(target, self.max_ngram)
+            .into_iter()
+            .filter(|phrase| self.target_counts.contains_key(phrase))
+            .collect();
+        for source_phrase in &source_phrases {
+            for target_phrase in &target_phrases {
+                *self
+                    .pair_counts
+                    .entry((source_phrase.clone(), target_phrase.clone()))
+                    .or_default() += 1;
+            }
+        }
+    }
+
+    pub fn scored_pairs(&self) -> Vec<PhrasePair> {
+        self.pair_counts
+            .iter()
+            .filter(|(_, count)| **count >= self.min_count)
+            .map(|((source, target), count)| {
+                let cooccurrences = *count as f64;
+                let source_count = self.source_counts[source] as f64;
+                let target_count = self.target_counts[target] as f64;
+                PhrasePair {
+                    source: source.clone(),
+                    target: target.clone(),
+                    cooccurrences: *count,
+                    dice: 2.0 * cooccurrences / (source_count + target_count),
+                    pmi: (cooccurrences * self.units as f64 / (source_count * target_count)).log2(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The distinct lowercased n-grams (up to `max_ngram` tokens) of a text.
+/// Tokens are sequences of alphanumeric characters.
+pub fn phrases(text: &str, max_ngram: usize) -> HashSet<String> {
+    let text = text.to_lowercase();
+    let tokens: Vec<&str> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .collect();
+    let mut phrases = HashSet::new();
+    for n in 1..=max_ngram {
+        for ngram in tokens.windows(n) {
+            phrases.insert(ngram.join(" "));
+        }
+    }
+    phrases
+}
+
+#[cfg(test)]
+mod test {
+    use super::{phrases, PhraseCounter};
+
+    #[test]
+    fn ngrams_are_lowercased_and_deduplicated() {
+        let mut expected: Vec<&str> = vec!["the", "council", "the council", "council the"];
+        expected.sort();
+        let mut actual: Vec<String> = phrases("The Council, the", 2).into_iter().collect();
+        actual.sort();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn translations_are_ranked_first() {
+        let mut counter = PhraseCounter::new(1, 2);
+        let corpus = [
+            ("the council", "rada"),
+            ("the commission", "komisja"),
+            ("the council decided", "rada postanowiła"),
+            ("the commission decided", "komisja postanowiła"),
+        ];
+        for (source, target) in corpus {
+            counter.count_phrases(source, target);
+        }
+        counter.prune();
+        for (source, target) in corpus {
+            counter.count_pairs(source, target);
+        }
+
+        let pairs = counter.scored_pairs();
+        let best_for_council = pairs
+            .iter()
+            .filter(|pair| pair.source == "council")
+            .max_by(|a, b| a.dice.total_cmp(&b.dice))
+            .unwrap();
+        assert_eq!(best_for_council.target, "rada");
+        assert_eq!(best_for_council.dice, 1.0);
+    }
+}
@@ -0,0 +1,185 @@
+use anyhow::{bail, Result};
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use dgt_parser::error::DgtError;
+use dgt_parser::functions::{decode_xml, hash_input_dir, TuCounter, Walk};
+use dgt_parser::input::Input;
+use dgt_parser::seen_files::SeenFiles;
+
+/// The TMX files of an input, recorded by the `catalog` subcommand so that
+/// later runs over the same input (see `--catalog`) do not have to scan the
+/// archives before parsing, e.g. to report progress.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Catalog {
+    /// Fingerprint of the input (see [hash_input_dir]), used to tell whether
+    /// the catalog is up to date.
+    pub input_hash: String,
+
+    /// `--tmx-glob` used to build the catalog, if any.
+    pub tmx_glob: Option<String>,
+
+    /// TMX files in the order in which they are visited.
+    pub files: Vec<CatalogEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct CatalogEntry {
+    /// Path of the TMX file relative to the input (see
+    /// [Input::relative_name]), prefixed with the path(s) of the archive(s)
+    /// it was found in.
+    pub name: String,
+
+    /// Size of the (uncompressed) TMX file in bytes.
+    pub size: u64,
+
+    /// Number of translation units (`<tu>` elements, see [TuCounter]), or
+    /// `None` if the file could not be decoded.
+    pub translation_units: Option<u64>,
+
+    /// Hash of the contents (see [SeenFiles::hash]), so that the files
+    /// recorded in `--seen-files` are skipped without being read. Missing
+    /// from catalogs saved by earlier versions.
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+impl Catalog {
+    /// Visit every TMX file of the input, decoding it to count its
+    /// translation units. They are counted without parsing the file, so that
+    /// the count does not depend on the parser it is used to check.
+    pub fn build(input: &Input, tmx_glob: Option<Pattern>) -> Result<Catalog> {
+        let mut files = Vec::new();
+        input.for_each_tmx_file(&mut Walk::new(tmx_glob.clone()), &mut |tmx_file| {
+            // A catalog is meant to describe a known good copy of the input.
+            if let Some(error) = tmx_file.error {
+                return Err(error);
+            }
+            let mut contents = Vec::new();
+            tmx_file.reader.read_to_end(&mut contents)?;
+            let size = contents.len() as u64;
+            let translation_units = decode_xml(&contents, &mut Vec::new()).ok().map(|decoded| {
+                let mut counter = TuCounter::default();
+                counter.feed(decoded.as_bytes());
+                counter.count
+            });
+            files.push(CatalogEntry {
+                name: input.relative_name(&tmx_file.name).to_string(),
+                size,
+                translation_units,
+                sha256: Some(SeenFiles::hash(&contents)),
+            });
+
+            Ok(())
+        })?;
+
+        Ok(Catalog {
+            input_hash: hash_input_dir(input.path())?,
+            tmx_glob: tmx_glob.map(|pattern| pattern.to_string()),
+            files,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Catalog> {
+        if !path.exists() {
+            bail!("the catalog {} does not exist", path.display());
+        }
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+
+        Ok(())
+    }
+
+    /// The number of translation units of each TMX file which could be
+    /// parsed, by name.
+    pub fn declared_units(&self) -> impl Iterator<Item = (String, u64)> + '_ {
+        self.files.iter().filter_map(|file| {
+            file.translation_units
+                .map(|translation_units| (file.name.clone(), translation_units))
+        })
+    }
+
+    /// The hash of the contents of each TMX file, by name.
+    pub fn content_hashes(&self) -> impl Iterator<Item = (String, String)> + '_ {
+        self.files.iter().filter_map(|file| {
+            file.sha256
+                .as_ref()
+                .map(|sha256| (file.name.clone(), sha256.clone()))
+        })
+    }
+
+    /// Whether the catalog describes the input, as walked with the pattern.
+    pub fn matches(&self, input: &Input, tmx_glob: Option<&Pattern>) -> Result<bool> {
+        Ok(self.input_hash == hash_input_dir(input.path())?
+            && self.tmx_glob == tmx_glob.map(|pattern| pattern.to_string()))
+    }
+}
+
+/// Save a catalog of the TMX files of the input.
+pub fn run(input: &Input, tmx_glob: Option<Pattern>, output_file: &Path) -> Result<()> {
+    if output_file.exists() {
+        bail!(DgtError::OutputExists(output_file.to_path_buf()));
+    }
+    let catalog = Catalog::build(input, tmx_glob)?;
+    catalog.save(output_file)?;
+
+    let broken = catalog
+        .files
+        .iter()
+        .filter(|file| file.translation_units.is_none())
+        .count();
+    println!(
+        "Cataloged {} TMX files with {} translation units.",
+        catalog.files.len(),
+        catalog
+            .files
+            .iter()
+            .filter_map(|file| file.translation_units)
+            .sum::<u64>()
+    );
+    if broken > 0 {
+        eprintln!(
+            "Warning: {} TMX files could not be decoded (listed without a number of translation units).",
+            broken
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::Catalog;
+    use dgt_parser::input::InputSource;
+
+    #[test]
+    fn catalog_lists_tmx_files() {
+        let input = InputSource::parse("test_data/zipped")
+            .unwrap()
+            .open()
+            .unwrap();
+        let catalog = Catalog::build(&input, None).unwrap();
+
+        assert_eq!(catalog.files.len(), 7);
+        assert_eq!(
+            catalog
+                .files
+                .iter()
+                .map(|file| file.translation_units.unwrap())
+                .sum::<u64>(),
+            462
+        );
+        assert!(catalog.files.iter().all(|file| file.size > 0));
+        assert_eq!(catalog.content_hashes().count(), 7);
+        assert_eq!(catalog.files[0].name, "1.zip/22019D0557.tmx");
+        assert!(catalog.matches(&input, None).unwrap());
+        let pattern = glob::Pattern::new("*.tmx").unwrap();
+        assert!(!catalog.matches(&input, Some(&pattern)).unwrap());
+    }
+}
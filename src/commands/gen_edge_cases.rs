@@ -0,0 +1,106 @@
+use anyhow::{bail, Result};
+use std::path::Path;
+
+use dgt_parser::error::DgtError;
+
+/// Length (in characters) of the text in `huge_segment.tmx`.
+const HUGE_SEGMENT_CHARS: usize = 1_000_000;
+
+/// TMX files exercising cases which the official release rarely (or never)
+/// contains, but which mirrored, repackaged or converted datasets do. Each
+/// file is named after its case and contains translation units of a single
+/// document (named after the file), except where the document ID itself is
+/// the edge case.
+fn edge_cases() -> Vec<(&'static str, String)> {
+    let huge_text = "Lorem ipsum dolor sit amet. ".repeat(HUGE_SEGMENT_CHARS / 28);
+
+    vec![
+        (
+            "empty_segments",
+            tu("EMPTY", &[("EN-GB", "Text"), ("PL-01", ""), ("DE-DE", " ")])
+                + &tu("EMPTY", &[("EN-GB", ""), ("PL-01", "Tekst")])
+                + r#"<tu><prop type="Txt::Doc. No.">EMPTY</prop><tuv lang="EN-GB"><seg/></tuv></tu>"#,
+        ),
+        (
+            "duplicate_langs",
+            tu("DUPLICATE", &[("EN-GB", "First"), ("PL-01", "Tekst"), ("EN-GB", "Second")]),
+        ),
+        (
+            "missing_doc_ids",
+            r#"<tu><tuv lang="EN-GB"><seg>No document</seg></tuv></tu>"#.to_string()
+                + r#"<tu><prop type="Txt::Other">X</prop><tuv lang="EN-GB"><seg>Other prop</seg></tuv></tu>"#
+                + &tu("MISSING", &[("EN-GB", "With document")]),
+        ),
+        (
+            "inline_markup",
+            r#"<tu><prop type="Txt::Doc. No.">MARKUP</prop><tuv lang="EN-GB"><seg>Text with <bpt i="1">&lt;b&gt;</bpt>bold<ept i="1">&lt;/b&gt;</ept> markup<ph>&lt;br/&gt;</ph></seg></tuv></tu>"#
+                .to_string(),
+        ),
+        (
+            "huge_segment",
+            tu("HUGE", &[("EN-GB", &huge_text), ("PL-01", "Krótki tekst")]),
+        ),
+        (
+            "multiple_doc_ids",
+            r#"<tu><prop type="Txt::Doc. No.">MULTI_A</prop><prop type="Txt::Doc. No.">MULTI_B</prop><tuv lang="EN-GB"><seg>Shared</seg></tuv></tu>"#
+                .to_string()
+                + &tu("MULTI_A", &[("EN-GB", "Own")]),
+        ),
+        (
+            "extra_props",
+            r#"<tu><prop type="Txt::Doc. No.">PROPS</prop><prop type="Txt::Doc. Type">DEC</prop><prop type="Txt::Year">2019</prop><prop type="Txt::Note">Corrigendum</prop><tuv lang="EN-GB"><seg>With props</seg></tuv></tu>"#
+                .to_string()
+                + r#"<tu><prop type="Txt::Doc. No.">PROPS</prop><prop type="Txt::Year">n/a</prop><tuv lang="EN-GB"><seg>Invalid year</seg></tuv></tu>"#,
+        ),
+        (
+            "invalid_lang_codes",
+            tu(
+                "INVALID",
+                &[("EN-GB", "Valid"), ("english", "Invalid"), ("", "Empty code")],
+            ),
+        ),
+    ]
+}
+
+/// Save the edge case TMX files in `output_dir`, encoded in UTF-16LE (with
+/// a byte order mark) like the official release.
+pub fn run(output_dir: &Path) -> Result<()> {
+    if output_dir.exists() {
+        bail!(DgtError::OutputExists(output_dir.to_path_buf()));
+    }
+    std::fs::create_dir_all(output_dir)?;
+    for (name, units) in edge_cases() {
+        let path = output_dir.join(format!("{}.tmx", name));
+        std::fs::write(&path, utf16le(&tmx(&units)))?;
+        println!("Saved {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// A translation unit of a document with a text in each of the languages.
+fn tu(doc: &str, texts: &[(&str, &str)]) -> String {
+    let tuvs: String = texts
+        .iter()
+        .map(|(lang, text)| format!(r#"<tuv lang="{}"><seg>{}</seg></tuv>"#, lang, text))
+        .collect();
+    format!(
+        r#"<tu><prop type="Txt::Doc. No.">{}</prop>{}</tu>"#,
+        doc, tuvs
+    )
+}
+
+fn tmx(units: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-16LE\"?>\n<tmx version=\"1.4\">\n<header srclang=\"EN-GB\">\n</header>\n<body>\n{}\n</body>\n</tmx>\n",
+        units
+    )
+}
+
+fn utf16le(text: &str) -> Vec<u8> {
+    let mut bytes = vec![0xFF, 0xFE];
+    for unit in text.encode_utf16() {
+        bytes.extend(unit.to_le_bytes());
+    }
+    bytes
+}
@@ -0,0 +1,280 @@
+use anyhow::{bail, Result};
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use dgt_parser::config::{Config, DEFAULT_CONFIG_FILE};
+use dgt_parser::functions::parse_lang;
+
+/// An output format offered by the wizard.
+struct Format {
+    subcommand: &'static str,
+    description: &'static str,
+    default_output: &'static str,
+
+    /// Minimum number of languages the format requires.
+    min_langs: usize,
+}
+
+/// The formats most useful to first-time users (of those built in), the
+/// first one being the default.
+const FORMATS: &[Format] = &[
+    #[cfg(feature = "sqlite")]
+    Format {
+        subcommand: "sqlite",
+        description: "SQLite database",
+        default_output: "dgt.sqlite",
+        min_langs: 0,
+    },
+    #[cfg(feature = "xlsx")]
+    Format {
+        subcommand: "xlsx",
+        description: "Excel workbook",
+        default_output: "dgt.xlsx",
+        min_langs: 1,
+    },
+    Format {
+        subcommand: "extract-all-pairs",
+        description: "parallel corpus for each pair of languages",
+        default_output: "pairs",
+        min_langs: 2,
+    },
+    Format {
+        subcommand: "html-review",
+        description: "HTML pages for reviewing the translations",
+        default_output: "review",
+        min_langs: 2,
+    },
+    Format {
+        subcommand: "lang-pairs",
+        description: "table of the language pairs (CSV)",
+        default_output: "lang_pairs.csv",
+        min_langs: 0,
+    },
+];
+
+/// Answers given to the wizard.
+#[derive(Debug, PartialEq)]
+pub struct Setup {
+    pub input_dir: String,
+
+    /// Empty for all languages.
+    pub langs: Vec<String>,
+
+    pub subcommand: &'static str,
+    pub output: String,
+}
+
+impl Setup {
+    /// The command which runs the setup, with the given global arguments.
+    fn command(&self, global_args: &[String]) -> String {
+        let mut args = vec![String::from("dgt_parser")];
+        args.extend(global_args.iter().cloned());
+        args.push(String::from(self.subcommand));
+        args.push(String::from("-o"));
+        args.push(self.output.clone());
+        args.iter()
+            .map(|arg| shell_quote(arg))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    fn args(&self) -> Vec<String> {
+        let mut args = vec![String::from("-i"), self.input_dir.clone()];
+        for lang in &self.langs {
+            args.push(String::from("-l"));
+            args.push(lang.clone());
+        }
+        args
+    }
+}
+
+/// Ask for the setup on the terminal and save it in the configuration file.
+/// Settings already in the file (e.g. handlers) are kept.
+pub fn run(config_file: &Path) -> Result<()> {
+    let mut stdout = std::io::stdout();
+    let setup = ask(&mut std::io::stdin().lock(), &mut stdout)?;
+
+    let mut config = Config::load_if_exists(config_file)?;
+    config.input_dir = Some(setup.input_dir.clone());
+    config.langs = match setup.langs.is_empty() {
+        true => None,
+        false => Some(setup.langs.clone()),
+    };
+    config.save(config_file)?;
+
+    let mut config_args = Vec::new();
+    if config_file != Path::new(DEFAULT_CONFIG_FILE) {
+        config_args.push(String::from("--config"));
+        config_args.push(config_file.display().to_string());
+    }
+    println!(
+        "\nSaved the input and the languages in {}. The equivalent command is:\n\n    {}\n\nWith the configuration file, the input and the languages can be left out:\n\n    {}",
+        config_file.display(),
+        setup.command(&setup.args()),
+        setup.command(&config_args)
+    );
+
+    Ok(())
+}
+
+/// Ask the questions of the wizard until each is answered validly.
+pub fn ask(input: &mut impl BufRead, output: &mut impl Write) -> Result<Setup> {
+    writeln!(
+        output,
+        "This wizard saves the settings of a first run in a configuration file.\n"
+    )?;
+
+    let input_dir = loop {
+        let answer = prompt(
+            input,
+            output,
+            "Directory (or file) with the DGT-TM archives",
+            None,
+        )?;
+        match Path::new(&answer).exists() {
+            true => break answer,
+            false => writeln!(output, "{} does not exist.", answer)?,
+        }
+    };
+
+    let langs = loop {
+        let answer = prompt(
+            input,
+            output,
+            "Languages to include, e.g. `en pl de` (leave empty for all)",
+            Some(""),
+        )?;
+        let langs: std::result::Result<Vec<String>, String> = answer
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|lang| !lang.is_empty())
+            .map(parse_lang)
+            .collect();
+        match langs {
+            Ok(langs) => break langs,
+            Err(error) => writeln!(output, "{}.", capitalize(&error))?,
+        }
+    };
+
+    writeln!(output, "Output formats:")?;
+    for (i, format) in FORMATS.iter().enumerate() {
+        writeln!(
+            output,
+            "  {}. {} ({})",
+            i + 1,
+            format.subcommand,
+            format.description
+        )?;
+    }
+    let format = loop {
+        let answer = prompt(input, output, "Output format (number or name)", Some("1"))?;
+        let format = match answer.parse::<usize>() {
+            Ok(number) => number.checked_sub(1).and_then(|i| FORMATS.get(i)),
+            Err(_) => FORMATS.iter().find(|format| format.subcommand == answer),
+        };
+        match format {
+            None => writeln!(output, "Unknown format: {}.", answer)?,
+            Some(format) if langs.len() < format.min_langs => writeln!(
+                output,
+                "The {} format requires at least {} languages.",
+                format.subcommand, format.min_langs
+            )?,
+            Some(format) => break format,
+        }
+    };
+
+    let output_path = loop {
+        let answer = prompt(input, output, "Output path", Some(format.default_output))?;
+        match Path::new(&answer).exists() {
+            true => writeln!(output, "{} already exists.", answer)?,
+            false => break answer,
+        }
+    };
+
+    Ok(Setup {
+        input_dir,
+        langs,
+        subcommand: format.subcommand,
+        output: output_path,
+    })
+}
+
+/// Ask a question, returning the trimmed answer (or the default, if the
+/// answer is empty and there is a default).
+fn prompt(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    question: &str,
+    default: Option<&str>,
+) -> Result<String> {
+    loop {
+        match default {
+            Some(default) if !default.is_empty() => write!(output, "{} [{}]: ", question, default)?,
+            _ => write!(output, "{}: ", question)?,
+        }
+        output.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            bail!("the wizard was interrupted before all questions were answered");
+        }
+        let answer = line.trim();
+        match (answer.is_empty(), default) {
+            (false, _) => return Ok(answer.to_string()),
+            (true, Some(default)) => return Ok(default.to_string()),
+            (true, None) => continue,
+        }
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Quote an argument for a POSIX shell, unless it is safe as it is.
+fn shell_quote(arg: &str) -> String {
+    let is_safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@,+".contains(c));
+    match is_safe {
+        true => arg.to_string(),
+        false => format!("'{}'", arg.replace('\'', r"'\''")),
+    }
+}
+
+// The answers depend on the formats built in.
+#[cfg(all(test, feature = "sqlite", feature = "xlsx"))]
+mod test {
+    use super::{ask, Setup};
+
+    #[test]
+    fn invalid_answers_are_asked_again() {
+        let answers = "\n/nonexistent/dir\n.\npolish\nen,\n9\n3\nxlsx\nsrc\n\n";
+        let mut output = Vec::new();
+        let setup = ask(&mut answers.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert_eq!(
+            setup,
+            Setup {
+                input_dir: String::from("."),
+                langs: vec![String::from("en")],
+                subcommand: "xlsx",
+                output: String::from("dgt.xlsx"),
+            }
+        );
+        assert!(output.contains("/nonexistent/dir does not exist."));
+        assert!(output.contains("Unknown language: polish (did you mean `pl`?)."));
+        assert!(output.contains("Unknown format: 9."));
+        assert!(output.contains("The extract-all-pairs format requires at least 2 languages."));
+        assert!(output.contains("src already exists."));
+        assert_eq!(
+            setup.command(&setup.args()),
+            "dgt_parser -i . -l en xlsx -o dgt.xlsx"
+        );
+        assert!(ask(&mut "".as_bytes(), &mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::Result;
+use regex::{Regex, RegexBuilder};
+use rusqlite::params;
+use serde::Serialize;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use super::{lang_column, open_database};
+use dgt_parser::functions::single_line;
+use dgt_parser::handlers::sqlite_db::quote_identifier;
+
+/// Options of the `kwic` subcommand.
+pub struct KwicOptions {
+    pub term: String,
+    pub lang: String,
+    pub target_lang: Option<String>,
+
+    /// Number of characters of context displayed on each side of the term.
+    pub window: usize,
+
+    /// Maximum number of matching translation units (0 for all of them).
+    pub limit: u32,
+
+    /// Number of matching translation units left out before the first one
+    /// printed, to page through the matches.
+    pub offset: u32,
+
+    pub format: KwicFormat,
+}
+
+/// Output format of the `kwic` subcommand.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum KwicFormat {
+    /// Lines aligned on the term, for reading in the terminal.
+    Text,
+
+    /// A JSON object per occurrence, e.g.
+    /// `{"document":"22019D0557","sequential_number":3,"left":"…","keyword":"…","right":"…"}`.
+    Jsonl,
+
+    /// A CSV table with a header row and a row per occurrence.
+    Csv,
+}
+
+/// An occurrence as exported in the `jsonl` format.
+#[derive(Serialize)]
+struct KwicRecord<'a> {
+    document: &'a str,
+    sequential_number: u32,
+    left: &'a str,
+    keyword: &'a str,
+    right: &'a str,
+
+    /// Only present with a target language.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    translation: Option<&'a str>,
+}
+
+/// A single occurrence of the term, with its context.
+#[derive(Debug, PartialEq)]
+pub struct KwicLine {
+    pub left: String,
+    pub keyword: String,
+    pub right: String,
+}
+
+/// Print keyword-in-context lines for every occurrence of the term in a
+/// previously generated SQLite database, each followed by the aligned
+/// translation in the target language.
+pub fn run(database: &Path, options: KwicOptions) -> Result<()> {
+    let conn = open_database(database)?;
+
+    let (_, column) = lang_column(&conn, &options.lang)?;
+    let column = quote_identifier(&column);
+    let target = match &options.target_lang {
+        Some(target_lang) => Some(lang_column(&conn, target_lang)?),
+        None => None,
+    };
+    let term_regex = RegexBuilder::new(&regex::escape(&options.term))
+        .case_insensitive(true)
+        .build()?;
+
+    let target_column = match &target {
+        Some((_, target_column)) => format!("tu.{}", quote_identifier(target_column)),
+        None => String::from("NULL"),
+    };
+    // `LIKE` is only case-insensitive for ASCII characters, so it narrows
+    // down the rows only for ASCII terms. Either way, the rows are checked
+    // again with the regex, and only then paged through, so that pages are
+    // not cut short. Ordered by the row ID, so that pages do not overlap.
+    let mut query = conn.prepare(&format!(
+        "SELECT d.name, tu.sequential_number, tu.{column}, {target_column}
+        FROM translation_units tu
+        JOIN documents d ON d.id = tu.document_id
+        WHERE tu.{column} LIKE ? ESCAPE '\\'
+        ORDER BY tu.id",
+    ))?;
+    let pattern = match options.term.is_ascii() {
+        true => like_pattern(&options.term),
+        false => String::from("%"),
+    };
+    let mut rows = query.query(params![pattern])?;
+    let mut to_skip = options.offset;
+    let mut to_print = match options.limit {
+        0 => None,
+        limit => Some(limit),
+    };
+
+    // Rows are written as they are read, so that large exports are not held
+    // in memory.
+    let mut output = BufWriter::new(std::io::stdout().lock());
+    if options.format == KwicFormat::Csv {
+        let mut header = vec!["document", "sequential_number", "left", "keyword", "right"];
+        if target.is_some() {
+            header.push("translation");
+        }
+        writeln!(output, "{}", header.join(","))?;
+    }
+    while let Some(row) = rows.next()? {
+        if to_print == Some(0) {
+            break;
+        }
+        let text: String = row.get(2)?;
+        let lines = kwic_lines(&text, &term_regex, options.window);
+        if lines.is_empty() {
+            continue;
+        }
+        if to_skip > 0 {
+            to_skip -= 1;
+            continue;
+        }
+        if let Some(to_print) = &mut to_print {
+            *to_print -= 1;
+        }
+        let doc_name: String = row.get(0)?;
+        let sequential_number: u32 = row.get(1)?;
+        let translation: Option<String> = row.get(3)?;
+        let translation = translation.map(|translation| single_line(&translation));
+
+        for line in lines {
+            match options.format {
+                KwicFormat::Text => {
+                    writeln!(
+                        output,
+                        "{}:{}\t{:>width$}[{}]{}",
+                        doc_name,
+                        sequential_number,
+                        line.left,
+                        line.keyword,
+                        line.right,
+                        width = options.window
+                    )?;
+                    if let (Some((target_lang, _)), Some(translation)) = (&target, &translation) {
+                        writeln!(output, "\t{}: {}", target_lang, translation)?;
+                    }
+                }
+                KwicFormat::Jsonl => {
+                    let record = KwicRecord {
+                        document: &doc_name,
+                        sequential_number,
+                        left: &line.left,
+                        keyword: &line.keyword,
+                        right: &line.right,
+                        translation: target
+                            .as_ref()
+                            .map(|_| translation.as_deref().unwrap_or_default()),
+                    };
+                    serde_json::to_writer(&mut output, &record)?;
+                    writeln!(output)?;
+                }
+                KwicFormat::Csv => {
+                    let mut fields = vec![
+                        csv_field(&doc_name),
+                        sequential_number.to_string(),
+                        csv_field(&line.left),
+                        csv_field(&line.keyword),
+                        csv_field(&line.right),
+                    ];
+                    if target.is_some() {
+                        fields.push(csv_field(translation.as_deref().unwrap_or_default()));
+                    }
+                    writeln!(output, "{}", fields.join(","))?;
+                }
+            }
+        }
+    }
+    output.flush()?;
+
+    Ok(())
+}
+
+/// Build a `LIKE` pattern matching texts which contain the term, with the
+/// wildcards of the term escaped with a backslash.
+fn like_pattern(term: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in term.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+#[test]
+fn like_wildcards_are_escaped() {
+    assert_eq!(like_pattern("state aid"), "%state aid%");
+    assert_eq!(like_pattern("100% of_it\\"), "%100\\% of\\_it\\\\%");
+}
+
+/// Quote a CSV field if it contains a comma, a quote or a line break.
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+#[test]
+fn csv_fields_are_quoted() {
+    assert_eq!(csv_field("state aid"), "state aid");
+    assert_eq!(
+        csv_field("aid, \"de minimis\""),
+        "\"aid, \"\"de minimis\"\"\""
+    );
+}
+
+/// Split the text around every occurrence of the term, keeping at most
+/// `window` characters of context on each side.
+pub fn kwic_lines(text: &str, term: &Regex, window: usize) -> Vec<KwicLine> {
+    let text = single_line(text);
+    term.find_iter(&text)
+        .map(|found| {
+            let left: Vec<char> = text[..found.start()].chars().collect();
+            let left: String = left[left.len().saturating_sub(window)..].iter().collect();
+            KwicLine {
+                left,
+                keyword: found.as_str().to_string(),
+                right: text[found.end()..].chars().take(window).collect(),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn context_is_trimmed_to_window() {
+    let term = RegexBuilder::new("state aid")
+        .case_insensitive(true)
+        .build()
+        .unwrap();
+    let lines = kwic_lines("Rules on State aid and on\nstate aid schemes", &term, 6);
+
+    assert_eq!(
+        lines,
+        vec![
+            KwicLine {
+                left: "es on ".to_string(),
+                keyword: "State aid".to_string(),
+                right: " and o".to_string(),
+            },
+            KwicLine {
+                left: "nd on ".to_string(),
+                keyword: "state aid".to_string(),
+                right: " schem".to_string(),
+            },
+        ]
+    );
+}
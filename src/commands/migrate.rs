@@ -0,0 +1,20 @@
+use anyhow::Result;
+use std::path::Path;
+
+use super::open_database;
+use dgt_parser::handlers::sqlite_db;
+
+/// Upgrade an existing SQLite database to the current schema version.
+pub fn run(database: &Path) -> Result<()> {
+    let mut conn = open_database(database)?;
+    let (from, to) = sqlite_db::migrate(&mut conn)?;
+    match from == to {
+        true => println!("The database is up to date (schema version {}).", to),
+        false => println!(
+            "Migrated the database from schema version {} to {}.",
+            from, to
+        ),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+#[cfg(feature = "sqlite")]
+use anyhow::{bail, Result};
+#[cfg(feature = "sqlite")]
+use rusqlite::Connection;
+#[cfg(feature = "sqlite")]
+use std::path::Path;
+
+#[cfg(feature = "sqlite")]
+use dgt_parser::error::DgtError;
+#[cfg(feature = "sqlite")]
+use dgt_parser::functions::{coerce_lang_codes, lang_code_to_db_column};
+#[cfg(feature = "sqlite")]
+use dgt_parser::handlers::sqlite_db::column_exists;
+
+#[cfg(feature = "sqlite")]
+pub mod align_phrases;
+pub mod catalog;
+pub mod gen_edge_cases;
+pub mod init;
+#[cfg(feature = "sqlite")]
+pub mod kwic;
+#[cfg(feature = "sqlite")]
+pub mod migrate;
+
+/// Open a database previously generated by the `sqlite` subcommand.
+#[cfg(feature = "sqlite")]
+fn open_database(database: &Path) -> Result<Connection> {
+    if !database.exists() {
+        bail!(DgtError::DatabaseNotFound(database.to_path_buf()));
+    }
+    Ok(Connection::open(database)?)
+}
+
+/// Coerce a language code provided by the user (e.g. `en` => `EN-GB`) and
+/// return it together with the name of its column, making sure that the
+/// column exists in the database.
+#[cfg(feature = "sqlite")]
+fn lang_column(conn: &Connection, lang: &str) -> Result<(String, String)> {
+    let lang_code = coerce_lang_codes(vec![lang.to_string()]).remove(0);
+    let column = lang_code_to_db_column(&lang_code)?;
+    if !column_exists(conn, "translation_units", &column)? {
+        bail!(DgtError::LangNotInDatabase(lang_code));
+    }
+    Ok((lang_code, column))
+}
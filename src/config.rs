@@ -0,0 +1,148 @@
+use crate::error::{DgtError, Result};
+use crate::functions::parse_lang;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Read by default from the current directory.
+pub const DEFAULT_CONFIG_FILE: &str = "dgt_parser.toml";
+
+/// Settings read from the file given with `--config`, e.g.:
+///
+/// ```toml
+/// input_dir = "./input_dir"
+/// langs = ["en", "pl"]
+///
+/// [handlers.custom]
+/// command = "/opt/sinks/custom-sink"
+/// args = ["--table", "dgt"]
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Input used if `--input-dir` is not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_dir: Option<String>,
+
+    /// Languages included if neither `-l` nor `--langs-file` is given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub langs: Option<Vec<String>>,
+
+    /// Out-of-tree handlers, keyed by the name under which they are run
+    /// (`dgt_parser external <NAME>`).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub handlers: BTreeMap<String, ExternalHandlerConfig>,
+}
+
+/// A program receiving the translation units over the protocol described in
+/// [crate::handlers::external].
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ExternalHandlerConfig {
+    /// Path of the program (or its name, if it is on the `PATH`).
+    pub command: PathBuf,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config> {
+        if !path.exists() {
+            return Err(DgtError::InvalidConfig(format!(
+                "{} does not exist",
+                path.display()
+            )));
+        }
+        let config: Config = toml::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|error| DgtError::InvalidConfig(format!("{}: {}", path.display(), error)))?;
+        for lang in config.langs.iter().flatten() {
+            parse_lang(lang).map_err(|error| {
+                DgtError::InvalidConfig(format!("{}: {}", path.display(), error))
+            })?;
+        }
+
+        Ok(config)
+    }
+
+    /// Load the file if it exists, e.g. the default configuration file, which
+    /// is optional (unless a setting is required from it).
+    pub fn load_if_exists(path: &Path) -> Result<Config> {
+        match path.exists() {
+            true => Config::load(path),
+            false => Ok(Config::default()),
+        }
+    }
+
+    /// Save the settings, replacing the file. Comments of a file read with
+    /// [Config::load] are not kept.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = toml::to_string(self)
+            .map_err(|error| DgtError::InvalidConfig(format!("{}: {}", path.display(), error)))?;
+        std::fs::write(path, contents)?;
+
+        Ok(())
+    }
+
+    pub fn handler(&self, name: &str) -> Result<&ExternalHandlerConfig> {
+        self.handlers
+            .get(name)
+            .ok_or_else(|| DgtError::UnknownHandler(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+    use crate::error::DgtError;
+
+    #[test]
+    fn handlers_are_read_from_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [handlers.custom]
+            command = "/opt/sinks/custom-sink"
+            args = ["--table", "dgt"]
+
+            [handlers.other]
+            command = "other-sink"
+            "#,
+        )
+        .unwrap();
+
+        let custom = config.handler("custom").unwrap();
+        assert_eq!(custom.command.to_str(), Some("/opt/sinks/custom-sink"));
+        assert_eq!(custom.args, vec!["--table", "dgt"]);
+        assert!(config.handler("other").unwrap().args.is_empty());
+        assert!(matches!(
+            config.handler("missing"),
+            Err(DgtError::UnknownHandler(name)) if name == "missing"
+        ));
+        assert!(toml::from_str::<Config>("[handlers.x]\ncmd = \"x\"").is_err());
+    }
+
+    #[test]
+    fn config_is_saved_and_loaded() {
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("dgt_parser.toml");
+        assert_eq!(Config::load_if_exists(&path).unwrap(), Config::default());
+
+        let mut config: Config = toml::from_str(
+            r#"
+            [handlers.custom]
+            command = "custom-sink"
+            "#,
+        )
+        .unwrap();
+        config.input_dir = Some(String::from("./input_dir"));
+        config.langs = Some(vec![String::from("en"), String::from("pl")]);
+        config.save(&path).unwrap();
+        assert_eq!(Config::load(&path).unwrap(), config);
+
+        std::fs::write(&path, "langs = [\"polish\"]").unwrap();
+        assert!(matches!(
+            Config::load(&path),
+            Err(DgtError::InvalidConfig(reason)) if reason.contains("did you mean `pl`?")
+        ));
+    }
+}
@@ -0,0 +1,1234 @@
+//! The engine shared by the subcommands which parse the translation memory:
+//! [process_corpus] walks over the TMX files of the input, parses them
+//! (through the cache, if any), applies the [UnitFilters] and passes the
+//! remaining translation units to a handler. Reporting is left to the
+//! caller, through [Hooks] and the [Summary].
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::alignment::AlignmentModel;
+use crate::artifacts::ArtifactDetector;
+use crate::boilerplate::Boilerplate;
+use crate::cache::Cache;
+use crate::error::{DgtError, Result};
+use crate::functions::{
+    coerce_lang_code, low_coverage_docs, normalize_doc_name, parse_tmx_file, parse_tmx_stream,
+    ParsedTmxFile, Walk,
+};
+use crate::input::Input;
+use crate::memory::{unit_size, MemoryUsage};
+use crate::near_duplicates::NearDuplicateDetector;
+use crate::quotas::LangQuotas;
+use crate::seen_files::SeenFiles;
+use crate::skip_log::SkipReason;
+use crate::tmx_parser::{LangMatch, TranslationUnit};
+use crate::types::{
+    MultiDoc, RequestedLangs, Summary, TranslationUnitHandler, JOINED_DOC_NAMES_SEPARATOR,
+};
+
+/// Where the translation units are read from.
+pub struct Source<'a> {
+    pub input: &'a Input,
+
+    /// Which TMX files are visited (see `--tmx-glob`).
+    pub walk: Walk,
+
+    /// Parsed TMX files are loaded from and saved into it (see
+    /// `--cache-dir`).
+    pub cache: Option<&'a Cache>,
+
+    /// TMX files recorded by previous runs are skipped, and those parsed
+    /// are recorded (see `--seen-files`). Saved by the caller, once the
+    /// output has been saved.
+    pub seen_files: Option<&'a mut SeenFiles>,
+
+    /// Records the translation units parsed and not yet handled.
+    pub memory: Arc<MemoryUsage>,
+
+    /// Number of translation units declared for the TMX files of the input,
+    /// by name relative to the input (see [Input::relative_name] and
+    /// `--catalog`). A TMX file parsed into a different number is reported
+    /// with a warning, as it may have been truncated.
+    pub declared_units: HashMap<String, u64>,
+
+    /// Hashes of the contents of the TMX files of the input, by name
+    /// relative to the input (see `--catalog`), if the catalog matches the
+    /// input. The files whose hash is recorded in `--seen-files` are skipped
+    /// without being read.
+    pub cataloged_hashes: HashMap<String, String>,
+
+    /// Read and parse the next TMX file on another thread while the handler
+    /// processes the current one (see `--read-ahead`).
+    pub read_ahead: bool,
+
+    /// Other corpora processed after the input, by name (see
+    /// `--merge-corpus`). If any, the translation units of every corpus,
+    /// including the input (named [MAIN_CORPUS]), are tagged with its name
+    /// (see [crate::tmx_parser::Annotations::corpus]).
+    pub merged_corpora: Vec<(String, &'a Input)>,
+}
+
+/// Name of the corpus of the input once other corpora are merged.
+pub const MAIN_CORPUS: &str = "dgt";
+
+/// Which translation units are passed to the handler. The filters are
+/// applied in the order of the fields.
+#[derive(Default)]
+pub struct UnitFilters {
+    /// Normalized names of the only documents whose translation units are
+    /// processed (see `--docs-file`).
+    pub listed_docs: Option<HashSet<String>>,
+
+    /// Normalized names of the documents whose translation units are
+    /// skipped (see `--exclude-docs-file`).
+    pub excluded_docs: HashSet<String>,
+
+    /// See `--require-doc-coverage`.
+    pub require_doc_coverage: Option<f64>,
+
+    /// Translation units without a text in this language are skipped.
+    pub source_lang: Option<String>,
+
+    pub requested_langs: RequestedLangs,
+
+    /// See `--min-langs`.
+    pub min_langs: Option<usize>,
+
+    /// Language and pattern of `--match-lang` and `--match-regex`.
+    pub text_match: Option<(String, Regex)>,
+
+    /// See `--boilerplate-file`.
+    pub boilerplate: Option<Boilerplate>,
+
+    /// Flag the translation units with boilerplate texts (see
+    /// [crate::tmx_parser::Annotations::is_boilerplate]) rather than
+    /// dropping them.
+    pub flag_boilerplate: bool,
+
+    /// Assigns the translation units to clusters of near-duplicates (of
+    /// their text in the source language).
+    pub near_duplicates: Option<NearDuplicateDetector>,
+
+    /// Drop all but the first translation unit of each cluster, rather than
+    /// only recording the cluster.
+    pub drop_near_duplicates: bool,
+
+    pub multi_doc: MultiDoc,
+
+    /// Record the position of each translation unit in its TMX file (see
+    /// [crate::tmx_parser::Annotations::index_in_file]).
+    pub index_in_file: bool,
+
+    /// See `--max-units-per-lang` and `--max-chars-per-lang`. Translation
+    /// units left without texts (or without the text in the source
+    /// language) once the quotas are reached are dropped.
+    pub quotas: Option<LangQuotas>,
+
+    /// Scores the alignment of the remaining translation units (see
+    /// `--score-alignment`).
+    pub alignment_model: Option<AlignmentModel>,
+
+    /// Flags the remaining translation units whose text in the source
+    /// language is a formatting artifact (see `--flag-artifacts`).
+    pub artifacts: Option<ArtifactDetector>,
+}
+
+/// When to stop before the whole input has been processed. Checked between
+/// TMX files, so that the files processed are complete and a run with the
+/// same `--seen-files` can continue where this one stopped.
+#[derive(Default)]
+pub struct Limits {
+    /// See `--max-duration`.
+    pub max_duration: Option<Duration>,
+
+    /// See `--max-units`.
+    pub max_units: Option<u64>,
+}
+
+/// Injection points of [process_corpus], e.g. to report progress. They do
+/// nothing by default.
+pub trait Hooks {
+    /// Called before a TMX file is read, with the number of bytes read from
+    /// the input before it.
+    fn file_started(&mut self, _file: &str, _bytes_read: u64) {}
+
+    /// Called once the translation units of a parsed TMX file have been
+    /// processed, with the number of them passed to the handler.
+    fn file_done(&mut self, _file: &str, _translation_units: u64) {}
+
+    /// Called when a TMX file cannot be read or parsed. The file is skipped
+    /// (and recorded in [Summary::errors]) unless an error is returned, which
+    /// aborts the run.
+    fn file_failed(&mut self, _file: &str, _error: &DgtError) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called for each translation unit which is not passed to the handler.
+    fn unit_skipped(
+        &mut self,
+        _reason: SkipReason,
+        _file: &str,
+        _document: Option<&str>,
+        _sequential_number: u32,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Hooks which do nothing.
+impl Hooks for () {}
+
+/// What the caller may report, besides the [Summary].
+#[derive(Default)]
+pub struct Processed {
+    /// Number of texts found in each requested language, to report the
+    /// languages which do not occur in the input at all.
+    pub requested_lang_hits: HashMap<String, u64>,
+
+    /// Size (see [unit_size]) of the translation units of the largest TMX
+    /// file, which are held in memory at once.
+    pub largest_parsed_size: usize,
+
+    /// The option whose limit stopped the run, e.g. `--max-units`.
+    pub stopped_by: Option<&'static str>,
+
+    /// Documents listed in `--docs-file` which do not occur in the input,
+    /// sorted. Left empty if the run was stopped (see [Limits]), since they
+    /// may occur in the files left out.
+    pub missing_listed_docs: Vec<String>,
+
+    /// Number of parsed TMX files whose number of translation units is
+    /// declared (see [Source::declared_units]).
+    pub cataloged_files: u64,
+}
+
+/// Parse the TMX files of the source and pass the translation units which
+/// pass the filters to the handler, counting them in the summary. The
+/// handler must have been started; it is not finished, so that the caller
+/// can complete the summary first.
+pub fn process_corpus(
+    source: Source,
+    filters: &mut UnitFilters,
+    limits: &Limits,
+    handler: &mut dyn TranslationUnitHandler,
+    hooks: &mut dyn Hooks,
+    summary: &mut Summary,
+) -> Result<Processed> {
+    let Source {
+        input,
+        mut walk,
+        cache,
+        seen_files,
+        memory,
+        declared_units,
+        cataloged_hashes,
+        read_ahead,
+        merged_corpora,
+    } = source;
+    let mut processed = Processed {
+        requested_lang_hits: match &filters.requested_langs {
+            RequestedLangs::Unlimited => HashMap::new(),
+            RequestedLangs::Some(langs) | RequestedLangs::Each(langs) => {
+                langs.iter().map(|lang| (lang.clone(), 0)).collect()
+            }
+        },
+        ..Default::default()
+    };
+    let started = Instant::now();
+    // Number of translation units of each document seen so far, across TMX
+    // files.
+    let mut doc_positions: HashMap<String, u32> = HashMap::new();
+    let mut listed_docs_found: HashSet<String> = HashSet::new();
+    // Documents not listed in `--docs-file` whose first translation unit has
+    // been reported as skipped; the others are only counted.
+    let mut unlisted_docs_reported: HashSet<String> = HashSet::new();
+
+    // Set once a limit has stopped the run (or the handler has failed), so
+    // that the reading thread of `--read-ahead` stops reading.
+    let stop = AtomicBool::new(false);
+    let seen_files = seen_files.map(Mutex::new);
+    let corpora: Vec<(Option<&str>, &Input)> = match merged_corpora.is_empty() {
+        true => vec![(None, input)],
+        false => std::iter::once((Some(MAIN_CORPUS), input))
+            .chain(
+                merged_corpora
+                    .iter()
+                    .map(|(corpus, input)| (Some(corpus.as_str()), *input)),
+            )
+            .collect(),
+    };
+
+    let mut process_file = |corpus: Option<&str>,
+                            name: &str,
+                            bytes_read: u64,
+                            read: &mut dyn FnMut() -> ReadTmxFile|
+     -> Result<()> {
+        if processed.stopped_by.is_none() {
+            if limits
+                .max_duration
+                .is_some_and(|max| started.elapsed() >= max)
+            {
+                processed.stopped_by = Some("--max-duration");
+            } else if limits
+                .max_units
+                .is_some_and(|max| summary.translation_units_handled >= max)
+            {
+                processed.stopped_by = Some("--max-units");
+            }
+        }
+        if processed.stopped_by.is_some() {
+            stop.store(true, Ordering::Relaxed);
+            summary.tmx_files_left_out += 1;
+            return Ok(());
+        }
+        hooks.file_started(name, bytes_read);
+        if cataloged_as_seen(&cataloged_hashes, seen_files.as_ref(), input, corpus, name) {
+            summary.tmx_files_seen_before += 1;
+            return Ok(());
+        }
+
+        // A broken file is skipped rather than aborting the whole run; whether
+        // this is fatal is decided by `--fail-on` at the end.
+        let (mut parsed, contents_hash) = match read() {
+            ReadTmxFile::Parsed {
+                parsed,
+                contents_hash,
+            } => (parsed, contents_hash),
+            ReadTmxFile::SeenBefore => {
+                summary.tmx_files_seen_before += 1;
+                return Ok(());
+            }
+            ReadTmxFile::LeftOut => {
+                summary.tmx_files_left_out += 1;
+                return Ok(());
+            }
+            ReadTmxFile::Failed(error) => {
+                hooks.file_failed(name, &error)?;
+                summary.tmx_files_skipped += 1;
+                summary
+                    .errors
+                    .push(format!("Skipped {}: {}", name, describe(&error)));
+                return Ok(());
+            }
+        };
+        // Checked here rather than by the reading thread of `--read-ahead`,
+        // which may read a file before the previous one has been recorded.
+        if let (Some(seen_files), Some(hash)) = (&seen_files, &contents_hash) {
+            if seen_files.lock().unwrap().contains(hash) {
+                summary.tmx_files_seen_before += 1;
+                return Ok(());
+            }
+        }
+        summary.tmx_files_parsed += 1;
+        if let Some(corpus) = corpus.filter(|corpus| *corpus != MAIN_CORPUS) {
+            let file_stem = Path::new(name)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy())
+                .unwrap_or_default();
+            for tu in &mut parsed.translation_units {
+                conform_to_dgt(tu, corpus, &file_stem);
+            }
+        }
+        let parsed_units = parsed.translation_units.len() as u64;
+        if parsed_units != parsed.tu_elements {
+            summary.warnings.push(format!(
+                "{}: parsed {} translation units out of {} <tu> elements",
+                name, parsed_units, parsed.tu_elements
+            ));
+        }
+        let declared = match corpus {
+            None | Some(MAIN_CORPUS) => declared_units.get(input.relative_name(name)),
+            Some(_) => None,
+        };
+        if let Some(declared) = declared {
+            processed.cataloged_files += 1;
+            if parsed_units != *declared {
+                summary.warnings.push(format!(
+                    "{}: parsed {} translation units, but the catalog declares {}; the file may be truncated",
+                    name, parsed_units, declared
+                ));
+            }
+        }
+        let parsed_size: usize = parsed.translation_units.iter().map(unit_size).sum();
+        memory.add_parsed(parsed_size);
+        processed.largest_parsed_size = processed.largest_parsed_size.max(parsed_size);
+        // Broken files are not recorded, so that they are retried.
+        if let (Some(seen_files), Some(hash)) = (&seen_files, contents_hash) {
+            seen_files.lock().unwrap().insert(hash, name);
+        }
+        for warning in parsed.warnings {
+            summary.warnings.push(format!("{}: {}", name, warning));
+        }
+
+        // Documents are not split across TMX files in the official release.
+        let low_coverage_docs = match filters.require_doc_coverage {
+            Some(min_coverage) => low_coverage_docs(
+                &parsed.translation_units,
+                &filters.requested_langs,
+                min_coverage,
+            ),
+            None => HashSet::new(),
+        };
+        summary.documents_with_low_coverage += low_coverage_docs.len() as u64;
+        let mut skip = |reason: SkipReason, document: Option<&String>, position: u32| {
+            hooks.unit_skipped(reason, name, document.map(String::as_str), position)
+        };
+
+        let handled_before = summary.translation_units_handled;
+        let mut units_without_doc_id = 0;
+        for (i, mut tu) in parsed.translation_units.into_iter().enumerate() {
+            // Passed on to the handler (which records its batches) or dropped.
+            memory.release_parsed(unit_size(&tu));
+            // Counted before the filters, so that the numbers do not depend
+            // on them. Translation units without a document are numbered
+            // among themselves.
+            let position = {
+                let document = match filters.multi_doc {
+                    MultiDoc::Join => tu
+                        .doc_names()
+                        .iter()
+                        .map(|name| name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(JOINED_DOC_NAMES_SEPARATOR),
+                    _ => tu.doc_name().cloned().unwrap_or_default(),
+                };
+                let next = doc_positions.entry(document).or_insert(0);
+                *next += 1;
+                *next - 1
+            };
+            let doc_name = match tu.doc_name() {
+                Some(doc_name) => doc_name,
+                None => {
+                    units_without_doc_id += 1;
+                    skip(SkipReason::MissingDocId, None, position)?;
+                    continue;
+                }
+            };
+            if let Some(listed_docs) = &filters.listed_docs {
+                let normalized = normalize_doc_name(doc_name, false);
+                if !listed_docs.contains(&normalized) {
+                    summary.translation_units_not_listed += 1;
+                    if unlisted_docs_reported.insert(normalized) {
+                        skip(SkipReason::UnlistedDoc, Some(doc_name), position)?;
+                    }
+                    continue;
+                }
+                listed_docs_found.insert(normalized);
+            }
+            if !filters.excluded_docs.is_empty()
+                && filters
+                    .excluded_docs
+                    .contains(&normalize_doc_name(doc_name, false))
+            {
+                summary.translation_units_excluded += 1;
+                skip(SkipReason::ExcludedDoc, Some(doc_name), position)?;
+                continue;
+            }
+            if low_coverage_docs.contains(doc_name) {
+                summary.translation_units_with_low_doc_coverage += 1;
+                skip(SkipReason::LowDocCoverage, Some(doc_name), position)?;
+                continue;
+            }
+            if let RequestedLangs::Some(langs) | RequestedLangs::Each(langs) =
+                &filters.requested_langs
+            {
+                for segment in &tu.segments {
+                    if let Some(i) = langs.position(&segment.lang) {
+                        *processed
+                            .requested_lang_hits
+                            .entry(langs[i].clone())
+                            .or_default() += 1;
+                    }
+                }
+            }
+            if let Some(source_lang) = &filters.source_lang {
+                if !tu.has_lang(source_lang) {
+                    summary.translation_units_skipped += 1;
+                    skip(SkipReason::MissingSourceLang, tu.doc_name(), position)?;
+                    continue;
+                }
+            }
+            if let RequestedLangs::Some(_) = &filters.requested_langs {
+                if !tu.contains_any_lang(&filters.requested_langs) {
+                    summary.translation_units_skipped += 1;
+                    skip(SkipReason::MissingLangs, tu.doc_name(), position)?;
+                    continue;
+                }
+            }
+            if let RequestedLangs::Each(_) = &filters.requested_langs {
+                if !tu.contains_each_lang(&filters.requested_langs) {
+                    summary.translation_units_skipped += 1;
+                    skip(SkipReason::MissingLangs, tu.doc_name(), position)?;
+                    continue;
+                }
+            }
+            if let Some(min_langs) = filters.min_langs {
+                if tu.lang_count(&filters.requested_langs) < min_langs {
+                    summary.translation_units_with_too_few_langs += 1;
+                    skip(SkipReason::TooFewLangs, tu.doc_name(), position)?;
+                    continue;
+                }
+            }
+            if let Some((match_lang, match_regex)) = &filters.text_match {
+                if !tu.segments.iter().any(|segment| {
+                    LangMatch::IgnoreCase.matches(&segment.lang, match_lang)
+                        && match_regex.is_match(&segment.content)
+                }) {
+                    summary.translation_units_not_matching += 1;
+                    skip(SkipReason::NoMatch, tu.doc_name(), position)?;
+                    continue;
+                }
+            }
+            // Before the clustering, so that a dropped boilerplate unit does
+            // not become the representative of a cluster.
+            if let Some(boilerplate) = &filters.boilerplate {
+                let is_boilerplate = boilerplate.is_boilerplate(&tu);
+                if is_boilerplate && !filters.flag_boilerplate {
+                    summary.boilerplate_dropped += 1;
+                    skip(SkipReason::Boilerplate, tu.doc_name(), position)?;
+                    continue;
+                }
+                if filters.flag_boilerplate {
+                    tu.annotations.is_boilerplate = Some(is_boilerplate);
+                }
+            }
+            if let Some(detector) = &mut filters.near_duplicates {
+                let cluster = detector.cluster(tu.source_text(filters.source_lang.as_ref()));
+                // Only the first translation unit of each cluster is kept.
+                if filters.drop_near_duplicates && !cluster.is_new {
+                    summary.near_duplicates_dropped += 1;
+                    skip(SkipReason::NearDuplicate, tu.doc_name(), position)?;
+                    continue;
+                }
+                tu.annotations.near_duplicate_cluster = Some(cluster.id);
+            }
+            if tu.doc_names().len() > 1 {
+                summary.translation_units_with_several_docs += 1;
+                if filters.multi_doc == MultiDoc::Join {
+                    tu.join_doc_names(JOINED_DOC_NAMES_SEPARATOR);
+                }
+            }
+            if let Some(quotas) = &mut filters.quotas {
+                quotas.apply(&mut tu);
+                // The language requirements checked above are checked again
+                // against the texts left.
+                let kept = match &filters.source_lang {
+                    Some(source_lang) => tu.has_lang(source_lang),
+                    None => !tu.segments.is_empty(),
+                } && match &filters.requested_langs {
+                    RequestedLangs::Each(_) => tu.contains_each_lang(&filters.requested_langs),
+                    _ => true,
+                } && filters
+                    .min_langs
+                    .is_none_or(|min_langs| tu.lang_count(&filters.requested_langs) >= min_langs);
+                if !kept {
+                    summary.translation_units_over_quota += 1;
+                    skip(SkipReason::OverQuota, tu.doc_name(), position)?;
+                    continue;
+                }
+            }
+            if let Some(model) = &filters.alignment_model {
+                tu.annotations.alignment_score = model.score(&tu);
+            }
+            if let Some(detector) = &filters.artifacts {
+                let flags = detector.detect(tu.source_text(filters.source_lang.as_ref()));
+                tu.annotations.artifact_flags = Some(flags);
+            }
+            if filters.index_in_file {
+                tu.annotations.index_in_file = Some(i as u32);
+            }
+            tu.annotations.corpus = corpus.map(str::to_string);
+            handler.handle(tu, position)?;
+            summary.translation_units_handled += 1;
+        }
+        if units_without_doc_id > 0 {
+            summary.translation_units_dropped += units_without_doc_id;
+            summary.warnings.push(format!(
+                "Dropped {} translation units without a document ID in {}",
+                units_without_doc_id, name
+            ));
+        }
+        hooks.file_done(name, summary.translation_units_handled - handled_before);
+
+        Ok(())
+    };
+
+    if read_ahead {
+        // The next TMX file is read and parsed on another thread while the
+        // handler processes the current one. At most one parsed file waits
+        // in the channel.
+        let walk = &mut walk;
+        let stop = &stop;
+        let hash_contents = seen_files.is_some();
+        let corpora = &corpora;
+        let (cataloged_hashes, seen_files) = (&cataloged_hashes, &seen_files);
+        std::thread::scope(|scope| {
+            let (sender, receiver) = mpsc::sync_channel(1);
+            let reader = scope.spawn(move || {
+                for (corpus, input) in corpora {
+                    input.for_each_tmx_file(walk, &mut |tmx_file| {
+                        // Once stopped, the remaining files are only counted.
+                        let read = if stop.load(Ordering::Relaxed) {
+                            ReadTmxFile::LeftOut
+                        } else if let Some(error) = tmx_file.error {
+                            ReadTmxFile::Failed(error)
+                        } else if cataloged_as_seen(
+                            cataloged_hashes,
+                            seen_files.as_ref(),
+                            input,
+                            *corpus,
+                            &tmx_file.name,
+                        ) {
+                            ReadTmxFile::SeenBefore
+                        } else {
+                            read_tmx_file(tmx_file.reader, cache, None, hash_contents)
+                        };
+                        // Fails only once the processing has failed, in which
+                        // case the walk is interrupted (the error is not
+                        // reported, since the processing error is).
+                        sender
+                            .send((*corpus, tmx_file.name, tmx_file.bytes_read, read))
+                            .map_err(|_| DgtError::Io(std::io::ErrorKind::Interrupted.into()))
+                    })?;
+                }
+                Ok(())
+            });
+            let processing = receiver
+                .iter()
+                .try_for_each(|(corpus, name, bytes_read, read)| {
+                    let mut read = Some(read);
+                    process_file(corpus, &name, bytes_read, &mut || {
+                        read.take().unwrap_or(ReadTmxFile::LeftOut)
+                    })
+                });
+            stop.store(true, Ordering::Relaxed);
+            drop(receiver);
+            let walking = reader
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+            processing.and(walking)
+        })?;
+    } else {
+        for (corpus, input) in &corpora {
+            input.for_each_tmx_file(&mut walk, &mut |tmx_file| {
+                let mut error = tmx_file.error;
+                process_file(
+                    *corpus,
+                    &tmx_file.name,
+                    tmx_file.bytes_read,
+                    &mut || match error.take() {
+                        Some(error) => ReadTmxFile::Failed(error),
+                        None => read_tmx_file(
+                            tmx_file.reader,
+                            cache,
+                            seen_files.as_ref(),
+                            seen_files.is_some(),
+                        ),
+                    },
+                )
+            })?;
+        }
+    }
+    summary.tmx_files_excluded = walk.excluded;
+    if let (Some(listed_docs), None) = (&filters.listed_docs, processed.stopped_by) {
+        processed.missing_listed_docs = listed_docs
+            .difference(&listed_docs_found)
+            .cloned()
+            .collect();
+        processed.missing_listed_docs.sort();
+    }
+
+    Ok(processed)
+}
+
+/// Bring a translation unit of another corpus in line with those of DGT-TM:
+/// the language codes are coerced (e.g. `en` => `EN-GB`), so that the texts
+/// end up in the same columns. The names of its documents are prefixed with
+/// the name of the corpus (e.g. `europarl:ep-00-01-17`), so that they are
+/// never taken for documents of DGT-TM (or of another corpus), and a unit
+/// without a document ID is assigned to a document named after the TMX file.
+fn conform_to_dgt(tu: &mut TranslationUnit, corpus: &str, file_stem: &str) {
+    for segment in &mut tu.segments {
+        segment.lang = coerce_lang_code(&segment.lang);
+    }
+    match tu.doc_name().is_some() {
+        true => tu.rename_docs(|name| format!("{}:{}", corpus, name)),
+        false => tu.add_doc_name(format!("{}:{}", corpus, file_stem)),
+    }
+}
+
+/// Whether the catalog records the contents of a TMX file of the input (not
+/// of a merged corpus) as processed by a previous run (see `--seen-files`),
+/// in which case the file does not need to be read.
+fn cataloged_as_seen(
+    cataloged_hashes: &HashMap<String, String>,
+    seen_files: Option<&Mutex<&mut SeenFiles>>,
+    input: &Input,
+    corpus: Option<&str>,
+    name: &str,
+) -> bool {
+    if !matches!(corpus, None | Some(MAIN_CORPUS)) {
+        return false;
+    }
+    match (seen_files, cataloged_hashes.get(input.relative_name(name))) {
+        (Some(seen_files), Some(hash)) => seen_files.lock().unwrap().contains(hash),
+        _ => false,
+    }
+}
+
+/// What reading a TMX file yielded.
+enum ReadTmxFile {
+    Parsed {
+        parsed: ParsedTmxFile,
+
+        /// Hash of the contents, to be recorded in `--seen-files`.
+        contents_hash: Option<String>,
+    },
+
+    /// Recorded in `--seen-files` by a previous run.
+    SeenBefore,
+
+    Failed(DgtError),
+
+    /// Not read, since the run has been stopped (by the reading thread of
+    /// `--read-ahead`).
+    LeftOut,
+}
+
+/// Read and parse a TMX file. Without a cache (or `hash_contents`, for
+/// `--seen-files`), the file is parsed as it is read rather than loaded into
+/// memory first (both are keyed by the hash of the whole file). A file found
+/// in `seen_files` is not parsed.
+fn read_tmx_file(
+    reader: &mut dyn Read,
+    cache: Option<&Cache>,
+    seen_files: Option<&Mutex<&mut SeenFiles>>,
+    hash_contents: bool,
+) -> ReadTmxFile {
+    let mut contents_hash: Option<String> = None;
+    let parsed = match (cache, hash_contents) {
+        (None, false) => parse_tmx_stream(reader),
+        (cache, _) => {
+            let mut contents: Vec<u8> = Vec::new();
+            let read = reader.read_to_end(&mut contents);
+            if read.is_ok() && hash_contents {
+                let hash = SeenFiles::hash(&contents);
+                if seen_files.is_some_and(|seen_files| seen_files.lock().unwrap().contains(&hash)) {
+                    return ReadTmxFile::SeenBefore;
+                }
+                contents_hash = Some(hash);
+            }
+            read.map_err(DgtError::from).and_then(|_| match cache {
+                Some(cache) => cache.load_or_parse(contents),
+                None => parse_tmx_file(contents),
+            })
+        }
+    };
+    match parsed {
+        Ok(parsed) => ReadTmxFile::Parsed {
+            parsed,
+            contents_hash,
+        },
+        Err(error) => ReadTmxFile::Failed(error),
+    }
+}
+
+/// Describe an error which is collected rather than fatal, e.g. a broken TMX
+/// file, with its code and a hint.
+pub fn describe(error: &DgtError) -> String {
+    match error.hint() {
+        Some(hint) => format!("{} [{}] ({})", error, error.code(), hint),
+        None => format!("{} [{}]", error, error.code()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{process_corpus, Hooks, Limits, Source, UnitFilters};
+    use crate::boilerplate::Boilerplate;
+    use crate::error::{DgtError, Result};
+    use crate::functions::Walk;
+    use crate::input::InputSource;
+    use crate::memory::MemoryUsage;
+    use crate::near_duplicates::NearDuplicateDetector;
+    use crate::quotas::LangQuotas;
+    use crate::seen_files::SeenFiles;
+    use crate::skip_log::SkipReason;
+    use crate::tmx_parser::TranslationUnit;
+    use crate::types::{RequestedLangs, Summary, TranslationUnitHandler};
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    #[derive(Default)]
+    struct Counter {
+        units: u64,
+        files_started: u32,
+        skipped: Vec<SkipReason>,
+
+        /// Document, sequential number and index in file of each unit.
+        positions: Vec<(String, u32, Option<u32>)>,
+    }
+
+    impl TranslationUnitHandler for Counter {
+        fn handle(&mut self, tu: TranslationUnit, sequential_number: u32) -> Result<()> {
+            self.units += 1;
+            self.positions.push((
+                tu.doc_name().cloned().unwrap_or_default(),
+                sequential_number,
+                tu.annotations.index_in_file,
+            ));
+            Ok(())
+        }
+    }
+
+    impl Hooks for Counter {
+        fn file_started(&mut self, _file: &str, _bytes_read: u64) {
+            self.files_started += 1;
+        }
+
+        fn file_done(&mut self, _file: &str, translation_units: u64) {
+            self.units += translation_units;
+        }
+
+        fn unit_skipped(
+            &mut self,
+            reason: SkipReason,
+            _file: &str,
+            _document: Option<&str>,
+            _sequential_number: u32,
+        ) -> Result<()> {
+            self.skipped.push(reason);
+            Ok(())
+        }
+    }
+
+    /// Fails on the first translation unit.
+    struct Failing;
+
+    impl TranslationUnitHandler for Failing {
+        fn handle(&mut self, _tu: TranslationUnit, _sequential_number: u32) -> Result<()> {
+            Err(DgtError::MissingDocId)
+        }
+    }
+
+    fn process(
+        filters: &mut UnitFilters,
+        limits: &Limits,
+        declared_units: HashMap<String, u64>,
+    ) -> (Counter, Counter, Summary) {
+        process_dir(
+            Path::new("test_data/zipped"),
+            filters,
+            limits,
+            declared_units,
+            false,
+        )
+    }
+
+    fn process_dir(
+        input_dir: &Path,
+        filters: &mut UnitFilters,
+        limits: &Limits,
+        declared_units: HashMap<String, u64>,
+        read_ahead: bool,
+    ) -> (Counter, Counter, Summary) {
+        let input = InputSource::LocalDir(input_dir.to_path_buf())
+            .open()
+            .unwrap();
+        let source = Source {
+            input: &input,
+            walk: Walk::new(None),
+            cache: None,
+            seen_files: None,
+            memory: MemoryUsage::new(None),
+            declared_units,
+            cataloged_hashes: HashMap::new(),
+            read_ahead,
+            merged_corpora: Vec::new(),
+        };
+        let (mut handler, mut hooks) = (Counter::default(), Counter::default());
+        let mut summary = Summary::default();
+        process_corpus(
+            source,
+            filters,
+            limits,
+            &mut handler,
+            &mut hooks,
+            &mut summary,
+        )
+        .unwrap();
+        (handler, hooks, summary)
+    }
+
+    #[test]
+    fn units_passing_filters_reach_handler() {
+        let (handler, hooks, summary) = process(
+            &mut UnitFilters::default(),
+            &Limits::default(),
+            HashMap::new(),
+        );
+        assert_eq!(handler.units, 462);
+        assert_eq!(summary.translation_units_handled, 462);
+        assert_eq!((hooks.files_started, summary.tmx_files_parsed), (7, 7));
+        assert_eq!(hooks.units, 462);
+
+        let mut filters = UnitFilters {
+            min_langs: Some(100),
+            ..Default::default()
+        };
+        let (handler, hooks, summary) = process(&mut filters, &Limits::default(), HashMap::new());
+        assert_eq!(handler.units, 0);
+        assert_eq!(hooks.skipped.len(), 462);
+        assert!(matches!(hooks.skipped[0], SkipReason::TooFewLangs));
+        assert_eq!(summary.translation_units_with_too_few_langs, 462);
+
+        let limits = Limits {
+            max_units: Some(1),
+            ..Default::default()
+        };
+        let (_handler, hooks, summary) =
+            process(&mut UnitFilters::default(), &limits, HashMap::new());
+        assert_eq!(hooks.files_started, 1);
+        assert_eq!(summary.tmx_files_left_out, 6);
+
+        // Units dropped by the quotas do not count towards `--max-units`.
+        let mut filters = UnitFilters {
+            quotas: Some(LangQuotas::new(None, Some(0))),
+            ..Default::default()
+        };
+        let (_handler, hooks, summary) = process(&mut filters, &limits, HashMap::new());
+        assert_eq!(hooks.files_started, 7);
+        assert_eq!(summary.translation_units_over_quota, 462);
+    }
+
+    #[test]
+    fn unit_counts_are_verified_against_declared_ones() {
+        let declared_units = HashMap::from([
+            ("1.zip/22019D0557.tmx".to_string(), 20),
+            ("1.zip/22019D0558.tmx".to_string(), 1000),
+        ]);
+        let (_handler, _hooks, summary) = process(
+            &mut UnitFilters::default(),
+            &Limits::default(),
+            declared_units,
+        );
+        assert_eq!(summary.warnings.len(), 1);
+        assert!(summary.warnings[0].starts_with(
+            "test_data/zipped/1.zip/22019D0558.tmx: parsed 22 translation units, but the catalog declares 1000"
+        ));
+    }
+
+    /// Write TMX files with the given translation units (`<tu>` elements)
+    /// into the directory.
+    fn write_tmx_files(dir: &Path, files: &[(&str, Vec<String>)]) {
+        for (file, units) in files {
+            std::fs::write(
+                dir.join(file),
+                format!(
+                    r#"<?xml version="1.0" encoding="utf-8"?><tmx><header></header><body>{}</body></tmx>"#,
+                    units.concat()
+                ),
+            )
+            .unwrap();
+        }
+    }
+
+    /// A translation unit of the document with the given texts, by language.
+    fn tu(doc: &str, texts: &[(&str, &str)]) -> String {
+        let tuvs: String = texts
+            .iter()
+            .map(|(lang, text)| format!(r#"<tuv lang="{}"><seg>{}</seg></tuv>"#, lang, text))
+            .collect();
+        format!(
+            r#"<tu><prop type="Txt::Doc. No.">{}</prop>{}</tu>"#,
+            doc, tuvs
+        )
+    }
+
+    #[test]
+    fn units_are_numbered_within_their_document_across_files() {
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        let en = |doc: &str, text: &str| tu(doc, &[("EN-GB", text)]);
+        write_tmx_files(
+            tmp_dir.path(),
+            &[
+                ("1.tmx", vec![en("A", "a0"), en("B", "b0"), en("A", "a1")]),
+                ("2.tmx", vec![en("A", "a2"), en("B", "b1"), en("C", "c0")]),
+            ],
+        );
+        let mut filters = UnitFilters {
+            index_in_file: true,
+            ..Default::default()
+        };
+        let (handler, _hooks, _summary) = process_dir(
+            tmp_dir.path(),
+            &mut filters,
+            &Limits::default(),
+            HashMap::new(),
+            false,
+        );
+        let position = |doc: &str, number, index| (doc.to_string(), number, Some(index));
+        assert_eq!(
+            handler.positions,
+            vec![
+                position("A", 0, 0),
+                position("B", 0, 1),
+                position("A", 1, 2),
+                position("A", 2, 0),
+                position("B", 1, 1),
+                position("C", 0, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn language_requirements_are_checked_after_quotas() {
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        write_tmx_files(
+            tmp_dir.path(),
+            &[(
+                "1.tmx",
+                vec![
+                    tu("A", &[("EN-GB", "One"), ("PL-01", "Jeden")]),
+                    tu("A", &[("EN-GB", "Two"), ("PL-01", "Dwa")]),
+                    tu("A", &[("EN-GB", "Three"), ("PL-01", "Trzy")]),
+                ],
+            )],
+        );
+        let langs = ["EN-GB", "PL-01"].iter().map(|lang| lang.to_string());
+        for (requested_langs, min_langs, handled) in [
+            (RequestedLangs::Some(langs.clone().collect()), None, 2),
+            (RequestedLangs::Some(langs.clone().collect()), Some(2), 1),
+            (RequestedLangs::Each(langs.clone().collect()), None, 1),
+        ] {
+            // `Dwa` would exceed the 7 characters of Polish, and `Three`
+            // and `Trzy` both languages.
+            let mut filters = UnitFilters {
+                requested_langs,
+                min_langs,
+                quotas: Some(LangQuotas::new(None, Some(7))),
+                ..Default::default()
+            };
+            let (handler, _hooks, summary) = process_dir(
+                tmp_dir.path(),
+                &mut filters,
+                &Limits::default(),
+                HashMap::new(),
+                false,
+            );
+            assert_eq!(handler.units, handled);
+            assert_eq!(summary.translation_units_over_quota, 3 - handled);
+        }
+    }
+
+    #[test]
+    fn lang_codes_are_matched_ignoring_case() {
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        write_tmx_files(
+            tmp_dir.path(),
+            &[(
+                "1.tmx",
+                vec![
+                    tu("A", &[("en-gb", "One"), ("pl-01", "Jeden")]),
+                    tu("A", &[("pl-01", "Dwa")]),
+                ],
+            )],
+        );
+        let mut filters = UnitFilters {
+            source_lang: Some(String::from("EN-GB")),
+            requested_langs: RequestedLangs::Each(
+                ["EN-GB", "PL-01"]
+                    .iter()
+                    .map(|lang| lang.to_string())
+                    .collect(),
+            ),
+            text_match: Some((String::from("EN-GB"), regex::Regex::new("One").unwrap())),
+            ..Default::default()
+        };
+        let (handler, _hooks, summary) = process_dir(
+            tmp_dir.path(),
+            &mut filters,
+            &Limits::default(),
+            HashMap::new(),
+            false,
+        );
+        assert_eq!(handler.units, 1);
+        assert_eq!(summary.translation_units_skipped, 1);
+    }
+
+    #[test]
+    fn boilerplate_is_dropped_before_clustering() {
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        let text = "This Decision shall enter into force on the day of its publication.";
+        write_tmx_files(
+            tmp_dir.path(),
+            &[(
+                "1.tmx",
+                vec![
+                    tu(
+                        "A",
+                        &[("EN-GB", text), ("PL-01", "Sporządzono w Brukseli.")],
+                    ),
+                    tu(
+                        "A",
+                        &[("EN-GB", text), ("PL-01", "Decyzja wchodzi w życie.")],
+                    ),
+                ],
+            )],
+        );
+        let boilerplate_path = tmp_dir.path().join("boilerplate_pl.txt");
+        std::fs::write(&boilerplate_path, "Sporządzono w Brukseli.\n").unwrap();
+        let mut boilerplate = Boilerplate::default();
+        boilerplate.add_file("PL-01", &boilerplate_path).unwrap();
+        let mut filters = UnitFilters {
+            source_lang: Some("EN-GB".to_string()),
+            boilerplate: Some(boilerplate),
+            near_duplicates: Some(NearDuplicateDetector::new(0.9)),
+            drop_near_duplicates: true,
+            ..Default::default()
+        };
+        let (handler, _hooks, summary) = process_dir(
+            tmp_dir.path(),
+            &mut filters,
+            &Limits::default(),
+            HashMap::new(),
+            false,
+        );
+        assert_eq!(handler.units, 1);
+        assert_eq!(summary.boilerplate_dropped, 1);
+        assert_eq!(summary.near_duplicates_dropped, 0);
+    }
+
+    #[test]
+    fn files_read_ahead_are_processed_in_order() {
+        let input_dir = Path::new("test_data/zipped");
+        let mut filters = UnitFilters {
+            index_in_file: true,
+            ..Default::default()
+        };
+        let (sequential, _hooks, _summary) = process_dir(
+            input_dir,
+            &mut filters,
+            &Limits::default(),
+            HashMap::new(),
+            false,
+        );
+        let (read_ahead, hooks, summary) = process_dir(
+            input_dir,
+            &mut filters,
+            &Limits::default(),
+            HashMap::new(),
+            true,
+        );
+        assert_eq!(read_ahead.positions, sequential.positions);
+        assert_eq!((hooks.files_started, summary.tmx_files_parsed), (7, 7));
+
+        let limits = Limits {
+            max_units: Some(1),
+            ..Default::default()
+        };
+        let (_handler, hooks, summary) = process_dir(
+            input_dir,
+            &mut UnitFilters::default(),
+            &limits,
+            HashMap::new(),
+            true,
+        );
+        assert_eq!(hooks.files_started, 1);
+        assert_eq!(summary.tmx_files_left_out, 6);
+    }
+
+    #[test]
+    fn files_read_ahead_are_checked_against_seen_files_in_order() {
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        let input_dir = tmp_dir.path().join("input");
+        std::fs::create_dir(&input_dir).unwrap();
+        // The same file twice: the second one is read before the first one
+        // has been recorded.
+        let units = vec![tu("A", &[("EN-GB", "a0")])];
+        write_tmx_files(&input_dir, &[("1.tmx", units.clone()), ("2.tmx", units)]);
+        let input = InputSource::LocalDir(input_dir).open().unwrap();
+        let mut seen_files = SeenFiles::open(tmp_dir.path().join("seen.txt")).unwrap();
+        let source = |seen_files| Source {
+            input: &input,
+            walk: Walk::new(None),
+            cache: None,
+            seen_files,
+            memory: MemoryUsage::new(None),
+            declared_units: HashMap::new(),
+            cataloged_hashes: HashMap::new(),
+            read_ahead: true,
+            merged_corpora: Vec::new(),
+        };
+        let mut summary = Summary::default();
+        process_corpus(
+            source(Some(&mut seen_files)),
+            &mut UnitFilters::default(),
+            &Limits::default(),
+            &mut Counter::default(),
+            &mut (),
+            &mut summary,
+        )
+        .unwrap();
+        assert_eq!(summary.tmx_files_parsed, 1);
+        assert_eq!(summary.tmx_files_seen_before, 1);
+
+        // The reading thread stops once the handler has failed.
+        let processing = process_corpus(
+            source(None),
+            &mut UnitFilters::default(),
+            &Limits::default(),
+            &mut Failing,
+            &mut (),
+            &mut Summary::default(),
+        );
+        assert!(matches!(processing, Err(DgtError::MissingDocId)));
+    }
+
+    #[test]
+    fn files_seen_according_to_the_catalog_are_not_read() {
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        let input_dir = tmp_dir.path().join("input");
+        std::fs::create_dir(&input_dir).unwrap();
+        write_tmx_files(&input_dir, &[("2.tmx", vec![tu("A", &[("EN-GB", "a0")])])]);
+        // Reading it would fail.
+        std::fs::write(input_dir.join("1.tmx"), "<tmx><body><tu>").unwrap();
+        let input = InputSource::LocalDir(input_dir).open().unwrap();
+        for read_ahead in [false, true] {
+            let mut seen_files = SeenFiles::open(tmp_dir.path().join("seen.txt")).unwrap();
+            seen_files.insert(String::from("cataloged"), "1.tmx");
+            let source = Source {
+                input: &input,
+                walk: Walk::new(None),
+                cache: None,
+                seen_files: Some(&mut seen_files),
+                memory: MemoryUsage::new(None),
+                declared_units: HashMap::new(),
+                cataloged_hashes: HashMap::from([(
+                    String::from("1.tmx"),
+                    String::from("cataloged"),
+                )]),
+                read_ahead,
+                merged_corpora: Vec::new(),
+            };
+            let mut summary = Summary::default();
+            process_corpus(
+                source,
+                &mut UnitFilters::default(),
+                &Limits::default(),
+                &mut Counter::default(),
+                &mut (),
+                &mut summary,
+            )
+            .unwrap();
+            assert_eq!(summary.tmx_files_seen_before, 1);
+            assert_eq!(summary.tmx_files_skipped, 0);
+            assert_eq!(summary.tmx_files_parsed, 1);
+        }
+    }
+}
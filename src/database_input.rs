@@ -0,0 +1,164 @@
+//! Reads a database generated by the `sqlite` subcommand back as the input
+//! (see the `export` subcommand): each document is rendered as a TMX file
+//! of its own, so that the translation units go through the same filters
+//! and reach the same outputs as those of the release files.
+use quick_xml::escape::escape;
+use rusqlite::{Connection, OpenFlags};
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::error::{DgtError, Result};
+use crate::functions::{lang_code_to_db_column, TmxEntry};
+use crate::handlers::sqlite_db::{column_exists, quote_identifier};
+
+/// Where the texts of a translation unit are stored (see
+/// [crate::handlers::sqlite_db::LangStorage]).
+enum Texts {
+    /// Language codes and the names of their columns.
+    Columns(Vec<(String, String)>),
+
+    /// A JSON object in the `texts` column, keyed by language code.
+    Json,
+}
+
+/// Perform an operation on every document of the database, in the order of
+/// their IDs, as a TMX file named after the database and the document, e.g.
+/// `db.sqlite/22019D0557.tmx`. The translation units of a document are
+/// ordered by their sequential number; only their texts, their document and
+/// the language of their original text (from the `srclang` column, if any)
+/// are read back. The export is lossy otherwise: the units are numbered
+/// again from 0 (without the gaps left by the filters of the run which
+/// created the database), and the props and annotations, including the
+/// corpus, are left out.
+pub fn for_each_document<F>(name: &str, path: &Path, callback: &mut F) -> Result<()>
+where
+    F: FnMut(TmxEntry) -> Result<()>,
+{
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let texts = stored_texts(&conn)?;
+    let source_lang_column = match column_exists(&conn, "translation_units", "srclang")? {
+        true => "t.srclang",
+        false => "NULL",
+    };
+    let text_columns = match &texts {
+        Texts::Columns(columns) => columns
+            .iter()
+            .map(|(_lang, column)| format!(", t.{}", quote_identifier(column)))
+            .collect(),
+        Texts::Json => String::from(", t.texts"),
+    };
+    let mut query = conn.prepare(&format!(
+        "SELECT d.id, IFNULL(d.raw_name, d.name), {}{}
+        FROM translation_units t JOIN documents d ON d.id = t.document_id
+        ORDER BY d.id, t.sequential_number, t.id",
+        source_lang_column, text_columns
+    ))?;
+    let mut rows = query.query([])?;
+
+    let mut bytes_read = 0;
+    let mut document: Option<(u32, String)> = None;
+    let mut units = String::new();
+    let mut emit = |document: &str, units: &str| {
+        let contents = format!(
+            r#"<?xml version="1.0" encoding="utf-8"?><tmx><header></header><body>{}</body></tmx>"#,
+            units
+        );
+        bytes_read += contents.len() as u64;
+        callback(TmxEntry {
+            name: format!("{}/{}.tmx", name, document),
+            reader: &mut Cursor::new(contents),
+            bytes_read,
+            error: None,
+        })
+    };
+    while let Some(row) = rows.next()? {
+        let id: u32 = row.get(0)?;
+        let doc_name = match document {
+            Some((doc_id, ref doc_name)) if doc_id == id => doc_name,
+            _ => {
+                if let Some((_, doc_name)) = &document {
+                    emit(doc_name, &units)?;
+                    units.clear();
+                }
+                &document.insert((id, row.get(1)?)).1
+            }
+        };
+
+        let mut tuvs = String::new();
+        let mut push_tuv = |lang: &str, text: &str| {
+            if !text.is_empty() {
+                tuvs += &format!(
+                    r#"<tuv lang="{}"><seg>{}</seg></tuv>"#,
+                    escape(lang),
+                    escape(text)
+                );
+            }
+        };
+        match &texts {
+            Texts::Columns(columns) => {
+                for (i, (lang, _column)) in columns.iter().enumerate() {
+                    if let Some(text) = row.get::<_, Option<String>>(i + 3)? {
+                        push_tuv(lang, &text);
+                    }
+                }
+            }
+            Texts::Json => {
+                let json: Option<String> = row.get(3)?;
+                let texts: serde_json::Map<String, serde_json::Value> =
+                    serde_json::from_str(json.as_deref().unwrap_or("{}"))?;
+                for (lang, text) in &texts {
+                    if let Some(text) = text.as_str() {
+                        push_tuv(lang, text);
+                    }
+                }
+            }
+        }
+        let source_lang = match row.get::<_, Option<String>>(2)? {
+            Some(lang) => format!(r#" srclang="{}""#, escape(&lang)),
+            None => String::new(),
+        };
+        units += &format!(
+            r#"<tu{}><prop type="Txt::Doc. No.">{}</prop>{}</tu>"#,
+            source_lang,
+            escape(doc_name),
+            tuvs
+        );
+    }
+    if let Some((_, doc_name)) = document {
+        emit(&doc_name, &units)?;
+    }
+
+    Ok(())
+}
+
+/// Number of documents in the database, i.e. of the TMX files of
+/// [for_each_document], counted without reading the translation units.
+pub fn count_documents(path: &Path) -> Result<u32> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    Ok(conn.query_row("SELECT count(*) FROM documents", [], |row| row.get(0))?)
+}
+
+/// Find out how the texts are stored in the database.
+fn stored_texts(conn: &Connection) -> Result<Texts> {
+    let mut query = conn.prepare("SELECT name FROM pragma_table_info('translation_units')")?;
+    let columns = query
+        .query_map([], |row| row.get(0))?
+        .collect::<std::result::Result<Vec<String>, _>>()?;
+    if columns.is_empty() {
+        return Err(DgtError::NotDgtDatabase);
+    }
+    if columns.iter().any(|column| column == "texts") {
+        return Ok(Texts::Json);
+    }
+
+    // Other columns hold annotations, e.g. `en_gb_search` or
+    // `alignment_score`.
+    Ok(Texts::Columns(
+        columns
+            .into_iter()
+            .filter(|column| !matches!(column.as_str(), "id" | "document_id" | "sequential_number"))
+            .filter(|column| lang_code_to_db_column(column).is_ok_and(|lang| lang == *column))
+            .map(|column| (column.to_uppercase().replace('_', "-"), column))
+            .collect(),
+    ))
+}
@@ -0,0 +1,204 @@
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Errors returned by the parsing and output layers, so that callers can tell
+/// the categories of failures apart. The command-line interface (`main` and
+/// the subcommands) reports them through `anyhow`, with the [code](DgtError::code)
+/// and the [hint](DgtError::hint) of the error.
+#[derive(Debug, Error)]
+pub enum DgtError {
+    #[error("{} does not exist", .0.display())]
+    InputNotFound(PathBuf),
+
+    /// The output file or directory would be overwritten.
+    #[error("{} already exists", .0.display())]
+    OutputExists(PathBuf),
+
+    /// A database to read or to append to does not exist.
+    #[error("{} does not exist", .0.display())]
+    DatabaseNotFound(PathBuf),
+
+    /// The subcommand needs languages selected with `-l`.
+    #[error("specify {} with -l", required_langs(*.0))]
+    MissingLangs(usize),
+
+    /// Two outputs would be written to the standard output.
+    #[error("{0}")]
+    ConflictingOutputs(&'static str),
+
+    #[error("checksum verification failed:\n{}", .0.join("\n"))]
+    ChecksumMismatch(Vec<String>),
+
+    #[error("the database contains no texts in {0}")]
+    LangNotInDatabase(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Archive(#[from] zip::result::ZipError),
+
+    /// The contents of a TMX file are not valid in the detected encoding.
+    #[error("error decoding input as {encoding}")]
+    Decode { encoding: &'static str },
+
+    #[error(transparent)]
+    Parse(#[from] quick_xml::DeError),
+
+    #[error("invalid language code: {0}")]
+    InvalidLang(String),
+
+    #[error("invalid checksum entry: {0}")]
+    InvalidChecksum(String),
+
+    #[error("no document ID provided for the translation unit")]
+    MissingDocId,
+
+    /// Failure of a handler, e.g. a database error.
+    #[error(transparent)]
+    Handler(Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("the database uses schema version {found}, which is newer than the latest supported version ({supported})")]
+    UnsupportedSchemaVersion { found: u32, supported: u32 },
+
+    #[error("the database was not created by dgt_parser")]
+    NotDgtDatabase,
+
+    #[error(transparent)]
+    Cache(#[from] bincode::Error),
+
+    /// No handler of this name is registered in the `--config` file.
+    #[error("unknown handler: {0}")]
+    UnknownHandler(String),
+
+    #[error("invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    /// An out-of-tree handler could not be run or exited with an error.
+    #[error("external handler {name} failed: {reason}")]
+    ExternalHandler { name: String, reason: String },
+
+    /// `convert` cannot infer the output format from the extension.
+    #[error("cannot infer the output format of {}", .0.display())]
+    UnknownOutputFormat(PathBuf),
+
+    /// Options which cannot be used together, e.g. because their results
+    /// would be inconsistent.
+    #[error("{0}")]
+    ConflictingOptions(&'static str),
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for DgtError {
+    fn from(error: rusqlite::Error) -> DgtError {
+        DgtError::Handler(Box::new(error))
+    }
+}
+
+impl From<serde_json::Error> for DgtError {
+    fn from(error: serde_json::Error) -> DgtError {
+        DgtError::Handler(Box::new(error))
+    }
+}
+
+#[cfg(feature = "kv")]
+impl From<sled::Error> for DgtError {
+    fn from(error: sled::Error) -> DgtError {
+        DgtError::Handler(Box::new(error))
+    }
+}
+
+impl DgtError {
+    /// Stable code of the error, for scripts to match on (see the list of
+    /// codes in the README). Codes are never reused for other errors.
+    pub fn code(&self) -> &'static str {
+        match self {
+            DgtError::InputNotFound(_) => "E001",
+            DgtError::OutputExists(_) => "E002",
+            DgtError::DatabaseNotFound(_) => "E003",
+            DgtError::InvalidLang(_) => "E004",
+            DgtError::MissingLangs(_) => "E005",
+            DgtError::ConflictingOutputs(_) => "E006",
+            DgtError::ChecksumMismatch(_) => "E008",
+            DgtError::InvalidChecksum(_) => "E009",
+            DgtError::Decode { .. } => "E010",
+            DgtError::Parse(_) => "E011",
+            DgtError::MissingDocId => "E012",
+            DgtError::UnsupportedSchemaVersion { .. } => "E013",
+            DgtError::NotDgtDatabase => "E014",
+            DgtError::LangNotInDatabase(_) => "E015",
+            DgtError::Archive(_) => "E016",
+            DgtError::Cache(_) => "E017",
+            DgtError::Io(_) => "E018",
+            DgtError::Handler(_) => "E019",
+            DgtError::UnknownHandler(_) => "E020",
+            DgtError::InvalidConfig(_) => "E021",
+            DgtError::ExternalHandler { .. } => "E022",
+            DgtError::UnknownOutputFormat(_) => "E023",
+            DgtError::ConflictingOptions(_) => "E024",
+        }
+    }
+
+    /// What the user can do about the error, if there is a common remedy.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            DgtError::InputNotFound(_) => {
+                Some("-i accepts a directory, an archive, a TMX file or - for the standard input")
+            }
+            DgtError::OutputExists(_) => Some(
+                "choose another output path or remove the existing one \
+                (a database can be extended with `sqlite --append`)",
+            ),
+            DgtError::DatabaseNotFound(_) => {
+                Some("create the database with the sqlite subcommand first")
+            }
+            DgtError::InvalidLang(_) => Some("use a language code such as `pl` or `PL-01`"),
+            DgtError::MissingLangs(_) => Some("e.g. `-l en -l pl`"),
+            DgtError::ConflictingOutputs(_) => Some("write one of the outputs to a file"),
+            DgtError::ChecksumMismatch(_) => {
+                Some("the files may be truncated or corrupted; download them again")
+            }
+            DgtError::InvalidChecksum(_) => {
+                Some("each line should read `<SHA-256 hash>  <file name>`, as written by sha256sum")
+            }
+            DgtError::Decode { .. } => Some(
+                "TMX files not encoded in UTF-16 should declare their encoding, \
+                e.g. <?xml version=\"1.0\" encoding=\"utf-8\"?>",
+            ),
+            DgtError::UnsupportedSchemaVersion { .. } => {
+                Some("the database was created by a newer version of dgt_parser; upgrade it")
+            }
+            DgtError::NotDgtDatabase => {
+                Some("use a database created by the sqlite subcommand of dgt_parser")
+            }
+            DgtError::Cache(_) => Some("the cache may be corrupted; remove the cache directory"),
+            DgtError::UnknownHandler(_) => {
+                Some("register it in a [handlers.<name>] table of the file given with --config")
+            }
+            DgtError::UnknownOutputFormat(_) => Some(CONVERT_HINT),
+            _ => None,
+        }
+    }
+}
+
+/// Hint of [DgtError::UnknownOutputFormat], listing the extensions of the
+/// output formats compiled in.
+#[cfg(all(feature = "sqlite", feature = "xlsx"))]
+const CONVERT_HINT: &str = "use the extension .db, .sqlite, .sqlite3, .xlsx or .jsonl, \
+    or the subcommand of the output format";
+#[cfg(all(feature = "sqlite", not(feature = "xlsx")))]
+const CONVERT_HINT: &str = "use the extension .db, .sqlite, .sqlite3 or .jsonl, \
+    or the subcommand of the output format";
+#[cfg(all(not(feature = "sqlite"), feature = "xlsx"))]
+const CONVERT_HINT: &str =
+    "use the extension .xlsx or .jsonl, or the subcommand of the output format";
+#[cfg(not(any(feature = "sqlite", feature = "xlsx")))]
+const CONVERT_HINT: &str = "use the extension .jsonl, or the subcommand of the output format";
+
+fn required_langs(count: usize) -> String {
+    match count {
+        1 => String::from("the languages"),
+        _ => format!("at least {} languages", count),
+    }
+}
+
+pub type Result<T, E = DgtError> = std::result::Result<T, E>;
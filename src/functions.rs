@@ -1,111 +1,2093 @@
+use flate2::read::GzDecoder;
+use glob::Pattern;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::BufReader;
 use std::io::Read;
-use std::path::PathBuf;
-use zip::read::ZipFile;
+use std::io::{BufReader, Cursor, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tempfile::NamedTempFile;
 use zip::ZipArchive;
 
-use anyhow::{bail, Result};
+use encoding_rs::{Decoder, DecoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
-pub fn parse_utf16_string(input: Vec<u8>) -> Result<String> {
-    let (result, malformed_sequences_present) =
-        encoding_rs::UTF_16LE.decode_with_bom_removal(&input);
-    if malformed_sequences_present {
-        bail!("Error decoding input");
+use crate::corpus::MAIN_CORPUS;
+use crate::error::{DgtError, Result};
+use crate::input::{InputSource, STDIN_NAME};
+use crate::tmx_parser::{parse_tmx, parse_tmx_reader, Tmx, TranslationUnit};
+use crate::types::{NewlinePolicy, PropColumn, PropType, RequestedLangs, TeeFormat, TeeTarget};
+
+/// Decode the contents of a TMX file.
+///
+/// DGT-TM files are UTF-16LE with a BOM, but some mirrored files lack the BOM
+/// or are big-endian, and TMX files produced by CAT tools are often UTF-8.
+/// Without a BOM, UTF-16 and its byte order are detected from the first
+/// character (the `<` of the XML declaration or of the root element), which
+/// is ASCII; otherwise, the encoding declared in the XML declaration is used
+/// (UTF-8 if none). A declared encoding which does not match the one used is
+/// reported as a warning.
+pub fn decode_xml(input: &[u8], warnings: &mut Vec<String>) -> Result<String> {
+    let (encoding, bom_length) = detect_encoding(input);
+    let text =
+        match encoding.decode_without_bom_handling_and_without_replacement(&input[bom_length..]) {
+            Some(text) => text.into_owned(),
+            None => {
+                return Err(DgtError::Decode {
+                    encoding: encoding.name(),
+                })
+            }
+        };
+    check_declared_encoding(&text, encoding, warnings);
+
+    Ok(text)
+}
+
+/// Detect the encoding of a TMX file from its leading bytes (see
+/// [decode_xml]). Returns the encoding and the length of the BOM, if any.
+fn detect_encoding(input: &[u8]) -> (&'static Encoding, usize) {
+    match Encoding::for_bom(input) {
+        Some(bom) => bom,
+        None => match input.get(..2) {
+            Some([0, byte]) if *byte != 0 => (UTF_16BE, 0),
+            Some([byte, 0]) if *byte != 0 => (UTF_16LE, 0),
+            // An ASCII-compatible encoding, so the declaration can be read
+            // before decoding.
+            _ => {
+                let prefix = String::from_utf8_lossy(&input[..input.len().min(256)]);
+                match declared_encoding(&prefix)
+                    .and_then(|label| Encoding::for_label(label.as_bytes()))
+                {
+                    Some(encoding) if !is_utf16(encoding) => (encoding, 0),
+                    _ => (UTF_8, 0),
+                }
+            }
+        },
     }
-    Ok(result.to_string())
 }
 
-pub fn read_utf16_file_to_string<T>(file: &mut T) -> Result<String>
-where
-    T: Read,
-{
-    let mut buffer: Vec<u8> = Vec::new();
-    file.read_to_end(&mut buffer)?;
-    let tmx_contents = parse_utf16_string(buffer)?;
-    Ok(tmx_contents)
+/// Report a declared encoding which does not match the one used to decode
+/// the text as a warning.
+fn check_declared_encoding(text: &str, encoding: &'static Encoding, warnings: &mut Vec<String>) {
+    if let Some(label) = declared_encoding(text) {
+        match Encoding::for_label(label.as_bytes()) {
+            None => warnings.push(format!("Unknown encoding declared: {}", label)),
+            // The byte order of UTF-16 is not part of the declaration.
+            Some(declared) if is_utf16(declared) && is_utf16(encoding) => {}
+            Some(declared) if declared != encoding => warnings.push(format!(
+                "Encoding declared as {}, but decoded as {}",
+                label,
+                encoding.name()
+            )),
+            Some(_) => {}
+        }
+    }
+}
+
+/// Decodes a byte stream into UTF-8 as it is read, so that large TMX files
+/// do not have to be held in memory both encoded and decoded.
+struct DecodingReader<R> {
+    inner: R,
+    decoder: Decoder,
+    input: Vec<u8>,
+
+    /// Decoded text which has not been read yet, starting at `position`.
+    output: String,
+    position: usize,
+
+    finished: bool,
+
+    /// Set if the input is not valid in the encoding, in which case reading
+    /// fails with an I/O error.
+    malformed: bool,
+}
+
+/// Size of the chunks of the input decoded at once.
+const DECODING_CHUNK_SIZE: usize = 64 * 1024;
+
+impl<R: Read> DecodingReader<R> {
+    fn new(inner: R, encoding: &'static Encoding) -> DecodingReader<R> {
+        DecodingReader {
+            inner,
+            decoder: encoding.new_decoder_without_bom_handling(),
+            input: vec![0; DECODING_CHUNK_SIZE],
+            output: String::new(),
+            position: 0,
+            finished: false,
+            malformed: false,
+        }
+    }
+
+    /// Decode the next chunk of the input into `output`.
+    fn fill(&mut self) -> std::io::Result<()> {
+        let length = self.inner.read(&mut self.input)?;
+        let last = length == 0;
+        self.output.clear();
+        self.position = 0;
+        self.output.reserve(
+            self.decoder
+                .max_utf8_buffer_length_without_replacement(length)
+                .unwrap_or(length * 3 + 16),
+        );
+        let (result, _read) = self.decoder.decode_to_string_without_replacement(
+            &self.input[..length],
+            &mut self.output,
+            last,
+        );
+        if let DecoderResult::Malformed(_, _) = result {
+            self.malformed = true;
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "malformed input",
+            ));
+        }
+        self.finished = last;
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // A chunk may decode to nothing, e.g. a single byte of UTF-16.
+        while self.position == self.output.len() && !self.finished {
+            self.fill()?;
+        }
+        let bytes = &self.output.as_bytes()[self.position..];
+        let length = bytes.len().min(buf.len());
+        buf[..length].copy_from_slice(&bytes[..length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+fn is_utf16(encoding: &Encoding) -> bool {
+    encoding == UTF_16LE || encoding == UTF_16BE
+}
+
+/// Read the `encoding` attribute of the XML declaration, e.g. `utf-8` in
+/// `<?xml version="1.0" encoding="utf-8"?>`.
+fn declared_encoding(text: &str) -> Option<String> {
+    static DECLARATION: OnceLock<Regex> = OnceLock::new();
+    let declaration = DECLARATION.get_or_init(|| {
+        Regex::new(r#"^\s*<\?xml[^>]*?\sencoding\s*=\s*["']([^"']+)["']"#).unwrap()
+    });
+    declaration
+        .captures(text.get(..text.len().min(256)).unwrap_or(text))
+        .map(|captures| captures[1].to_string())
+}
+
+#[test]
+fn byte_order_is_detected() {
+    let utf16 = |text: &str, big_endian: bool| -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| match big_endian {
+                true => unit.to_be_bytes(),
+                false => unit.to_le_bytes(),
+            })
+            .collect()
+    };
+
+    for input in [
+        utf16("\u{feff}<tmx>Zażółć</tmx>", false),
+        utf16("<tmx>Zażółć</tmx>", false),
+        utf16("\u{feff}<tmx>Zażółć</tmx>", true),
+        utf16("<tmx>Zażółć</tmx>", true),
+    ] {
+        assert_eq!(
+            decode_xml(&input, &mut Vec::new()).unwrap(),
+            "<tmx>Zażółć</tmx>"
+        );
+    }
+    assert!(decode_xml(&[0x3c, 0x00, 0x00], &mut Vec::new()).is_err());
+}
+
+#[test]
+fn declared_encoding_is_honored_and_checked() {
+    let mut warnings: Vec<String> = Vec::new();
+    let utf8 = "<?xml version=\"1.0\" encoding=\"utf-8\"?><tmx>Zażółć</tmx>";
+    assert_eq!(decode_xml(utf8.as_bytes(), &mut warnings).unwrap(), utf8);
+    assert!(warnings.is_empty());
+
+    let latin1 = b"<?xml version='1.0' encoding='ISO-8859-1'?><tmx>Caf\xe9</tmx>";
+    let text = decode_xml(latin1, &mut warnings).unwrap();
+    assert!(text.ends_with("<tmx>Café</tmx>"));
+    assert!(warnings.is_empty());
+
+    let mismatched: Vec<u8> = "<?xml version=\"1.0\" encoding=\"utf-8\"?><tmx/>"
+        .encode_utf16()
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect();
+    decode_xml(&mismatched, &mut warnings).unwrap();
+    assert_eq!(
+        warnings,
+        vec!["Encoding declared as utf-8, but decoded as UTF-16LE"]
+    );
+}
+
+/// The translation units of a TMX file, together with the problems found
+/// while decoding it.
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ParsedTmxFile {
+    pub translation_units: Vec<TranslationUnit>,
+    pub warnings: Vec<String>,
+
+    /// Number of `<tu>` elements in the TMX file (see [TuCounter]), which
+    /// differs from the number of translation units if the parser lost some.
+    pub tu_elements: u64,
+}
+
+/// Counts the `<tu>` elements of a TMX file in its decoded contents without
+/// parsing them, so that the count does not depend on the parser. The
+/// contents may be fed in any number of chunks.
+#[derive(Default)]
+pub struct TuCounter {
+    /// Length of the prefix of `<tu` matched by the last bytes.
+    matched: usize,
+    pub count: u64,
+}
+
+impl TuCounter {
+    pub fn feed(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.matched = match (self.matched, byte) {
+                (3, b'>' | b'/' | b' ' | b'\t' | b'\r' | b'\n') => {
+                    self.count += 1;
+                    0
+                }
+                (_, b'<') => 1,
+                (1, b't') => 2,
+                (2, b'u') => 3,
+                _ => 0,
+            };
+        }
+    }
+}
+
+/// Passes the contents read through a [TuCounter].
+struct TuCountingReader<R> {
+    inner: R,
+    counter: TuCounter,
+}
+
+impl<R: Read> Read for TuCountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.counter.feed(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// Decode the raw contents of a TMX file and return its translation units,
+/// with the problems found while decoding it and the number of `<tu>`
+/// elements.
+pub fn parse_tmx_file(contents: Vec<u8>) -> Result<ParsedTmxFile> {
+    let mut warnings: Vec<String> = Vec::new();
+    let tmx_contents = decode_xml(&contents, &mut warnings)?;
+    let mut counter = TuCounter::default();
+    counter.feed(tmx_contents.as_bytes());
+    let Tmx { body, header: _ } = parse_tmx(tmx_contents)?;
+    Ok(ParsedTmxFile {
+        translation_units: body.translation_units,
+        warnings,
+        tu_elements: counter.count,
+    })
+}
+
+/// Same as [parse_tmx_file], but the TMX file is decoded and parsed as it is
+/// read, so that only the translation units are held in memory.
+pub fn parse_tmx_stream(reader: &mut dyn Read) -> Result<ParsedTmxFile> {
+    let mut prefix = Vec::with_capacity(512);
+    reader.take(512).read_to_end(&mut prefix)?;
+    let (encoding, bom_length) = detect_encoding(&prefix);
+    let mut warnings: Vec<String> = Vec::new();
+    // The prefix may end in the middle of a character, which does not matter
+    // for reading the XML declaration.
+    let (declaration, _) = encoding.decode_without_bom_handling(&prefix[bom_length..]);
+    check_declared_encoding(&declaration, encoding, &mut warnings);
+
+    let mut decoder = TuCountingReader {
+        inner: DecodingReader::new(Cursor::new(&prefix[bom_length..]).chain(reader), encoding),
+        counter: TuCounter::default(),
+    };
+    let tmx = parse_tmx_reader(BufReader::new(&mut decoder));
+    if decoder.inner.malformed {
+        return Err(DgtError::Decode {
+            encoding: encoding.name(),
+        });
+    }
+    let Tmx { body, header: _ } = tmx?;
+    Ok(ParsedTmxFile {
+        translation_units: body.translation_units,
+        warnings,
+        tu_elements: decoder.counter.count,
+    })
+}
+
+#[test]
+fn tu_elements_are_counted_across_chunks() {
+    let mut counter = TuCounter::default();
+    for chunk in [
+        "<body><t",
+        "u><tuv/></tu><",
+        "tu\n",
+        "id=\"1\"/><tux/><tu",
+        ">",
+    ] {
+        counter.feed(chunk.as_bytes());
+    }
+    assert_eq!(counter.count, 3);
+}
+
+#[test]
+fn streamed_parsing_matches_parsing_in_memory() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-16LE"?><tmx><header></header><body>
+        <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="PL-01"><seg>Zażółć gęślą jaźń</seg></tuv></tu>
+        </body></tmx>"#;
+    let contents: Vec<u8> = "\u{feff}"
+        .chars()
+        .chain(xml.chars())
+        .collect::<String>()
+        .encode_utf16()
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect();
+    let streamed = parse_tmx_stream(&mut Cursor::new(&contents)).unwrap();
+    assert_eq!(streamed, parse_tmx_file(contents).unwrap());
+    assert_eq!(
+        streamed.translation_units[0].segments[0].content,
+        "Zażółć gęślą jaźń"
+    );
+
+    let truncated = [0xff, 0xfe, 0x3c, 0x00, 0x3c];
+    assert!(matches!(
+        parse_tmx_stream(&mut Cursor::new(&truncated)),
+        Err(DgtError::Decode { .. })
+    ));
 }
 
 /// - `en` => `EN-GB`
 /// - `pl` => `PL-01`
 /// - `Asdf` => `Asdf`
+/// - `slavic` => `PL-01`, `CS-01`, `SK-01`, `SL-01`, `BG-01`, `HR-HR` (see
+///   [LANG_PRESETS])
 pub fn coerce_lang_codes(input: Vec<String>) -> Vec<String> {
     input
         .iter()
-        .map(|lang_code| coerce_lang_code(lang_code))
+        .flat_map(|lang| {
+            match LANG_PRESETS
+                .iter()
+                .find(|(name, _langs)| name.eq_ignore_ascii_case(lang))
+            {
+                Some((_name, langs)) => langs.iter().map(|lang| lang.to_string()).collect(),
+                None => vec![lang.clone()],
+            }
+        })
+        .map(|lang| coerce_lang_code(&lang))
         .collect()
 }
 
-fn coerce_lang_code(input: &String) -> String {
-    match input.to_ascii_lowercase().as_str() {
-        "en" => String::from("EN-GB"),
-        "pl" => String::from("PL-01"),
-        "de" => String::from("DE-DE"),
-        "da" => String::from("DA-01"),
-        "el" => String::from("EL-01"),
-        "es" => String::from("ES-ES"),
-        "fi" => String::from("FI-01"),
-        "fr" => String::from("FR-FR"),
-        "it" => String::from("IT-IT"),
-        "nl" => String::from("NL-NL"),
-        "pt" => String::from("PT-PT"),
-        "sv" => String::from("SV-SE"),
-        "lv" => String::from("LV-01"),
-        "cs" => String::from("CS-01"),
-        "et" => String::from("ET-01"),
-        "hu" => String::from("HU-01"),
-        "sl" => String::from("SL-01"),
-        "lt" => String::from("LT-01"),
-        "mt" => String::from("MT-01"),
-        "sk" => String::from("SK-01"),
-        "ro" => String::from("RO-RO"),
-        "bg" => String::from("BG-01"),
-        "hr" => String::from("HR-HR"),
-        "ga" => String::from("GA-IE"),
-        _ => String::from(input),
+/// Named sets of DGT languages accepted by `--langs-preset`, expanded by
+/// [coerce_lang_codes].
+pub const LANG_PRESETS: [(&str, &[&str]); 4] = [
+    // The official languages of the 15 member states before 2004.
+    (
+        "eu15",
+        &[
+            "en", "da", "de", "el", "es", "fi", "fr", "it", "nl", "pt", "sv",
+        ],
+    ),
+    (
+        "eu24",
+        &[
+            "bg", "cs", "da", "de", "el", "en", "es", "et", "fi", "fr", "ga", "hr", "hu", "it",
+            "lt", "lv", "mt", "nl", "pl", "pt", "ro", "sk", "sl", "sv",
+        ],
+    ),
+    ("slavic", &["pl", "cs", "sk", "sl", "bg", "hr"]),
+    ("romance", &["es", "fr", "it", "pt", "ro"]),
+];
+
+#[test]
+fn lang_presets_are_expanded() {
+    assert_eq!(
+        coerce_lang_codes(vec!["en".to_string(), "Romance".to_string()]),
+        vec!["EN-GB", "ES-ES", "FR-FR", "IT-IT", "PT-PT", "RO-RO"]
+    );
+    assert_eq!(coerce_lang_codes(vec!["eu24".to_string()]).len(), 24);
+    for (_name, langs) in LANG_PRESETS {
+        assert!(langs.iter().all(|lang| DGT_LANGS
+            .iter()
+            .any(|(short_code, _code, _name)| short_code == lang)));
+    }
+}
+
+/// Languages of the DGT-TM: the short code accepted on the command line, the
+/// code used in the TMX files and the English name.
+const DGT_LANGS: [(&str, &str, &str); 24] = [
+    ("en", "EN-GB", "English"),
+    ("pl", "PL-01", "Polish"),
+    ("de", "DE-DE", "German"),
+    ("da", "DA-01", "Danish"),
+    ("el", "EL-01", "Greek"),
+    ("es", "ES-ES", "Spanish"),
+    ("fi", "FI-01", "Finnish"),
+    ("fr", "FR-FR", "French"),
+    ("it", "IT-IT", "Italian"),
+    ("nl", "NL-NL", "Dutch"),
+    ("pt", "PT-PT", "Portuguese"),
+    ("sv", "SV-SE", "Swedish"),
+    ("lv", "LV-01", "Latvian"),
+    ("cs", "CS-01", "Czech"),
+    ("et", "ET-01", "Estonian"),
+    ("hu", "HU-01", "Hungarian"),
+    ("sl", "SL-01", "Slovenian"),
+    ("lt", "LT-01", "Lithuanian"),
+    ("mt", "MT-01", "Maltese"),
+    ("sk", "SK-01", "Slovak"),
+    ("ro", "RO-RO", "Romanian"),
+    ("bg", "BG-01", "Bulgarian"),
+    ("hr", "HR-HR", "Croatian"),
+    ("ga", "GA-IE", "Irish"),
+];
+
+pub fn coerce_lang_code(input: &String) -> String {
+    match DGT_LANGS
+        .iter()
+        .find(|(short_code, _code, _name)| short_code.eq_ignore_ascii_case(input))
+    {
+        Some((_short_code, code, _name)) => String::from(*code),
+        None => String::from(input),
+    }
+}
+
+/// Convert a language code into the name of the corresponding database
+/// column, e.g. `EN-GB` => `en_gb`.
+pub fn lang_code_to_db_column(lang_code: &str) -> Result<String> {
+    let column = lang_code.to_ascii_lowercase().replace('-', "_");
+    static LANG_CODE_REGEX: OnceLock<Regex> = OnceLock::new();
+    let lang_code_regex =
+        LANG_CODE_REGEX.get_or_init(|| Regex::new(r"^\w{2}(-|_)(\w|\d){2}$").unwrap());
+    if lang_code_regex.is_match(&column) {
+        Ok(column)
+    } else {
+        Err(DgtError::InvalidLang(column))
     }
 }
 
+/// Name of a language used in directory and file names, e.g. `EN-GB` => `en`.
+pub fn short_lang_name(lang_code: &str) -> String {
+    lang_code
+        .split(['-', '_'])
+        .next()
+        .unwrap_or(lang_code)
+        .to_ascii_lowercase()
+}
+
+/// Whether the input looks like a language code, e.g. `en`, `EN-GB` or
+/// `pl_01`.
+fn is_lang_code(input: &str) -> bool {
+    static LANG_REGEX: OnceLock<Regex> = OnceLock::new();
+    let lang_regex =
+        LANG_REGEX.get_or_init(|| Regex::new(r"^[A-Za-z]{2}([-_][A-Za-z0-9]{2})?$").unwrap());
+    lang_regex.is_match(input)
+}
+
+/// Parse a language specified on the command line. Values which are not
+/// language codes (e.g. `portugese` or `pol`) are rejected, with the closest
+/// DGT language suggested, so that a typo does not result in an empty output.
+pub fn parse_lang(input: &str) -> std::result::Result<String, String> {
+    if is_lang_code(input) {
+        return Ok(input.to_string());
+    }
+    match suggest_lang(input) {
+        Some(short_code) => Err(format!(
+            "unknown language: {} (did you mean `{}`?)",
+            input, short_code
+        )),
+        None => Err(format!(
+            "unknown language: {} (expected a code such as `en` or `EN-GB`)",
+            input
+        )),
+    }
+}
+
+/// Find the short code of the DGT language whose code or name is the most
+/// similar to the input, if any is similar enough.
+fn suggest_lang(input: &str) -> Option<&'static str> {
+    let input = input.trim().to_lowercase();
+    let max_distance = (input.chars().count() / 3).max(1);
+    DGT_LANGS
+        .iter()
+        .map(|(short_code, code, name)| {
+            let name = name.to_lowercase();
+            let distance = match input.len() >= 3 && name.starts_with(&input) {
+                true => 0,
+                false => [*short_code, &code.to_lowercase(), &name]
+                    .iter()
+                    .map(|candidate| strsim::levenshtein(&input, candidate))
+                    .min()
+                    .unwrap_or(usize::MAX),
+            };
+            (distance, *short_code)
+        })
+        .filter(|(distance, _short_code)| *distance <= max_distance)
+        .min_by_key(|(distance, _short_code)| *distance)
+        .map(|(_distance, short_code)| short_code)
+}
+
 #[test]
 fn coercion_leaves_unrecognized_string_intact() {
     assert_eq!(coerce_lang_code(&"en".to_string()), "EN-GB".to_string());
     assert_eq!(coerce_lang_code(&"Hello".to_string()), "Hello".to_string());
 }
 
-/// Perform an operation on every ZIP file in the input directory.
-pub fn for_each_zip<F>(input_dir: &PathBuf, callback: &mut F) -> Result<()>
+#[test]
+fn close_matches_are_suggested_for_invalid_langs() {
+    assert_eq!(parse_lang("pt"), Ok(String::from("pt")));
+    assert_eq!(parse_lang("PT-BR"), Ok(String::from("PT-BR")));
+    assert_eq!(
+        parse_lang("portugese"),
+        Err(String::from(
+            "unknown language: portugese (did you mean `pt`?)"
+        ))
+    );
+    assert_eq!(suggest_lang("pol"), Some("pl"));
+    assert_eq!(suggest_lang("Eng"), Some("en"));
+    assert_eq!(suggest_lang("en-gbr"), Some("en"));
+    assert_eq!(suggest_lang("klingon"), None);
+}
+
+/// Read the entries of a list file, one per line, with their line numbers.
+/// Empty lines and comments (starting with `#`, also after an entry) are
+/// ignored.
+fn read_list_file(path: &Path) -> Result<Vec<(usize, String)>> {
+    let contents = std::fs::read_to_string(path)?;
+
+    Ok(contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.split('#').next().unwrap_or_default().trim()))
+        .filter(|(_line_number, entry)| !entry.is_empty())
+        .map(|(line_number, entry)| (line_number, entry.to_string()))
+        .collect())
+}
+
+/// Read language codes from a file (see [read_list_file]).
+pub fn read_langs_file(path: &Path) -> Result<Vec<String>> {
+    let mut langs = Vec::new();
+    for (line_number, lang) in read_list_file(path)? {
+        if !is_lang_code(&lang) {
+            return Err(DgtError::InvalidLang(format!(
+                "{} (line {} of {})",
+                lang,
+                line_number,
+                path.display()
+            )));
+        }
+        langs.push(lang);
+    }
+
+    Ok(langs)
+}
+
+/// Read document names (CELEX numbers) from a file (see [read_list_file]),
+/// normalized with [normalize_doc_name].
+pub fn read_docs_file(path: &Path) -> Result<HashSet<String>> {
+    Ok(read_list_file(path)?
+        .into_iter()
+        .map(|(_line_number, name)| normalize_doc_name(&name, false))
+        .collect())
+}
+
+/// Read a list of SHA-256 checksums in the format of `sha256sum`
+/// (`<checksum>  <file name>`, or `<checksum> *<file name>` for files read in
+/// binary mode), keyed by file name. Empty lines and comments (starting with
+/// `#`) are ignored.
+pub fn read_checksums_file(path: &Path) -> Result<HashMap<String, String>> {
+    let mut checksums = HashMap::new();
+    for (line_number, entry) in read_list_file(path)? {
+        let invalid = || {
+            DgtError::InvalidChecksum(format!(
+                "{} (line {} of {})",
+                entry,
+                line_number,
+                path.display()
+            ))
+        };
+        let (checksum, name) = entry.split_once(char::is_whitespace).ok_or_else(invalid)?;
+        let name = name.trim_start();
+        let name = name.strip_prefix('*').unwrap_or(name);
+        if checksum.len() != 64 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        checksums.insert(
+            name.trim_start_matches("./").to_string(),
+            checksum.to_ascii_lowercase(),
+        );
+    }
+
+    Ok(checksums)
+}
+
+/// Compute the SHA-256 checksum of a file, as a lowercase hex string.
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+#[test]
+fn checksums_file_is_read() {
+    let tmp_dir = assert_fs::TempDir::new().unwrap();
+    let path = tmp_dir.path().join("SHA256SUMS");
+    let checksum = "bf2bce61698621ec1867c6425d2b4205074f586a285220443fedf4c391620605";
+    std::fs::write(
+        &path,
+        format!(
+            "{}  Vol_2019_1.zip\n{} *./Vol_2019_2.zip\n",
+            checksum,
+            checksum.to_uppercase()
+        ),
+    )
+    .unwrap();
+    let checksums = read_checksums_file(&path).unwrap();
+    assert_eq!(checksums.len(), 2);
+    assert_eq!(checksums["Vol_2019_1.zip"], checksum);
+    assert_eq!(checksums["Vol_2019_2.zip"], checksum);
+    assert_eq!(
+        sha256_file(Path::new("./test_data/zipped/1.zip")).unwrap(),
+        checksum
+    );
+
+    std::fs::write(&path, "1234  Vol_2019_1.zip\n").unwrap();
+    assert!(matches!(
+        read_checksums_file(&path),
+        Err(DgtError::InvalidChecksum(_))
+    ));
+}
+
+#[test]
+fn docs_file_is_read() {
+    let tmp_dir = assert_fs::TempDir::new().unwrap();
+    let path = tmp_dir.path().join("docs.txt");
+    std::fs::write(
+        &path,
+        "# Corrigenda\n32019r0557(01)\n\n 22019D0391 # misaligned\n",
+    )
+    .unwrap();
+    let docs = read_docs_file(&path).unwrap();
+    assert_eq!(docs.len(), 2);
+    assert!(docs.contains("32019R0557(01)"));
+    assert!(docs.contains("22019D0391"));
+}
+
+#[test]
+fn langs_file_is_read() {
+    let tmp_dir = assert_fs::TempDir::new().unwrap();
+    let path = tmp_dir.path().join("langs.txt");
+    std::fs::write(&path, "# Baltic languages\nlt\n\nLV-01 # Latvian\n  et\n").unwrap();
+    assert_eq!(read_langs_file(&path).unwrap(), vec!["lt", "LV-01", "et"]);
+
+    std::fs::write(&path, "en\nen fr\n").unwrap();
+    assert!(matches!(
+        read_langs_file(&path),
+        Err(DgtError::InvalidLang(lang)) if lang.starts_with("en fr (line 2")
+    ));
+}
+
+/// Fingerprint of the input directory: the SHA-256 of the relative path and
+/// size of every file in it. File contents are not read, so the hash is cheap
+/// to compute even for the full multi-gigabyte release.
+pub fn hash_input_dir(input_dir: &Path) -> Result<String> {
+    let mut files: Vec<(String, u64)> = Vec::new();
+    collect_files(input_dir, input_dir, &mut files)?;
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for (path, size) in files {
+        hasher.update(path.as_bytes());
+        hasher.update([0]);
+        hasher.update(size.to_le_bytes());
+    }
+
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Hash of the input (see [hash_input_dir]) and of the corpora merged with it
+/// (see `--merge-corpus`), by name. Without merged corpora, this is the hash
+/// of the input alone.
+pub fn hash_inputs(input_dir: &Path, merged_corpora: &[(&str, &Path)]) -> Result<String> {
+    let input_hash = hash_input_dir(input_dir)?;
+    if merged_corpora.is_empty() {
+        return Ok(input_hash);
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(input_hash.as_bytes());
+    for (name, input_dir) in merged_corpora {
+        hasher.update([0]);
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update(hash_input_dir(input_dir)?.as_bytes());
+    }
+
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Total size (in bytes) of the files in the input directory.
+pub fn input_size(input_dir: &Path) -> Result<u64> {
+    let mut files: Vec<(String, u64)> = Vec::new();
+    collect_files(input_dir, input_dir, &mut files)?;
+
+    Ok(files.iter().map(|(_path, size)| size).sum())
+}
+
+pub fn collect_files(root: &Path, path: &Path, files: &mut Vec<(String, u64)>) -> Result<()> {
+    if path.is_dir() {
+        for entry in std::fs::read_dir(path)?.flatten() {
+            collect_files(root, &entry.path(), files)?;
+        }
+    } else {
+        let relative_path = path.strip_prefix(root).unwrap_or(path);
+        let size = std::fs::metadata(path)?.len();
+        files.push((relative_path.display().to_string(), size));
+    }
+
+    Ok(())
+}
+
+/// Lowercase hexadecimal representation of a byte slice.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[test]
+fn input_hash_is_stable() {
+    let hash = hash_input_dir(Path::new("./test_data/zipped")).unwrap();
+    assert_eq!(hash.len(), 64);
+    assert_eq!(
+        hash,
+        hash_input_dir(Path::new("./test_data/zipped")).unwrap()
+    );
+    assert_ne!(
+        hash,
+        hash_input_dir(Path::new("./test_data/tar_gz")).unwrap()
+    );
+}
+
+/// Return the names of the documents in which a language has texts in fewer
+/// than `min_coverage` (a share) of the translation units of the document.
+/// The specified languages are expected in every document; otherwise only
+/// the languages found in a document are checked.
+pub fn low_coverage_docs(
+    translation_units: &[TranslationUnit],
+    langs: &RequestedLangs,
+    min_coverage: f64,
+) -> HashSet<String> {
+    // Number of translation units, and of those with a text in each
+    // language (keyed by the code in upper case), keyed by document name.
+    let mut docs: HashMap<&String, (u32, HashMap<String, u32>)> = HashMap::new();
+    for tu in translation_units {
+        let doc_name = match tu.doc_name() {
+            Some(doc_name) => doc_name,
+            None => continue,
+        };
+        let (units, texts) = docs.entry(doc_name).or_default();
+        *units += 1;
+        let found: HashSet<String> = tu
+            .segments
+            .iter()
+            .filter(|segment| !segment.content.is_empty())
+            .map(|segment| segment.lang.to_ascii_uppercase())
+            .collect();
+        for lang in found {
+            *texts.entry(lang).or_default() += 1;
+        }
+    }
+
+    docs.into_iter()
+        .filter(|(_doc_name, (units, texts))| {
+            let counts: Vec<u32> = match langs {
+                RequestedLangs::Unlimited => texts.values().copied().collect(),
+                RequestedLangs::Each(langs) | RequestedLangs::Some(langs) => langs
+                    .iter()
+                    .map(|lang| texts.get(&lang.to_ascii_uppercase()).copied().unwrap_or(0))
+                    .collect(),
+            };
+            let lowest = counts.into_iter().min().unwrap_or(0);
+            (lowest as f64) < *units as f64 * min_coverage
+        })
+        .map(|(doc_name, _)| doc_name.clone())
+        .collect()
+}
+
+#[test]
+fn documents_with_low_coverage_are_found() {
+    let tmx = parse_tmx(String::from(
+        r#"<tmx><header></header><body>
+        <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>1</seg></tuv><tuv lang="PL-01"><seg>1</seg></tuv></tu>
+        <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>2</seg></tuv><tuv lang="PL-01"><seg></seg></tuv></tu>
+        <tu><prop type="Txt::Doc. No.">B</prop><tuv lang="EN-GB"><seg>1</seg></tuv><tuv lang="PL-01"><seg>1</seg></tuv></tu>
+        <tu><prop type="Txt::Doc. No.">B</prop><tuv lang="EN-GB"><seg>2</seg></tuv><tuv lang="PL-01"><seg>2</seg></tuv></tu>
+        <tu><prop type="Txt::Doc. No.">C</prop><tuv lang="EN-GB"><seg>1</seg></tuv><tuv lang="pl-01"><seg>1</seg></tuv></tu>
+        <tu><prop type="Txt::Doc. No.">C</prop><tuv lang="en-gb"><seg>2</seg></tuv><tuv lang="PL-01"><seg>2</seg></tuv></tu>
+        </body></tmx>"#,
+    ))
+    .unwrap();
+    let units = tmx.body.translation_units;
+    let langs =
+        |langs: &[&str]| RequestedLangs::Some(langs.iter().map(|l| l.to_string()).collect());
+
+    assert_eq!(
+        low_coverage_docs(&units, &RequestedLangs::Unlimited, 0.9),
+        HashSet::from([String::from("A")])
+    );
+    assert!(low_coverage_docs(&units, &RequestedLangs::Unlimited, 0.5).is_empty());
+    assert!(low_coverage_docs(&units, &langs(&["EN-GB"]), 0.9).is_empty());
+    assert_eq!(
+        low_coverage_docs(&units, &langs(&["EN-GB", "DE-DE"]), 0.9).len(),
+        3
+    );
+    // Language codes are compared case-insensitively.
+    assert!(low_coverage_docs(&units, &langs(&["en-gb", "pl-01"]), 0.9).contains("A"));
+    assert!(!low_coverage_docs(&units, &langs(&["en-gb", "pl-01"]), 0.9).contains("C"));
+}
+
+/// Replace line breaks and tabs with spaces, so that a text can be written
+/// as a single line (or a single field of a TSV file).
+pub fn single_line(text: &str) -> String {
+    text.replace(['\n', '\r', '\t'], " ")
+}
+
+/// Prepare a text to be written as a field of a line-based output with
+/// fields separated by `separator`, following the [NewlinePolicy].
+pub fn text_field(text: &str, policy: NewlinePolicy, separator: char) -> String {
+    match policy {
+        NewlinePolicy::Replace => single_line(text),
+        NewlinePolicy::Escape => {
+            let mut escaped = String::with_capacity(text.len());
+            for c in text.chars() {
+                match c {
+                    '\\' => escaped.push_str("\\\\"),
+                    '\n' => escaped.push_str("\\n"),
+                    '\r' => escaped.push_str("\\r"),
+                    '\t' => escaped.push_str("\\t"),
+                    _ => escaped.push(c),
+                }
+            }
+            escaped
+        }
+        NewlinePolicy::Keep if text.contains([separator, '"', '\n', '\r']) => {
+            format!("\"{}\"", text.replace('"', "\"\""))
+        }
+        NewlinePolicy::Keep => text.to_string(),
+    }
+}
+
+#[test]
+fn text_fields_follow_newline_policy() {
+    let text = "Article 1\r\n\tScope \\ \"aid\"";
+    assert_eq!(
+        text_field(text, NewlinePolicy::Replace, '\t'),
+        "Article 1   Scope \\ \"aid\""
+    );
+    assert_eq!(
+        text_field(text, NewlinePolicy::Escape, '\t'),
+        "Article 1\\r\\n\\tScope \\\\ \"aid\""
+    );
+    assert_eq!(
+        text_field(text, NewlinePolicy::Keep, '\t'),
+        "\"Article 1\r\n\tScope \\ \"\"aid\"\"\""
+    );
+    assert_eq!(text_field("Scope", NewlinePolicy::Keep, '\t'), "Scope");
+}
+
+/// Normalize a document name, so that it can be matched with the CELEX
+/// numbers of external datasets: surrounding whitespace is removed and
+/// letters are uppercased. If `strip_suffix` is set, a trailing parenthetical
+/// suffix (e.g. the `(01)` of a corrigendum) is removed as well.
+pub fn normalize_doc_name(name: &str, strip_suffix: bool) -> String {
+    let mut name = name.trim().to_uppercase();
+    if strip_suffix && name.ends_with(')') {
+        if let Some(start) = name.rfind('(') {
+            name.truncate(start);
+            name = name.trim_end().to_string();
+        }
+    }
+    name
+}
+
+#[test]
+fn doc_names_are_normalized() {
+    assert_eq!(normalize_doc_name(" 32019r0557 ", false), "32019R0557");
+    assert_eq!(
+        normalize_doc_name("32019R0557(01)", false),
+        "32019R0557(01)"
+    );
+    assert_eq!(normalize_doc_name("32019R0557 (01)", true), "32019R0557");
+    assert_eq!(normalize_doc_name("32019R0557", true), "32019R0557");
+}
+
+/// Prefix of the EUR-Lex URLs of documents, followed by their CELEX numbers.
+/// `AUTO` shows the document in the language of the browser.
+pub const EUR_LEX_URL_PREFIX: &str = "https://eur-lex.europa.eu/legal-content/AUTO/?uri=CELEX:";
+
+/// The EUR-Lex URL of the document, if its name (normalized with
+/// [normalize_doc_name], without stripping the suffix) is a CELEX number,
+/// e.g. `32019R0557` or `22019A0315(01)`: a sector (a digit, `C` or `E`), a
+/// year, a descriptor of one or two letters and a number, optionally
+/// followed by a suffix.
+pub fn eur_lex_url(doc_name: &str) -> Option<String> {
+    let name = normalize_doc_name(doc_name, false);
+    let bytes = name.as_bytes();
+    if bytes.len() < 10
+        || !matches!(bytes[0], b'0'..=b'9' | b'C' | b'E')
+        || !bytes[1..5].iter().all(u8::is_ascii_digit)
+    {
+        return None;
+    }
+    let descriptor_len = bytes[5..]
+        .iter()
+        .take_while(|byte| byte.is_ascii_uppercase())
+        .count();
+    let number = &bytes[5 + descriptor_len..];
+    let is_celex = (1..=2).contains(&descriptor_len)
+        && number.len() >= 4
+        && number[..4].iter().all(u8::is_ascii_digit)
+        && number[4..]
+            .iter()
+            .all(|byte| byte.is_ascii_alphanumeric() || b"()-".contains(byte));
+    match is_celex {
+        true => Some(format!("{}{}", EUR_LEX_URL_PREFIX, name)),
+        false => None,
+    }
+}
+
+#[test]
+fn eur_lex_urls_are_generated_for_celex_numbers() {
+    assert_eq!(
+        eur_lex_url(" 32019r0557 ").unwrap(),
+        "https://eur-lex.europa.eu/legal-content/AUTO/?uri=CELEX:32019R0557"
+    );
+    assert_eq!(
+        eur_lex_url("22019A0315(01)").unwrap(),
+        "https://eur-lex.europa.eu/legal-content/AUTO/?uri=CELEX:22019A0315(01)"
+    );
+    assert!(eur_lex_url("52019PC0123").is_some());
+    assert!(eur_lex_url("MISSING").is_none());
+    assert!(eur_lex_url("32019R05").is_none());
+    assert!(eur_lex_url("32019ABC0557").is_none());
+    assert!(eur_lex_url("32019R0557 (01)").is_none());
+}
+
+/// Fold a text for accent- and case-insensitive search: letters are
+/// lowercased and stripped of diacritics (e.g. `Źródło` => `zrodlo`),
+/// including letters which do not decompose into a base letter and a
+/// combining mark (e.g. `ł` => `l`, `ß` => `ss`).
+pub fn fold_for_search(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.nfd().filter(|c| !is_combining_mark(*c)) {
+        for c in c.to_lowercase() {
+            match c {
+                'ł' => folded.push('l'),
+                'đ' => folded.push('d'),
+                'ø' => folded.push('o'),
+                'ħ' => folded.push('h'),
+                'ı' => folded.push('i'),
+                'ß' => folded.push_str("ss"),
+                'æ' => folded.push_str("ae"),
+                'œ' => folded.push_str("oe"),
+                _ => folded.push(c),
+            }
+        }
+    }
+    folded
+}
+
+#[test]
+fn texts_are_folded_for_search() {
+    assert_eq!(fold_for_search("Źródło"), "zrodlo");
+    assert_eq!(fold_for_search("Łódź"), "lodz");
+    assert_eq!(fold_for_search("ΠΑΡΆΡΤΗΜΑ"), "παραρτημα");
+    assert_eq!(fold_for_search("Straße"), "strasse");
+    assert_eq!(fold_for_search("Årsrapport"), "arsrapport");
+}
+
+/// Convert a TMX date (`YYYYMMDDThhmmssZ`, UTC) into the ISO 8601 extended
+/// format, e.g. `20190827T102232Z` => `2019-08-27T10:22:32Z`. Dates in any
+/// other format are returned unchanged.
+pub fn tmx_date_to_iso8601(date: &str) -> String {
+    let bytes = date.as_bytes();
+    let is_tmx_date = bytes.len() == 16
+        && bytes[8] == b'T'
+        && bytes[15] == b'Z'
+        && bytes[..8]
+            .iter()
+            .chain(&bytes[9..15])
+            .all(u8::is_ascii_digit);
+    match is_tmx_date {
+        true => format!(
+            "{}-{}-{}T{}:{}:{}Z",
+            &date[0..4],
+            &date[4..6],
+            &date[6..8],
+            &date[9..11],
+            &date[11..13],
+            &date[13..15]
+        ),
+        false => date.to_string(),
+    }
+}
+
+#[test]
+fn tmx_dates_are_converted() {
+    assert_eq!(
+        tmx_date_to_iso8601("20190827T102232Z"),
+        "2019-08-27T10:22:32Z"
+    );
+    assert_eq!(tmx_date_to_iso8601("2019-08-27"), "2019-08-27");
+}
+
+/// Parse a size in bytes, optionally followed by a `K`, `M` or `G` suffix
+/// (powers of 1024), e.g. `64M`.
+pub fn parse_byte_size(input: &str) -> Result<usize, String> {
+    let input = input.trim();
+    let (number, multiplier) = match input.to_ascii_uppercase().chars().last() {
+        Some('K') => (&input[..input.len() - 1], 1 << 10),
+        Some('M') => (&input[..input.len() - 1], 1 << 20),
+        Some('G') => (&input[..input.len() - 1], 1 << 30),
+        _ => (input, 1),
+    };
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size: {}", input))
+}
+
+#[test]
+fn byte_sizes_are_parsed() {
+    assert_eq!(parse_byte_size("512"), Ok(512));
+    assert_eq!(parse_byte_size("64k"), Ok(64 * 1024));
+    assert_eq!(parse_byte_size("2G"), Ok(2 * 1024 * 1024 * 1024));
+    assert!(parse_byte_size("M").is_err());
+    assert!(parse_byte_size(&format!("{}G", usize::MAX)).is_err());
+}
+
+/// Parse a `--pragma` setting given as `name=value`, e.g.
+/// `journal_mode=WAL`. The name must be a plain SQLite identifier; the value
+/// is passed to SQLite as a string literal.
+pub fn parse_pragma(input: &str) -> Result<(String, String), String> {
+    let invalid = || {
+        format!(
+            "invalid pragma: {} (expected e.g. `cache_size=-64000`)",
+            input
+        )
+    };
+    let (name, value) = input.split_once('=').ok_or_else(invalid)?;
+    let (name, value) = (name.trim(), value.trim());
+    let is_identifier = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    match is_identifier && !value.is_empty() {
+        true => Ok((name.to_string(), value.to_string())),
+        false => Err(invalid()),
+    }
+}
+
+#[test]
+fn pragmas_are_parsed() {
+    assert_eq!(
+        parse_pragma("journal_mode=WAL"),
+        Ok(("journal_mode".to_string(), "WAL".to_string()))
+    );
+    assert_eq!(
+        parse_pragma(" cache_size = -64000"),
+        Ok(("cache_size".to_string(), "-64000".to_string()))
+    );
+    assert!(parse_pragma("journal_mode").is_err());
+    assert!(parse_pragma("page_size=").is_err());
+    assert!(parse_pragma("x; DROP TABLE documents=1").is_err());
+}
+
+/// Parse a duration given as numbers of hours, minutes and seconds, each
+/// followed by its unit, e.g. `2h`, `90m` or `1h30m`.
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let invalid = || {
+        format!(
+            "invalid duration: {} (expected e.g. `2h` or `1h30m`)",
+            input
+        )
+    };
+    let mut seconds: u64 = 0;
+    let mut number = String::new();
+    for c in input.trim().chars() {
+        let unit = match c.to_ascii_lowercase() {
+            '0'..='9' => {
+                number.push(c);
+                continue;
+            }
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid()),
+        };
+        seconds = number
+            .parse::<u64>()
+            .ok()
+            .and_then(|value| value.checked_mul(unit))
+            .and_then(|value| value.checked_add(seconds))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+    match number.is_empty() && seconds > 0 {
+        true => Ok(Duration::from_secs(seconds)),
+        false => Err(invalid()),
+    }
+}
+
+#[test]
+fn durations_are_parsed() {
+    assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+    assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+    assert_eq!(parse_duration("45s"), Ok(Duration::from_secs(45)));
+    assert!(parse_duration("90").is_err());
+    assert!(parse_duration("h").is_err());
+    assert!(parse_duration("0m").is_err());
+    assert!(parse_duration("2 days").is_err());
+    assert!(parse_duration(&format!("{}h", u64::MAX)).is_err());
+}
+
+pub fn parse_glob(input: &str) -> Result<Pattern, String> {
+    Pattern::new(input).map_err(|error| error.to_string())
+}
+
+/// Parse a `--boilerplate-file` argument, `LANG=FILE`, e.g.
+/// `en=boilerplate_en.txt`. The language is coerced to its DGT code.
+pub fn parse_boilerplate_file(input: &str) -> Result<(String, PathBuf), String> {
+    let (lang, path) = input.split_once('=').ok_or_else(|| {
+        format!(
+            "expected LANG=FILE (e.g. en=boilerplate_en.txt), got: {}",
+            input
+        )
+    })?;
+    let lang = parse_lang(lang.trim())?;
+    match path.is_empty() {
+        true => Err(String::from("missing file")),
+        false => Ok((coerce_lang_code(&lang), PathBuf::from(path))),
+    }
+}
+
+#[test]
+fn boilerplate_files_are_parsed() {
+    assert_eq!(
+        parse_boilerplate_file("en=boiler_en.txt"),
+        Ok((String::from("EN-GB"), PathBuf::from("boiler_en.txt")))
+    );
+    assert!(parse_boilerplate_file("boiler_en.txt").is_err());
+    assert!(parse_boilerplate_file("en=").is_err());
+    assert!(parse_boilerplate_file("english=boiler_en.txt").is_err());
+}
+
+/// Parse a `--merge-corpus` argument, `NAME=INPUT`, e.g.
+/// `europarl=./europarl_tmx`. The input is parsed as `--input-dir`.
+pub fn parse_merge_corpus(input: &str) -> Result<(String, InputSource), String> {
+    let (name, source) = input.split_once('=').ok_or_else(|| {
+        format!(
+            "expected NAME=INPUT (e.g. europarl=./europarl_tmx), got: {}",
+            input
+        )
+    })?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(String::from("missing corpus name"));
+    }
+    if name.eq_ignore_ascii_case(MAIN_CORPUS) {
+        return Err(format!(
+            "{} is the name of the corpus of --input-dir",
+            MAIN_CORPUS
+        ));
+    }
+    match source.is_empty() {
+        true => Err(String::from("missing input")),
+        false => Ok((name.to_string(), InputSource::parse(source)?)),
+    }
+}
+
+#[test]
+fn merged_corpora_are_parsed() {
+    assert_eq!(
+        parse_merge_corpus("europarl=test_data/zipped"),
+        Ok((
+            String::from("europarl"),
+            InputSource::LocalDir(PathBuf::from("test_data/zipped"))
+        ))
+    );
+    assert!(parse_merge_corpus("test_data/zipped").is_err());
+    assert!(parse_merge_corpus("=test_data/zipped").is_err());
+    assert!(parse_merge_corpus("europarl=").is_err());
+    assert!(parse_merge_corpus("DGT=test_data/zipped").is_err());
+}
+
+/// Parse a regular expression specified on the command line, so that an
+/// invalid one is reported by clap along with the option it was given for.
+pub fn parse_regex(input: &str) -> Result<Regex, String> {
+    Regex::new(input).map_err(|error| error.to_string())
+}
+
+/// Parse a `--tee` target, `FORMAT:PATH`, e.g. `jsonl:-` (for the standard
+/// output) or `jsonl:units.jsonl`.
+pub fn parse_tee_target(input: &str) -> Result<TeeTarget, String> {
+    let (format, path) = input
+        .split_once(':')
+        .ok_or_else(|| format!("expected FORMAT:PATH (e.g. jsonl:-), got: {}", input))?;
+    let format = match format {
+        "jsonl" => TeeFormat::Jsonl,
+        _ => return Err(format!("unsupported format: {} (expected jsonl)", format)),
+    };
+    let path = match path {
+        "-" => None,
+        "" => return Err(String::from("missing path (use - for the standard output)")),
+        path => Some(PathBuf::from(path)),
+    };
+
+    Ok(TeeTarget { format, path })
+}
+
+/// Names of the columns of the tabular outputs which cannot be used by
+/// `--prop-column`, besides the language columns and the columns derived
+/// from them (see [LANG_COLUMN_SUFFIXES]).
+const RESERVED_COLUMNS: [&str; 16] = [
+    "id",
+    "document_id",
+    "sequential_number",
+    "texts",
+    "props",
+    "srclang",
+    "near_duplicate_cluster",
+    "is_uppercase",
+    "is_numeric",
+    "is_list_marker",
+    "alignment_score",
+    "is_boilerplate",
+    "index_in_file",
+    "corpus",
+    "name",
+    "raw_name",
+];
+
+/// Suffixes of the columns saved next to a language column, e.g.
+/// `en_gb_search` (see `--search-columns` and `--dates`).
+const LANG_COLUMN_SUFFIXES: [&str; 3] = ["_search", "_created", "_changed"];
+
+/// Parse a `--prop-column` mapping, `KEY=COLUMN[:TYPE]`, e.g.
+/// `Txt::Doc. Type=doc_type` or `Txt::Year=year:integer`. Column names are
+/// lowercase identifiers which are neither reserved nor language columns.
+pub fn parse_prop_column(input: &str) -> Result<PropColumn, String> {
+    let (key, column) = input.rsplit_once('=').ok_or_else(|| {
+        format!(
+            "expected KEY=COLUMN (e.g. \"Txt::Doc. Type=doc_type\"), got: {}",
+            input
+        )
+    })?;
+    let (column, column_type) = match column.split_once(':') {
+        None => (column, PropType::Text),
+        Some((column, "text")) => (column, PropType::Text),
+        Some((column, "integer")) => (column, PropType::Integer),
+        Some((_, column_type)) => {
+            return Err(format!(
+                "unsupported column type: {} (expected text or integer)",
+                column_type
+            ))
+        }
+    };
+    let is_identifier = column.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && column
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if key.trim().is_empty() || !is_identifier {
+        return Err(format!(
+            "expected a prop type and a lowercase column name, got: {}",
+            input
+        ));
+    }
+    let is_lang_column = |column: &str| lang_code_to_db_column(column).is_ok();
+    if RESERVED_COLUMNS.contains(&column)
+        || is_lang_column(column)
+        || LANG_COLUMN_SUFFIXES
+            .iter()
+            .any(|suffix| column.strip_suffix(suffix).is_some_and(is_lang_column))
+    {
+        return Err(format!("the column name {} is reserved", column));
+    }
+
+    Ok(PropColumn {
+        key: key.to_string(),
+        column: column.to_string(),
+        column_type,
+    })
+}
+
+#[test]
+fn prop_columns_are_parsed() {
+    assert_eq!(
+        parse_prop_column("Txt::Doc. Type=doc_type"),
+        Ok(PropColumn {
+            key: String::from("Txt::Doc. Type"),
+            column: String::from("doc_type"),
+            column_type: PropType::Text,
+        })
+    );
+    assert_eq!(
+        parse_prop_column("Txt::Year=year:integer")
+            .unwrap()
+            .column_type,
+        PropType::Integer
+    );
+    assert!(parse_prop_column("Txt::Year=year:date").is_err());
+    assert!(parse_prop_column("Txt::Year").is_err());
+    assert!(parse_prop_column("Txt::Year=Year").is_err());
+    assert!(parse_prop_column("Txt::Year=document_id").is_err());
+    assert!(parse_prop_column("Txt::Lang=en_gb").is_err());
+    assert!(parse_prop_column("Txt::Corpus=corpus").is_err());
+    assert!(parse_prop_column("Txt::Index=index_in_file").is_err());
+    assert!(parse_prop_column("Txt::Note=en_gb_search").is_err());
+    assert!(parse_prop_column("Txt::Date=pl_01_changed").is_err());
+    assert!(parse_prop_column("Txt::Date=date_changed").is_ok());
+}
+
+#[test]
+fn tee_targets_are_parsed() {
+    assert_eq!(
+        parse_tee_target("jsonl:-"),
+        Ok(TeeTarget {
+            format: TeeFormat::Jsonl,
+            path: None
+        })
+    );
+    assert_eq!(
+        parse_tee_target("jsonl:out/units.jsonl").unwrap().path,
+        Some(PathBuf::from("out/units.jsonl"))
+    );
+    assert!(parse_tee_target("csv:-").is_err());
+    assert!(parse_tee_target("jsonl").is_err());
+}
+
+/// Parse a similarity threshold, i.e. a number between 0 (exclusive) and 1.
+pub fn parse_threshold(input: &str) -> Result<f64, String> {
+    match input.trim().parse::<f64>() {
+        Ok(threshold) if threshold > 0.0 && threshold <= 1.0 => Ok(threshold),
+        _ => Err(format!("expected a number between 0 and 1, got: {}", input)),
+    }
+}
+
+/// Container formats recognized by the input layer.
+#[derive(Debug, PartialEq)]
+pub enum Container {
+    Zip,
+    Tar,
+    TarGz,
+    Tmx,
+    Unknown,
+}
+
+/// A TMX file found in the input, together with a reader over its raw
+/// (undecoded) contents.
+pub struct TmxEntry<'a> {
+    /// Path of the TMX file, prefixed with the path(s) of the archive(s) it
+    /// was found in, e.g. `Vol_2019_1.zip/22019D1360.tmx`.
+    pub name: String,
+    pub reader: &'a mut dyn Read,
+
+    /// Number of bytes read from the input files before this entry (an
+    /// approximation, since archives are not read strictly sequentially).
+    pub bytes_read: u64,
+
+    /// Set if the archive (or its entry) could not be read, in which case
+    /// the reader is empty, so that it is reported as a skipped file (see
+    /// `--fail-on`) rather than silently left out.
+    pub error: Option<DgtError>,
+}
+
+/// Determine the container format from the file name and its leading bytes
+/// (at least 262 bytes are needed to recognize a tar archive).
+pub fn detect_container(name: &str, header: &[u8]) -> Container {
+    if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+        Container::Zip
+    } else if header.starts_with(&[0x1f, 0x8b]) {
+        Container::TarGz
+    } else if header.len() >= 262 && &header[257..262] == b"ustar" {
+        Container::Tar
+    } else if is_tmx_name(name) {
+        Container::Tmx
+    } else if name == STDIN_NAME {
+        // The standard input has no file name to tell a TMX file by.
+        Container::Tmx
+    } else {
+        Container::Unknown
+    }
+}
+
+/// State of a walk over the input, see [for_each_tmx_file].
+#[derive(Default)]
+pub struct Walk {
+    /// Only the TMX entries in archives whose path within the archive
+    /// matches the pattern are visited (see `--tmx-glob`). Plain TMX files in
+    /// the input directory are always visited.
+    pattern: Option<Pattern>,
+
+    /// Number of TMX entries which did not match the pattern.
+    pub excluded: u32,
+
+    /// The TMX entries are only listed, not read (e.g. to count them), so
+    /// that nested archives are descended into as they are read, without
+    /// being copied into temporary files.
+    pub list_only: bool,
+
+    /// Number of bytes read from the input files walked so far, not
+    /// counting the current one.
+    bytes_read: u64,
+
+    /// Bytes read from the input file being walked, with the count when the
+    /// walk entered it (it keeps growing for archives reused from the pool).
+    file_bytes_read: Option<(Arc<AtomicU64>, u64)>,
+
+    /// ZIP archives kept open between walks over the same input.
+    archives: Option<Arc<Mutex<ArchivePool>>>,
+}
+
+impl Walk {
+    pub fn new(pattern: Option<Pattern>) -> Walk {
+        Walk {
+            pattern,
+            ..Default::default()
+        }
+    }
+
+    /// Check the path of a TMX entry within its archive against the pattern,
+    /// counting the excluded entries.
+    fn accepts(&mut self, entry_name: &str) -> bool {
+        match &self.pattern {
+            Some(pattern) if !pattern.matches(entry_name) => {
+                self.excluded += 1;
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Walk over the input through a pool of open archives, see
+    /// [ArchivePool].
+    pub fn use_archives(&mut self, archives: Arc<Mutex<ArchivePool>>) {
+        self.archives = Some(archives);
+    }
+
+    /// Number of bytes read from the input files so far.
+    fn bytes_read(&self) -> u64 {
+        let file_bytes_read = match &self.file_bytes_read {
+            Some((count, start)) => count.load(Ordering::Relaxed) - start,
+            None => 0,
+        };
+        self.bytes_read + file_bytes_read
+    }
+}
+
+/// Default of `--max-open-files` for the input archives.
+pub const DEFAULT_MAX_OPEN_ARCHIVES: usize = 16;
+
+/// ZIP archives of an input kept open between walks over it (e.g. counting
+/// the TMX files for `--progress files`, then processing them), so that
+/// their directories of entries are read once. At most `max_open` archives
+/// are kept open, the least recently used one being closed first.
+pub struct ArchivePool {
+    max_open: usize,
+
+    /// Ordered from the least to the most recently used.
+    archives: Vec<PooledArchive>,
+}
+
+struct PooledArchive {
+    path: PathBuf,
+    archive: ZipArchive<BufReader<CountingReader<File>>>,
+
+    /// Bytes read from the archive since it was opened.
+    bytes_read: Arc<AtomicU64>,
+}
+
+impl ArchivePool {
+    pub fn new(max_open: usize) -> ArchivePool {
+        ArchivePool {
+            max_open,
+            archives: Vec::new(),
+        }
+    }
+
+    /// Take an open archive out of the pool while it is walked.
+    fn take(&mut self, path: &Path) -> Option<PooledArchive> {
+        let position = self
+            .archives
+            .iter()
+            .position(|pooled| pooled.path == path)?;
+        Some(self.archives.remove(position))
+    }
+
+    /// Return an archive to the pool once it has been walked, closing the
+    /// least recently used ones over the limit.
+    fn put(&mut self, pooled: PooledArchive) {
+        self.archives.push(pooled);
+        if self.archives.len() > self.max_open {
+            self.archives.drain(..self.archives.len() - self.max_open);
+        }
+    }
+}
+
+/// Counts the bytes read from an input file, so that progress can be
+/// reported without knowing the number of TMX files up front.
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let bytes = self.inner.read(buf)?;
+        self.count.fetch_add(bytes as u64, Ordering::Relaxed);
+        Ok(bytes)
+    }
+}
+
+impl<R: Seek> Seek for CountingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Whether a file or an archive entry is a TMX file, judging by its name.
+fn is_tmx_name(name: &str) -> bool {
+    name.to_ascii_lowercase().ends_with(".tmx")
+}
+
+/// Whether an archive entry with the given name should be descended into.
+fn is_nested_container(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    [".zip", ".tar", ".tar.gz", ".tgz"]
+        .iter()
+        .any(|extension| name.ends_with(extension))
+}
+
+/// Perform an operation on every TMX file in the input directory.
+///
+/// The directory may contain (in any combination) ZIP archives, tar and
+/// tar.gz archives, plain TMX files and subdirectories. Archives nested in
+/// other archives (e.g. ZIP files inside a tar.gz) are descended into as
+/// well. Files of any other format are skipped, as are the TMX entries in
+/// archives which do not match the pattern of the walk.
+pub fn for_each_tmx_file<F>(input_dir: &Path, walk: &mut Walk, callback: &mut F) -> Result<()>
+where
+    F: FnMut(TmxEntry) -> Result<()>,
+{
+    visit_path(input_dir, walk, callback)
+}
+
+fn visit_path<F>(path: &Path, walk: &mut Walk, callback: &mut F) -> Result<()>
+where
+    F: FnMut(TmxEntry) -> Result<()>,
+{
+    if path.is_dir() {
+        // Sorted so that the output does not depend on the file system’s
+        // directory order.
+        let mut children: Vec<PathBuf> = std::fs::read_dir(path)?
+            .flatten()
+            .map(|entry| entry.path())
+            .collect();
+        children.sort();
+        for child in children {
+            visit_path(&child, walk, callback)?;
+        }
+    } else {
+        for_each_tmx_file_in(&path.display().to_string(), path, walk, callback)?;
+    }
+
+    Ok(())
+}
+
+/// Perform an operation on every TMX file in a single file (an archive or a
+/// TMX file), reported under the given name.
+pub fn for_each_tmx_file_in<F>(
+    name: &str,
+    path: &Path,
+    walk: &mut Walk,
+    callback: &mut F,
+) -> Result<()>
+where
+    F: FnMut(TmxEntry) -> Result<()>,
+{
+    let archives = walk.archives.clone();
+    let pooled = archives
+        .as_ref()
+        .and_then(|archives| archives.lock().unwrap().take(path));
+    let bytes_read = match &pooled {
+        Some(pooled) => pooled.bytes_read.clone(),
+        None => Arc::new(AtomicU64::new(0)),
+    };
+    walk.file_bytes_read = Some((bytes_read.clone(), bytes_read.load(Ordering::Relaxed)));
+
+    let walked = match pooled {
+        Some(mut pooled) => for_each_tmx_file_in_zip(&mut pooled.archive, name, walk, callback)
+            .map(|()| Some(pooled)),
+        None => visit_file(name, path, bytes_read, archives.is_some(), walk, callback),
+    };
+
+    walk.bytes_read = walk.bytes_read();
+    walk.file_bytes_read = None;
+    if let (Some(archives), Some(pooled)) = (&archives, walked?) {
+        archives.lock().unwrap().put(pooled);
+    }
+
+    Ok(())
+}
+
+/// Open a file of the input and dispatch it to the matching handler. A ZIP
+/// archive is returned once walked, to be kept open in the pool.
+fn visit_file<F>(
+    name: &str,
+    path: &Path,
+    bytes_read: Arc<AtomicU64>,
+    pooled: bool,
+    walk: &mut Walk,
+    callback: &mut F,
+) -> Result<Option<PooledArchive>>
+where
+    F: FnMut(TmxEntry) -> Result<()>,
+{
+    let mut reader = BufReader::new(CountingReader {
+        inner: File::open(path)?,
+        count: bytes_read.clone(),
+    });
+    let is_zip = detect_container(name, &read_header(&mut reader)?) == Container::Zip;
+    if !(pooled && is_zip) {
+        visit_container(name, reader, walk, callback)?;
+        return Ok(None);
+    }
+    match ZipArchive::new(reader) {
+        Ok(mut archive) => {
+            for_each_tmx_file_in_zip(&mut archive, name, walk, callback)?;
+            Ok(Some(PooledArchive {
+                path: path.to_path_buf(),
+                archive,
+                bytes_read,
+            }))
+        }
+        Err(error) => {
+            report_unreadable(name, error.into(), walk, callback)?;
+            Ok(None)
+        }
+    }
+}
+
+/// Read the leading bytes of a file (see [detect_container]), leaving the
+/// reader at the start.
+fn read_header<R: Read + Seek>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(512);
+    reader.take(512).read_to_end(&mut header)?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    Ok(header)
+}
+
+/// Detect the format of a file and dispatch it to the matching handler.
+fn visit_container<R, F>(name: &str, mut reader: R, walk: &mut Walk, callback: &mut F) -> Result<()>
+where
+    R: Read + Seek,
+    F: FnMut(TmxEntry) -> Result<()>,
+{
+    let header = read_header(&mut reader)?;
+    match detect_container(name, &header) {
+        Container::Zip => match ZipArchive::new(reader) {
+            Ok(mut zip_archive) => {
+                for_each_tmx_file_in_zip(&mut zip_archive, name, walk, callback)?
+            }
+            Err(error) => report_unreadable(name, error.into(), walk, callback)?,
+        },
+        Container::TarGz => for_each_tmx_file_in_tar(GzDecoder::new(reader), name, walk, callback)?,
+        Container::Tar => for_each_tmx_file_in_tar(reader, name, walk, callback)?,
+        Container::Tmx => callback(TmxEntry {
+            name: name.to_string(),
+            reader: &mut reader,
+            bytes_read: walk.bytes_read(),
+            error: None,
+        })?,
+        Container::Unknown => {}
+    }
+
+    Ok(())
+}
+
+/// Pass an archive (or an entry of one) which could not be read to the
+/// callback, see [TmxEntry::error].
+fn report_unreadable<F>(name: &str, error: DgtError, walk: &Walk, callback: &mut F) -> Result<()>
+where
+    F: FnMut(TmxEntry) -> Result<()>,
+{
+    callback(TmxEntry {
+        name: name.to_string(),
+        reader: &mut std::io::empty(),
+        bytes_read: walk.bytes_read(),
+        error: Some(error),
+    })
+}
+
+/// Nested archives are copied into a temporary file, since descending into
+/// them requires random access, and they may be too large to be held in
+/// memory.
+fn visit_nested_container<R, F>(
+    name: String,
+    entry: &mut R,
+    walk: &mut Walk,
+    callback: &mut F,
+) -> Result<()>
 where
-    F: FnMut(ZipArchive<BufReader<File>>) -> Result<()>,
+    R: Read,
+    F: FnMut(TmxEntry) -> Result<()>,
 {
-    let zip_files = std::fs::read_dir(input_dir)?;
-    for zip_file in zip_files {
-        if let Ok(zip_file) = zip_file {
-            let f = File::open(zip_file.path())?;
-            let reader = BufReader::new(f);
-            let zip_archive = zip::ZipArchive::new(reader);
-            if let Ok(zip_archive) = zip_archive {
-                callback(zip_archive)?;
+    if walk.list_only {
+        return list_nested_container(name, entry, walk, callback);
+    }
+    let spooled = SpooledEntry::new(entry)?;
+    let reader = BufReader::new(File::open(spooled.path())?);
+    visit_container(&name, reader, walk, callback)
+}
+
+/// Descend into a nested archive as it is read, which is enough to list its
+/// entries. A ZIP archive is read through the local headers of its entries,
+/// so the entries whose length is only known from the central directory
+/// (and the rest of the archive) are not listed.
+fn list_nested_container<F>(
+    name: String,
+    entry: &mut dyn Read,
+    walk: &mut Walk,
+    callback: &mut F,
+) -> Result<()>
+where
+    F: FnMut(TmxEntry) -> Result<()>,
+{
+    let mut header = Vec::with_capacity(512);
+    entry.take(512).read_to_end(&mut header)?;
+    let container = detect_container(&name, &header);
+    let mut reader = Cursor::new(header).chain(entry);
+    match container {
+        Container::Zip => {
+            while let Ok(Some(mut file)) = zip::read::read_zipfile_from_stream(&mut reader) {
+                visit_zip_entry(&mut file, &name, walk, callback)?;
             }
         }
+        Container::TarGz => {
+            for_each_tmx_file_in_tar(GzDecoder::new(reader), &name, walk, callback)?
+        }
+        Container::Tar => for_each_tmx_file_in_tar(reader, &name, walk, callback)?,
+        Container::Tmx | Container::Unknown => {}
     }
+
     Ok(())
 }
 
+/// Temporary file holding a copy of a nested archive (or of the standard
+/// input), removed when dropped.
+pub struct SpooledEntry {
+    file: NamedTempFile,
+}
+
+impl SpooledEntry {
+    /// Copy the contents of the reader into a new temporary file.
+    pub fn new<R: Read + ?Sized>(reader: &mut R) -> Result<SpooledEntry> {
+        let mut file = tempfile::Builder::new()
+            .prefix("dgt_parser-")
+            .suffix(".nested")
+            .tempfile()?;
+        std::io::copy(reader, &mut file)?;
+        Ok(SpooledEntry { file })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+}
+
 /// Perform an operation on every TMX file in a ZIP archive.
-pub fn for_each_tmx_file_in_zip<F>(
-    zip_archive: &mut ZipArchive<BufReader<File>>,
+pub fn for_each_tmx_file_in_zip<R, F>(
+    zip_archive: &mut ZipArchive<R>,
+    archive_name: &str,
+    walk: &mut Walk,
     callback: &mut F,
 ) -> Result<()>
 where
-    F: FnMut(ZipFile) -> Result<()>,
+    R: Read + Seek,
+    F: FnMut(TmxEntry) -> Result<()>,
 {
     for i in 0..zip_archive.len() {
-        if let Ok(file) = zip_archive.by_index(i) {
-            if file.name().ends_with(".tmx") {
-                callback(file)?;
+        match zip_archive.by_index(i) {
+            Ok(mut file) => visit_zip_entry(&mut file, archive_name, walk, callback)?,
+            Err(error) => {
+                let name = format!("{} (entry {})", archive_name, i);
+                report_unreadable(&name, error.into(), walk, callback)?;
             }
         }
     }
 
     Ok(())
 }
+
+/// Perform an operation on an entry of a ZIP archive, if it is a TMX file,
+/// or on every TMX file in it, if it is a nested archive.
+fn visit_zip_entry<F>(
+    file: &mut zip::read::ZipFile,
+    archive_name: &str,
+    walk: &mut Walk,
+    callback: &mut F,
+) -> Result<()>
+where
+    F: FnMut(TmxEntry) -> Result<()>,
+{
+    let entry_name = file.name().to_string();
+    let name = format!("{}/{}", archive_name, entry_name);
+    if is_tmx_name(&entry_name) {
+        if walk.accepts(&entry_name) {
+            callback(TmxEntry {
+                name,
+                reader: file,
+                bytes_read: walk.bytes_read(),
+                error: None,
+            })?;
+        }
+    } else if is_nested_container(&entry_name) {
+        visit_nested_container(name, file, walk, callback)?;
+    }
+
+    Ok(())
+}
+
+/// Perform an operation on every TMX file in a (decompressed) tar archive.
+fn for_each_tmx_file_in_tar<R, F>(
+    reader: R,
+    archive_name: &str,
+    walk: &mut Walk,
+    callback: &mut F,
+) -> Result<()>
+where
+    R: Read,
+    F: FnMut(TmxEntry) -> Result<()>,
+{
+    let mut tar_archive = tar::Archive::new(reader);
+    for entry in tar_archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let entry_name = entry.path()?.display().to_string();
+        let name = format!("{}/{}", archive_name, entry_name);
+        if is_tmx_name(&entry_name) {
+            if !walk.accepts(&entry_name) {
+                continue;
+            }
+            callback(TmxEntry {
+                name,
+                reader: &mut entry,
+                bytes_read: walk.bytes_read(),
+                error: None,
+            })?;
+        } else if is_nested_container(&entry_name) {
+            visit_nested_container(name, &mut entry, walk, callback)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn tmx_entries_are_filtered_by_path_in_archive() {
+    let mut walk = Walk::new(Some(Pattern::new("*2019D055?.tmx").unwrap()));
+    let mut names: Vec<String> = Vec::new();
+    for_each_tmx_file(
+        Path::new("./test_data/zipped"),
+        &mut walk,
+        &mut |tmx_file| {
+            names.push(tmx_file.name);
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    assert_eq!(names.len(), 4);
+    assert!(names.iter().all(|name| name.contains("2019D055")));
+    assert_eq!(walk.excluded, 3);
+}
+
+#[test]
+fn archives_are_reused_until_least_recently_used() {
+    let input_dir = Path::new("./test_data/zipped");
+    let pooled_paths = |archives: &Arc<Mutex<ArchivePool>>| -> Vec<PathBuf> {
+        let archives = archives.lock().unwrap();
+        archives
+            .archives
+            .iter()
+            .map(|pooled| pooled.path.clone())
+            .collect()
+    };
+    let walk_names = |path: &Path, archives: &Arc<Mutex<ArchivePool>>| -> Vec<String> {
+        let mut walk = Walk::default();
+        walk.use_archives(archives.clone());
+        let mut names = Vec::new();
+        for_each_tmx_file(path, &mut walk, &mut |tmx_file| {
+            assert!(tmx_file.bytes_read <= input_size(input_dir).unwrap());
+            names.push(tmx_file.name);
+            Ok(())
+        })
+        .unwrap();
+        names
+    };
+
+    // Only the last archive stays open, and is reused by the next walk over
+    // it.
+    let archives = Arc::new(Mutex::new(ArchivePool::new(1)));
+    let names = walk_names(input_dir, &archives);
+    assert_eq!(pooled_paths(&archives), vec![input_dir.join("2.zip")]);
+    let bytes_read = archives.lock().unwrap().archives[0].bytes_read.clone();
+    let last_names = walk_names(&input_dir.join("2.zip"), &archives);
+    assert!(names.ends_with(&last_names));
+    assert!(Arc::ptr_eq(
+        &archives.lock().unwrap().archives[0].bytes_read,
+        &bytes_read
+    ));
+
+    // Walking an archive makes it the most recently used.
+    let archives = Arc::new(Mutex::new(ArchivePool::new(2)));
+    walk_names(input_dir, &archives);
+    walk_names(&input_dir.join("1.zip"), &archives);
+    assert_eq!(
+        pooled_paths(&archives),
+        vec![input_dir.join("2.zip"), input_dir.join("1.zip")]
+    );
+}
+
+#[test]
+fn bytes_read_grow_up_to_input_size() {
+    let input_dir = Path::new("./test_data/zipped");
+    let mut bytes_read: Vec<u64> = Vec::new();
+    for_each_tmx_file(input_dir, &mut Walk::default(), &mut |tmx_file| {
+        bytes_read.push(tmx_file.bytes_read);
+        Ok(())
+    })
+    .unwrap();
+
+    assert!(bytes_read.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert!(*bytes_read.last().unwrap() <= input_size(input_dir).unwrap());
+}
+
+#[test]
+fn container_is_detected_from_leading_bytes() {
+    assert_eq!(detect_container("1.zip", b"PK\x03\x04..."), Container::Zip);
+    assert_eq!(
+        detect_container("1.tgz", &[0x1f, 0x8b, 0x08]),
+        Container::TarGz
+    );
+    assert_eq!(detect_container("1.TMX", b"\xff\xfe<\0"), Container::Tmx);
+    assert_eq!(detect_container("README", b"Hello"), Container::Unknown);
+    assert_eq!(detect_container("-", b"\xff\xfe<\0"), Container::Tmx);
+
+    let mut tar_header = vec![0; 512];
+    tar_header[257..262].copy_from_slice(b"ustar");
+    assert_eq!(detect_container("1.tar", &tar_header), Container::Tar);
+}
+
+#[test]
+fn zip64_entries_in_nested_archives_are_read() {
+    use std::io::Write;
+    use zip::write::{FileOptions, ZipWriter};
+
+    let tmx = std::fs::read("./test_data/22019A0315(01).tmx").unwrap();
+    // ZIP64 extra fields are written for entries marked as large files.
+    let mut inner = ZipWriter::new(Cursor::new(Vec::new()));
+    inner
+        .start_file(
+            "vol/22019A0315(01).tmx",
+            FileOptions::default().large_file(true),
+        )
+        .unwrap();
+    inner.write_all(&tmx).unwrap();
+    let inner = inner.finish().unwrap().into_inner();
+    let mut outer = ZipWriter::new(Cursor::new(Vec::new()));
+    outer
+        .start_file("Vol_2019_1.zip", FileOptions::default())
+        .unwrap();
+    outer.write_all(&inner).unwrap();
+    let outer = Cursor::new(outer.finish().unwrap().into_inner());
+
+    let mut names: Vec<String> = Vec::new();
+    let mut sizes: Vec<usize> = Vec::new();
+    visit_container(
+        "release.zip",
+        outer,
+        &mut Walk::default(),
+        &mut |tmx_file| {
+            names.push(tmx_file.name);
+            sizes.push(std::io::copy(tmx_file.reader, &mut std::io::sink())? as usize);
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        names,
+        vec!["release.zip/Vol_2019_1.zip/vol/22019A0315(01).tmx"]
+    );
+    assert_eq!(sizes, vec![tmx.len()]);
+}
+
+#[test]
+fn nested_archives_are_listed_as_read() {
+    use std::io::Write;
+    use zip::write::{FileOptions, ZipWriter};
+
+    let mut inner = ZipWriter::new(Cursor::new(Vec::new()));
+    for name in ["vol/A.tmx", "vol/B.TMX", "vol/README"] {
+        inner.start_file(name, FileOptions::default()).unwrap();
+        inner.write_all(b"<tmx/>").unwrap();
+    }
+    let inner = inner.finish().unwrap().into_inner();
+    let mut outer = ZipWriter::new(Cursor::new(Vec::new()));
+    outer
+        .start_file("Vol_2019_1.zip", FileOptions::default())
+        .unwrap();
+    outer.write_all(&inner).unwrap();
+    let outer = outer.finish().unwrap().into_inner();
+
+    let names = |list_only: bool| -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        let mut walk = Walk {
+            list_only,
+            ..Default::default()
+        };
+        visit_container(
+            "release.zip",
+            Cursor::new(outer.clone()),
+            &mut walk,
+            &mut |tmx_file| {
+                names.push(tmx_file.name);
+                Ok(())
+            },
+        )
+        .unwrap();
+        names
+    };
+
+    let expected = vec![
+        "release.zip/Vol_2019_1.zip/vol/A.tmx",
+        "release.zip/Vol_2019_1.zip/vol/B.TMX",
+    ];
+    assert_eq!(names(false), expected);
+    assert_eq!(names(true), expected);
+}
@@ -0,0 +1,213 @@
+use crate::error::Result;
+
+use crate::tmx_parser::TranslationUnit;
+use crate::types::{RunInfo, Summary, TranslationUnitHandler};
+
+/// Combinators for assembling processing pipelines from existing handlers,
+/// e.g. `handler.filtered(|tu| ...).mapped(|tu| ...)`. The wrapped handler
+/// still does the batching and output; the adapters only decide which
+/// translation units reach it, and in what form.
+pub trait HandlerExt: TranslationUnitHandler + Sized {
+    /// Only pass on the translation units for which `predicate` returns
+    /// `true`.
+    fn filtered<P>(self, predicate: P) -> Filtered<Self, P>
+    where
+        P: FnMut(&TranslationUnit) -> bool,
+    {
+        Filtered {
+            inner: self,
+            predicate,
+            rejected: 0,
+        }
+    }
+
+    /// Pass on each translation unit transformed by `map`, e.g. with
+    /// additional annotations.
+    fn mapped<F>(self, map: F) -> Mapped<Self, F>
+    where
+        F: FnMut(TranslationUnit) -> TranslationUnit,
+    {
+        Mapped { inner: self, map }
+    }
+
+    /// Also pass each translation unit to another handler, e.g. to stream
+    /// the units while the main output is being built.
+    fn tee<T>(self, other: T) -> Tee<Self, T>
+    where
+        T: TranslationUnitHandler,
+    {
+        Tee { inner: self, other }
+    }
+}
+
+impl<H: TranslationUnitHandler> HandlerExt for H {}
+
+pub struct Filtered<H, P> {
+    inner: H,
+    predicate: P,
+
+    /// Number of translation units rejected by the predicate. They are not
+    /// counted as handled in the summary.
+    rejected: u64,
+}
+
+impl<H, P> TranslationUnitHandler for Filtered<H, P>
+where
+    H: TranslationUnitHandler,
+    P: FnMut(&TranslationUnit) -> bool,
+{
+    fn start(&mut self, run: &RunInfo) -> Result<()> {
+        self.inner.start(run)
+    }
+
+    fn declare_langs(&mut self, langs: &[String]) -> Result<()> {
+        self.inner.declare_langs(langs)
+    }
+
+    fn handle(
+        &mut self,
+        translation_unit: TranslationUnit,
+        sequential_number_in_doc: u32,
+    ) -> Result<()> {
+        match (self.predicate)(&translation_unit) {
+            true => self
+                .inner
+                .handle(translation_unit, sequential_number_in_doc),
+            false => {
+                self.rejected += 1;
+                Ok(())
+            }
+        }
+    }
+
+    fn finish(&mut self, summary: &mut Summary) -> Result<()> {
+        summary.translation_units_handled = summary
+            .translation_units_handled
+            .saturating_sub(self.rejected);
+        self.inner.finish(summary)
+    }
+}
+
+pub struct Mapped<H, F> {
+    inner: H,
+    map: F,
+}
+
+impl<H, F> TranslationUnitHandler for Mapped<H, F>
+where
+    H: TranslationUnitHandler,
+    F: FnMut(TranslationUnit) -> TranslationUnit,
+{
+    fn start(&mut self, run: &RunInfo) -> Result<()> {
+        self.inner.start(run)
+    }
+
+    fn declare_langs(&mut self, langs: &[String]) -> Result<()> {
+        self.inner.declare_langs(langs)
+    }
+
+    fn handle(
+        &mut self,
+        translation_unit: TranslationUnit,
+        sequential_number_in_doc: u32,
+    ) -> Result<()> {
+        self.inner
+            .handle((self.map)(translation_unit), sequential_number_in_doc)
+    }
+
+    fn finish(&mut self, summary: &mut Summary) -> Result<()> {
+        self.inner.finish(summary)
+    }
+}
+
+pub struct Tee<H, T> {
+    inner: H,
+    other: T,
+}
+
+impl<H, T> TranslationUnitHandler for Tee<H, T>
+where
+    H: TranslationUnitHandler,
+    T: TranslationUnitHandler,
+{
+    fn start(&mut self, run: &RunInfo) -> Result<()> {
+        self.other.start(run)?;
+        self.inner.start(run)
+    }
+
+    fn declare_langs(&mut self, langs: &[String]) -> Result<()> {
+        self.other.declare_langs(langs)?;
+        self.inner.declare_langs(langs)
+    }
+
+    fn handle(
+        &mut self,
+        translation_unit: TranslationUnit,
+        sequential_number_in_doc: u32,
+    ) -> Result<()> {
+        self.other
+            .handle(translation_unit.clone(), sequential_number_in_doc)?;
+        self.inner
+            .handle(translation_unit, sequential_number_in_doc)
+    }
+
+    fn finish(&mut self, summary: &mut Summary) -> Result<()> {
+        self.other.finish(summary)?;
+        self.inner.finish(summary)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HandlerExt;
+    use crate::error::Result;
+    use crate::tmx_parser::{parse_tmx, TranslationUnit};
+    use crate::types::{Summary, TranslationUnitHandler};
+
+    /// Collects the document names of the handled translation units.
+    #[derive(Default)]
+    struct Collect(Vec<String>);
+
+    impl TranslationUnitHandler for &mut Collect {
+        fn handle(&mut self, translation_unit: TranslationUnit, _seq: u32) -> Result<()> {
+            self.0.push(translation_unit.doc_name().unwrap().clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn adapters_filter_and_map_translation_units() -> Result<()> {
+        let tmx = parse_tmx(String::from(
+            r#"<tmx><header></header><body>
+            <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>1</seg></tuv></tu>
+            <tu><prop type="Txt::Doc. No.">B</prop><tuv lang="PL-01"><seg>2</seg></tuv></tu>
+            <tu><prop type="Txt::Doc. No.">C</prop><tuv lang="EN-GB"><seg>3</seg></tuv></tu>
+            </body></tmx>"#,
+        ))?;
+        let mut collect = Collect::default();
+        let mut handler = (&mut collect)
+            .mapped(|mut tu| {
+                tu.props[0].value.push('!');
+                tu
+            })
+            .filtered(|tu| tu.segments[0].lang == "EN-GB");
+        let mut summary = Summary {
+            translation_units_handled: 3,
+            ..Default::default()
+        };
+        for (i, tu) in tmx.body.translation_units.into_iter().enumerate() {
+            handler.handle(tu, i as u32)?;
+        }
+        handler.finish(&mut summary)?;
+        assert_eq!(summary.translation_units_handled, 2);
+
+        // The rejected units are not subtracted below zero.
+        let mut summary = Summary::default();
+        handler.finish(&mut summary)?;
+        assert_eq!(summary.translation_units_handled, 0);
+
+        assert_eq!(collect.0, vec!["A!", "C!"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+use crate::error::Result;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::functions::{short_lang_name, text_field};
+use crate::tmx_parser::{LangMatch, TranslationUnit};
+use crate::types::{NewlinePolicy, RunInfo, Summary, TranslationUnitHandler};
+
+/// Format of the parallel corpora.
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum PairFormat {
+    /// Two files with one text per line, e.g. `corpus.en` and `corpus.fr`.
+    Moses,
+
+    /// A single `corpus.tsv` file with a pair of texts per line.
+    Tsv,
+}
+
+enum PairFiles {
+    Moses(PathBuf, PathBuf),
+    Tsv(PathBuf),
+}
+
+/// A parallel corpus of two languages.
+struct Pair {
+    langs: (String, String),
+    files: PairFiles,
+}
+
+/// Keeps at most `max_open` output files open, closing the least recently
+/// used one when another file is needed. Files are created empty in
+/// [Handler::start] and reopened in append mode.
+struct WriterPool {
+    max_open: Option<usize>,
+    writers: HashMap<PathBuf, BufWriter<File>>,
+
+    /// Paths of the open files, from the least to the most recently used.
+    used: VecDeque<PathBuf>,
+}
+
+impl WriterPool {
+    fn get(&mut self, path: &PathBuf) -> Result<&mut BufWriter<File>> {
+        if self.writers.contains_key(path) {
+            if let Some(position) = self.used.iter().position(|used| used == path) {
+                let path = self.used.remove(position).unwrap();
+                self.used.push_back(path);
+            }
+        } else {
+            if let Some(max_open) = self.max_open {
+                while self.writers.len() >= max_open.max(1) {
+                    let least_used = self.used.pop_front().unwrap();
+                    self.writers.remove(&least_used).unwrap().flush()?;
+                }
+            }
+            let file = OpenOptions::new().append(true).open(path)?;
+            self.writers.insert(path.clone(), BufWriter::new(file));
+            self.used.push_back(path.clone());
+        }
+
+        Ok(self.writers.get_mut(path).unwrap())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        for writer in self.writers.values_mut() {
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Saves a parallel corpus for every pair of the requested languages in a
+/// single pass over the input, each in its own `<out>/<lang_a>-<lang_b>/`
+/// directory.
+pub struct Handler {
+    output_dir: PathBuf,
+    format: PairFormat,
+
+    /// How line breaks in the texts are written. [NewlinePolicy::Keep] is
+    /// only used with [PairFormat::Tsv].
+    newline_policy: NewlinePolicy,
+
+    /// Language codes in the order in which they were requested.
+    langs: Vec<String>,
+
+    /// Created in [Handler::start], once the source language is known.
+    pairs: Vec<Pair>,
+
+    writers: WriterPool,
+}
+
+impl TranslationUnitHandler for Handler {
+    fn start(&mut self, run: &RunInfo) -> Result<()> {
+        // If a source language has been selected, only the pairs of the
+        // source language and another language are extracted.
+        let mut lang_pairs: Vec<(String, String)> = Vec::new();
+        for (i, lang_a) in self.langs.iter().enumerate() {
+            for lang_b in &self.langs[i + 1..] {
+                match &run.source_lang {
+                    Some(source_lang) if LangMatch::IgnoreCase.matches(source_lang, lang_b) => {
+                        lang_pairs.push((lang_b.clone(), lang_a.clone()))
+                    }
+                    Some(source_lang) if !LangMatch::IgnoreCase.matches(source_lang, lang_a) => {}
+                    _ => lang_pairs.push((lang_a.clone(), lang_b.clone())),
+                }
+            }
+        }
+
+        for (lang_a, lang_b) in lang_pairs {
+            let (name_a, name_b) = (short_lang_name(&lang_a), short_lang_name(&lang_b));
+            let dir = self.output_dir.join(format!("{}-{}", name_a, name_b));
+            std::fs::create_dir_all(&dir)?;
+            let create = |file_name: String| -> Result<PathBuf> {
+                let path = dir.join(file_name);
+                File::create(&path)?;
+                Ok(path)
+            };
+            let files = match self.format {
+                PairFormat::Moses => PairFiles::Moses(
+                    create(format!("corpus.{}", name_a))?,
+                    create(format!("corpus.{}", name_b))?,
+                ),
+                PairFormat::Tsv => PairFiles::Tsv(create(String::from("corpus.tsv"))?),
+            };
+            self.pairs.push(Pair {
+                langs: (lang_a, lang_b),
+                files,
+            });
+        }
+        // Kept out of the corpus files, whose lines must stay aligned.
+        if let Some(attribution) = &run.attribution {
+            std::fs::create_dir_all(&self.output_dir)?;
+            std::fs::write(
+                self.output_dir.join("ATTRIBUTION.txt"),
+                format!("{}\n", attribution),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn handle(
+        &mut self,
+        translation_unit: TranslationUnit,
+        _sequential_number_in_doc: u32,
+    ) -> Result<()> {
+        let text = |lang: &String| -> Option<String> {
+            translation_unit
+                .get_lang(lang, LangMatch::Variant)
+                .map(|segment| text_field(&segment.content, self.newline_policy, '\t'))
+        };
+
+        for pair in &self.pairs {
+            let (text_a, text_b) = match (text(&pair.langs.0), text(&pair.langs.1)) {
+                (Some(text_a), Some(text_b)) => (text_a, text_b),
+                _ => continue,
+            };
+            match &pair.files {
+                PairFiles::Moses(path_a, path_b) => {
+                    writeln!(self.writers.get(path_a)?, "{}", text_a)?;
+                    writeln!(self.writers.get(path_b)?, "{}", text_b)?;
+                }
+                PairFiles::Tsv(path) => {
+                    writeln!(self.writers.get(path)?, "{}\t{}", text_a, text_b)?
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, _summary: &mut Summary) -> Result<()> {
+        self.writers.flush()
+    }
+}
+
+impl Handler {
+    /// `max_open_outputs` limits the number of output files open at the same
+    /// time (two per pair in the Moses format), e.g. on systems with a low
+    /// limit of file descriptors.
+    pub fn new(
+        output_dir: &Path,
+        langs: Vec<String>,
+        format: PairFormat,
+        newline_policy: NewlinePolicy,
+        max_open_outputs: Option<usize>,
+    ) -> Handler {
+        Handler {
+            output_dir: output_dir.to_path_buf(),
+            format,
+            newline_policy,
+            langs,
+            pairs: Vec::new(),
+            writers: WriterPool {
+                max_open: max_open_outputs,
+                writers: HashMap::new(),
+                used: VecDeque::new(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Handler, PairFormat};
+    use crate::manifest::Filters;
+    use crate::tmx_parser::parse_tmx;
+    use crate::types::{NewlinePolicy, RunInfo, Summary, TranslationUnitHandler};
+
+    fn run_info(source_lang: Option<&str>) -> RunInfo {
+        RunInfo {
+            tool_version: String::new(),
+            arguments: Vec::new(),
+            input_hash: String::new(),
+            source_lang: source_lang.map(String::from),
+            started_at: 0,
+            attribution: None,
+            filters: Filters::default(),
+        }
+    }
+
+    #[test]
+    fn every_pair_is_extracted() {
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        let langs = vec![
+            String::from("EN-GB"),
+            String::from("FR-FR"),
+            String::from("DE-DE"),
+        ];
+        let mut handler = Handler::new(
+            tmp_dir.path(),
+            langs,
+            PairFormat::Moses,
+            NewlinePolicy::Replace,
+            Some(2),
+        );
+        let tmx = parse_tmx(String::from(
+            r#"<tmx><header></header><body>
+            <tu><tuv lang="EN-GB"><seg>Article 1</seg></tuv><tuv lang="FR-FR"><seg>Article premier</seg></tuv><tuv lang="DE-DE"><seg>Artikel 1</seg></tuv></tu>
+            <tu><tuv lang="EN-GB"><seg>Annex</seg></tuv><tuv lang="DE-DE"><seg>Anhang</seg></tuv></tu>
+            </body></tmx>"#,
+        ))
+        .unwrap();
+        let run = RunInfo {
+            attribution: Some(String::from("Source: DGT-TM")),
+            ..run_info(None)
+        };
+        handler.start(&run).unwrap();
+        for tu in tmx.body.translation_units {
+            handler.handle(tu, 0).unwrap();
+        }
+        handler.finish(&mut Summary::default()).unwrap();
+
+        let read = |path: &str| std::fs::read_to_string(tmp_dir.path().join(path)).unwrap();
+        assert_eq!(read("en-fr/corpus.fr"), "Article premier\n");
+        assert_eq!(read("en-de/corpus.en"), "Article 1\nAnnex\n");
+        assert_eq!(read("en-de/corpus.de"), "Artikel 1\nAnhang\n");
+        assert_eq!(read("fr-de/corpus.fr"), "Article premier\n");
+        assert_eq!(read("ATTRIBUTION.txt"), "Source: DGT-TM\n");
+    }
+
+    #[test]
+    fn pairs_start_with_source_lang() {
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        let langs = vec![
+            String::from("EN-GB"),
+            String::from("FR-FR"),
+            String::from("DE-DE"),
+        ];
+        let mut handler = Handler::new(
+            tmp_dir.path(),
+            langs,
+            PairFormat::Tsv,
+            NewlinePolicy::Replace,
+            None,
+        );
+        handler.start(&run_info(Some("DE-DE"))).unwrap();
+
+        let mut dirs: Vec<String> = std::fs::read_dir(tmp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        dirs.sort();
+        assert_eq!(dirs, vec!["de-en", "de-fr"]);
+    }
+}
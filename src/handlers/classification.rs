@@ -0,0 +1,237 @@
+use crate::error::Result;
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::functions::short_lang_name;
+use crate::functions::text_field;
+use crate::tmx_parser::{LangMatch, TranslationUnit};
+use crate::types::{NewlinePolicy, RunInfo, Summary, TranslationUnitHandler};
+
+/// Format of the training data.
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ClassificationFormat {
+    /// A line per text, labelled with its language in the format of fastText,
+    /// e.g. `__label__pl Artykuł 1`, for training language identification.
+    LangId,
+
+    /// A line per pair of texts of the first language and another language,
+    /// e.g. `Article 1<TAB>Artykuł 1<TAB>1`, for training cross-lingual
+    /// classifiers. Each aligned pair (labelled `1`) is followed by a
+    /// mismatched pair (labelled `0`), made of the same first text and the
+    /// other text of the previous translation unit.
+    Pairs,
+}
+
+/// Saves the texts as training data for text classifiers, in one of the
+/// [ClassificationFormat]s. Texts are written on a single line each, unless
+/// line breaks are kept (see [NewlinePolicy]).
+pub struct Handler {
+    writer: Box<dyn Write>,
+    format: ClassificationFormat,
+
+    /// How line breaks in the texts are written. [NewlinePolicy::Keep] is
+    /// only used with [ClassificationFormat::Pairs].
+    newline_policy: NewlinePolicy,
+
+    /// Languages of the texts, in order (all languages if empty). With
+    /// [ClassificationFormat::Pairs], the first language is paired with each
+    /// other language.
+    langs: Vec<String>,
+
+    /// Text of each language in the previous translation unit which had one,
+    /// used for the mismatched pairs.
+    previous_texts: HashMap<String, String>,
+}
+
+impl TranslationUnitHandler for Handler {
+    /// The source language (if any) is the first language.
+    fn start(&mut self, run: &RunInfo) -> Result<()> {
+        if let Some(source_lang) = &run.source_lang {
+            if let Some(position) = self
+                .langs
+                .iter()
+                .position(|lang| LangMatch::IgnoreCase.matches(lang, source_lang))
+            {
+                let lang = self.langs.remove(position);
+                self.langs.insert(0, lang);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle(
+        &mut self,
+        translation_unit: TranslationUnit,
+        _sequential_number_in_doc: u32,
+    ) -> Result<()> {
+        let text = |lang: &String| -> Option<String> {
+            translation_unit
+                .get_lang(lang, LangMatch::Variant)
+                .map(|segment| text_field(&segment.content, self.newline_policy, '\t'))
+        };
+
+        match self.format {
+            ClassificationFormat::LangId => {
+                for segment in &translation_unit.segments {
+                    if segment.content.is_empty()
+                        || !(self.langs.is_empty()
+                            || self
+                                .langs
+                                .iter()
+                                .any(|lang| LangMatch::IgnoreCase.matches(lang, &segment.lang)))
+                    {
+                        continue;
+                    }
+                    writeln!(
+                        self.writer,
+                        "__label__{} {}",
+                        short_lang_name(&segment.lang),
+                        text_field(&segment.content, self.newline_policy, ' ')
+                    )?;
+                }
+            }
+            ClassificationFormat::Pairs => {
+                let first_text = match self.langs.first().and_then(text) {
+                    Some(first_text) => first_text,
+                    None => return Ok(()),
+                };
+                for lang in &self.langs[1..] {
+                    let other_text = match text(lang) {
+                        Some(other_text) => other_text,
+                        None => continue,
+                    };
+                    writeln!(self.writer, "{}\t{}\t1", first_text, other_text)?;
+                    // Identical texts (e.g. article numbers) would make a
+                    // mismatched pair which is in fact aligned.
+                    if let Some(previous_text) = self.previous_texts.get(lang) {
+                        if previous_text != &other_text {
+                            writeln!(self.writer, "{}\t{}\t0", first_text, previous_text)?;
+                        }
+                    }
+                    self.previous_texts.insert(lang.clone(), other_text);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, _summary: &mut Summary) -> Result<()> {
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
+
+impl Handler {
+    pub fn new(
+        writer: Box<dyn Write>,
+        format: ClassificationFormat,
+        newline_policy: NewlinePolicy,
+        langs: Vec<String>,
+    ) -> Handler {
+        Handler {
+            writer,
+            format,
+            newline_policy,
+            langs,
+            previous_texts: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ClassificationFormat, Handler};
+    use crate::manifest::Filters;
+    use crate::tmx_parser::parse_tmx;
+    use crate::types::{NewlinePolicy, RunInfo, Summary, TranslationUnitHandler};
+    use std::fs::File;
+    use std::io::BufWriter;
+
+    fn run(format: ClassificationFormat, newline_policy: NewlinePolicy, langs: &[&str]) -> String {
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("train.txt");
+        let langs = langs.iter().map(|lang| lang.to_string()).collect();
+        let writer = Box::new(BufWriter::new(File::create(&path).unwrap()));
+        let mut handler = Handler::new(writer, format, newline_policy, langs);
+        let tmx = parse_tmx(String::from(
+            r#"<tmx><header></header><body>
+            <tu><tuv lang="EN-GB"><seg>Article 1</seg></tuv><tuv lang="PL-01"><seg>Artykuł 1</seg></tuv></tu>
+            <tu><tuv lang="EN-GB"><seg>Annex</seg></tuv><tuv lang="DE-DE"><seg>Anhang</seg></tuv></tu>
+            <tu><tuv lang="EN-GB"><seg>The
+Council</seg></tuv><tuv lang="PL-01"><seg>Rada</seg></tuv></tu>
+            </body></tmx>"#,
+        ))
+        .unwrap();
+        let run = RunInfo {
+            tool_version: String::new(),
+            arguments: Vec::new(),
+            input_hash: String::new(),
+            source_lang: Some(String::from("EN-GB")),
+            started_at: 0,
+            attribution: None,
+            filters: Filters::default(),
+        };
+        handler.start(&run).unwrap();
+        for tu in tmx.body.translation_units {
+            handler.handle(tu, 0).unwrap();
+        }
+        handler.finish(&mut Summary::default()).unwrap();
+
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn texts_are_labelled_with_langs() {
+        assert_eq!(
+            run(
+                ClassificationFormat::LangId,
+                NewlinePolicy::Replace,
+                &["PL-01", "DE-DE"]
+            ),
+            "__label__pl Artykuł 1\n__label__de Anhang\n__label__pl Rada\n"
+        );
+        assert_eq!(
+            run(ClassificationFormat::LangId, NewlinePolicy::Replace, &[])
+                .lines()
+                .count(),
+            6
+        );
+    }
+
+    #[test]
+    fn aligned_pairs_are_followed_by_mismatched_pairs() {
+        assert_eq!(
+            run(
+                ClassificationFormat::Pairs,
+                NewlinePolicy::Replace,
+                &["PL-01", "EN-GB"]
+            ),
+            "Article 1\tArtykuł 1\t1\n\
+            The Council\tRada\t1\n\
+            The Council\tArtykuł 1\t0\n"
+        );
+    }
+
+    #[test]
+    fn line_breaks_are_kept_in_quoted_fields() {
+        assert_eq!(
+            run(
+                ClassificationFormat::Pairs,
+                NewlinePolicy::Keep,
+                &["PL-01", "EN-GB"]
+            ),
+            "Article 1\tArtykuł 1\t1\n\
+            \"The\nCouncil\"\tRada\t1\n\
+            \"The\nCouncil\"\tArtykuł 1\t0\n"
+        );
+        assert!(run(
+            ClassificationFormat::Pairs,
+            NewlinePolicy::Escape,
+            &["PL-01", "EN-GB"]
+        )
+        .starts_with("Article 1\tArtykuł 1\t1\nThe\\nCouncil\tRada\t1\n"));
+    }
+}
@@ -0,0 +1,213 @@
+//! Behavior shared by the handlers which save each translation unit (e.g. as
+//! a row or a record), checked by the same tests for every such handler.
+//!
+//! A handler opts in by implementing [Conformance] in its test module, which
+//! creates the handler and reads its output back, and invoking
+//! [handler_conformance_tests!](crate::handler_conformance_tests), e.g.:
+//!
+//! ```ignore
+//! crate::handler_conformance_tests!(Harness::default());
+//! ```
+use crate::manifest::Filters;
+use crate::tmx_parser::{parse_tmx, TranslationUnit};
+use crate::types::{RequestedLangs, RunInfo, Summary, TranslationUnitHandler};
+
+/// Languages of the translation units fed to the handlers.
+pub const LANGS: [&str; 2] = ["EN-GB", "PL-01"];
+
+/// A translation unit as read back from the output of a handler, with its
+/// non-empty texts sorted by language.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct SavedUnit {
+    pub document: String,
+    pub sequential_number: u32,
+    pub texts: Vec<(String, String)>,
+}
+
+impl SavedUnit {
+    pub fn new(document: &str, sequential_number: u32, mut texts: Vec<(String, String)>) -> Self {
+        texts.retain(|(_lang, text)| !text.is_empty());
+        texts.sort();
+        SavedUnit {
+            document: document.to_string(),
+            sequential_number,
+            texts,
+        }
+    }
+}
+
+/// Creates a handler writing to a fresh output and reads the output back.
+pub trait Conformance {
+    type Handler: TranslationUnitHandler;
+
+    /// If the handler writes in batches, the batches should be small enough
+    /// for the translation units of the tests to span several of them.
+    fn handler(&mut self, requested_langs: RequestedLangs) -> Self::Handler;
+
+    /// Read the translation units saved by a finished handler, in any order.
+    fn saved_units(&mut self, handler: &mut Self::Handler) -> Vec<SavedUnit>;
+}
+
+/// Translation units of documents `A`, `B` and `C`, interleaved, with their
+/// sequential numbers within the documents. Every third unit has no Polish
+/// text.
+fn translation_units(count: usize) -> Vec<(TranslationUnit, u32)> {
+    let mut xml = String::from("<tmx><header></header><body>");
+    for i in 0..count {
+        xml.push_str(&format!(
+            r#"<tu><prop type="Txt::Doc. No.">{}</prop><tuv lang="EN-GB"><seg>Text {}</seg></tuv>"#,
+            ["A", "B", "C"][i % 3],
+            i
+        ));
+        if i % 3 != 2 {
+            xml.push_str(&format!(
+                r#"<tuv lang="PL-01"><seg>Tekst {}</seg></tuv>"#,
+                i
+            ));
+        }
+        xml.push_str("</tu>");
+    }
+    xml.push_str("</body></tmx>");
+
+    parse_tmx(xml)
+        .unwrap()
+        .body
+        .translation_units
+        .into_iter()
+        .enumerate()
+        .map(|(i, tu)| (tu, (i / 3) as u32))
+        .collect()
+}
+
+/// Feed the translation units to a new handler the way the parser does, and
+/// read back what it saved.
+fn run<C: Conformance>(
+    harness: &mut C,
+    requested_langs: RequestedLangs,
+    units: &[(TranslationUnit, u32)],
+) -> Vec<SavedUnit> {
+    let declared_langs = match &requested_langs {
+        RequestedLangs::Unlimited => Vec::new(),
+        RequestedLangs::Some(langs) | RequestedLangs::Each(langs) => langs.to_vec(),
+    };
+    let mut handler = harness.handler(requested_langs);
+    let run = RunInfo {
+        tool_version: String::new(),
+        arguments: Vec::new(),
+        input_hash: String::new(),
+        source_lang: None,
+        started_at: 0,
+        attribution: None,
+        filters: Filters::default(),
+    };
+    handler.start(&run).unwrap();
+    handler.declare_langs(&declared_langs).unwrap();
+    for (tu, sequential_number) in units {
+        handler.handle(tu.clone(), *sequential_number).unwrap();
+    }
+    handler.finish(&mut Summary::default()).unwrap();
+
+    let mut saved = harness.saved_units(&mut handler);
+    saved.sort();
+    saved
+}
+
+fn expected_units(units: &[(TranslationUnit, u32)], langs: &[&str]) -> Vec<SavedUnit> {
+    let mut expected: Vec<SavedUnit> = units
+        .iter()
+        .map(|(tu, sequential_number)| {
+            let texts = tu
+                .segments
+                .iter()
+                .filter(|segment| langs.contains(&segment.lang.as_str()))
+                .map(|segment| (segment.lang.clone(), segment.content.clone()))
+                .collect();
+            SavedUnit::new(tu.doc_name().unwrap(), *sequential_number, texts)
+        })
+        .collect();
+    expected.sort();
+    expected
+}
+
+/// Every translation unit is saved exactly once, whichever batch it falls
+/// into.
+pub fn all_units_are_saved_across_batches<C: Conformance>(mut harness: C) {
+    let units = translation_units(250);
+    let saved = run(&mut harness, RequestedLangs::Unlimited, &units);
+
+    assert_eq!(saved, expected_units(&units, &LANGS));
+}
+
+/// Translation units still buffered when the handler is finished are saved.
+pub fn output_is_flushed_on_finish<C: Conformance>(mut harness: C) {
+    let units = translation_units(2);
+    let saved = run(&mut harness, RequestedLangs::Unlimited, &units);
+
+    assert_eq!(saved, expected_units(&units, &LANGS));
+}
+
+/// Only the texts in the requested languages are saved.
+pub fn only_requested_langs_are_saved<C: Conformance>(mut harness: C) {
+    let units = translation_units(6);
+    let langs = RequestedLangs::Some([String::from("PL-01")].into_iter().collect());
+    let saved = run(&mut harness, langs, &units);
+
+    // Translation units without a Polish text may be saved without texts.
+    let saved: Vec<SavedUnit> = saved
+        .into_iter()
+        .filter(|unit| !unit.texts.is_empty())
+        .collect();
+    let expected: Vec<SavedUnit> = expected_units(&units, &["PL-01"])
+        .into_iter()
+        .filter(|unit| !unit.texts.is_empty())
+        .collect();
+    assert_eq!(saved, expected);
+}
+
+/// Interleaved translation units are assigned to their documents, which are
+/// numbered consecutively from 0.
+pub fn units_are_grouped_by_document<C: Conformance>(mut harness: C) {
+    let units = translation_units(9);
+    let saved = run(&mut harness, RequestedLangs::Unlimited, &units);
+
+    for document in ["A", "B", "C"] {
+        let numbers: Vec<u32> = saved
+            .iter()
+            .filter(|unit| unit.document == document)
+            .map(|unit| unit.sequential_number)
+            .collect();
+        assert_eq!(numbers, vec![0, 1, 2], "document {}", document);
+    }
+}
+
+/// Generate the conformance tests for a handler, given an expression which
+/// creates its [Conformance] harness.
+#[macro_export]
+macro_rules! handler_conformance_tests {
+    ($harness:expr) => {
+        mod conformance {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #[test]
+            fn all_units_are_saved_across_batches() {
+                $crate::handlers::conformance::all_units_are_saved_across_batches($harness);
+            }
+
+            #[test]
+            fn output_is_flushed_on_finish() {
+                $crate::handlers::conformance::output_is_flushed_on_finish($harness);
+            }
+
+            #[test]
+            fn only_requested_langs_are_saved() {
+                $crate::handlers::conformance::only_requested_langs_are_saved($harness);
+            }
+
+            #[test]
+            fn units_are_grouped_by_document() {
+                $crate::handlers::conformance::units_are_grouped_by_document($harness);
+            }
+        }
+    };
+}
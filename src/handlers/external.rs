@@ -0,0 +1,321 @@
+//! Out-of-tree handlers: programs registered in the `--config` file, which
+//! receive the translation units as JSON objects on their standard input,
+//! one per line, e.g.:
+//!
+//! ```text
+//! {"type":"start","protocol":1,"tool_version":"0.1.1","arguments":[…],"input_hash":"…","source_lang":null,"attribution":"…"}
+//! {"type":"langs","langs":["EN-GB","PL-01"]}
+//! {"type":"unit","document":"22019D0557","document_url":"https://eur-lex.europa.eu/legal-content/AUTO/?uri=CELEX:22019D0557","sequential_number":0,"texts":[{"lang":"EN-GB","text":"…","is_source":true}]}
+//! {"type":"finish","translation_units":1}
+//! ```
+//!
+//! Units have the format of the `jsonl` output. The standard input is closed
+//! after the `finish` message, and the program is expected to exit with
+//! status 0 once it has saved its output. It may report problems by writing
+//! `{"warning":"…"}` or `{"error":"…"}` lines to its standard output, which
+//! are added to the summary (and count for `--fail-on`); its standard error is
+//! passed through. Messages only gain fields within a protocol version.
+use crate::error::{DgtError, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::thread::JoinHandle;
+
+use super::jsonl::Record;
+use crate::config::ExternalHandlerConfig;
+use crate::tmx_parser::TranslationUnit;
+use crate::types::{RequestedLangs, RunInfo, Summary, TranslationUnitHandler};
+
+/// Version of the protocol, sent in the `start` message. Changed only if
+/// messages change in a way which breaks existing handlers.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message<'a> {
+    Start {
+        protocol: u32,
+        tool_version: &'a str,
+        arguments: &'a [String],
+        input_hash: &'a str,
+        source_lang: Option<&'a str>,
+        attribution: Option<&'a str>,
+    },
+    Langs {
+        langs: &'a [String],
+    },
+    Unit(Record<'a>),
+    Finish {
+        translation_units: u64,
+    },
+}
+
+/// A line written by the program to its standard output.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Reply {
+    Warning(String),
+    Error(String),
+}
+
+/// Passes the translation units to a program over the protocol described in
+/// the [module documentation](self).
+pub struct Handler {
+    /// Name under which the handler is registered.
+    name: String,
+    child: Child,
+
+    /// Closed once the handler is finished.
+    stdin: Option<BufWriter<ChildStdin>>,
+
+    /// Collects the lines of the standard output of the program.
+    replies: Option<JoinHandle<Vec<String>>>,
+
+    /// Config value provided by the user. Determines if a text in a given
+    /// language should be sent or skipped.
+    requested_langs: RequestedLangs,
+
+    translation_units: u64,
+}
+
+impl TranslationUnitHandler for Handler {
+    fn start(&mut self, run: &RunInfo) -> Result<()> {
+        self.send(&Message::Start {
+            protocol: PROTOCOL_VERSION,
+            tool_version: &run.tool_version,
+            arguments: &run.arguments,
+            input_hash: &run.input_hash,
+            source_lang: run.source_lang.as_deref(),
+            attribution: run.attribution.as_deref(),
+        })
+    }
+
+    fn declare_langs(&mut self, langs: &[String]) -> Result<()> {
+        self.send(&Message::Langs { langs })
+    }
+
+    fn handle(
+        &mut self,
+        translation_unit: TranslationUnit,
+        sequential_number_in_doc: u32,
+    ) -> Result<()> {
+        let record = Record::new(
+            &translation_unit,
+            sequential_number_in_doc,
+            &self.requested_langs,
+        );
+        self.send(&Message::Unit(record))?;
+        self.translation_units += 1;
+
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &mut Summary) -> Result<()> {
+        self.send(&Message::Finish {
+            translation_units: self.translation_units,
+        })?;
+        if let Some(mut stdin) = self.stdin.take() {
+            stdin.flush().map_err(|error| self.failure(error))?;
+        }
+        let status = self.child.wait()?;
+        let replies = match self.replies.take() {
+            Some(replies) => replies.join().unwrap_or_default(),
+            None => Vec::new(),
+        };
+        for line in replies {
+            match serde_json::from_str(&line) {
+                Ok(Reply::Warning(warning)) => {
+                    summary.warnings.push(format!("{}: {}", self.name, warning))
+                }
+                Ok(Reply::Error(error)) => summary.errors.push(format!("{}: {}", self.name, error)),
+                Err(_) => summary
+                    .warnings
+                    .push(format!("{}: unexpected output: {}", self.name, line)),
+            }
+        }
+        if !status.success() {
+            return Err(self.failure(format!("exited with {}", status)));
+        }
+
+        Ok(())
+    }
+}
+
+impl Handler {
+    /// Start the program registered as `name`.
+    pub fn spawn(
+        name: &str,
+        config: &ExternalHandlerConfig,
+        requested_langs: RequestedLangs,
+    ) -> Result<Handler> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|error| DgtError::ExternalHandler {
+                name: name.to_string(),
+                reason: format!("cannot run {}: {}", config.command.display(), error),
+            })?;
+        let stdin = child.stdin.take().map(BufWriter::new);
+        // Read on a thread of its own, so that the program does not block on
+        // a full pipe while it is sent translation units.
+        let replies = child.stdout.take().map(|stdout| {
+            std::thread::spawn(move || {
+                BufReader::new(stdout)
+                    .lines()
+                    .map_while(|line| line.ok())
+                    .collect()
+            })
+        });
+
+        Ok(Handler {
+            name: name.to_string(),
+            child,
+            stdin,
+            replies,
+            requested_langs,
+            translation_units: 0,
+        })
+    }
+
+    fn send(&mut self, message: &Message) -> Result<()> {
+        let stdin = match &mut self.stdin {
+            Some(stdin) => stdin,
+            None => return Err(self.failure("the standard input is closed")),
+        };
+        let sent = serde_json::to_writer(&mut *stdin, message)
+            .map_err(std::io::Error::from)
+            .and_then(|_| stdin.write_all(b"\n"));
+        // Most likely, the program has exited (a broken pipe).
+        sent.map_err(|error| self.failure(error))
+    }
+
+    fn failure(&self, reason: impl ToString) -> DgtError {
+        DgtError::ExternalHandler {
+            name: self.name.clone(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl Drop for Handler {
+    /// The program is stopped if the run fails before the handler is
+    /// finished, rather than left to save a partial output.
+    fn drop(&mut self) {
+        if self.stdin.take().is_some() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Handler;
+    use crate::config::ExternalHandlerConfig;
+    use crate::error::DgtError;
+    use crate::handlers::conformance::{Conformance, SavedUnit};
+    use crate::types::{RequestedLangs, Summary, TranslationUnitHandler};
+    use std::path::PathBuf;
+
+    /// A handler running a shell script.
+    fn script(script: &str, args: &[&str]) -> ExternalHandlerConfig {
+        let mut config_args = vec![String::from("-c"), script.to_string()];
+        config_args.extend(args.iter().map(|arg| arg.to_string()));
+        ExternalHandlerConfig {
+            command: PathBuf::from("sh"),
+            args: config_args,
+        }
+    }
+
+    #[test]
+    fn replies_are_added_to_summary() {
+        let config = script(
+            r#"cat > /dev/null; echo '{"warning":"slow"}'; echo '{"error":"full"}'; echo done"#,
+            &[],
+        );
+        let mut handler = Handler::spawn("sink", &config, RequestedLangs::Unlimited).unwrap();
+        let mut summary = Summary::default();
+        handler.finish(&mut summary).unwrap();
+
+        assert_eq!(
+            summary.warnings,
+            vec!["sink: slow", "sink: unexpected output: done"]
+        );
+        assert_eq!(summary.errors, vec!["sink: full"]);
+    }
+
+    #[test]
+    fn failures_are_reported() {
+        let config = script("cat > /dev/null; exit 3", &[]);
+        let mut handler = Handler::spawn("sink", &config, RequestedLangs::Unlimited).unwrap();
+        assert!(matches!(
+            handler.finish(&mut Summary::default()),
+            Err(DgtError::ExternalHandler { reason, .. }) if reason.contains("3")
+        ));
+
+        let config = ExternalHandlerConfig {
+            command: PathBuf::from("/nonexistent/sink"),
+            args: Vec::new(),
+        };
+        assert!(Handler::spawn("sink", &config, RequestedLangs::Unlimited).is_err());
+    }
+
+    /// The messages are saved by `cat` in a file of their own, removed when
+    /// the harness is dropped.
+    struct Harness {
+        _tmp_dir: assert_fs::TempDir,
+        output_file: PathBuf,
+    }
+
+    impl Harness {
+        fn new() -> Harness {
+            let tmp_dir = assert_fs::TempDir::new().unwrap();
+            let output_file = tmp_dir.path().join("received.jsonl");
+            Harness {
+                _tmp_dir: tmp_dir,
+                output_file,
+            }
+        }
+    }
+
+    impl Conformance for Harness {
+        type Handler = Handler;
+
+        fn handler(&mut self, requested_langs: RequestedLangs) -> Handler {
+            let output_file = self.output_file.display().to_string();
+            let config = script(r#"cat > "$0""#, &[&output_file]);
+            Handler::spawn("cat", &config, requested_langs).unwrap()
+        }
+
+        fn saved_units(&mut self, _handler: &mut Handler) -> Vec<SavedUnit> {
+            std::fs::read_to_string(&self.output_file)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+                .filter(|message| message["type"] == "unit")
+                .map(|unit| {
+                    let texts = unit["texts"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|text| {
+                            (
+                                text["lang"].as_str().unwrap().to_string(),
+                                text["text"].as_str().unwrap().to_string(),
+                            )
+                        })
+                        .collect();
+                    SavedUnit::new(
+                        unit["document"].as_str().unwrap(),
+                        unit["sequential_number"].as_u64().unwrap() as u32,
+                        texts,
+                    )
+                })
+                .collect()
+        }
+    }
+
+    crate::handler_conformance_tests!(Harness::new());
+}
@@ -0,0 +1,342 @@
+use crate::error::Result;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::DgtError;
+use crate::functions::{short_lang_name, to_hex};
+use crate::tmx_parser::{LangMatch, TranslationUnit};
+use crate::types::{RunInfo, Summary, TranslationUnitHandler};
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em}\
+    table{border-collapse:collapse;width:100%}\
+    th,td{border:1px solid #ccc;padding:.4em;vertical-align:top;text-align:left}\
+    td.n{width:3em;color:#666}td.n a{color:inherit}\
+    tr:target{background:#ffc}td.missing{background:#f4f4f4}";
+
+/// Saves a static HTML page for each document, with the texts of the
+/// translation units side by side (a column per language, the source language
+/// first), and an `index.html` page listing the documents. Each row can be
+/// linked to by its sequential number, e.g. `22019D0391.html#12`, which makes
+/// the pages a lightweight artifact for reviewing translations.
+pub struct Handler {
+    output_dir: PathBuf,
+
+    /// Languages of the columns, in order.
+    langs: Vec<String>,
+
+    /// The document whose page is currently written, with its writer.
+    current_page: Option<(String, BufWriter<File>)>,
+
+    /// Number of translation units of each document, keyed by document name.
+    docs: BTreeMap<String, u64>,
+
+    /// Notice in the footer of every page (see [RunInfo::attribution]).
+    footer: String,
+}
+
+impl TranslationUnitHandler for Handler {
+    /// The source language (if any) is the first language column.
+    fn start(&mut self, run: &RunInfo) -> Result<()> {
+        if let Some(attribution) = &run.attribution {
+            self.footer = format!("<footer><p>{}</p></footer>\n", escape_html(attribution));
+        }
+        if let Some(source_lang) = &run.source_lang {
+            if let Some(position) = self
+                .langs
+                .iter()
+                .position(|lang| LangMatch::IgnoreCase.matches(lang, source_lang))
+            {
+                let lang = self.langs.remove(position);
+                self.langs.insert(0, lang);
+            }
+        }
+        std::fs::create_dir_all(&self.output_dir)?;
+
+        Ok(())
+    }
+
+    fn handle(
+        &mut self,
+        translation_unit: TranslationUnit,
+        sequential_number_in_doc: u32,
+    ) -> Result<()> {
+        let doc_name = translation_unit
+            .doc_name()
+            .ok_or(DgtError::MissingDocId)?
+            .clone();
+        let mut row = format!(
+            "<tr id=\"{0}\"><td class=\"n\"><a href=\"#{0}\">{0}</a></td>",
+            sequential_number_in_doc
+        );
+        for lang in &self.langs {
+            let text = translation_unit.get_lang(lang, LangMatch::Variant);
+            match text {
+                Some(segment) => row.push_str(&format!(
+                    "<td lang=\"{}\">{}</td>",
+                    short_lang_name(lang),
+                    escape_html(&segment.content)
+                )),
+                None => row.push_str("<td class=\"missing\"></td>"),
+            }
+        }
+        row.push_str("</tr>\n");
+
+        self.page(&doc_name)?.write_all(row.as_bytes())?;
+        *self.docs.entry(doc_name).or_default() += 1;
+
+        Ok(())
+    }
+
+    fn finish(&mut self, _summary: &mut Summary) -> Result<()> {
+        if let Some((_, mut writer)) = self.current_page.take() {
+            writer.flush()?;
+        }
+        // The rows of a document may be written in several passes (if its
+        // translation units are not contiguous), so the pages are closed
+        // once all rows have been written.
+        for doc_name in self.docs.keys() {
+            let path = self.output_dir.join(page_file_name(doc_name));
+            let mut file = OpenOptions::new().append(true).open(path)?;
+            write!(file, "</tbody></table>\n{}</body></html>\n", self.footer)?;
+        }
+        self.write_index()
+    }
+}
+
+impl Handler {
+    pub fn new(output_dir: &Path, langs: Vec<String>) -> Handler {
+        Handler {
+            output_dir: output_dir.to_path_buf(),
+            langs,
+            current_page: None,
+            docs: BTreeMap::new(),
+            footer: String::new(),
+        }
+    }
+
+    /// Return the writer of the page of a document, creating the page (with
+    /// its header) if the document has not been seen before.
+    fn page(&mut self, doc_name: &str) -> Result<&mut BufWriter<File>> {
+        let is_current = matches!(&self.current_page, Some((name, _)) if name == doc_name);
+        if !is_current {
+            if let Some((_, mut writer)) = self.current_page.take() {
+                writer.flush()?;
+            }
+            let path = self.output_dir.join(page_file_name(doc_name));
+            let writer = match self.docs.contains_key(doc_name) {
+                true => BufWriter::new(OpenOptions::new().append(true).open(path)?),
+                false => {
+                    let mut writer = BufWriter::new(File::create(path)?);
+                    self.write_page_header(&mut writer, doc_name)?;
+                    writer
+                }
+            };
+            self.current_page = Some((doc_name.to_string(), writer));
+        }
+
+        Ok(&mut self.current_page.as_mut().unwrap().1)
+    }
+
+    fn write_page_header(&self, writer: &mut impl Write, doc_name: &str) -> Result<()> {
+        let title = escape_html(doc_name);
+        write!(
+            writer,
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0}</title>\
+            <style>{1}</style></head>\n<body>\n<p><a href=\"index.html\">All documents</a></p>\n\
+            <h1>{0}</h1>\n<table><thead><tr><th>No.</th>",
+            title, STYLE
+        )?;
+        for lang in &self.langs {
+            write!(writer, "<th>{}</th>", escape_html(lang))?;
+        }
+        writeln!(writer, "</tr></thead><tbody>")?;
+
+        Ok(())
+    }
+
+    /// Save the list of documents, with links to their pages.
+    fn write_index(&self) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(self.output_dir.join("index.html"))?);
+        write!(
+            writer,
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Documents</title>\
+            <style>{}</style></head>\n<body>\n<h1>Documents</h1>\n\
+            <table><thead><tr><th>Document</th><th>Translation units</th></tr></thead><tbody>\n",
+            STYLE
+        )?;
+        for (doc_name, translation_units) in &self.docs {
+            writeln!(
+                writer,
+                "<tr><td><a href=\"{}\">{}</a></td><td>{}</td></tr>",
+                escape_html(&page_file_name(doc_name)),
+                escape_html(doc_name),
+                translation_units
+            )?;
+        }
+        writeln!(writer, "</tbody></table>\n{}</body></html>", self.footer)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Name of the page of a document, e.g. `22019A0315(01).html`. Characters
+/// which are not safe in file names are replaced, in which case a hash of the
+/// document name is appended, so that e.g. `a/b` and `a b` do not share a page.
+fn page_file_name(doc_name: &str) -> String {
+    let name: String = doc_name
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || "()._-".contains(c) {
+            true => c,
+            false => '_',
+        })
+        .collect();
+    // `index` is reserved for the list of documents.
+    match name == doc_name && name != "index" {
+        true => format!("{}.html", name),
+        false => {
+            let hash = to_hex(&Sha256::digest(doc_name.as_bytes()));
+            format!("{}_{}.html", name, &hash[..8])
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::{escape_html, page_file_name, Handler};
+    use crate::functions::short_lang_name;
+    use crate::handlers::conformance::{Conformance, SavedUnit, LANGS};
+    use crate::manifest::Filters;
+    use crate::tmx_parser::parse_tmx;
+    use crate::types::{RequestedLangs, RunInfo, Summary, TranslationUnitHandler};
+    use regex::Regex;
+    use std::path::PathBuf;
+
+    #[test]
+    fn documents_are_saved_as_pages() {
+        let tmx = parse_tmx(String::from(
+            r#"<tmx><header></header><body>
+            <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>Annex &lt;I&gt;</seg></tuv><tuv lang="PL-01"><seg>Załącznik</seg></tuv></tu>
+            <tu><prop type="Txt::Doc. No.">B</prop><tuv lang="EN-GB"><seg>Article</seg></tuv></tu>
+            <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>Article</seg></tuv><tuv lang="PL-01"><seg>Artykuł</seg></tuv></tu>
+            </body></tmx>"#,
+        ))
+        .unwrap();
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        let output_dir = tmp_dir.path().join("review");
+        let langs = vec![String::from("EN-GB"), String::from("PL-01")];
+        let mut handler = Handler::new(&output_dir, langs);
+        let run = RunInfo {
+            tool_version: String::new(),
+            arguments: Vec::new(),
+            input_hash: String::new(),
+            source_lang: Some(String::from("PL-01")),
+            started_at: 0,
+            attribution: Some(String::from("© European Union")),
+            filters: Filters::default(),
+        };
+        handler.start(&run).unwrap();
+        for (i, tu) in tmx.body.translation_units.into_iter().enumerate() {
+            handler.handle(tu, i as u32).unwrap();
+        }
+        handler.finish(&mut Summary::default()).unwrap();
+
+        let page = std::fs::read_to_string(output_dir.join("A.html")).unwrap();
+        let index = std::fs::read_to_string(output_dir.join("index.html")).unwrap();
+
+        assert!(page.contains("<th>No.</th><th>PL-01</th><th>EN-GB</th>"));
+        assert!(page.contains(
+            "<tr id=\"0\"><td class=\"n\"><a href=\"#0\">0</a></td><td lang=\"pl\">Załącznik</td><td lang=\"en\">Annex &lt;I&gt;</td></tr>"
+        ));
+        assert!(page.contains("<tr id=\"2\">"));
+        assert!(page.ends_with(
+            "</tbody></table>\n<footer><p>© European Union</p></footer>\n</body></html>\n"
+        ));
+        assert_eq!(page.matches("<h1>").count(), 1);
+        assert!(index.contains("<tr><td><a href=\"B.html\">B</a></td><td>1</td></tr>"));
+    }
+
+    #[test]
+    fn names_are_escaped() {
+        assert_eq!(page_file_name("32019R0557(01)"), "32019R0557(01).html");
+        assert_eq!(page_file_name("../a b"), ".._a_b_2c2c791a.html");
+        assert_eq!(page_file_name("index"), "index_1bc04b52.html");
+        assert_eq!(page_file_name("a/b"), "a_b_c14cddc0.html");
+        assert_eq!(page_file_name("a b"), "a_b_c8687a08.html");
+        assert_eq!(escape_html("a < \"b\" & c"), "a &lt; &quot;b&quot; &amp; c");
+    }
+
+    /// The pages are saved in a directory of their own, removed when the
+    /// harness is dropped.
+    struct Harness {
+        _tmp_dir: assert_fs::TempDir,
+        output_dir: PathBuf,
+    }
+
+    impl Harness {
+        fn new() -> Harness {
+            let tmp_dir = assert_fs::TempDir::new().unwrap();
+            let output_dir = tmp_dir.path().join("review");
+            Harness {
+                _tmp_dir: tmp_dir,
+                output_dir,
+            }
+        }
+    }
+
+    impl Conformance for Harness {
+        type Handler = Handler;
+
+        /// Languages are required (as with `-l` on the command line).
+        fn handler(&mut self, requested_langs: RequestedLangs) -> Handler {
+            let langs = match requested_langs {
+                RequestedLangs::Unlimited => LANGS.iter().map(|lang| lang.to_string()).collect(),
+                RequestedLangs::Some(langs) | RequestedLangs::Each(langs) => langs.to_vec(),
+            };
+            Handler::new(&self.output_dir, langs)
+        }
+
+        fn saved_units(&mut self, handler: &mut Handler) -> Vec<SavedUnit> {
+            let row_regex = Regex::new(r#"<tr id="(\d+)">(.*)</tr>"#).unwrap();
+            let cell_regex = Regex::new(r#"<td lang="(\w+)">([^<]*)</td>"#).unwrap();
+            let mut saved = Vec::new();
+            for document in handler.docs.keys() {
+                let page = std::fs::read_to_string(self.output_dir.join(page_file_name(document)))
+                    .unwrap();
+                for row in row_regex.captures_iter(&page) {
+                    let texts = cell_regex
+                        .captures_iter(&row[2])
+                        .map(|cell| {
+                            let lang = LANGS
+                                .iter()
+                                .find(|lang| short_lang_name(lang) == cell[1])
+                                .unwrap();
+                            (lang.to_string(), cell[2].to_string())
+                        })
+                        .collect();
+                    saved.push(SavedUnit::new(document, row[1].parse().unwrap(), texts));
+                }
+            }
+            saved
+        }
+    }
+
+    crate::handler_conformance_tests!(Harness::new());
+}
@@ -0,0 +1,195 @@
+use crate::error::Result;
+use serde::Serialize;
+use std::io::Write;
+
+use crate::functions::eur_lex_url;
+use crate::tmx_parser::TranslationUnit;
+use crate::types::{RequestedLangs, Summary, TranslationUnitHandler};
+
+/// Writes each translation unit as a JSON object on a line of its own, e.g.
+/// `{"document":"22019D0557","document_url":"https://eur-lex.europa.eu/legal-content/AUTO/?uri=CELEX:22019D0557","sequential_number":0,"texts":[{"lang":"EN-GB","text":"…","is_source":true}]}`.
+pub struct Handler {
+    writer: Box<dyn Write>,
+
+    /// Config value provided by the user. Determines if a text in a given
+    /// language should be included in the output or skipped.
+    requested_langs: RequestedLangs,
+}
+
+/// A translation unit as serialized in the output, with the texts in the
+/// requested languages only. Also used by the `kv` output.
+#[derive(Serialize)]
+pub(crate) struct Record<'a> {
+    document: Option<&'a String>,
+
+    /// See [eur_lex_url].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    document_url: Option<String>,
+
+    sequential_number: u32,
+    texts: Vec<Text<'a>>,
+
+    /// See `--score-alignment`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alignment_score: Option<f64>,
+
+    /// See `--flag-boilerplate`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    is_boilerplate: Option<bool>,
+
+    /// See `--index-in-file`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index_in_file: Option<u32>,
+
+    /// See `--merge-corpus`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    corpus: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct Text<'a> {
+    lang: &'a str,
+    text: &'a str,
+
+    /// Only present (as `true`) for the original text.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    is_source: bool,
+}
+
+impl<'a> Record<'a> {
+    pub(crate) fn new(
+        translation_unit: &'a TranslationUnit,
+        sequential_number_in_doc: u32,
+        requested_langs: &RequestedLangs,
+    ) -> Record<'a> {
+        let texts = translation_unit
+            .segments
+            .iter()
+            .filter(|segment| match requested_langs {
+                RequestedLangs::Unlimited => true,
+                RequestedLangs::Each(langs) | RequestedLangs::Some(langs) => {
+                    langs.contains(&segment.lang)
+                }
+            })
+            .map(|segment| Text {
+                lang: &segment.lang,
+                text: &segment.content,
+                is_source: segment.is_source,
+            })
+            .collect();
+        Record {
+            document: translation_unit.doc_name(),
+            document_url: translation_unit
+                .doc_name()
+                .and_then(|doc_name| eur_lex_url(doc_name)),
+            sequential_number: sequential_number_in_doc,
+            texts,
+            alignment_score: translation_unit.annotations.alignment_score,
+            is_boilerplate: translation_unit.annotations.is_boilerplate,
+            index_in_file: translation_unit.annotations.index_in_file,
+            corpus: translation_unit.annotations.corpus.as_deref(),
+        }
+    }
+}
+
+impl TranslationUnitHandler for Handler {
+    fn handle(
+        &mut self,
+        translation_unit: TranslationUnit,
+        sequential_number_in_doc: u32,
+    ) -> Result<()> {
+        let record = Record::new(
+            &translation_unit,
+            sequential_number_in_doc,
+            &self.requested_langs,
+        );
+        serde_json::to_writer(&mut self.writer, &record)?;
+        self.writer.write_all(b"\n")?;
+
+        Ok(())
+    }
+
+    fn finish(&mut self, _summary: &mut Summary) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Handler {
+    /// Lines are written as soon as the translation units are handled if the
+    /// writer is unbuffered (e.g. the standard output), so that the output
+    /// can be followed live.
+    pub fn new(writer: Box<dyn Write>, requested_langs: RequestedLangs) -> Handler {
+        Handler {
+            writer,
+            requested_langs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Handler;
+    use crate::handlers::conformance::{Conformance, SavedUnit};
+    use crate::types::RequestedLangs;
+    use std::cell::RefCell;
+    use std::io::{BufWriter, Write};
+    use std::rc::Rc;
+
+    /// The output, shared with the test once the handler is finished.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct Harness {
+        output: SharedBuffer,
+    }
+
+    impl Conformance for Harness {
+        type Handler = Handler;
+
+        fn handler(&mut self, requested_langs: RequestedLangs) -> Handler {
+            let writer = BufWriter::new(self.output.clone());
+            Handler::new(Box::new(writer), requested_langs)
+        }
+
+        fn saved_units(&mut self, _handler: &mut Handler) -> Vec<SavedUnit> {
+            let output = self.output.0.borrow();
+            std::str::from_utf8(&output)
+                .unwrap()
+                .lines()
+                .map(|line| {
+                    let record: serde_json::Value = serde_json::from_str(line).unwrap();
+                    let texts = record["texts"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|text| {
+                            (
+                                text["lang"].as_str().unwrap().to_string(),
+                                text["text"].as_str().unwrap().to_string(),
+                            )
+                        })
+                        .collect();
+                    SavedUnit::new(
+                        record["document"].as_str().unwrap(),
+                        record["sequential_number"].as_u64().unwrap() as u32,
+                        texts,
+                    )
+                })
+                .collect()
+        }
+    }
+
+    crate::handler_conformance_tests!(Harness::default());
+}
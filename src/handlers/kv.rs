@@ -0,0 +1,137 @@
+use crate::error::{DgtError, Result};
+
+use super::jsonl::Record;
+use crate::tmx_parser::TranslationUnit;
+use crate::types::{RequestedLangs, Summary, TranslationUnitHandler};
+
+/// Saves the translation units in a [sled](https://sled.rs) key-value store,
+/// for services which look up aligned texts by document and position.
+///
+/// Keys are the document name, a zero byte and the sequential number of the
+/// translation unit within the document (a big-endian `u32`), so that the
+/// translation units of a document can be read in order with a prefix scan.
+/// Values are the translation units serialized as JSON, in the same format as
+/// the lines of `--tee jsonl`.
+pub struct Handler {
+    db: sled::Db,
+
+    /// Config value provided by the user. Determines if a text in a given
+    /// language should be included in the output or skipped.
+    requested_langs: RequestedLangs,
+}
+
+impl TranslationUnitHandler for Handler {
+    fn handle(
+        &mut self,
+        translation_unit: TranslationUnit,
+        sequential_number_in_doc: u32,
+    ) -> Result<()> {
+        let doc_name = translation_unit.doc_name().ok_or(DgtError::MissingDocId)?;
+        let record = Record::new(
+            &translation_unit,
+            sequential_number_in_doc,
+            &self.requested_langs,
+        );
+        self.db.insert(
+            key(doc_name, sequential_number_in_doc),
+            serde_json::to_vec(&record)?,
+        )?;
+
+        Ok(())
+    }
+
+    fn finish(&mut self, _summary: &mut Summary) -> Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+impl Handler {
+    pub fn new(db: sled::Db, requested_langs: RequestedLangs) -> Handler {
+        Handler {
+            db,
+            requested_langs,
+        }
+    }
+}
+
+/// The key of a translation unit.
+fn key(doc_name: &str, sequential_number_in_doc: u32) -> Vec<u8> {
+    let mut key = Vec::with_capacity(doc_name.len() + 5);
+    key.extend_from_slice(doc_name.as_bytes());
+    key.push(0);
+    key.extend_from_slice(&sequential_number_in_doc.to_be_bytes());
+    key
+}
+
+#[cfg(test)]
+mod test {
+    use super::{key, Handler};
+    use crate::handlers::conformance::{Conformance, SavedUnit};
+    use crate::tmx_parser::parse_tmx;
+    use crate::types::{RequestedLangs, Summary, TranslationUnitHandler};
+
+    #[test]
+    fn units_are_looked_up_by_document_and_number() {
+        let tmx = parse_tmx(String::from(
+            r#"<tmx><header></header><body>
+            <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>First</seg></tuv><tuv lang="PL-01"><seg>Pierwszy</seg></tuv></tu>
+            <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>Second</seg></tuv><tuv lang="PL-01"><seg>Drugi</seg></tuv></tu>
+            </body></tmx>"#,
+        ))
+        .unwrap();
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let mut handler = Handler::new(db.clone(), RequestedLangs::Unlimited);
+        for (i, tu) in tmx.body.translation_units.into_iter().enumerate() {
+            handler.handle(tu, i as u32).unwrap();
+        }
+        handler.finish(&mut Summary::default()).unwrap();
+
+        let value = db.get(key("A", 1)).unwrap().unwrap();
+        assert_eq!(
+            std::str::from_utf8(&value).unwrap(),
+            r#"{"document":"A","sequential_number":1,"texts":[{"lang":"EN-GB","text":"Second"},{"lang":"PL-01","text":"Drugi"}]}"#
+        );
+        assert_eq!(db.scan_prefix(b"A\0").count(), 2);
+    }
+
+    struct Harness;
+
+    impl Conformance for Harness {
+        type Handler = Handler;
+
+        fn handler(&mut self, requested_langs: RequestedLangs) -> Handler {
+            let db = sled::Config::new().temporary(true).open().unwrap();
+            Handler::new(db, requested_langs)
+        }
+
+        fn saved_units(&mut self, handler: &mut Handler) -> Vec<SavedUnit> {
+            handler
+                .db
+                .iter()
+                .map(|entry| {
+                    let (key, value) = entry.unwrap();
+                    let separator = key.iter().position(|byte| *byte == 0).unwrap();
+                    let document = std::str::from_utf8(&key[..separator]).unwrap();
+                    let sequential_number =
+                        u32::from_be_bytes(key[separator + 1..].try_into().unwrap());
+                    let record: serde_json::Value = serde_json::from_slice(&value).unwrap();
+                    let texts = record["texts"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|text| {
+                            (
+                                text["lang"].as_str().unwrap().to_string(),
+                                text["text"].as_str().unwrap().to_string(),
+                            )
+                        })
+                        .collect();
+                    SavedUnit::new(document, sequential_number, texts)
+                })
+                .collect()
+        }
+    }
+
+    crate::handler_conformance_tests!(Harness);
+}
@@ -0,0 +1,168 @@
+use crate::error::Result;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::tmx_parser::{LangMatch, TranslationUnit};
+use crate::types::{RequestedLangs, RunInfo, Summary, TranslationUnitHandler};
+
+/// Number of translation units and whitespace-separated tokens shared by a
+/// pair of languages.
+#[derive(Default)]
+struct Edge {
+    shared_units: u64,
+    shared_tokens: u64,
+}
+
+/// Builds a language co-occurrence graph, saved as a CSV edge list once all
+/// translation units have been handled.
+pub struct Handler {
+    output_file: String,
+
+    /// Keyed by pairs of language codes, ordered alphabetically or, if a
+    /// source language has been selected, starting with the source language.
+    edges: BTreeMap<(String, String), Edge>,
+
+    /// If selected, only the edges connecting the source language with other
+    /// languages are included in the output.
+    source_lang: Option<String>,
+
+    /// Config value provided by the user. Determines if a text in a given
+    /// language should be included in the output or skipped.
+    requested_langs: RequestedLangs,
+}
+
+impl TranslationUnitHandler for Handler {
+    fn start(&mut self, run: &RunInfo) -> Result<()> {
+        self.source_lang = run.source_lang.clone();
+        Ok(())
+    }
+
+    fn handle(
+        &mut self,
+        translation_unit: TranslationUnit,
+        _sequential_number_in_doc: u32,
+    ) -> Result<()> {
+        let segments: Vec<(&String, u64)> = translation_unit
+            .segments
+            .iter()
+            .filter(|segment| !segment.content.is_empty())
+            .filter(|segment| match &self.requested_langs {
+                RequestedLangs::Unlimited => true,
+                RequestedLangs::Each(langs) | RequestedLangs::Some(langs) => {
+                    langs.contains(&segment.lang)
+                }
+            })
+            .map(|segment| {
+                let tokens = segment.content.split_whitespace().count() as u64;
+                (&segment.lang, tokens)
+            })
+            .collect();
+
+        for (i, (lang_a, tokens_a)) in segments.iter().enumerate() {
+            for (lang_b, tokens_b) in &segments[i + 1..] {
+                if lang_a == lang_b {
+                    continue;
+                }
+                let key = match &self.source_lang {
+                    Some(source_lang) if LangMatch::IgnoreCase.matches(source_lang, lang_a) => {
+                        (lang_a.to_string(), lang_b.to_string())
+                    }
+                    Some(source_lang) if LangMatch::IgnoreCase.matches(source_lang, lang_b) => {
+                        (lang_b.to_string(), lang_a.to_string())
+                    }
+                    Some(_) => continue,
+                    None => match lang_a < lang_b {
+                        true => (lang_a.to_string(), lang_b.to_string()),
+                        false => (lang_b.to_string(), lang_a.to_string()),
+                    },
+                };
+                let edge = self.edges.entry(key).or_default();
+                edge.shared_units += 1;
+                edge.shared_tokens += tokens_a + tokens_b;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, _summary: &mut Summary) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(&self.output_file)?);
+        writeln!(writer, "lang_a,lang_b,shared_units,shared_tokens")?;
+        for ((lang_a, lang_b), edge) in &self.edges {
+            writeln!(
+                writer,
+                "{},{},{},{}",
+                lang_a, lang_b, edge.shared_units, edge.shared_tokens
+            )?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+impl Handler {
+    pub fn new(output_file: String, requested_langs: RequestedLangs) -> Handler {
+        Handler {
+            output_file,
+            edges: BTreeMap::new(),
+            source_lang: None,
+            requested_langs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Handler;
+    use crate::tmx_parser::parse_tmx;
+    use crate::types::{RequestedLangs, TranslationUnitHandler};
+
+    #[test]
+    fn pairs_are_counted_once_per_unit() {
+        let tmx = parse_tmx(String::from(
+            r#"<tmx><header></header><body>
+            <tu><tuv lang="EN-GB"><seg>Two words</seg></tuv><tuv lang="PL-01"><seg>Dwa</seg></tuv><tuv lang="DE-DE"><seg></seg></tuv></tu>
+            <tu><tuv lang="PL-01"><seg>Jeden</seg></tuv><tuv lang="EN-GB"><seg>One</seg></tuv></tu>
+            </body></tmx>"#,
+        ))
+        .unwrap();
+        let mut handler = Handler::new(String::new(), RequestedLangs::Unlimited);
+        for (i, tu) in tmx.body.translation_units.into_iter().enumerate() {
+            handler.handle(tu, i as u32).unwrap();
+        }
+
+        assert_eq!(handler.edges.len(), 1);
+        let edge = handler
+            .edges
+            .get(&(String::from("EN-GB"), String::from("PL-01")))
+            .unwrap();
+        assert_eq!(edge.shared_units, 2);
+        assert_eq!(edge.shared_tokens, 5);
+    }
+
+    #[test]
+    fn edges_start_with_source_lang() {
+        let tmx = parse_tmx(String::from(
+            r#"<tmx><header></header><body>
+            <tu><tuv lang="EN-GB"><seg>A</seg></tuv><tuv lang="DE-DE"><seg>B</seg></tuv><tuv lang="PL-01"><seg>C</seg></tuv></tu>
+            </body></tmx>"#,
+        ))
+        .unwrap();
+        let mut handler = Handler::new(String::new(), RequestedLangs::Unlimited);
+        handler.source_lang = Some(String::from("PL-01"));
+        for tu in tmx.body.translation_units {
+            handler.handle(tu, 0).unwrap();
+        }
+
+        let keys: Vec<&(String, String)> = handler.edges.keys().collect();
+        assert_eq!(
+            keys,
+            vec![
+                &(String::from("PL-01"), String::from("DE-DE")),
+                &(String::from("PL-01"), String::from("EN-GB"))
+            ]
+        );
+    }
+}
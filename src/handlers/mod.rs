@@ -1 +1,16 @@
+pub mod adapters;
+pub mod all_pairs;
+pub mod classification;
+#[cfg(test)]
+pub mod conformance;
+pub mod external;
+pub mod html_review;
+pub mod jsonl;
+#[cfg(feature = "kv")]
+pub mod kv;
+pub mod lang_pairs;
+#[cfg(feature = "sqlite")]
 pub mod sqlite_db;
+pub mod verify;
+#[cfg(feature = "xlsx")]
+pub mod xlsx;
@@ -1,14 +1,278 @@
-use anyhow::{bail, Result};
-use regex::Regex;
-use rusqlite::{params, params_from_iter, Connection, ParamsFromIter};
-use std::collections::HashMap;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tempfile::TempPath;
 
-use crate::tmx_parser::TranslationUnit;
-use crate::types::{RequestedLangs, TranslationUnitHandler};
+use crate::error::{DgtError, Result};
+use crate::functions::{
+    eur_lex_url, fold_for_search, lang_code_to_db_column, normalize_doc_name, tmx_date_to_iso8601,
+    to_hex,
+};
+use crate::memory::MemoryUsage;
+use crate::progress::{ProgressEvent, ProgressEvents};
+use crate::props::{PropColumns, PropValue};
+use crate::tmx_parser::{TranslationUnit, Tuv};
+use crate::types::{MissingAs, PropType, RequestedLangs, RunInfo, Summary, TranslationUnitHandler};
 
 /// How many translation units to insert in one batch.
 const TRANSACTION_SIZE: usize = 20_000;
 
+/// Version of the database schema, stored in the `user_version` pragma.
+/// Databases created before versioning was introduced have version 0.
+pub const SCHEMA_VERSION: u32 = 9;
+
+/// `MIGRATIONS[n]` upgrades a database from schema version `n` to `n + 1`.
+/// Past migrations must not change, hence they do not reuse the statements
+/// creating the current schema.
+const MIGRATIONS: [&str; SCHEMA_VERSION as usize] = [
+    "
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY,
+        tool_version TEXT,
+        arguments TEXT,
+        input_hash TEXT,
+        started_at TEXT,
+        finished_at TEXT,
+        tmx_files INTEGER,
+        translation_units INTEGER,
+        skipped_translation_units INTEGER
+    )",
+    // Merge duplicate documents into the one with the lowest ID before the
+    // names are made unique.
+    "
+    UPDATE translation_units SET document_id = (
+        SELECT min(d2.id) FROM documents d1
+        JOIN documents d2 ON d1.name = d2.name
+        WHERE d1.id = translation_units.document_id
+    );
+    DELETE FROM documents WHERE id NOT IN (SELECT min(id) FROM documents GROUP BY name);
+    CREATE UNIQUE INDEX IF NOT EXISTS documents_name ON documents (name);
+    ",
+    "ALTER TABLE runs ADD COLUMN source_lang TEXT",
+    "
+    ALTER TABLE documents ADD COLUMN raw_name TEXT;
+    UPDATE documents SET raw_name = name;
+    ",
+    // An approximation of `eur_lex_url`, which cannot be called from SQL.
+    "
+    ALTER TABLE documents ADD COLUMN url TEXT;
+    UPDATE documents
+    SET url = 'https://eur-lex.europa.eu/legal-content/AUTO/?uri=CELEX:' || upper(trim(raw_name))
+    WHERE upper(trim(raw_name)) GLOB '[0-9CE][0-9][0-9][0-9][0-9][A-Z]*[0-9][0-9][0-9][0-9]*'
+    AND upper(trim(raw_name)) NOT GLOB '*[^0-9A-Z()-]*';
+    ",
+    "ALTER TABLE runs ADD COLUMN filters TEXT",
+    "
+    CREATE TABLE IF NOT EXISTS revisions (
+        id INTEGER PRIMARY KEY,
+        translation_unit_id INTEGER,
+        run_id INTEGER,
+        lang TEXT,
+        old_text_hash TEXT,
+        new_text TEXT,
+        revised_at TEXT
+    )",
+    // The total length lets the statistics be updated by each run, rather
+    // than measured again over the whole table.
+    "
+    CREATE TABLE IF NOT EXISTS column_stats (
+        column_name TEXT PRIMARY KEY,
+        texts INTEGER,
+        max_length INTEGER,
+        avg_length REAL
+    );
+    ALTER TABLE column_stats ADD COLUMN total_length INTEGER;
+    UPDATE column_stats SET total_length = CAST(round(avg_length * texts) AS INTEGER);
+    ",
+    "CREATE TABLE IF NOT EXISTS license (notice TEXT)",
+];
+
+const CREATE_RUNS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY,
+        tool_version TEXT,
+        arguments TEXT,
+        input_hash TEXT,
+        started_at TEXT,
+        finished_at TEXT,
+        tmx_files INTEGER,
+        translation_units INTEGER,
+        skipped_translation_units INTEGER,
+        source_lang TEXT,
+        filters TEXT
+    )";
+
+const CREATE_REVISIONS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS revisions (
+        id INTEGER PRIMARY KEY,
+        translation_unit_id INTEGER,
+        run_id INTEGER,
+        lang TEXT,
+        old_text_hash TEXT,
+        new_text TEXT,
+        revised_at TEXT
+    )";
+
+const CREATE_COLUMN_STATS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS column_stats (
+        column_name TEXT PRIMARY KEY,
+        texts INTEGER,
+        max_length INTEGER,
+        avg_length REAL,
+        total_length INTEGER
+    )";
+
+const CREATE_LICENSE_TABLE: &str = "CREATE TABLE IF NOT EXISTS license (notice TEXT)";
+
+/// How the texts of the translation units are stored.
+#[derive(Clone, Copy, Default, PartialEq, Debug, clap::ValueEnum)]
+pub enum LangStorage {
+    /// A column per language (e.g. `en_gb`), added as the languages are
+    /// found in the input.
+    #[default]
+    Columns,
+
+    /// A single `texts` column holding a JSON object which maps language
+    /// codes to texts, e.g. `{"EN-GB":"…","PL-01":"…"}`, so that the schema
+    /// does not depend on the languages. Missing and empty texts are left out.
+    Json,
+}
+
+/// Set the pragmas and load the extensions given in the options
+/// (`--pragma` and `--load-extension`) on a connection, before the handler
+/// uses it. Extension loading is enabled only while the extensions are
+/// loaded, so that SQL cannot load others.
+pub fn configure_connection(conn: &Connection, options: &Options) -> Result<()> {
+    for (name, value) in &options.pragmas {
+        conn.pragma_update(None, name, value)?;
+    }
+    for extension in &options.extensions {
+        // Safety: the extensions are given by the user, who trusts them as
+        // much as this program.
+        unsafe {
+            let _guard = rusqlite::LoadExtensionGuard::new(conn)?;
+            conn.load_extension(extension, None)?;
+        }
+    }
+    Ok(())
+}
+
+/// Configuration of the SQLite handler.
+#[derive(Default)]
+pub struct Options {
+    /// Commit the current batch once the texts it contains exceed this many
+    /// bytes, even if it holds fewer than [TRANSACTION_SIZE] translation
+    /// units.
+    pub max_batch_bytes: Option<usize>,
+
+    /// Whether missing and empty texts are saved as `NULL` or as empty
+    /// strings.
+    pub missing_as: MissingAs,
+
+    /// Save the creation and change dates of the texts.
+    pub dates: bool,
+
+    /// Remove parenthetical suffixes from document names.
+    pub strip_doc_name_suffixes: bool,
+
+    /// Only save the documents with their number of translation units and
+    /// texts per language, without the texts.
+    pub metadata_only: bool,
+
+    /// Save a copy of each text folded for search (see [fold_for_search]) in
+    /// a `<lang>_search` column.
+    pub search_columns: bool,
+
+    /// Save the language of the original text (see [crate::tmx_parser::Tuv::is_source]) in a
+    /// `srclang` column.
+    pub srclang_column: bool,
+
+    /// Number the documents in the order of their names rather than in the
+    /// order in which they were found (see [Handler::renumber_documents]).
+    pub deterministic: bool,
+
+    pub lang_storage: LangStorage,
+
+    /// Create [STRICT](https://www.sqlite.org/stricttables.html) tables, in
+    /// which values of the wrong type are rejected rather than stored.
+    pub strict: bool,
+
+    /// Link each translation unit to all of the documents it names (see
+    /// [TranslationUnit::doc_names]) in the `unit_documents` table. The
+    /// `document_id` column still holds the first document. Ignored with
+    /// [Options::metadata_only].
+    pub link_all_documents: bool,
+
+    /// Props saved in columns of their own (added as the props are found in
+    /// the input), and in the `props` column as JSON.
+    pub props: PropColumns,
+
+    /// The size of the current batch is recorded in it, and the batch is
+    /// committed early once the memory limit is exceeded (see
+    /// `--max-memory`).
+    pub memory: Option<Arc<MemoryUsage>>,
+
+    /// Emits a `batch_committed` event for each batch (see
+    /// `--progress-json`).
+    pub progress_events: ProgressEvents,
+
+    /// Update the translation units already in the database (with the same
+    /// document and sequential number) rather than adding them again,
+    /// recording changed texts in the `revisions` table (see
+    /// [Handler::revise_translation_unit]). Only used with
+    /// [Handler::append] and [LangStorage::Columns].
+    pub update_existing: bool,
+
+    /// Index the columns by which the tables are joined and run `ANALYZE`
+    /// once all translation units have been saved (see
+    /// [Handler::create_indexes]).
+    pub with_indexes: bool,
+
+    /// Pragmas set on the connection before the schema is created (see
+    /// [configure_connection]), as name and value.
+    pub pragmas: Vec<(String, String)>,
+
+    /// SQLite extensions loaded into the connection before the schema is
+    /// created (see [configure_connection]).
+    pub extensions: Vec<PathBuf>,
+}
+
+/// Collected for each document with [Options::metadata_only].
+#[derive(Default)]
+struct DocStats {
+    translation_units: u64,
+
+    /// Number of non-empty texts, keyed by language code.
+    texts: BTreeMap<String, u64>,
+}
+
+/// Collected for each language column (or each language, with
+/// [LangStorage::Json]) from the texts inserted by the current run, see
+/// [Handler::save_column_stats].
+#[derive(Default)]
+struct ColumnStats {
+    /// Number of non-empty texts.
+    texts: u64,
+
+    /// Maximum and total length of the texts, in characters.
+    max_length: u64,
+    total_length: u64,
+}
+
+impl ColumnStats {
+    fn add(&mut self, text: &str) {
+        let length = text.chars().count() as u64;
+        self.texts += 1;
+        self.max_length = self.max_length.max(length);
+        self.total_length += length;
+    }
+}
+
 pub struct Handler {
     /// SQLite connection.
     conn: Connection,
@@ -17,13 +281,33 @@ pub struct Handler {
     /// determine if a new column should be added.
     language_columns_in_db: Vec<String>,
 
-    /// Keeps track of document IDs (names) that are already in the database to
-    /// determine if a new document should be added.
+    /// Columns of the languages declared by the run (see
+    /// [Handler::create_lang_pair_views]), in their order.
+    declared_lang_columns: Vec<String>,
+
+    /// Keeps track of the columns with data derived from the translation
+    /// units (e.g. dates or flags) that are already in the database. Like the
+    /// language columns, they are only added if needed.
+    annotation_columns_in_db: Vec<String>,
+
+    /// Keeps track of document IDs (raw names, as found in the input) that
+    /// are already in the database to determine if a new document should be
+    /// added.
     docs_in_db: HashMap<String, u32>,
 
     /// Current batch of translation unit insert queries, which will be executed
-    /// in the next transaction.
-    queries: Vec<(String, ParamsFromIter<Vec<String>>)>,
+    /// in the next transaction, with the IDs of the documents to link the
+    /// inserted translation unit to (see [Options::link_all_documents]).
+    queries: Vec<(String, Vec<Value>, Vec<u32>)>,
+
+    /// Approximate size of the current batch (in bytes).
+    batch_bytes: usize,
+
+    /// Number of translation units inserted or revised by the current batch,
+    /// which may hold several queries per translation unit.
+    batch_units: u64,
+
+    options: Options,
 
     /// Config value provided by the user. Determines if a text in a given
     /// language should be included in the output or skipped.
@@ -31,71 +315,406 @@ pub struct Handler {
 
     /// Used to validate language codes (used a database columns).
     valid_lang_codes: Vec<String>,
+
+    /// ID of the row describing the current run in the `runs` table.
+    run_id: Option<i64>,
+
+    /// Keyed by document ID. Only used with [Options::metadata_only].
+    doc_stats: BTreeMap<u32, DocStats>,
+
+    /// Keyed by column name (or language code, with [LangStorage::Json]).
+    column_stats: BTreeMap<String, ColumnStats>,
+
+    /// Whether the statistics of the columns have to be measured over the
+    /// whole table, e.g. for a database created before they were saved.
+    measure_columns: bool,
+
+    /// Number following the last sequential number of each document already
+    /// in the database, keyed by document ID. Added to the numbers of the
+    /// appended translation units of the document, so that they continue
+    /// those of the previous runs. Not used with [Options::update_existing],
+    /// which looks up the existing units by their numbers.
+    next_numbers_in_db: HashMap<u32, u32>,
+
+    /// Translation units found in the database with different (or the
+    /// same) texts. Only used with [Options::update_existing].
+    revised_units: u64,
+    unchanged_units: u64,
+
+    /// Temporary database file which is written to the standard output once
+    /// finished, and then removed. See [Handler::to_stdout]. Declared after
+    /// `conn`, so that it is removed once the connection has been closed
+    /// (fields are dropped in order).
+    stdout_copy_of: Option<TempPath>,
 }
 
 impl TranslationUnitHandler for Handler {
-    fn handle(&mut self, translation_unit: TranslationUnit, sequential_number_in_doc: u32) {
+    fn start(&mut self, run: &RunInfo) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO runs (tool_version, arguments, input_hash, started_at, source_lang, filters)
+            VALUES (?, ?, ?, datetime(?, 'unixepoch'), ?, ?)",
+            params![
+                run.tool_version,
+                serde_json::to_string(&run.arguments)?,
+                run.input_hash,
+                run.started_at,
+                run.source_lang,
+                serde_json::to_string(&run.filters)?
+            ],
+        )?;
+        self.run_id = Some(self.conn.last_insert_rowid());
+        if let Some(attribution) = &run.attribution {
+            self.save_attribution(attribution)?;
+        }
+
+        Ok(())
+    }
+
+    /// The columns of the declared languages are created up front, so that
+    /// the order of the columns does not depend on the order in which the
+    /// languages first appear in the input.
+    fn declare_langs(&mut self, langs: &[String]) -> Result<()> {
+        if self.options.metadata_only || self.options.lang_storage == LangStorage::Json {
+            return Ok(());
+        }
+        for lang in langs {
+            let column = self.lang_code_to_db_column(lang)?;
+            if !self.language_columns_in_db.contains(&column) {
+                self.add_lang_column(&column)?;
+            }
+            self.declared_lang_columns.push(column);
+        }
+
+        Ok(())
+    }
+
+    fn handle(
+        &mut self,
+        translation_unit: TranslationUnit,
+        sequential_number_in_doc: u32,
+    ) -> Result<()> {
         self.handle_translation_unit(translation_unit, sequential_number_in_doc)
-            .unwrap();
+    }
+
+    fn finish(&mut self, summary: &mut Summary) -> Result<()> {
+        self.commit_translation_units()?;
+        self.options.props.report(summary);
+        summary.translation_units_revised = self.revised_units;
+        summary.translation_units_unchanged = self.unchanged_units;
+        if self.options.metadata_only {
+            self.save_doc_stats()?;
+        } else {
+            self.save_column_stats()?;
+            self.create_lang_pair_views()?;
+        }
+        if self.options.deterministic {
+            self.renumber_documents()?;
+        }
+        if self.options.with_indexes {
+            self.create_indexes()?;
+        }
+        if let Some(run_id) = self.run_id {
+            self.conn.execute(
+                "UPDATE runs SET
+                    finished_at = datetime(?, 'unixepoch'),
+                    tmx_files = ?,
+                    translation_units = ?,
+                    skipped_translation_units = ?
+                WHERE id = ?",
+                params![
+                    summary.finished_at,
+                    summary.tmx_files_parsed,
+                    summary.translation_units_handled,
+                    summary.translation_units_skipped,
+                    run_id
+                ],
+            )?;
+        }
+        if let Some(path) = &self.stdout_copy_of {
+            // With `journal_mode=WAL`, the committed transactions may still be
+            // in the `-wal` file, which is not copied.
+            self.conn
+                .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_row| Ok(()))?;
+            let mut stdout = std::io::stdout().lock();
+            std::io::copy(&mut File::open(path)?, &mut stdout)?;
+            stdout.flush()?;
+        }
+
+        Ok(())
     }
 }
 
 impl Handler {
-    pub fn new(conn: rusqlite::Connection, requested_langs: RequestedLangs) -> Handler {
-        let handler = Handler {
+    pub fn new(
+        conn: rusqlite::Connection,
+        requested_langs: RequestedLangs,
+        options: Options,
+    ) -> Result<Handler> {
+        let handler = Handler::with_connection(conn, requested_langs, options);
+        handler.setup()?;
+        Ok(handler)
+    }
+
+    /// Append translation units to a database created by a previous run.
+    /// The database is migrated to the current schema first. The existing
+    /// documents keep their IDs (translation units of a document already in
+    /// the database are assigned to it), and new documents get new IDs.
+    pub fn append(
+        mut conn: rusqlite::Connection,
+        requested_langs: RequestedLangs,
+        options: Options,
+    ) -> Result<Handler> {
+        migrate(&mut conn)?;
+        if options.update_existing && column_exists(&conn, "translation_units", "texts")? {
+            return Err(DgtError::ConflictingOutputs(
+                "cannot update a database storing the texts as JSON (--lang-storage json)",
+            ));
+        }
+        let mut handler = Handler::with_connection(conn, requested_langs, options);
+        handler.set_up_schema()?;
+        handler.load_existing_schema()?;
+        handler.measure_columns = handler.conn.query_row(
+            "SELECT NOT EXISTS (SELECT 1 FROM column_stats)
+            AND EXISTS (SELECT 1 FROM translation_units)",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(handler)
+    }
+
+    fn with_connection(
+        conn: rusqlite::Connection,
+        requested_langs: RequestedLangs,
+        options: Options,
+    ) -> Handler {
+        Handler {
             conn,
             language_columns_in_db: Vec::new(),
+            declared_lang_columns: Vec::new(),
+            annotation_columns_in_db: Vec::new(),
             queries: Vec::new(),
+            batch_bytes: 0,
+            batch_units: 0,
+            options,
             docs_in_db: HashMap::new(),
             requested_langs,
             valid_lang_codes: Vec::new(),
-        };
-        handler.setup();
-        handler
+            run_id: None,
+            revised_units: 0,
+            unchanged_units: 0,
+            doc_stats: BTreeMap::new(),
+            column_stats: BTreeMap::new(),
+            measure_columns: false,
+            next_numbers_in_db: HashMap::new(),
+            stdout_copy_of: None,
+        }
     }
 
-    fn setup(&self) -> () {
-        self.drop_table_if_exists();
-        self.set_up_schema();
+    /// Build the database in a temporary file and write it to the standard
+    /// output once all translation units have been handled, e.g. to pipe it
+    /// into another program.
+    pub fn to_stdout(requested_langs: RequestedLangs, options: Options) -> Result<Handler> {
+        let path = tempfile::Builder::new()
+            .prefix("dgt_parser-")
+            .suffix(".sqlite")
+            .tempfile()?
+            .into_temp_path();
+        let conn = Connection::open(&path)?;
+        configure_connection(&conn, &options)?;
+        let mut handler = Handler::new(conn, requested_langs, options)?;
+        handler.stdout_copy_of = Some(path);
+        Ok(handler)
     }
 
-    fn drop_table_if_exists(&self) -> () {
-        let query = format!("DROP TABLE IF EXISTS translation_units");
-        self.conn.execute(&query, []).unwrap();
+    fn setup(&self) -> Result<()> {
+        self.drop_table_if_exists()?;
+        self.set_up_schema()
     }
 
-    fn set_up_schema(&self) -> () {
-        let queries = vec![
-            format!(
+    /// Load the documents and the columns already in the database, so that
+    /// they are reused rather than inserted again.
+    fn load_existing_schema(&mut self) -> Result<()> {
+        let mut query = self
+            .conn
+            .prepare("SELECT IFNULL(raw_name, name), id FROM documents")?;
+        let docs = query.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        for doc in docs {
+            let (name, id): (String, u32) = doc?;
+            self.docs_in_db.insert(name, id);
+        }
+        if !self.options.update_existing {
+            let mut query = self.conn.prepare(
+                "SELECT document_id, MAX(sequential_number) + 1 FROM translation_units
+                GROUP BY document_id",
+            )?;
+            let numbers = query.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            for number in numbers {
+                let (doc_id, next_number): (u32, u32) = number?;
+                self.next_numbers_in_db.insert(doc_id, next_number);
+            }
+        }
+
+        let mut query = self
+            .conn
+            .prepare("SELECT name FROM pragma_table_info('translation_units')")?;
+        let columns = query.query_map([], |row| row.get(0))?;
+        for column in columns {
+            let column: String = column?;
+            match column.as_str() {
+                "id" | "document_id" | "sequential_number" | "texts" => continue,
+                // Normalized, so that e.g. an `EN_GB` column (column names
+                // are case-insensitive) is reused for `EN-GB`.
+                _ => match lang_code_to_db_column(&column) {
+                    Ok(lang_column) => self.language_columns_in_db.push(lang_column),
+                    Err(_) => self.annotation_columns_in_db.push(column),
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    fn drop_table_if_exists(&self) -> Result<()> {
+        let query = "DROP TABLE IF EXISTS translation_units";
+        self.conn.execute(query, [])?;
+
+        Ok(())
+    }
+
+    fn set_up_schema(&self) -> Result<()> {
+        let units_table = match (self.options.metadata_only, self.options.lang_storage) {
+            (false, LangStorage::Columns) => {
                 "
             CREATE TABLE IF NOT EXISTS translation_units (
                 id INTEGER PRIMARY KEY,
                 document_id INTEGER,
-                sequential_number NUMBER
+                sequential_number INTEGER
             )"
-            ),
+            }
+            (false, LangStorage::Json) => {
+                "
+            CREATE TABLE IF NOT EXISTS translation_units (
+                id INTEGER PRIMARY KEY,
+                document_id INTEGER,
+                sequential_number INTEGER,
+                texts TEXT
+            )"
+            }
+            (true, _) => {
+                "
+            CREATE TABLE IF NOT EXISTS document_languages (
+                document_id INTEGER,
+                lang TEXT,
+                texts INTEGER
+            )"
+            }
+        };
+        let mut queries = vec![
+            format!("{}{}", units_table, self.table_options()),
             format!(
                 "
             CREATE TABLE IF NOT EXISTS documents (
                 id INTEGER PRIMARY KEY,
-                name TEXT
-            )"
+                name TEXT,
+                raw_name TEXT,
+                url TEXT
+            ){}",
+                self.table_options()
             ),
+            String::from("CREATE UNIQUE INDEX IF NOT EXISTS documents_name ON documents (name)"),
+            format!("{}{}", CREATE_RUNS_TABLE, self.table_options()),
+            format!("{}{}", CREATE_REVISIONS_TABLE, self.table_options()),
+            format!("{}{}", CREATE_COLUMN_STATS_TABLE, self.table_options()),
+            format!("{}{}", CREATE_LICENSE_TABLE, self.table_options()),
+            format!("PRAGMA user_version = {}", SCHEMA_VERSION),
         ];
+        if self.options.update_existing {
+            // Translation units are looked up by their position.
+            queries.push(String::from(
+                "CREATE INDEX IF NOT EXISTS translation_units_position
+                ON translation_units (document_id, sequential_number)",
+            ));
+        }
+        if self.links_documents() {
+            queries.push(format!(
+                "
+            CREATE TABLE IF NOT EXISTS unit_documents (
+                translation_unit_id INTEGER,
+                document_id INTEGER,
+                PRIMARY KEY (translation_unit_id, document_id)
+            ){}",
+                self.table_options()
+            ));
+        }
 
         for query in queries {
-            self.conn
-                .execute(&query, [])
-                .expect("error setting up dgt table");
+            self.conn.execute(&query, [])?;
+        }
+
+        Ok(())
+    }
+
+    /// Save the reuse notice in the `license` table, replacing the notice of
+    /// a previous run.
+    fn save_attribution(&mut self, attribution: &str) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM license", [])?;
+        tx.execute("INSERT INTO license (notice) VALUES (?)", [attribution])?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Whether the `unit_documents` table is filled.
+    fn links_documents(&self) -> bool {
+        self.options.link_all_documents && !self.options.metadata_only
+    }
+
+    /// Appended to the statements creating tables.
+    fn table_options(&self) -> &'static str {
+        match self.options.strict {
+            true => " STRICT",
+            false => "",
         }
     }
 
-    fn add_lang_column(&mut self, column: &String) -> Result<()> {
-        let query = format!("ALTER TABLE translation_units ADD COLUMN {}", &column);
-        self.conn
-            .execute(&query, [])
-            .expect("Failed to add new column to database.");
-        self.language_columns_in_db.push(column.clone());
+    /// Add a column for a language, unless the table already has one of the
+    /// same name (which [Handler::load_existing_schema] did not recognize as
+    /// a language column), in which case it is reused.
+    fn add_lang_column(&mut self, column: &str) -> Result<()> {
+        self.language_columns_in_db.push(column.to_string());
+        if column_exists(&self.conn, "translation_units", column)? {
+            self.annotation_columns_in_db
+                .retain(|existing| !existing.eq_ignore_ascii_case(column));
+            return Ok(());
+        }
+        // The default value also applies to the rows inserted before the
+        // column was added.
+        let query = match self.options.missing_as {
+            MissingAs::Null => format!(
+                "ALTER TABLE translation_units ADD COLUMN {} TEXT",
+                quote_identifier(column)
+            ),
+            MissingAs::Empty => format!(
+                "ALTER TABLE translation_units ADD COLUMN {} TEXT NOT NULL DEFAULT ''",
+                quote_identifier(column)
+            ),
+        };
+        self.conn.execute(&query, [])?;
+
+        Ok(())
+    }
+
+    fn add_annotation_column(&mut self, column: &str, column_type: &str) -> Result<()> {
+        if !self.annotation_columns_in_db.iter().any(|c| c == column) {
+            let query = format!(
+                "ALTER TABLE translation_units ADD COLUMN {} {}",
+                quote_identifier(column),
+                column_type
+            );
+            self.conn.execute(&query, [])?;
+            self.annotation_columns_in_db.push(column.to_string());
+        }
 
         Ok(())
     }
@@ -105,128 +724,736 @@ impl Handler {
         tu: TranslationUnit,
         sequential_number_in_doc: u32,
     ) -> Result<()> {
-        self.insert_document(&tu)?;
-        let query = self.create_translation_unit_insert_query(&tu, sequential_number_in_doc)?;
-        self.queries.push(query);
-        if self.queries.len() > TRANSACTION_SIZE {
+        self.insert_documents(&tu)?;
+        if self.options.metadata_only {
+            return self.count_translation_unit(&tu);
+        }
+        if self.options.update_existing
+            && self.revise_translation_unit(&tu, sequential_number_in_doc)?
+        {
+            return self.commit_if_batch_is_full();
+        }
+        let (query, values) =
+            self.create_translation_unit_insert_query(&tu, sequential_number_in_doc)?;
+        self.batch_bytes += query.len()
+            + tu.segments
+                .iter()
+                .map(|segment| segment.content.len())
+                .sum::<usize>();
+        let linked_docs = match self.options.link_all_documents {
+            true => tu
+                .doc_names()
+                .into_iter()
+                .map(|doc_name| self.docs_in_db[doc_name])
+                .collect(),
+            false => Vec::new(),
+        };
+        self.queries.push((query, values, linked_docs));
+        self.batch_units += 1;
+
+        self.commit_if_batch_is_full()
+    }
+
+    /// Commit the current batch if it holds [TRANSACTION_SIZE] statements or
+    /// exceeds the byte or memory limits.
+    fn commit_if_batch_is_full(&mut self) -> Result<()> {
+        let batch_is_full = match self.options.max_batch_bytes {
+            Some(max_batch_bytes) => self.batch_bytes >= max_batch_bytes,
+            None => false,
+        };
+        let memory_is_full = match &self.options.memory {
+            Some(memory) => {
+                memory.set_batches(self.batch_bytes);
+                memory.is_over_limit()
+            }
+            None => false,
+        };
+        if self.queries.len() > TRANSACTION_SIZE || batch_is_full || memory_is_full {
             self.commit_translation_units()?;
         }
 
         Ok(())
     }
 
-    fn create_translation_unit_insert_query(
+    /// If the database already holds a translation unit with the same
+    /// document and sequential number, replace its texts which differ from
+    /// those of the translation unit, recording each change in the
+    /// `revisions` table: the hash of the old text (`NULL` if there was
+    /// none), the new text and the time. Texts missing from the translation
+    /// unit are kept. The columns derived from the replaced texts (see
+    /// [Handler::text_columns]) and from the translation unit (see
+    /// [Handler::unit_columns]) are saved again. Returns whether the
+    /// translation unit was found.
+    fn revise_translation_unit(
         &mut self,
         tu: &TranslationUnit,
         sequential_number_in_doc: u32,
-    ) -> Result<(String, ParamsFromIter<Vec<String>>)> {
-        let doc_name = match tu.doc_name() {
-            Some(doc) => doc.to_string(),
-            None => bail!("Error: no document ID provided for the translation segment."),
+    ) -> Result<bool> {
+        let doc_name = tu.doc_name().ok_or(DgtError::MissingDocId)?;
+
+        // The first text in each language is compared.
+        let mut segments: Vec<(&Tuv, String)> = Vec::new();
+        for segment in &tu.segments {
+            if segment.content.is_empty() || !self.lang_is_eligible(&segment.lang) {
+                continue;
+            }
+            let column = self.lang_code_to_db_column(&segment.lang)?;
+            if segments.iter().any(|(_, existing)| existing == &column) {
+                continue;
+            }
+            if !self.language_columns_in_db.contains(&column) {
+                self.add_lang_column(&column)?;
+            }
+            segments.push((segment, column));
+        }
+
+        // The existing texts are read at once. The query is the same for the
+        // translation units with the same languages, so it is reused.
+        let selected_columns: String = segments
+            .iter()
+            .map(|(_, column)| format!(", {}", quote_identifier(column)))
+            .collect();
+        let existing: Option<(i64, Vec<Option<String>>)> = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT id{} FROM translation_units
+                WHERE document_id = ? AND sequential_number = ?
+                ORDER BY id LIMIT 1",
+                selected_columns
+            ))?
+            .query_row(
+                params![self.docs_in_db[doc_name], sequential_number_in_doc],
+                |row| {
+                    let old_texts = (1..=segments.len())
+                        .map(|i| row.get(i))
+                        .collect::<rusqlite::Result<_>>()?;
+                    Ok((row.get(0)?, old_texts))
+                },
+            )
+            .optional()?;
+        let (unit_id, old_texts) = match existing {
+            Some(existing) => existing,
+            None => return Ok(false),
         };
 
-        #[derive(Clone)]
-        enum StringOrNumberValue {
-            StringValue(String),
-            NumberValue(u32),
+        let mut revised_columns: Vec<String> = Vec::new();
+        let mut columns: Vec<String> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+        for ((segment, column), old_text) in segments.into_iter().zip(old_texts) {
+            let old_text = old_text.filter(|text| !text.is_empty());
+            if old_text.as_deref() == Some(segment.content.as_str()) {
+                continue;
+            }
+            let old_text_hash = old_text.map(|text| to_hex(&Sha256::digest(text.as_bytes())));
+            // The dates the new text does not have are cleared.
+            for (derived_column, value) in self.text_columns(segment, &column) {
+                self.add_annotation_column(&derived_column, "TEXT")?;
+                columns.push(derived_column);
+                values.push(value.unwrap_or(Value::Null));
+            }
+            columns.push(column.clone());
+            values.push(Value::Text(segment.content.clone()));
+            self.queries.push((
+                String::from(
+                    "INSERT INTO revisions
+                    (translation_unit_id, run_id, lang, old_text_hash, new_text, revised_at)
+                    VALUES (?, ?, ?, ?, ?, datetime('now'))",
+                ),
+                vec![
+                    Value::Integer(unit_id),
+                    self.run_id.map_or(Value::Null, Value::Integer),
+                    Value::Text(segment.lang.clone()),
+                    old_text_hash.map_or(Value::Null, Value::Text),
+                    Value::Text(segment.content.clone()),
+                ],
+                Vec::new(),
+            ));
+            self.batch_bytes += 2 * segment.content.len();
+            revised_columns.push(column);
         }
+        if revised_columns.is_empty() {
+            self.unchanged_units += 1;
+            return Ok(true);
+        }
+        self.revised_units += 1;
+        self.batch_units += 1;
 
-        #[derive(Clone)]
-        struct InsertMap {
-            column: String,
-            value: StringOrNumberValue,
+        for (column, column_type, value) in self.unit_columns(tu) {
+            self.add_annotation_column(&column, column_type)?;
+            columns.push(column);
+            values.push(value);
         }
+        let assignments: Vec<String> = columns
+            .iter()
+            .map(|column| format!("{} = ?", quote_identifier(column)))
+            .collect();
+        values.push(Value::Integer(unit_id));
+        self.queries.push((
+            format!(
+                "UPDATE translation_units SET {} WHERE id = ?",
+                assignments.join(", ")
+            ),
+            values,
+            Vec::new(),
+        ));
+
+        Ok(true)
+    }
+
+    fn create_translation_unit_insert_query(
+        &mut self,
+        tu: &TranslationUnit,
+        sequential_number_in_doc: u32,
+    ) -> Result<(String, Vec<Value>)> {
+        let doc_name = match tu.doc_name() {
+            Some(doc) => doc.to_string(),
+            None => return Err(DgtError::MissingDocId),
+        };
 
-        let mut insert_map: Vec<InsertMap> = Vec::new();
+        // The values are bound as they are when the batch is committed, so
+        // they are not copied (nor formatted) again.
+        let mut columns: Vec<String> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        // Only used with `LangStorage::Json`. If a language occurs more than
+        // once, the first non-empty text is kept.
+        let mut texts = serde_json::Map::new();
 
         for el in &tu.segments {
             if !self.lang_is_eligible(&el.lang) {
                 continue;
             }
 
-            let lang_code = self.lang_code_to_db_column(&el.lang)?;
+            let lang_code = self.lang_code_to_db_column(&el.lang)?;
+
+            if self.options.lang_storage == LangStorage::Json {
+                if !el.content.is_empty() && !texts.contains_key(&el.lang) {
+                    texts.insert(el.lang.clone(), el.content.clone().into());
+                    self.column_stats
+                        .entry(el.lang.clone())
+                        .or_default()
+                        .add(&el.content);
+                }
+                continue;
+            }
+
+            if !&self.language_columns_in_db.contains(&lang_code) {
+                self.add_lang_column(&lang_code)?;
+            }
+
+            // Omitted values are filled in according to `missing_as`.
+            if el.content.is_empty() {
+                continue;
+            }
+
+            for (column, value) in self.text_columns(el, &lang_code) {
+                if let Some(value) = value {
+                    self.add_annotation_column(&column, "TEXT")?;
+                    columns.push(column);
+                    values.push(value);
+                }
+            }
+
+            self.column_stats
+                .entry(lang_code.clone())
+                .or_default()
+                .add(&el.content);
+            columns.push(lang_code);
+            values.push(Value::Text(el.content.clone()));
+        }
+
+        if self.options.lang_storage == LangStorage::Json {
+            columns.push(String::from("texts"));
+            values.push(Value::Text(serde_json::to_string(&texts)?));
+        }
+
+        for (column, column_type, value) in self.unit_columns(tu) {
+            self.add_annotation_column(&column, column_type)?;
+            columns.push(column);
+            values.push(value);
+        }
+
+        let doc_id = self.docs_in_db[&doc_name];
+        let first_number = self
+            .next_numbers_in_db
+            .get(&doc_id)
+            .copied()
+            .unwrap_or_default();
+        columns.push(String::from("sequential_number"));
+        values.push(Value::Integer(
+            (first_number + sequential_number_in_doc).into(),
+        ));
+
+        columns.push(String::from("document_id"));
+        values.push(Value::Integer(doc_id.into()));
+
+        // e.g.: `INSERT INTO translation_units ("en_gb","pl_01") VALUES (?,?);`
+        let columns: Vec<String> = columns.iter().map(|c| quote_identifier(c)).collect();
+        let query = format!(
+            "INSERT INTO translation_units ({}) VALUES ({});",
+            columns.join(","),
+            repeat_vars(values.len())
+        );
+
+        Ok((query, values))
+    }
+
+    /// Columns derived from a text saved in the `lang_code` column: its
+    /// creation and change dates (with [Options::dates]) and its folded copy
+    /// (with [Options::search_columns]). The dates the text does not have
+    /// are `None`.
+    fn text_columns(&self, tuv: &Tuv, lang_code: &str) -> Vec<(String, Option<Value>)> {
+        let mut columns = Vec::new();
+        if self.options.dates {
+            for (suffix, date) in [
+                ("created", &tuv.creation_date),
+                ("changed", &tuv.change_date),
+            ] {
+                columns.push((
+                    format!("{}_{}", lang_code, suffix),
+                    date.as_deref()
+                        .map(|date| Value::Text(tmx_date_to_iso8601(date))),
+                ));
+            }
+        }
+        if self.options.search_columns {
+            columns.push((
+                format!("{}_search", lang_code),
+                Some(Value::Text(fold_for_search(&tuv.content))),
+            ));
+        }
+        columns
+    }
+
+    /// Columns derived from the translation unit as a whole (e.g. its
+    /// annotations and props), with their types.
+    fn unit_columns(&mut self, tu: &TranslationUnit) -> Vec<(String, &'static str, Value)> {
+        let mut columns = Vec::new();
+        if let Some(cluster) = tu.annotations.near_duplicate_cluster {
+            columns.push((
+                String::from("near_duplicate_cluster"),
+                "INTEGER",
+                Value::Integer(cluster.into()),
+            ));
+        }
+
+        if let Some(flags) = tu.annotations.artifact_flags {
+            for (column, flag) in [
+                ("is_uppercase", flags.uppercase),
+                ("is_numeric", flags.numeric),
+                ("is_list_marker", flags.list_marker),
+            ] {
+                columns.push((String::from(column), "INTEGER", Value::Integer(flag.into())));
+            }
+        }
+
+        if let Some(score) = tu.annotations.alignment_score {
+            columns.push((String::from("alignment_score"), "REAL", Value::Real(score)));
+        }
+
+        if let Some(is_boilerplate) = tu.annotations.is_boilerplate {
+            columns.push((
+                String::from("is_boilerplate"),
+                "INTEGER",
+                Value::Integer(is_boilerplate.into()),
+            ));
+        }
+
+        if let Some(index) = tu.annotations.index_in_file {
+            columns.push((
+                String::from("index_in_file"),
+                "INTEGER",
+                Value::Integer(index.into()),
+            ));
+        }
+
+        if let Some(corpus) = &tu.annotations.corpus {
+            columns.push((String::from("corpus"), "TEXT", Value::Text(corpus.clone())));
+        }
+
+        // The language of the original text, e.g. `EN-GB`, if the TMX file
+        // declares it.
+        if let Some(source) = tu
+            .segments
+            .iter()
+            .find(|segment| segment.is_source && self.options.srclang_column)
+        {
+            columns.push((
+                String::from("srclang"),
+                "TEXT",
+                Value::Text(source.lang.clone()),
+            ));
+        }
+
+        let prop_values = self.options.props.values(tu);
+        for (i, value) in prop_values.into_iter().enumerate() {
+            let column = &self.options.props.columns[i];
+            let column_type = match column.column_type {
+                PropType::Text => "TEXT",
+                PropType::Integer => "INTEGER",
+            };
+            let value = match value {
+                Some(PropValue::Text(text)) => Value::Text(text.to_string()),
+                Some(PropValue::Integer(number)) => Value::Integer(number),
+                None => continue,
+            };
+            columns.push((column.column.clone(), column_type, value));
+        }
+        if let Some(props) = self.options.props.unmapped_json(tu) {
+            columns.push((String::from("props"), "TEXT", Value::Text(props)));
+        }
+        columns
+    }
+
+    /// Add the translation unit to the statistics of its document.
+    fn count_translation_unit(&mut self, tu: &TranslationUnit) -> Result<()> {
+        let doc_name = tu.doc_name().ok_or(DgtError::MissingDocId)?;
+        let doc_id = self.docs_in_db[doc_name];
+        let mut langs: Vec<&String> = Vec::new();
+        for segment in &tu.segments {
+            if !segment.content.is_empty() && self.lang_is_eligible(&segment.lang) {
+                langs.push(&segment.lang);
+            }
+        }
+        let stats = self.doc_stats.entry(doc_id).or_default();
+        stats.translation_units += 1;
+        // A language is counted once per translation unit.
+        langs.sort();
+        langs.dedup();
+        for lang in langs {
+            *stats.texts.entry(lang.clone()).or_default() += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Save the statistics collected with [Options::metadata_only]: the
+    /// number of translation units in the `translation_units` column of the
+    /// `documents` table, and the number of texts per language in the
+    /// `document_languages` table.
+    fn save_doc_stats(&mut self) -> Result<()> {
+        if !column_exists(&self.conn, "documents", "translation_units")? {
+            self.conn.execute(
+                "ALTER TABLE documents ADD COLUMN translation_units INTEGER",
+                [],
+            )?;
+        }
+        let tx = self.conn.transaction()?;
+        for (doc_id, stats) in &self.doc_stats {
+            tx.execute(
+                "UPDATE documents SET translation_units = ? WHERE id = ?",
+                params![stats.translation_units, doc_id],
+            )?;
+            for (lang, texts) in &stats.texts {
+                tx.execute(
+                    "INSERT INTO document_languages (document_id, lang, texts) VALUES (?, ?, ?)",
+                    params![doc_id, lang, texts],
+                )?;
+            }
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Index the columns by which the tables are joined and the translation
+    /// units are looked up, and gather statistics for the query planner, so
+    /// that the database is ready to be queried. Done once the rows have
+    /// been inserted, which is faster than updating the indexes as they are.
+    fn create_indexes(&mut self) -> Result<()> {
+        let mut queries = Vec::new();
+        if self.options.metadata_only {
+            queries.push(
+                "CREATE INDEX IF NOT EXISTS document_languages_document
+                ON document_languages (document_id)",
+            );
+        } else {
+            queries.push(
+                "CREATE INDEX IF NOT EXISTS translation_units_position
+                ON translation_units (document_id, sequential_number)",
+            );
+            if self.links_documents() {
+                queries.push(
+                    "CREATE INDEX IF NOT EXISTS unit_documents_document
+                    ON unit_documents (document_id)",
+                );
+            }
+        }
+        queries.push("ANALYZE");
+        for query in queries {
+            self.conn.execute_batch(query)?;
+        }
+
+        Ok(())
+    }
 
-            if !&self.language_columns_in_db.contains(&lang_code) {
-                self.add_lang_column(&lang_code)?;
+    /// Create a view for each pair of declared languages with the translation
+    /// units which have texts in both, e.g. `v_en_fr` with the
+    /// `document_id`, `sequential_number`, `en_gb` and `fr_fr` columns. Views
+    /// are named after the primary language subtags (or after the columns, if
+    /// two declared languages share the subtag), and the pairs are in the
+    /// order of declaration, i.e. the source language first.
+    fn create_lang_pair_views(&mut self) -> Result<()> {
+        let columns = &self.declared_lang_columns;
+        let view_part = |column: &String| -> String {
+            let subtag = column.split('_').next().unwrap_or(column);
+            let is_shared = columns
+                .iter()
+                .filter(|other| other.split('_').next() == Some(subtag))
+                .count()
+                > 1;
+            match is_shared {
+                true => column.clone(),
+                false => subtag.to_string(),
+            }
+        };
+        for (i, first) in columns.iter().enumerate() {
+            for second in &columns[i + 1..] {
+                // Empty texts (see `--missing-as`) are left out as well.
+                let view = format!("v_{}_{}", view_part(first), view_part(second));
+                let (first, second) = (quote_identifier(first), quote_identifier(second));
+                self.conn.execute(
+                    &format!(
+                        "CREATE VIEW IF NOT EXISTS {} AS
+                        SELECT document_id, sequential_number, {first}, {second}
+                        FROM translation_units
+                        WHERE {first} <> '' AND {second} <> ''",
+                        quote_identifier(&view),
+                    ),
+                    [],
+                )?;
             }
-
-            insert_map.push(InsertMap {
-                column: lang_code,
-                value: StringOrNumberValue::StringValue(el.content.clone()),
-            });
         }
 
-        insert_map.push(InsertMap {
-            column: String::from("sequential_number"),
-            value: StringOrNumberValue::NumberValue(sequential_number_in_doc),
-        });
+        Ok(())
+    }
 
-        insert_map.push(InsertMap {
-            column: String::from("document_id"),
-            value: StringOrNumberValue::NumberValue(*self.docs_in_db.get(&doc_name).unwrap()),
-        });
+    /// Save the number, maximum and average length (in characters) of the
+    /// non-empty texts of each language column (or of each language, with
+    /// [LangStorage::Json]) in the `column_stats` table, e.g. to choose
+    /// column types when moving the data into another database. The
+    /// statistics cover all rows: those of the texts inserted by the run are
+    /// added to those saved by previous runs. When texts were revised (or the
+    /// database has no statistics yet), the whole table is measured again.
+    fn save_column_stats(&mut self) -> Result<()> {
+        let measure_columns = self.measure_columns || self.revised_units > 0;
+        let stats = match measure_columns {
+            true => self.measure_columns()?,
+            false => std::mem::take(&mut self.column_stats),
+        };
 
-        let columns: Vec<String> = insert_map
-            .clone()
-            .iter()
-            .map(|el: &InsertMap| el.column.clone())
-            .collect();
+        let tx = self.conn.transaction()?;
+        if measure_columns {
+            tx.execute("DELETE FROM column_stats", [])?;
+        }
+        for (column, stats) in stats.into_iter().filter(|(_, stats)| stats.texts > 0) {
+            tx.execute(
+                "INSERT INTO column_stats (column_name, texts, max_length, total_length)
+                VALUES (?, ?, ?, ?)
+                ON CONFLICT (column_name) DO UPDATE SET
+                texts = texts + excluded.texts,
+                max_length = max(coalesce(max_length, 0), excluded.max_length),
+                total_length = coalesce(total_length, 0) + excluded.total_length",
+                params![column, stats.texts, stats.max_length, stats.total_length],
+            )?;
+        }
+        // Columns without texts are listed too.
+        for column in &self.language_columns_in_db {
+            tx.execute(
+                "INSERT OR IGNORE INTO column_stats (column_name, texts, total_length)
+                VALUES (?, 0, 0)",
+                [column],
+            )?;
+        }
+        tx.execute(
+            "UPDATE column_stats SET avg_length = CAST(total_length AS REAL) / texts
+            WHERE texts > 0",
+            [],
+        )?;
+        tx.commit()?;
 
-        let values: Vec<String> = insert_map
-            .iter()
-            .map(|el: &InsertMap| match &el.value {
-                StringOrNumberValue::StringValue(v) => format!("{}", v),
-                StringOrNumberValue::NumberValue(v) => format!("{}", v),
+        Ok(())
+    }
+
+    /// Measure the texts of all rows, see [Handler::save_column_stats].
+    fn measure_columns(&self) -> Result<BTreeMap<String, ColumnStats>> {
+        let columns = &self.language_columns_in_db;
+        let mut stats = BTreeMap::new();
+        let column_stats = |row: &rusqlite::Row, i: usize| -> rusqlite::Result<ColumnStats> {
+            let max_length: Option<u64> = row.get(i + 1)?;
+            Ok(ColumnStats {
+                texts: row.get(i)?,
+                max_length: max_length.unwrap_or_default(),
+                total_length: row.get(i + 2)?,
             })
-            .collect();
+        };
+        if self.options.lang_storage == LangStorage::Json {
+            // Measured per language code, the keys of the JSON objects.
+            let mut query = self.conn.prepare(
+                "SELECT key, count(*), max(length(value)), sum(length(value))
+                FROM translation_units, json_each(translation_units.texts)
+                GROUP BY key ORDER BY key",
+            )?;
+            let rows = query.query_map([], |row| Ok((row.get(0)?, column_stats(row, 1)?)))?;
+            for row in rows {
+                let (lang, lang_stats): (String, ColumnStats) = row?;
+                stats.insert(lang, lang_stats);
+            }
+        } else if !columns.is_empty() {
+            // All columns are measured in a single pass over the table.
+            let aggregates: Vec<String> = columns
+                .iter()
+                .map(|column| {
+                    format!(
+                        "count(NULLIF({0}, '')), max(length(NULLIF({0}, ''))), coalesce(sum(length(NULLIF({0}, ''))), 0)",
+                        quote_identifier(column)
+                    )
+                })
+                .collect();
+            let query = format!("SELECT {} FROM translation_units", aggregates.join(", "));
+            self.conn.query_row(&query, [], |row| {
+                for (i, column) in columns.iter().enumerate() {
+                    stats.insert(column.clone(), column_stats(row, i * 3)?);
+                }
+                Ok(())
+            })?;
+        }
 
-        // e.g.: `INSERT INTO translation_units (en_gb,pl_01) VALUES (?,?);`
-        let query = format!(
-            "INSERT INTO translation_units ({}) VALUES ({});",
-            columns.join(","),
-            repeat_vars(*&values.len())
-        );
-        let params = params_from_iter(values);
+        Ok(stats)
+    }
+
+    /// Renumber the documents in the order of their (normalized) names, so
+    /// that the IDs only depend on the set of documents, not on the order of
+    /// the input files or the file system. Names are compared byte by byte,
+    /// which does not depend on the locale either.
+    fn renumber_documents(&mut self) -> Result<()> {
+        let units_table = match self.options.metadata_only {
+            false => "translation_units",
+            true => "document_languages",
+        };
+        // Negated first, like the IDs of the documents, since each
+        // translation unit is linked to a document at most once.
+        let links_table_update = match self.links_documents() {
+            true => {
+                "UPDATE unit_documents SET document_id =
+                -(SELECT new_id FROM document_ids WHERE old_id = unit_documents.document_id);
+                UPDATE unit_documents SET document_id = -document_id;"
+            }
+            false => "",
+        };
+        let tx = self.conn.transaction()?;
+        tx.execute_batch(&format!(
+            "
+            CREATE TEMP TABLE document_ids AS
+                SELECT id AS old_id, row_number() OVER (ORDER BY name COLLATE BINARY) AS new_id
+                FROM documents;
+            CREATE UNIQUE INDEX temp.document_ids_old_id ON document_ids (old_id);
+            UPDATE {0} SET document_id =
+                (SELECT new_id FROM document_ids WHERE old_id = {0}.document_id);
+            {1}
+            -- Negated first, so that the new IDs do not collide with the old ones.
+            UPDATE documents SET id =
+                -(SELECT new_id FROM document_ids WHERE old_id = documents.id);
+            UPDATE documents SET id = -id;
+            DROP TABLE document_ids;
+            ",
+            units_table, links_table_update
+        ))?;
+        tx.commit()?;
 
-        Ok((query, params))
+        Ok(())
     }
 
     /// Take the current batch of queries and commit them into the database.
+    /// The last batch is committed by [TranslationUnitHandler::finish]; a
+    /// handler dropped without it (e.g. when the run fails) discards it.
     fn commit_translation_units(&mut self) -> Result<()> {
+        if self.queries.is_empty() {
+            return Ok(());
+        }
         let tx = self.conn.transaction()?;
-        for query in &self.queries {
-            tx.execute(&query.0, query.1.clone())?;
+        for (query, values, linked_docs) in self.queries.drain(..) {
+            // The queries of translation units with the same languages are
+            // identical, so their statements are reused.
+            tx.prepare_cached(&query)?
+                .execute(params_from_iter(values))?;
+            if !linked_docs.is_empty() {
+                let unit_id = tx.last_insert_rowid();
+                let mut link = tx.prepare_cached(
+                    "INSERT OR IGNORE INTO unit_documents (translation_unit_id, document_id)
+                    VALUES (?, ?)",
+                )?;
+                for doc_id in linked_docs {
+                    link.execute(params![unit_id, doc_id])?;
+                }
+            }
         }
         tx.commit()?;
-        self.queries.clear();
+        self.batch_bytes = 0;
+        if let Some(memory) = &self.options.memory {
+            memory.set_batches(0);
+        }
+        self.options
+            .progress_events
+            .emit(ProgressEvent::BatchCommitted {
+                translation_units: self.batch_units,
+            });
+        self.batch_units = 0;
 
         Ok(())
     }
 
-    /// If the translation unit is the child of a document that doesn’t yet
-    /// exist in the database, insert the document into the database.
-    fn insert_document(&mut self, translation_unit: &TranslationUnit) -> Result<()> {
-        if let Some(doc_name) = translation_unit.doc_name() {
-            if let None = self.docs_in_db.get(doc_name) {
-                let mut query = self
-                    .conn
-                    .prepare("INSERT INTO documents (name) VALUES (?)")?;
-                query.execute(params![doc_name])?;
-                let id: u32 = self.conn.query_row(
-                    "SELECT id FROM documents WHERE name = ?",
-                    params![doc_name],
-                    |row| Ok(row.get(0)),
-                )??;
+    /// Insert the document of the translation unit (or, with
+    /// [Options::link_all_documents], each of its documents) into the
+    /// database, unless it already exists.
+    fn insert_documents(&mut self, translation_unit: &TranslationUnit) -> Result<()> {
+        let doc_names = match self.links_documents() {
+            true => translation_unit.doc_names(),
+            false => translation_unit.doc_name().into_iter().collect(),
+        };
+        for doc_name in doc_names {
+            self.insert_document(doc_name)?;
+        }
+
+        Ok(())
+    }
 
-                self.docs_in_db.insert(doc_name.clone(), id);
+    /// If the document doesn’t yet exist in the database, insert it into the
+    /// database. The document is saved under its normalized name (see
+    /// [normalize_doc_name]), with the name found in the input in
+    /// `raw_name` and its EUR-Lex URL (see [eur_lex_url]) in `url`.
+    fn insert_document(&mut self, doc_name: &String) -> Result<()> {
+        if !self.docs_in_db.contains_key(doc_name) {
+            let name = normalize_doc_name(doc_name, self.options.strip_doc_name_suffixes);
+            // Nothing is returned if the document already exists, e.g.
+            // when it was inserted by another process, or when another
+            // raw name has the same normalized name.
+            let inserted_id: Option<u32> = self
+                .conn
+                .query_row(
+                    "INSERT INTO documents (name, raw_name, url) VALUES (?, ?, ?)
+                    ON CONFLICT (name) DO NOTHING RETURNING id",
+                    params![name, doc_name, eur_lex_url(doc_name)],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let id: u32 = match inserted_id {
+                Some(id) => id,
+                None => self.conn.query_row(
+                    "SELECT id FROM documents WHERE name = ?",
+                    params![name],
+                    |row| row.get(0),
+                )?,
             };
+
+            self.docs_in_db.insert(doc_name.clone(), id);
         }
 
         Ok(())
     }
 
     /// Determine if the text in a language should be included in the output.
-    fn lang_is_eligible(&mut self, lang_code: &String) -> bool {
+    fn lang_is_eligible(&mut self, lang_code: &str) -> bool {
         match &self.requested_langs {
             RequestedLangs::Unlimited => true,
             RequestedLangs::Each(langs) | RequestedLangs::Some(langs) => langs.contains(lang_code),
@@ -239,25 +1466,61 @@ impl Handler {
     /// - `EN-GB` => `en_gb`
     /// - `PL-01` => `pl_01`
     fn lang_code_to_db_column(&mut self, lang_code: &str) -> Result<String> {
-        let lang_code = lang_code.to_ascii_lowercase().replace("-", "_");
-        if self.valid_lang_codes.contains(&lang_code) {
-            return Ok(lang_code);
+        let column = lang_code.to_ascii_lowercase().replace('-', "_");
+        if self.valid_lang_codes.contains(&column) {
+            Ok(column)
         } else {
-            let lang_code_regex = Regex::new(r"^\w{2}(-|_)(\w|\d){2}$")?;
-            if lang_code_regex.is_match(&lang_code) {
-                self.valid_lang_codes.push(lang_code.clone());
-                Ok(lang_code)
-            } else {
-                bail!("Error: invalid language code: {}.", lang_code);
-            }
+            let column = lang_code_to_db_column(lang_code)?;
+            self.valid_lang_codes.push(column.clone());
+            Ok(column)
         }
     }
 }
 
-impl Drop for Handler {
-    fn drop(&mut self) {
-        self.commit_translation_units().unwrap();
+/// Quote an identifier (e.g. a column or view name) for SQLite, so that any
+/// name (e.g. a column renamed with `--prop-column`) yields valid SQL:
+/// `en_gb` => `"en_gb"`, `a"b` => `"a""b"`. Every identifier interpolated
+/// into a query is quoted with it.
+pub fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Check whether a table in the database has a column with the given name
+/// (compared case-insensitively, as SQLite does).
+pub fn column_exists(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut query =
+        conn.prepare("SELECT 1 FROM pragma_table_info(?) WHERE name = ? COLLATE NOCASE")?;
+    Ok(query.exists(params![table, column])?)
+}
+
+/// Upgrade a database created by an older version of the tool to the current
+/// schema version. Returns the schema versions before and after the
+/// migration.
+pub fn migrate(conn: &mut Connection) -> Result<(u32, u32)> {
+    let version: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > SCHEMA_VERSION {
+        return Err(DgtError::UnsupportedSchemaVersion {
+            found: version,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    let has_translation_units: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'translation_units')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_translation_units {
+        return Err(DgtError::NotDgtDatabase);
+    }
+
+    let tx = conn.transaction()?;
+    for migration in &MIGRATIONS[version as usize..] {
+        tx.execute_batch(migration)?;
     }
+    tx.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))?;
+    tx.commit()?;
+
+    Ok((version, SCHEMA_VERSION))
 }
 
 /// Helper function to return a comma-separated sequence of `?`. See
@@ -284,32 +1547,38 @@ mod test {
     use anyhow::Result;
 
     use crate::{
-        functions::{for_each_tmx_file_in_zip, for_each_zip, read_utf16_file_to_string},
-        tmx_parser::{parse_tmx, Tmx},
+        error::DgtError,
+        functions::{for_each_tmx_file, parse_tmx_file, Walk},
         types::TranslationUnitHandler,
     };
 
-    use super::Handler;
+    use super::{
+        column_exists, lang_code_to_db_column, migrate, quote_identifier, Handler, Options,
+        SCHEMA_VERSION,
+    };
+    use crate::handlers::conformance::{Conformance, SavedUnit, LANGS};
+    use crate::props::PropColumns;
+    use crate::types::{PropColumn, PropType, RequestedLangs};
 
     fn setup() -> Handler {
         let conn = rusqlite::Connection::open_in_memory().unwrap();
         let langs = crate::types::RequestedLangs::Unlimited;
-        let mut handler = Handler::new(conn, langs);
+        let mut handler = Handler::new(conn, langs, Options::default()).unwrap();
         let input_dir = PathBuf::from("./test_data/zipped");
         let mut parsed_translation_units = 0;
         let mut parsed_tmx_files = 0;
-        for_each_zip(&input_dir, &mut |mut zip_archive| {
-            for_each_tmx_file_in_zip(&mut zip_archive, &mut |mut tmx_file| {
-                parsed_tmx_files += 1;
-                let tmx_contents = read_utf16_file_to_string(&mut tmx_file)?;
-                let Tmx { body, header: _ } = parse_tmx(tmx_contents)?;
-                for (i, tu) in body.translation_units.into_iter().enumerate() {
-                    parsed_translation_units += 1;
-                    handler.handle(tu, i as u32);
-                }
-                Ok(())
-            })?;
-
+        for_each_tmx_file(&input_dir, &mut Walk::default(), &mut |tmx_file| {
+            parsed_tmx_files += 1;
+            let mut contents: Vec<u8> = Vec::new();
+            tmx_file.reader.read_to_end(&mut contents)?;
+            for (i, tu) in parse_tmx_file(contents)?
+                .translation_units
+                .into_iter()
+                .enumerate()
+            {
+                parsed_translation_units += 1;
+                handler.handle(tu, i as u32).unwrap();
+            }
             Ok(())
         })
         .unwrap();
@@ -330,6 +1599,23 @@ mod test {
         Ok(result)
     }
 
+    /// Save translation units (`<tu>` elements) in an in-memory database,
+    /// numbered in order, with the options.
+    fn save_units(options: Options, units: &str) -> Result<Handler> {
+        let conn = rusqlite::Connection::open_in_memory()?;
+        let mut handler = Handler::new(conn, RequestedLangs::Unlimited, options)?;
+        let tmx = crate::tmx_parser::parse_tmx(format!(
+            "<tmx><header></header><body>{}</body></tmx>",
+            units
+        ))?;
+        for (i, tu) in tmx.body.translation_units.into_iter().enumerate() {
+            handler.handle(tu, i as u32)?;
+        }
+        handler.finish(&mut crate::types::Summary::default())?;
+
+        Ok(handler)
+    }
+
     #[test]
     fn correct_number_of_translation_units_parsed() -> Result<()> {
         let mut handler = setup();
@@ -378,20 +1664,16 @@ mod test {
     fn english_text_of_each_translation_unit_is_identical_to_tmx() {
         let mut english_texts: Vec<String> = Vec::new();
         let input_dir = PathBuf::from("./test_data/zipped");
-        for_each_zip(&input_dir, &mut |mut zip_archive| {
-            for_each_tmx_file_in_zip(&mut zip_archive, &mut |mut tmx_file| {
-                let tmx_contents = read_utf16_file_to_string(&mut tmx_file)?;
-                let Tmx { body, header: _ } = parse_tmx(tmx_contents)?;
-                for (_i, tu) in body.translation_units.into_iter().enumerate() {
-                    for segment in tu.segments {
-                        if segment.lang == "EN-GB" {
-                            english_texts.push(segment.content);
-                        }
+        for_each_tmx_file(&input_dir, &mut Walk::default(), &mut |tmx_file| {
+            let mut contents: Vec<u8> = Vec::new();
+            tmx_file.reader.read_to_end(&mut contents)?;
+            for tu in parse_tmx_file(contents)?.translation_units.into_iter() {
+                for segment in tu.segments {
+                    if segment.lang == "EN-GB" {
+                        english_texts.push(segment.content);
                     }
                 }
-                Ok(())
-            })?;
-
+            }
             Ok(())
         })
         .unwrap();
@@ -415,4 +1697,630 @@ mod test {
             assert_eq!(text, english_texts_in_db.get(i).unwrap().to_string());
         }
     }
+
+    #[test]
+    fn unversioned_database_is_migrated() -> Result<()> {
+        let mut conn = rusqlite::Connection::open_in_memory()?;
+        conn.execute_batch(
+            "
+            CREATE TABLE translation_units (id INTEGER PRIMARY KEY, document_id INTEGER, sequential_number NUMBER);
+            CREATE TABLE documents (id INTEGER PRIMARY KEY, name TEXT);
+            ",
+        )?;
+
+        assert_eq!(migrate(&mut conn)?, (0, SCHEMA_VERSION));
+        assert_eq!(migrate(&mut conn)?, (SCHEMA_VERSION, SCHEMA_VERSION));
+        let runs: u32 = conn.query_row("SELECT count(*) FROM runs", [], |row| row.get(0))?;
+        assert_eq!(runs, 0);
+        for (table, column) in [("column_stats", "total_length"), ("license", "notice")] {
+            assert!(column_exists(&conn, table, column)?);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn newer_database_is_not_migrated() -> Result<()> {
+        let mut conn = rusqlite::Connection::open_in_memory()?;
+        conn.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION + 1))?;
+
+        assert!(matches!(
+            migrate(&mut conn),
+            Err(DgtError::UnsupportedSchemaVersion { found, .. }) if found == SCHEMA_VERSION + 1
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_documents_are_merged_by_migration() -> Result<()> {
+        let mut conn = rusqlite::Connection::open_in_memory()?;
+        conn.execute_batch(
+            "
+            CREATE TABLE translation_units (id INTEGER PRIMARY KEY, document_id INTEGER, sequential_number NUMBER);
+            CREATE TABLE documents (id INTEGER PRIMARY KEY, name TEXT);
+            CREATE TABLE runs (id INTEGER PRIMARY KEY);
+            INSERT INTO documents (id, name) VALUES (1, 'A'), (2, 'B'), (3, 'A');
+            INSERT INTO translation_units (document_id, sequential_number) VALUES (1, 0), (2, 0), (3, 1);
+            PRAGMA user_version = 1;
+            ",
+        )?;
+        migrate(&mut conn)?;
+
+        let documents: u32 =
+            conn.query_row("SELECT count(*) FROM documents", [], |row| row.get(0))?;
+        let units_in_a: u32 = conn.query_row(
+            "SELECT count(*) FROM translation_units WHERE document_id = 1",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(documents, 2);
+        assert_eq!(units_in_a, 2);
+        assert!(conn
+            .execute("INSERT INTO documents (name) VALUES ('A')", [])
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn identifiers_are_quoted() -> Result<()> {
+        assert_eq!(quote_identifier("en_gb"), r#""en_gb""#);
+        assert_eq!(quote_identifier(r#"a"b"#), r#""a""b""#);
+
+        // A column named like a keyword, with a quote and a space.
+        let options = Options {
+            props: PropColumns::new(
+                vec![PropColumn {
+                    key: String::from("Txt::Note"),
+                    column: String::from(r#"select "note""#),
+                    column_type: PropType::Text,
+                }],
+                false,
+            ),
+            ..Default::default()
+        };
+        let handler = save_units(
+            options,
+            r#"
+            <tu><prop type="Txt::Doc. No.">A</prop><prop type="Txt::Note">x</prop><tuv lang="EN-GB"><seg>Annex</seg></tuv></tu>
+            "#,
+        )?;
+
+        let note: String = handler.conn.query_row(
+            &format!(
+                "SELECT {} FROM translation_units",
+                quote_identifier(r#"select "note""#)
+            ),
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(note, "x");
+
+        Ok(())
+    }
+
+    #[test]
+    fn eur_lex_urls_are_added_by_migration() -> Result<()> {
+        let mut conn = rusqlite::Connection::open_in_memory()?;
+        conn.execute_batch(
+            "
+            CREATE TABLE translation_units (id INTEGER PRIMARY KEY, document_id INTEGER);
+            CREATE TABLE documents (id INTEGER PRIMARY KEY, name TEXT, raw_name TEXT);
+            CREATE TABLE runs (id INTEGER PRIMARY KEY, source_lang TEXT);
+            INSERT INTO documents (name, raw_name)
+            VALUES ('32019R0557', '32019r0557 '), ('22019A0315(01)', '22019A0315(01)'), ('X', 'X');
+            PRAGMA user_version = 4;
+            ",
+        )?;
+        migrate(&mut conn)?;
+
+        let mut statement = conn.prepare("SELECT raw_name, url FROM documents ORDER BY id")?;
+        let documents = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+        })?;
+        for document in documents {
+            let (raw_name, url) = document?;
+            assert_eq!(url, crate::functions::eur_lex_url(&raw_name));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn texts_are_stored_as_json() -> Result<()> {
+        let options = Options {
+            lang_storage: super::LangStorage::Json,
+            ..Default::default()
+        };
+        let mut handler = save_units(
+            options,
+            r#"
+            <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>Annex</seg></tuv><tuv lang="PL-01"><seg>Załącznik</seg></tuv></tu>
+            <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>Article 1</seg></tuv><tuv lang="PL-01"><seg></seg></tuv></tu>
+            "#,
+        )?;
+
+        let texts: Vec<String> = handler
+            .conn
+            .prepare("SELECT texts FROM translation_units ORDER BY id")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        assert_eq!(
+            texts,
+            vec![
+                r#"{"EN-GB":"Annex","PL-01":"Załącznik"}"#,
+                r#"{"EN-GB":"Article 1"}"#
+            ]
+        );
+        assert!(!column_exists(&handler.conn, "translation_units", "en_gb")?);
+        assert_eq!(
+            query_number(
+                &mut handler,
+                "SELECT max_length FROM column_stats WHERE column_name = 'PL-01'"
+            )?,
+            9
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn documents_are_numbered_by_name() -> Result<()> {
+        for metadata_only in [false, true] {
+            let options = Options {
+                deterministic: true,
+                metadata_only,
+                ..Default::default()
+            };
+            let mut handler = save_units(
+                options,
+                r#"
+                <tu><prop type="Txt::Doc. No.">C</prop><tuv lang="EN-GB"><seg>C1</seg></tuv></tu>
+                <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>A1</seg></tuv></tu>
+                <tu><prop type="Txt::Doc. No.">B</prop><tuv lang="EN-GB"><seg>B1</seg></tuv></tu>
+                <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>A2</seg></tuv></tu>
+                "#,
+            )?;
+
+            let names: Vec<String> = handler
+                .conn
+                .prepare("SELECT name FROM documents ORDER BY id")?
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()?;
+            assert_eq!(names, vec!["A", "B", "C"]);
+            let query = match metadata_only {
+                false => "SELECT count(*) FROM translation_units WHERE document_id = 1",
+                true => "SELECT texts FROM document_languages WHERE document_id = 1",
+            };
+            assert_eq!(query_number(&mut handler, query)?, 2);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn document_names_are_normalized() -> Result<()> {
+        let options = Options {
+            strip_doc_name_suffixes: true,
+            ..Default::default()
+        };
+        let mut handler = save_units(
+            options,
+            r#"
+            <tu><prop type="Txt::Doc. No.">32019r0557</prop><tuv lang="EN-GB"><seg>A</seg></tuv></tu>
+            <tu><prop type="Txt::Doc. No.">32019R0557(01)</prop><tuv lang="EN-GB"><seg>B</seg></tuv></tu>
+            "#,
+        )?;
+
+        let (documents, name, raw_name): (u32, String, String) = handler.conn.query_row(
+            "SELECT count(*), name, raw_name FROM documents",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        assert_eq!(documents, 1);
+        assert_eq!(name, "32019R0557");
+        assert_eq!(raw_name, "32019r0557");
+        assert_eq!(
+            query_number(&mut handler, "SELECT count(*) FROM translation_units")?,
+            2
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn search_columns_are_folded() -> Result<()> {
+        let options = Options {
+            search_columns: true,
+            ..Default::default()
+        };
+        let mut handler = save_units(
+            options,
+            r#"
+            <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>Source</seg></tuv><tuv lang="PL-01"><seg>Źródło</seg></tuv></tu>
+            "#,
+        )?;
+
+        assert_eq!(
+            query_number(
+                &mut handler,
+                "SELECT count(*) FROM translation_units
+                WHERE pl_01_search LIKE '%zrodl%' AND en_gb_search = 'source'"
+            )?,
+            1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn column_stats_are_saved() -> Result<()> {
+        let options = Options {
+            missing_as: crate::types::MissingAs::Empty,
+            ..Default::default()
+        };
+        let handler = save_units(
+            options,
+            r#"
+            <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>Annex</seg></tuv><tuv lang="PL-01"><seg>Załącznik</seg></tuv></tu>
+            <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>Article 1</seg></tuv><tuv lang="PL-01"><seg></seg></tuv></tu>
+            "#,
+        )?;
+
+        let stats = |column: &str| -> Result<(u64, u64, f64)> {
+            Ok(handler.conn.query_row(
+                "SELECT texts, max_length, avg_length FROM column_stats WHERE column_name = ?",
+                [column],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )?)
+        };
+        assert_eq!(stats("en_gb")?, (2, 9, 7.0));
+        assert_eq!(stats("pl_01")?, (1, 9, 9.0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn column_stats_are_updated_by_appending_runs() -> Result<()> {
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("db.sqlite");
+        let run = |units: Option<&str>, append: bool| -> Result<Handler> {
+            let conn = rusqlite::Connection::open(&path)?;
+            let requested_langs = crate::types::RequestedLangs::Unlimited;
+            let mut handler = match append {
+                false => Handler::new(conn, requested_langs, Options::default())?,
+                true => Handler::append(conn, requested_langs, Options::default())?,
+            };
+            if let Some(units) = units {
+                let tmx = crate::tmx_parser::parse_tmx(format!(
+                    "<tmx><header></header><body>{}</body></tmx>",
+                    units
+                ))?;
+                for (i, tu) in tmx.body.translation_units.into_iter().enumerate() {
+                    handler.handle(tu, i as u32)?;
+                }
+            }
+            handler.finish(&mut crate::types::Summary::default())?;
+            Ok(handler)
+        };
+        let stats = |handler: &Handler| -> Result<Vec<(String, u64, Option<u64>)>> {
+            Ok(handler
+                .conn
+                .prepare("SELECT column_name, texts, max_length FROM column_stats ORDER BY 1")?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+                .collect::<rusqlite::Result<_>>()?)
+        };
+
+        run(
+            Some(
+                r#"<tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>Annex</seg></tuv></tu>"#,
+            ),
+            false,
+        )?;
+        let handler = run(
+            Some(
+                r#"<tu><prop type="Txt::Doc. No.">B</prop><tuv lang="EN-GB"><seg>Decision</seg></tuv><tuv lang="PL-01"><seg>Decyzja</seg></tuv></tu>"#,
+            ),
+            true,
+        )?;
+        let appended = vec![
+            (String::from("en_gb"), 2, Some(8)),
+            (String::from("pl_01"), 1, Some(7)),
+        ];
+        assert_eq!(stats(&handler)?, appended);
+        let avg_length: f64 = handler.conn.query_row(
+            "SELECT avg_length FROM column_stats WHERE column_name = 'en_gb'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(avg_length, 6.5);
+
+        // A database without statistics is measured as a whole.
+        handler.conn.execute("DELETE FROM column_stats", [])?;
+        let handler = run(None, true)?;
+        assert_eq!(stats(&handler)?, appended);
+
+        Ok(())
+    }
+
+    #[test]
+    fn metadata_only_saves_document_statistics() -> Result<()> {
+        let options = Options {
+            metadata_only: true,
+            ..Default::default()
+        };
+        let mut handler = save_units(
+            options,
+            r#"
+            <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>1</seg></tuv><tuv lang="PL-01"><seg>1</seg></tuv></tu>
+            <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>2</seg></tuv><tuv lang="PL-01"><seg></seg></tuv></tu>
+            "#,
+        )?;
+
+        assert_eq!(
+            query_number(&mut handler, "SELECT translation_units FROM documents")?,
+            2
+        );
+        assert_eq!(
+            query_number(
+                &mut handler,
+                "SELECT texts FROM document_languages WHERE lang = 'PL-01'"
+            )?,
+            1
+        );
+        assert!(!column_exists(&handler.conn, "translation_units", "id")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn batch_is_committed_when_byte_limit_is_reached() -> Result<()> {
+        let conn = rusqlite::Connection::open_in_memory()?;
+        let options = Options {
+            max_batch_bytes: Some(1),
+            ..Default::default()
+        };
+        let mut handler = Handler::new(conn, crate::types::RequestedLangs::Unlimited, options)?;
+        let tmx = crate::tmx_parser::parse_tmx(String::from(
+            r#"<tmx><header></header><body><tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>Text</seg></tuv></tu></body></tmx>"#,
+        ))?;
+        for tu in tmx.body.translation_units {
+            handler.handle(tu, 0)?;
+        }
+
+        assert!(handler.queries.is_empty());
+        let count = query_number(&mut handler, "select count(*) from translation_units")?;
+        assert_eq!(count, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn batch_is_committed_when_memory_limit_is_exceeded() -> Result<()> {
+        let memory = crate::memory::MemoryUsage::new(Some(1024));
+        let conn = rusqlite::Connection::open_in_memory()?;
+        let options = Options {
+            memory: Some(memory.clone()),
+            ..Default::default()
+        };
+        let mut handler = Handler::new(conn, crate::types::RequestedLangs::Unlimited, options)?;
+        let tmx = crate::tmx_parser::parse_tmx(String::from(
+            r#"<tmx><header></header><body><tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>Text</seg></tuv></tu></body></tmx>"#,
+        ))?;
+        let tu = tmx.body.translation_units[0].clone();
+
+        handler.handle(tu.clone(), 0)?;
+        assert_eq!(handler.queries.len(), 1);
+        assert!(memory.current() > 0);
+
+        // E.g. a large TMX file has been parsed in the meantime.
+        memory.add_parsed(1024);
+        handler.handle(tu, 1)?;
+        assert!(handler.queries.is_empty());
+        assert_eq!(memory.current(), 1024);
+        assert!(memory.peak() > 1024);
+
+        Ok(())
+    }
+
+    #[test]
+    fn missing_and_empty_texts_follow_policy() -> Result<()> {
+        for (missing_as, expected) in [
+            (crate::types::MissingAs::Null, 0),
+            (crate::types::MissingAs::Empty, 5),
+        ] {
+            let options = Options {
+                missing_as,
+                ..Default::default()
+            };
+            let mut handler = save_units(
+                options,
+                r#"
+                <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>Text</seg></tuv><tuv lang="PL-01"><seg></seg></tuv></tu>
+                <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>Text</seg></tuv></tu>
+                <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="DE-DE"><seg>Text</seg></tuv></tu>
+                "#,
+            )?;
+
+            let empty = query_number(
+                &mut handler,
+                "select (select count(*) from translation_units where pl_01 = '')
+                    + (select count(*) from translation_units where de_de = '')",
+            )?;
+            assert_eq!(empty, expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn dates_are_saved_in_iso_8601_format() -> Result<()> {
+        let options = Options {
+            dates: true,
+            ..Default::default()
+        };
+        let handler = save_units(
+            options,
+            r#"<tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB" creationdate="20190827T102232Z"><seg>Text</seg></tuv></tu>"#,
+        )?;
+
+        let created: String =
+            handler
+                .conn
+                .query_row("SELECT en_gb_created FROM translation_units", [], |row| {
+                    row.get(0)
+                })?;
+        assert_eq!(created, "2019-08-27T10:22:32Z");
+        assert!(!column_exists(
+            &handler.conn,
+            "translation_units",
+            "en_gb_changed"
+        )?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn new_database_uses_latest_schema_version() -> Result<()> {
+        let mut handler = setup();
+        let version = query_number(&mut handler, "PRAGMA user_version")?;
+        assert_eq!(version, SCHEMA_VERSION);
+
+        Ok(())
+    }
+
+    #[test]
+    fn strict_tables_reject_values_of_wrong_type() -> Result<()> {
+        let options = Options {
+            strict: true,
+            ..Options::default()
+        };
+        let mut handler = save_units(
+            options,
+            r#"<tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>Source</seg></tuv></tu>"#,
+        )?;
+
+        let units_table: String = handler.conn.query_row(
+            "SELECT sql FROM sqlite_master WHERE name = 'translation_units'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert!(units_table.ends_with("STRICT"));
+        assert!(handler
+            .conn
+            .execute(
+                "INSERT INTO translation_units (sequential_number, en_gb) VALUES ('first', 1)",
+                [],
+            )
+            .is_err());
+        assert_eq!(
+            query_number(&mut handler, "SELECT count(*) FROM translation_units")?,
+            1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn existing_lang_columns_are_reused_when_appending() -> Result<()> {
+        let tmx = |text: &str| {
+            crate::tmx_parser::parse_tmx(format!(
+                r#"<tmx><header></header><body>
+                <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>{}</seg></tuv></tu>
+                </body></tmx>"#,
+                text
+            ))
+        };
+        let langs = || RequestedLangs::Some(vec![String::from("EN-GB")].into_iter().collect());
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("db.sqlite");
+        let mut handler = Handler::new(
+            rusqlite::Connection::open(&path)?,
+            langs(),
+            Options::default(),
+        )?;
+        handler.declare_langs(&[String::from("EN-GB")])?;
+        for tu in tmx("First")?.body.translation_units {
+            handler.handle(tu, 0)?;
+        }
+        handler.finish(&mut crate::types::Summary::default())?;
+        // E.g. renamed by another tool.
+        handler.conn.execute(
+            "ALTER TABLE translation_units RENAME COLUMN en_gb TO \"EN_GB\"",
+            [],
+        )?;
+
+        drop(handler);
+
+        let conn = rusqlite::Connection::open(&path)?;
+        let mut handler = Handler::append(conn, langs(), Options::default())?;
+        handler.declare_langs(&[String::from("EN-GB")])?;
+        for tu in tmx("Second")?.body.translation_units {
+            handler.handle(tu, 1)?;
+        }
+        handler.finish(&mut crate::types::Summary::default())?;
+        assert_eq!(
+            query_number(
+                &mut handler,
+                "SELECT count(*) FROM translation_units WHERE en_gb IS NOT NULL"
+            )?,
+            2
+        );
+
+        Ok(())
+    }
+
+    /// Batches are committed every few translation units.
+    struct Harness;
+
+    impl Conformance for Harness {
+        type Handler = Handler;
+
+        fn handler(&mut self, requested_langs: RequestedLangs) -> Handler {
+            let conn = rusqlite::Connection::open_in_memory().unwrap();
+            let options = Options {
+                max_batch_bytes: Some(512),
+                ..Options::default()
+            };
+            Handler::new(conn, requested_langs, options).unwrap()
+        }
+
+        fn saved_units(&mut self, handler: &mut Handler) -> Vec<SavedUnit> {
+            let mut langs: Vec<(&str, String)> = Vec::new();
+            for lang in LANGS {
+                let column = lang_code_to_db_column(lang).unwrap();
+                if column_exists(&handler.conn, "translation_units", &column).unwrap() {
+                    langs.push((lang, column));
+                }
+            }
+            let columns: Vec<String> = langs
+                .iter()
+                .map(|(_lang, column)| format!(", {}", quote_identifier(column)))
+                .collect();
+            let mut query = handler
+                .conn
+                .prepare(&format!(
+                    "SELECT documents.name, sequential_number{} FROM translation_units
+                    JOIN documents ON documents.id = translation_units.document_id",
+                    columns.concat()
+                ))
+                .unwrap();
+            let rows = query
+                .query_map([], |row| {
+                    let mut texts = Vec::new();
+                    for (i, (lang, _column)) in langs.iter().enumerate() {
+                        if let Some(text) = row.get::<_, Option<String>>(i + 2)? {
+                            texts.push((lang.to_string(), text));
+                        }
+                    }
+                    let document: String = row.get(0)?;
+                    Ok(SavedUnit::new(&document, row.get(1)?, texts))
+                })
+                .unwrap();
+            rows.collect::<rusqlite::Result<_>>().unwrap()
+        }
+    }
+
+    crate::handler_conformance_tests!(Harness);
 }
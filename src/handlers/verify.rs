@@ -0,0 +1,125 @@
+use crate::error::Result;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::tmx_parser::TranslationUnit;
+use crate::types::{RequestedLangs, Summary, TranslationUnitHandler};
+
+/// Checks that, within each document, every language has (approximately) as
+/// many texts as the best-covered language. Documents with gaps, a common
+/// symptom of partially corrupted TMX volumes, are reported as warnings.
+pub struct Handler {
+    /// Number of non-empty texts per language, keyed by document name.
+    docs: BTreeMap<String, BTreeMap<String, u32>>,
+
+    /// A language is reported if it covers less than this share of the texts
+    /// of the best-covered language in the document.
+    min_coverage: f64,
+
+    /// Config value provided by the user. If languages were specified, they
+    /// are expected in every document; otherwise only the languages found in
+    /// a document are checked.
+    requested_langs: RequestedLangs,
+}
+
+impl TranslationUnitHandler for Handler {
+    fn handle(
+        &mut self,
+        translation_unit: TranslationUnit,
+        _sequential_number_in_doc: u32,
+    ) -> Result<()> {
+        let doc_name = match translation_unit.doc_name() {
+            Some(doc_name) => doc_name.clone(),
+            None => return Ok(()),
+        };
+        let counts = self.docs.entry(doc_name).or_default();
+        // A language is counted once per translation unit.
+        let langs: BTreeSet<&String> = translation_unit
+            .segments
+            .iter()
+            .filter(|segment| !segment.content.is_empty())
+            .map(|segment| &segment.lang)
+            .collect();
+        for lang in langs {
+            *counts.entry(lang.clone()).or_default() += 1;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &mut Summary) -> Result<()> {
+        for (doc_name, counts) in &self.docs {
+            let gaps = self.gaps(counts);
+            if gaps.is_empty() {
+                continue;
+            }
+            let gaps: Vec<String> = gaps
+                .iter()
+                .map(|(lang, count, expected)| format!("{} {}/{}", lang, count, expected))
+                .collect();
+            summary.warnings.push(format!(
+                "Document {} has missing texts: {}",
+                doc_name,
+                gaps.join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Handler {
+    pub fn new(requested_langs: RequestedLangs, min_coverage: f64) -> Handler {
+        Handler {
+            docs: BTreeMap::new(),
+            min_coverage,
+            requested_langs,
+        }
+    }
+
+    /// Return the languages of a document which are not covered well enough,
+    /// with their number of texts and the expected number.
+    fn gaps<'a>(&'a self, counts: &'a BTreeMap<String, u32>) -> Vec<(&'a String, u32, u32)> {
+        let expected = counts.values().copied().max().unwrap_or(0);
+        let langs: Vec<&String> = match &self.requested_langs {
+            RequestedLangs::Unlimited => counts.keys().collect(),
+            RequestedLangs::Each(langs) | RequestedLangs::Some(langs) => langs.iter().collect(),
+        };
+
+        langs
+            .into_iter()
+            .map(|lang| (lang, counts.get(lang).copied().unwrap_or(0)))
+            .filter(|(_lang, count)| (*count as f64) < expected as f64 * self.min_coverage)
+            .map(|(lang, count)| (lang, count, expected))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Handler;
+    use crate::tmx_parser::parse_tmx;
+    use crate::types::{RequestedLangs, Summary, TranslationUnitHandler};
+
+    #[test]
+    fn documents_with_missing_texts_are_reported() {
+        let tmx = parse_tmx(String::from(
+            r#"<tmx><header></header><body>
+            <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>1</seg></tuv><tuv lang="PL-01"><seg>1</seg></tuv></tu>
+            <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>2</seg></tuv><tuv lang="PL-01"><seg></seg></tuv></tu>
+            <tu><prop type="Txt::Doc. No.">B</prop><tuv lang="EN-GB"><seg>1</seg></tuv><tuv lang="PL-01"><seg>1</seg></tuv></tu>
+            </body></tmx>"#,
+        ))
+        .unwrap();
+        let mut handler = Handler::new(RequestedLangs::Unlimited, 0.9);
+        for tu in tmx.body.translation_units {
+            handler.handle(tu, 0).unwrap();
+        }
+        let mut summary = Summary::default();
+        handler.finish(&mut summary).unwrap();
+
+        assert_eq!(
+            summary.warnings,
+            vec!["Document A has missing texts: PL-01 1/2"]
+        );
+    }
+}
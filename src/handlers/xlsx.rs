@@ -0,0 +1,342 @@
+use crate::error::Result;
+use rust_xlsxwriter::{DocProperties, Format, Workbook, Worksheet, XlsxError};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use crate::error::DgtError;
+use crate::props::{PropColumns, PropValue};
+use crate::tmx_parser::{LangMatch, TranslationUnit};
+use crate::types::{RunInfo, Summary, TranslationUnitHandler};
+
+/// Maximum number of rows of a worksheet (including the header row).
+const MAX_ROWS: u32 = 1_048_576;
+
+/// Maximum number of characters in a cell.
+const MAX_CELL_CHARS: usize = 32_767;
+
+/// Maximum length of a worksheet name.
+const MAX_SHEET_NAME_CHARS: usize = 31;
+
+/// Saves the translation units in an Excel workbook, with a column per
+/// language. By default, each document gets a worksheet of its own (named
+/// after the document); with `rows_per_sheet`, the translation units are
+/// split into worksheets of that many rows instead, with the document in the
+/// first column. Props mapped to columns (and the other props, as JSON)
+/// follow the texts.
+///
+/// The workbook is held in memory until it is saved, which limits the size
+/// of the output to what a reviewer would open in a spreadsheet anyway.
+pub struct Handler {
+    workbook: Workbook,
+    output_file: PathBuf,
+
+    /// Languages of the columns, in order.
+    langs: Vec<String>,
+
+    rows_per_sheet: Option<u32>,
+
+    props: PropColumns,
+
+    /// The document of the current worksheet (empty with `rows_per_sheet`),
+    /// and the next row in it.
+    current_sheet: Option<(String, u32)>,
+
+    /// Names of the worksheets, lowercased (names are case-insensitive).
+    sheet_names: HashSet<String>,
+
+    header_format: Format,
+    text_format: Format,
+
+    /// Number of texts truncated to [MAX_CELL_CHARS].
+    truncated_texts: u64,
+}
+
+impl TranslationUnitHandler for Handler {
+    /// The source language (if any) is the first language column. The
+    /// attribution is saved as the comment of the workbook.
+    fn start(&mut self, run: &RunInfo) -> Result<()> {
+        if let Some(source_lang) = &run.source_lang {
+            if let Some(position) = self
+                .langs
+                .iter()
+                .position(|lang| LangMatch::IgnoreCase.matches(lang, source_lang))
+            {
+                let lang = self.langs.remove(position);
+                self.langs.insert(0, lang);
+            }
+        }
+        if let Some(attribution) = &run.attribution {
+            self.workbook
+                .set_properties(&DocProperties::new().set_comment(attribution));
+        }
+
+        Ok(())
+    }
+
+    fn handle(
+        &mut self,
+        translation_unit: TranslationUnit,
+        sequential_number_in_doc: u32,
+    ) -> Result<()> {
+        let doc_name = translation_unit
+            .doc_name()
+            .ok_or(DgtError::MissingDocId)?
+            .clone();
+        let sheet_key = match self.rows_per_sheet {
+            Some(_) => String::new(),
+            None => doc_name.clone(),
+        };
+        let max_rows = self.rows_per_sheet.map_or(MAX_ROWS, |rows| rows + 1);
+        let row = match &mut self.current_sheet {
+            Some((key, row)) if *key == sheet_key && *row < max_rows => {
+                *row += 1;
+                *row - 1
+            }
+            _ => {
+                self.add_sheet(&sheet_key)?;
+                self.current_sheet = Some((sheet_key, 2));
+                1
+            }
+        };
+
+        let mut texts: Vec<String> = Vec::with_capacity(self.langs.len());
+        for lang in &self.langs {
+            // Only the first non-empty text is saved if a language occurs
+            // more than once.
+            let text = translation_unit
+                .get_lang(lang, LangMatch::Variant)
+                .map(|segment| segment.content.as_str())
+                .unwrap_or_default();
+            texts.push(match text.chars().nth(MAX_CELL_CHARS) {
+                Some(_) => {
+                    self.truncated_texts += 1;
+                    text.chars().take(MAX_CELL_CHARS).collect()
+                }
+                None => text.to_string(),
+            });
+        }
+
+        let prop_values = self.props.values(&translation_unit);
+        let unmapped_props = match self.props.unmapped_as_json {
+            true => Some(
+                self.props
+                    .unmapped_json(&translation_unit)
+                    .unwrap_or_default(),
+            ),
+            false => None,
+        };
+
+        let text_format = self.text_format.clone();
+        let chunked = self.rows_per_sheet.is_some();
+        let sheet = self.current_worksheet()?;
+        let mut column = 0;
+        if chunked {
+            sheet.write_string(row, column, doc_name)?;
+            column += 1;
+        }
+        sheet.write_number(row, column, sequential_number_in_doc)?;
+        for text in texts {
+            column += 1;
+            sheet.write_string_with_format(row, column, text, &text_format)?;
+        }
+        for value in prop_values {
+            column += 1;
+            match value {
+                Some(PropValue::Text(text)) => sheet.write_string(row, column, text)?,
+                Some(PropValue::Integer(number)) => {
+                    sheet.write_number(row, column, number as f64)?
+                }
+                None => continue,
+            };
+        }
+        if let Some(props) = unmapped_props {
+            sheet.write_string(row, column + 1, props)?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, summary: &mut Summary) -> Result<()> {
+        self.props.report(summary);
+        if self.truncated_texts > 0 {
+            summary.warnings.push(format!(
+                "Truncated {} texts longer than {} characters (the limit of a cell)",
+                self.truncated_texts, MAX_CELL_CHARS
+            ));
+        }
+        // Excel cannot open a workbook without worksheets.
+        if self.current_sheet.is_none() {
+            self.add_sheet("Empty")?;
+        }
+        self.workbook.save(&self.output_file)?;
+
+        Ok(())
+    }
+}
+
+impl Handler {
+    pub fn new(
+        output_file: PathBuf,
+        langs: Vec<String>,
+        rows_per_sheet: Option<u32>,
+        props: PropColumns,
+    ) -> Handler {
+        Handler {
+            workbook: Workbook::new(),
+            output_file,
+            langs,
+            rows_per_sheet: rows_per_sheet.map(|rows| rows.clamp(1, MAX_ROWS - 1)),
+            props,
+            current_sheet: None,
+            sheet_names: HashSet::new(),
+            header_format: Format::new().set_bold(),
+            text_format: Format::new().set_text_wrap(),
+            truncated_texts: 0,
+        }
+    }
+
+    /// Add a worksheet named after the document (or numbered, with
+    /// `rows_per_sheet`), with a header row.
+    fn add_sheet(&mut self, doc_name: &str) -> Result<()> {
+        let name = match self.rows_per_sheet {
+            Some(_) => self.unique_sheet_name(&format!("Units {}", self.sheet_names.len() + 1)),
+            None => self.unique_sheet_name(doc_name),
+        };
+        // Headers with the widths of their columns.
+        let mut headers: Vec<(&str, u16)> = Vec::new();
+        if self.rows_per_sheet.is_some() {
+            headers.push(("Document", 14));
+        }
+        headers.push(("No.", 6));
+        headers.extend(self.langs.iter().map(|lang| (lang.as_str(), 60)));
+        headers.extend(
+            self.props
+                .columns
+                .iter()
+                .map(|column| (column.column.as_str(), 14)),
+        );
+        if self.props.unmapped_as_json {
+            headers.push(("props", 40));
+        }
+
+        let header_format = self.header_format.clone();
+        let sheet = self.workbook.add_worksheet();
+        sheet.set_name(name)?;
+        for (column, (header, width)) in headers.iter().enumerate() {
+            let column = column as u16;
+            sheet.write_string_with_format(0, column, *header, &header_format)?;
+            sheet.set_column_width(column, *width)?;
+        }
+        sheet.set_freeze_panes(1, 0)?;
+
+        Ok(())
+    }
+
+    fn current_worksheet(&mut self) -> Result<&mut Worksheet> {
+        let index = self.sheet_names.len() - 1;
+        Ok(self.workbook.worksheet_from_index(index)?)
+    }
+
+    /// Make a valid worksheet name which is not used yet: characters not
+    /// allowed in worksheet names are replaced, the name is shortened to
+    /// [MAX_SHEET_NAME_CHARS], and a number is appended if needed, e.g.
+    /// `22019D0437 (2)` for the second worksheet of a long document.
+    fn unique_sheet_name(&mut self, name: &str) -> String {
+        let base: String = name
+            .chars()
+            .map(|c| match c {
+                '[' | ']' | ':' | '*' | '?' | '/' | '\\' => '_',
+                _ => c,
+            })
+            .collect();
+        let base = base.trim_matches('\'');
+        let base = match base.is_empty() {
+            true => "Document",
+            false => base,
+        };
+        let mut candidate: String = base.chars().take(MAX_SHEET_NAME_CHARS).collect();
+        let mut number = 1;
+        while self.sheet_names.contains(&candidate.to_lowercase()) {
+            number += 1;
+            let suffix = format!(" ({})", number);
+            candidate = base
+                .chars()
+                .take(MAX_SHEET_NAME_CHARS - suffix.len())
+                .chain(suffix.chars())
+                .collect();
+        }
+        self.sheet_names.insert(candidate.to_lowercase());
+        candidate
+    }
+}
+
+impl From<XlsxError> for DgtError {
+    fn from(error: XlsxError) -> DgtError {
+        DgtError::Handler(Box::new(error))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Handler;
+    use crate::props::PropColumns;
+    use crate::tmx_parser::parse_tmx;
+    use crate::types::{Summary, TranslationUnitHandler};
+    use std::io::Read;
+
+    fn sheets_of(rows_per_sheet: Option<u32>) -> Vec<String> {
+        let tmx = parse_tmx(String::from(
+            r#"<tmx><header></header><body>
+            <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>1</seg></tuv><tuv lang="PL-01"><seg>1</seg></tuv></tu>
+            <tu><prop type="Txt::Doc. No.">A</prop><tuv lang="EN-GB"><seg>2</seg></tuv></tu>
+            <tu><prop type="Txt::Doc. No.">B/1</prop><tuv lang="EN-GB"><seg>1</seg></tuv><tuv lang="PL-01"><seg>1</seg></tuv></tu>
+            </body></tmx>"#,
+        ))
+        .unwrap();
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("units.xlsx");
+        let langs = vec![String::from("EN-GB"), String::from("PL-01")];
+        let mut handler = Handler::new(path.clone(), langs, rows_per_sheet, PropColumns::default());
+        for (i, tu) in tmx.body.translation_units.into_iter().enumerate() {
+            handler.handle(tu, i as u32).unwrap();
+        }
+        handler.finish(&mut Summary::default()).unwrap();
+
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut workbook = String::new();
+        archive
+            .by_name("xl/workbook.xml")
+            .unwrap()
+            .read_to_string(&mut workbook)
+            .unwrap();
+        regex::Regex::new(r#"<sheet name="([^"]*)""#)
+            .unwrap()
+            .captures_iter(&workbook)
+            .map(|captures| captures[1].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn documents_are_saved_in_worksheets() {
+        assert_eq!(sheets_of(None), vec!["A", "B_1"]);
+        assert_eq!(sheets_of(Some(2)), vec!["Units 1", "Units 2"]);
+    }
+
+    #[test]
+    fn sheet_names_are_unique() {
+        let mut handler = Handler::new(
+            std::path::PathBuf::new(),
+            Vec::new(),
+            None,
+            PropColumns::default(),
+        );
+        let long_name = "x".repeat(40);
+        assert_eq!(handler.unique_sheet_name(&long_name), "x".repeat(31));
+        assert_eq!(
+            handler.unique_sheet_name(&long_name),
+            format!("{} (2)", "x".repeat(27))
+        );
+        assert_eq!(handler.unique_sheet_name("a"), "a");
+        assert_eq!(handler.unique_sheet_name("A"), "A (2)");
+    }
+}
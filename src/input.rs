@@ -0,0 +1,229 @@
+use crate::error::{DgtError, Result};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "sqlite")]
+use crate::database_input::{count_documents, for_each_document};
+use crate::functions::{
+    for_each_tmx_file, for_each_tmx_file_in, ArchivePool, SpooledEntry, TmxEntry, Walk,
+    DEFAULT_MAX_OPEN_ARCHIVES,
+};
+
+/// Name under which the standard input is given (and reported).
+pub const STDIN_NAME: &str = "-";
+
+/// Where the translation memory is read from (`--input-dir`). Every way of
+/// providing the input is a variant of this enum, so that new ones plug into
+/// the walk over the TMX files rather than into the subcommands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputSource {
+    /// A directory of release files, see [for_each_tmx_file].
+    LocalDir(PathBuf),
+
+    /// A single archive or TMX file.
+    LocalFile(PathBuf),
+
+    /// An archive or a TMX file piped into the standard input.
+    Stdin,
+
+    /// A database generated by the `sqlite` subcommand, whose documents are
+    /// read back as TMX files (see the `export` subcommand).
+    #[cfg(feature = "sqlite")]
+    Database(PathBuf),
+}
+
+impl InputSource {
+    /// Parse the value of `--input-dir`: `-` for the standard input, or a
+    /// local path.
+    pub fn parse(input: &str) -> std::result::Result<InputSource, String> {
+        Ok(match input {
+            STDIN_NAME => InputSource::Stdin,
+            _ if Path::new(input).is_file() => InputSource::LocalFile(PathBuf::from(input)),
+            _ => InputSource::LocalDir(PathBuf::from(input)),
+        })
+    }
+
+    /// Make the input available locally. The standard input is copied into
+    /// a temporary file, since archives require random access and some
+    /// options (e.g. `--progress files`) read the input twice.
+    pub fn open(&self) -> Result<Input> {
+        match self {
+            InputSource::LocalDir(path) if !path.exists() => {
+                Err(DgtError::InputNotFound(path.clone()))
+            }
+            InputSource::LocalDir(path) | InputSource::LocalFile(path) => Ok(Input {
+                source: self.clone(),
+                path: path.clone(),
+                archives: Input::archive_pool(),
+                _spooled: None,
+            }),
+            #[cfg(feature = "sqlite")]
+            InputSource::Database(path) if !path.exists() => {
+                Err(DgtError::DatabaseNotFound(path.clone()))
+            }
+            #[cfg(feature = "sqlite")]
+            InputSource::Database(path) => Ok(Input {
+                source: self.clone(),
+                path: path.clone(),
+                archives: Input::archive_pool(),
+                _spooled: None,
+            }),
+            InputSource::Stdin => {
+                let spooled = SpooledEntry::new(&mut std::io::stdin().lock())?;
+                Ok(Input {
+                    source: self.clone(),
+                    path: spooled.path().to_path_buf(),
+                    archives: Input::archive_pool(),
+                    _spooled: Some(spooled),
+                })
+            }
+        }
+    }
+}
+
+/// As given on the command line (or in the configuration file).
+impl std::fmt::Display for InputSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputSource::LocalDir(path) | InputSource::LocalFile(path) => {
+                write!(f, "{}", path.display())
+            }
+            #[cfg(feature = "sqlite")]
+            InputSource::Database(path) => write!(f, "{}", path.display()),
+            InputSource::Stdin => write!(f, "{}", STDIN_NAME),
+        }
+    }
+}
+
+/// An [InputSource] available in the local file system.
+pub struct Input {
+    source: InputSource,
+
+    /// The directory or file to read.
+    path: PathBuf,
+
+    /// Archives kept open between walks over the input.
+    archives: Arc<Mutex<ArchivePool>>,
+
+    /// Removes the copy of the standard input when dropped.
+    _spooled: Option<SpooledEntry>,
+}
+
+impl Input {
+    fn archive_pool() -> Arc<Mutex<ArchivePool>> {
+        Arc::new(Mutex::new(ArchivePool::new(DEFAULT_MAX_OPEN_ARCHIVES)))
+    }
+
+    /// Keep at most this many archives of the input open between walks over
+    /// it (see `--max-open-files`).
+    pub fn with_max_open_files(self, max_open_files: usize) -> Input {
+        Input {
+            archives: Arc::new(Mutex::new(ArchivePool::new(max_open_files))),
+            ..self
+        }
+    }
+
+    /// Local path of the input, e.g. to compute its fingerprint.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Name of a TMX file of the input (see [TmxEntry::name]) relative to
+    /// the input directory, so that it does not depend on where the input
+    /// is, e.g. `1.zip/22019D0557.tmx`. The TMX files of an archive (or a
+    /// single TMX file) given as the input are named after it.
+    pub fn relative_name<'a>(&self, name: &'a str) -> &'a str {
+        let base = match self.path.is_dir() {
+            true => Some(self.path.as_path()),
+            false => self.path.parent(),
+        };
+        match base.map(|base| base.display().to_string()) {
+            Some(base) if !base.is_empty() => name
+                .strip_prefix(base.as_str())
+                .and_then(|name| name.strip_prefix('/'))
+                .unwrap_or(name),
+            _ => name,
+        }
+    }
+
+    /// Perform an operation on every TMX file in the input. The documents
+    /// of a database are not matched against `--tmx-glob`. The ZIP archives
+    /// stay open for the next walk, up to the limit of open files.
+    pub fn for_each_tmx_file<F>(&self, walk: &mut Walk, callback: &mut F) -> Result<()>
+    where
+        F: FnMut(TmxEntry) -> Result<()>,
+    {
+        walk.use_archives(self.archives.clone());
+        match &self.source {
+            InputSource::Stdin => for_each_tmx_file_in(STDIN_NAME, &self.path, walk, callback),
+            #[cfg(feature = "sqlite")]
+            InputSource::Database(path) => {
+                for_each_document(&path.display().to_string(), path, callback)
+            }
+            _ => for_each_tmx_file(&self.path, walk, callback),
+        }
+    }
+
+    /// Count the TMX files in the input, e.g. to report progress. The TMX
+    /// files are not read, and nested archives are not extracted (see
+    /// [Walk::list_only]).
+    pub fn count_tmx_files(&self, walk: &mut Walk) -> Result<u32> {
+        walk.list_only = true;
+        match &self.source {
+            #[cfg(feature = "sqlite")]
+            InputSource::Database(path) => count_documents(path),
+            _ => {
+                let mut count = 0;
+                self.for_each_tmx_file(walk, &mut |_tmx_file| {
+                    count += 1;
+
+                    Ok(())
+                })?;
+
+                Ok(count)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::InputSource;
+    use crate::functions::Walk;
+    use std::path::PathBuf;
+
+    #[test]
+    fn input_sources_are_parsed() {
+        assert_eq!(InputSource::parse("-"), Ok(InputSource::Stdin));
+        assert_eq!(
+            InputSource::parse("test_data/zipped/1.zip"),
+            Ok(InputSource::LocalFile(PathBuf::from(
+                "test_data/zipped/1.zip"
+            )))
+        );
+        assert_eq!(
+            InputSource::parse("test_data/zipped"),
+            Ok(InputSource::LocalDir(PathBuf::from("test_data/zipped")))
+        );
+    }
+
+    #[test]
+    fn local_files_are_walked() {
+        let input = InputSource::parse("test_data/zipped/1.zip")
+            .unwrap()
+            .open()
+            .unwrap();
+        let mut names = Vec::new();
+        input
+            .for_each_tmx_file(&mut Walk::default(), &mut |tmx_file| {
+                names.push(tmx_file.name);
+                Ok(())
+            })
+            .unwrap();
+
+        assert!(!names.is_empty());
+        assert!(names
+            .iter()
+            .all(|name| name.starts_with("test_data/zipped/1.zip/")));
+    }
+}
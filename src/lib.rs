@@ -0,0 +1,28 @@
+//! Parses the DGT-TM (the translation memory of the Directorate-General
+//! for Translation of the European Commission) and saves its translation
+//! units in other formats. The `dgt_parser` binary is a command line
+//! interface to this library; the engine is [corpus::process_corpus], which
+//! passes the translation units to a [types::TranslationUnitHandler].
+
+pub mod alignment;
+pub mod artifacts;
+pub mod boilerplate;
+pub mod cache;
+pub mod config;
+pub mod corpus;
+#[cfg(feature = "sqlite")]
+pub mod database_input;
+pub mod error;
+pub mod functions;
+pub mod handlers;
+pub mod input;
+pub mod manifest;
+pub mod memory;
+pub mod near_duplicates;
+pub mod progress;
+pub mod props;
+pub mod quotas;
+pub mod seen_files;
+pub mod skip_log;
+pub mod tmx_parser;
+pub mod types;
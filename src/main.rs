@@ -1,123 +1,1294 @@
 mod cli;
-mod functions;
-mod handlers;
-mod tmx_parser;
-mod types;
+mod commands;
 
+use dgt_parser::{
+    alignment, artifacts, boilerplate, cache, config, corpus, error, functions, handlers, input,
+    manifest, memory, near_duplicates, progress, quotas, seen_files, skip_log, types,
+};
+
+use alignment::{AlignmentModel, AlignmentTrainer};
 use anyhow::{bail, Result};
-use clap::Parser;
-use cli::Commands;
+use artifacts::ArtifactDetector;
+use boilerplate::Boilerplate;
+use cache::Cache;
+use clap::{CommandFactory, Parser};
+use cli::{Cli, Commands};
+use commands::catalog::Catalog;
+use config::{Config, DEFAULT_CONFIG_FILE};
+use corpus::{describe, process_corpus, Hooks, Limits, Processed, Source, UnitFilters};
+use error::DgtError;
 use functions::{
-    coerce_lang_codes, for_each_tmx_file_in_zip, for_each_zip, read_utf16_file_to_string,
+    coerce_lang_codes, collect_files, hash_inputs, input_size, parse_tmx_stream,
+    read_checksums_file, read_docs_file, read_langs_file, sha256_file, Walk,
 };
-use rusqlite;
-use std::io::Write;
+use handlers::adapters::HandlerExt;
+use handlers::all_pairs::PairFormat;
+use handlers::classification::ClassificationFormat;
+use input::{Input, InputSource};
+use manifest::{Filters, Manifest};
+use memory::{format_bytes, MemoryUsage};
+use near_duplicates::NearDuplicateDetector;
+use progress::{ProgressEvent, ProgressEvents, ProgressReporter, PROGRESS_PROTOCOL_VERSION};
+use quotas::LangQuotas;
+use seen_files::SeenFiles;
+use skip_log::{SkipLog, SkipReason};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use tmx_parser::{parse_tmx, Tmx};
-use types::RequestedLangs;
+use types::{
+    LangSet, MultiDoc, NewlinePolicy, Progress, RequestedLangs, RunInfo, Summary, TeeFormat,
+    DGT_ATTRIBUTION,
+};
 
-fn main() -> Result<()> {
-    let cli = cli::Cli::parse();
+/// Exit code used when the output was generated, but problems with the input
+/// data were found (see `--fail-on`).
+const DATA_QUALITY_EXIT_CODE: i32 = 3;
 
-    // Total count of TMX documents across the ZIP files in the input
-    // directory.
-    let total_tmx_files = count_tmx_files(&cli.input_dir)?;
+fn main() {
+    let cli = Cli::parse();
+    let progress_events = ProgressEvents::new(cli.progress_json);
+    if let Err(error) = run(cli) {
+        report_error(&error, progress_events);
+        std::process::exit(1);
+    }
+}
 
-    // Reported back to the user.
-    let mut tmx_files_parsed = 0;
+fn run(cli: Cli) -> Result<()> {
+    match &cli.command {
+        Commands::Init => commands::init::run(&cli.config),
+        #[cfg(feature = "sqlite")]
+        Commands::Migrate { database } => commands::migrate::run(database),
+        Commands::GenEdgeCases { output_dir } => commands::gen_edge_cases::run(output_dir),
+        #[cfg(feature = "sqlite")]
+        Commands::Kwic {
+            database,
+            term,
+            lang,
+            target_lang,
+            window,
+            limit,
+            offset,
+            format,
+        } => commands::kwic::run(
+            database,
+            commands::kwic::KwicOptions {
+                term: term.clone(),
+                lang: lang.clone(),
+                target_lang: target_lang.clone(),
+                window: *window,
+                limit: *limit,
+                offset: *offset,
+                format: *format,
+            },
+        ),
+        #[cfg(feature = "sqlite")]
+        Commands::AlignPhrases {
+            database,
+            source_lang,
+            target_lang,
+            max_ngram,
+            min_count,
+            measure,
+            limit,
+        } => commands::align_phrases::run(
+            database,
+            commands::align_phrases::AlignPhrasesOptions {
+                source_lang: source_lang.clone(),
+                target_lang: target_lang.clone(),
+                max_ngram: *max_ngram,
+                min_count: *min_count,
+                measure: *measure,
+                limit: *limit,
+            },
+        ),
+        Commands::Catalog { output_file } => {
+            let config = Config::load_if_exists(&cli.config)?;
+            let input = required_input(cli.input_dir.clone(), &config)?.open()?;
+            commands::catalog::run(&input, cli.tmx_glob.clone(), output_file)
+        }
+        _ => parse_corpus(cli),
+    }
+}
 
-    // Allows the user to restrict which languages are included in the output.
-    //
-    // By default, the output will contain texts in all languages. If language
-    // codes are specified, only the specified languages will be included in the
-    // output.
-    let requested_langs: RequestedLangs = match cli.langs {
-        None => RequestedLangs::Unlimited,
-        Some(langs) => match cli.require_each_lang {
-            true => RequestedLangs::Each(coerce_lang_codes(langs)),
-            false => RequestedLangs::Some(coerce_lang_codes(langs)),
+/// The input, which is required by the subcommands reading the translation
+/// memory (and by no other subcommand). It may be given in the configuration
+/// file instead.
+fn required_input(input_dir: Option<InputSource>, config: &Config) -> Result<InputSource> {
+    match (input_dir, &config.input_dir) {
+        (Some(input_source), _) => Ok(input_source),
+        (None, Some(input_dir)) => {
+            Ok(InputSource::parse(input_dir).map_err(DgtError::InvalidConfig)?)
+        }
+        (None, None) => Cli::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "the following required argument was not provided: --input-dir <INPUT_DIR>",
+            )
+            .exit(),
+    }
+}
+
+/// Exit with a usage error for options which cannot be combined, as for
+/// the conflicts declared to clap.
+fn conflicting_args(message: &str) -> ! {
+    Cli::command()
+        .error(clap::error::ErrorKind::ArgumentConflict, message)
+        .exit()
+}
+
+/// Parse the translation memory and save the translation units using the
+/// handler selected by the subcommand.
+fn parse_corpus(mut cli: Cli) -> Result<()> {
+    #[cfg(feature = "sqlite")]
+    {
+        cli = cli.resolve_export();
+    }
+    cli.command = cli.command.resolve_convert()?;
+    let config = Config::load_if_exists(&cli.config)?;
+    // Settings taken from the default configuration file, which is read
+    // without being asked for, are reported (see below).
+    let mut config_settings: Vec<&str> = Vec::new();
+    if cli.config == Path::new(DEFAULT_CONFIG_FILE) {
+        if cli.input_dir.is_none() && config.input_dir.is_some() {
+            config_settings.push("the input");
+        }
+        let langs_given = cli.langs.is_some() || cli.langs_file.is_some();
+        if !langs_given && cli.langs_preset.is_none() && config.langs.is_some() {
+            config_settings.push("the languages");
+        }
+    }
+    let input_source = required_input(cli.input_dir.clone(), &config)?;
+
+    // Nothing but the output may be printed to the standard output if the
+    // output is written there.
+    let tee_to_stdout = matches!(&cli.tee, Some(tee) if tee.path.is_none());
+    if tee_to_stdout && cli.command.writes_to_stdout() {
+        bail!(DgtError::ConflictingOutputs(
+            "the output and --tee cannot both be written to the standard output"
+        ));
+    }
+    let quiet = cli.quiet || cli.command.writes_to_stdout() || tee_to_stdout;
+
+    // The translation units to update are looked up by their sequential
+    // numbers, which are only the same as in the database if the TMX files
+    // before them are processed too.
+    #[cfg(feature = "sqlite")]
+    if matches!(cli.command, Commands::Sqlite { update: true, .. })
+        && (cli.tmx_glob.is_some() || cli.seen_files.is_some())
+    {
+        bail!(DgtError::ConflictingOptions(
+            "sqlite --update cannot be combined with --tmx-glob or --seen-files"
+        ));
+    }
+
+    // Read from the local file system (the standard input is copied there).
+    let input = input_source.open()?.with_max_open_files(cli.max_open_files);
+
+    // Processed after the input (see `--merge-corpus`).
+    let merged_inputs = cli
+        .merge_corpus
+        .iter()
+        .map(|(name, source)| {
+            let input = source.open()?.with_max_open_files(cli.max_open_files);
+            Ok((name.clone(), input))
+        })
+        .collect::<Result<Vec<(String, Input)>>>()?;
+
+    // Also used to verify the number of translation units of each TMX file.
+    let catalog = match &cli.catalog {
+        Some(path) => Some(Catalog::load(path)?),
+        None => None,
+    };
+    // Otherwise, the TMX files may have changed since they were cataloged.
+    let catalog_matches = match &catalog {
+        Some(catalog) => catalog.matches(&input, cli.tmx_glob.as_ref())?,
+        None => false,
+    };
+
+    // Machine-readable progress (see `--progress-json`).
+    let progress_events = ProgressEvents::new(cli.progress_json);
+    progress_events.emit(ProgressEvent::Started {
+        protocol: PROGRESS_PROTOCOL_VERSION,
+    });
+
+    if !config_settings.is_empty() {
+        let warning = format!(
+            "using {} from {} in the current directory",
+            config_settings.join(" and "),
+            DEFAULT_CONFIG_FILE
+        );
+        match progress_events.enabled() {
+            true => progress_events.emit(ProgressEvent::Warning { message: warning }),
+            false if !quiet => eprintln!("Warning: {}", warning),
+            false => {}
+        }
+    }
+
+    // Used to report progress to the user: either the total count of TMX
+    // documents across the archives in the input directory (which requires
+    // an extra pass over the input) or the total size of the input files.
+    // The progress line would be mixed with the events of `--progress-json`.
+    let progress = match quiet || cli.progress_json {
+        true => Progress::None,
+        false => cli.progress,
+    };
+    let mut total = match progress {
+        Progress::Files => match (&catalog, &cli.catalog) {
+            (Some(catalog), Some(path)) => match catalog_matches {
+                true => catalog.files.len() as u64,
+                false => {
+                    let warning = format!(
+                        "the catalog {} does not match the input; counting the TMX files instead.",
+                        path.display()
+                    );
+                    match progress_events.enabled() {
+                        true => progress_events.emit(ProgressEvent::Warning { message: warning }),
+                        false => eprintln!("Warning: {}", warning),
+                    }
+                    count_tmx_files(&input, cli.tmx_glob.clone())? as u64
+                }
+            },
+            _ => count_tmx_files(&input, cli.tmx_glob.clone())? as u64,
+        },
+        Progress::Bytes => input_size(input.path())?,
+        Progress::None => 0,
+    };
+    // The merged corpora have no catalog.
+    for (_name, merged_input) in &merged_inputs {
+        total += match progress {
+            Progress::Files => {
+                merged_input.count_tmx_files(&mut Walk::new(cli.tmx_glob.clone()))? as u64
+            }
+            Progress::Bytes => input_size(merged_input.path())?,
+            Progress::None => 0,
+        };
+    }
+
+    // Reported back to the user and recorded by the handler.
+    let mut summary = Summary::default();
+
+    let langs = resolve_langs(&cli, &config)?;
+
+    // Recorded in the manifest (if requested).
+    let filters = recorded_filters(&cli, &input_source, &langs);
+    let RunLangs {
+        source_lang,
+        requested_langs,
+        langs_from_file,
+        match_lang,
+        alignment_langs,
+    } = langs;
+
+    let output_path = cli.command.output_path();
+    let schema_version = match &cli.command {
+        #[cfg(feature = "sqlite")]
+        Commands::Sqlite { .. } => Some(handlers::sqlite_db::SCHEMA_VERSION),
+        _ => None,
+    };
+
+    let run = RunInfo {
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        arguments: std::env::args().collect(),
+        input_hash: hash_inputs(
+            input.path(),
+            &merged_inputs
+                .iter()
+                .map(|(name, merged_input)| (name.as_str(), merged_input.path()))
+                .collect::<Vec<_>>(),
+        )?,
+        source_lang: source_lang.clone(),
+        started_at: unix_timestamp(),
+        attribution: match cli.attribution.clone() {
+            None => Some(DGT_ATTRIBUTION.to_string()),
+            Some(attribution) if attribution.trim().is_empty() => None,
+            Some(attribution) => Some(attribution),
         },
+        filters,
+    };
+
+    // Built before anything is written, since they read the files they are
+    // given.
+    let mut filters = unit_filters(&cli, &source_lang, &requested_langs, &match_lang)?;
+
+    // Verified before anything is written, so that a truncated download does
+    // not produce a partial output.
+    if let Some(checksums_file) = &cli.checksums {
+        if !quiet {
+            println!("Verifying checksums...");
+        }
+        let checksums = read_checksums_file(checksums_file)?;
+        let mismatches = verify_checksums(input.path(), &checksums, &mut summary)?;
+        if !mismatches.is_empty() {
+            bail!(DgtError::ChecksumMismatch(mismatches));
+        }
+    }
+
+    let cache = match cli.cache_dir {
+        Some(dir) => Some(Cache::new(dir)?),
+        None => None,
     };
+    let mut seen_files = match &cli.seen_files {
+        Some(path) => Some(SeenFiles::open(path.clone())?),
+        None => None,
+    };
+
+    // Trained before anything is written, in a pass of its own.
+    filters.alignment_model = match &alignment_langs {
+        Some(langs) => Some(train_alignment_model(
+            &input,
+            cli.tmx_glob.clone(),
+            langs,
+            cli.alignment_iterations,
+            quiet,
+        )?),
+        None => None,
+    };
+
+    // Approximate memory used by the translation units held at once (see
+    // `--max-memory`).
+    let memory = MemoryUsage::new(cli.max_memory);
 
     // Saves each translation unit received into the handler’s dedicated output
     // format.
-    let mut handler = init_handler(cli.command, requested_langs.clone())?;
+    let mut handler = init_handler(
+        cli.command,
+        requested_langs.clone(),
+        #[cfg(feature = "sqlite")]
+        cli.missing_as,
+        #[cfg(feature = "sqlite")]
+        cli.multi_doc,
+        #[cfg(any(feature = "sqlite", feature = "xlsx"))]
+        dgt_parser::props::PropColumns::new(cli.prop_column.clone(), cli.unmapped_props_json),
+        #[cfg(feature = "sqlite")]
+        Reporting {
+            memory: memory.clone(),
+            progress_events,
+        },
+        &cli.config,
+    )?;
+
+    // The secondary output receives the same units as the handler.
+    if let Some(tee) = &cli.tee {
+        let writer: Box<dyn Write> = match &tee.path {
+            None => Box::new(std::io::stdout()),
+            Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        };
+        let tee_handler = match tee.format {
+            TeeFormat::Jsonl => handlers::jsonl::Handler::new(writer, requested_langs.clone()),
+        };
+        handler = Box::new(handler.tee(tee_handler));
+    }
+
+    handler.start(&run)?;
+    // The source language comes first, even if it was specified after other
+    // languages.
+    let declared_langs: LangSet = match &requested_langs {
+        RequestedLangs::Unlimited => source_lang.iter().cloned().collect(),
+        RequestedLangs::Some(langs) | RequestedLangs::Each(langs) => {
+            source_lang.iter().chain(langs.iter()).cloned().collect()
+        }
+    };
+    handler.declare_langs(&declared_langs)?;
 
     // Keep track of the number of TMX documents parsed and report progress to
     // the user.
-    let mut incr_count_and_report_progress = || -> Result<()> {
-        tmx_files_parsed += 1;
-        let percentage: f32 = (tmx_files_parsed as f32 / total_tmx_files as f32) * 100 as f32;
-        print!(
-            "\rParsing {} out of {} documents ({:.0}%).",
-            tmx_files_parsed, total_tmx_files, percentage
-        );
-        std::io::stdout().flush()?;
+    let mut progress_reporter = ProgressReporter::start(progress, total, memory.clone());
 
-        Ok(())
+    // Records why translation units were skipped (see `--skip-log`).
+    let mut skip_log = match &cli.skip_log {
+        Some(path) => Some(SkipLog::create(path, cli.skip_log_limit)?),
+        None => None,
     };
 
-    for_each_zip(&cli.input_dir, &mut |mut zip_archive| {
-        for_each_tmx_file_in_zip(&mut zip_archive, &mut |mut file| {
-            incr_count_and_report_progress()?;
-            let tmx_contents = read_utf16_file_to_string(&mut file)?;
-            let Tmx { body, header: _ } = parse_tmx(tmx_contents)?;
-            for (i, tu) in body.translation_units.into_iter().enumerate() {
-                if let RequestedLangs::Some(_) = &requested_langs {
-                    if !tu.contains_any_lang(&requested_langs) {
-                        continue;
-                    }
+    let mut hooks = RunHooks {
+        progress_reporter: &progress_reporter,
+        progress_events,
+        skip_log: skip_log.as_mut(),
+    };
+    let limits = Limits {
+        max_duration: cli.max_duration,
+        max_units: cli.max_units,
+    };
+    let source = Source {
+        input: &input,
+        walk: Walk::new(cli.tmx_glob),
+        cache: cache.as_ref(),
+        seen_files: seen_files.as_mut(),
+        memory: memory.clone(),
+        // Even if the catalog does not match the input, e.g. because some
+        // archives were only partially downloaded.
+        declared_units: catalog
+            .iter()
+            .flat_map(|catalog| catalog.declared_units())
+            .collect(),
+        cataloged_hashes: catalog
+            .iter()
+            .filter(|_| catalog_matches)
+            .flat_map(|catalog| catalog.content_hashes())
+            .collect(),
+        read_ahead: cli.read_ahead,
+        merged_corpora: merged_inputs
+            .iter()
+            .map(|(name, merged_input)| (name.clone(), merged_input))
+            .collect(),
+    };
+    let processed = process_corpus(
+        source,
+        &mut filters,
+        &limits,
+        handler.as_mut(),
+        &mut hooks,
+        &mut summary,
+    )?;
+
+    progress_reporter.finish();
+    // Only the skipped TMX files are reported as errors while processing.
+    let emitted_errors = summary.errors.len();
+    record_problems(
+        &mut summary,
+        &processed,
+        cli.catalog.as_deref(),
+        &requested_langs,
+        cli.strict_langs,
+        &langs_from_file,
+    );
+    summary.finished_at = unix_timestamp();
+    handler.finish(&mut summary)?;
+    drop(handler);
+    if progress_events.enabled() {
+        emit_problems(&summary, progress_events, emitted_errors);
+    }
+    progress_events.emit(ProgressEvent::Finished {
+        tmx_files_parsed: summary.tmx_files_parsed,
+        tmx_files_skipped: summary.tmx_files_skipped,
+        translation_units_handled: summary.translation_units_handled,
+    });
+
+    let skips_not_logged = match &mut skip_log {
+        Some(skip_log) => skip_log.finish()?,
+        None => 0,
+    };
+
+    // Only recorded once the output has been saved.
+    if let Some(seen_files) = &mut seen_files {
+        seen_files.save()?;
+    }
+
+    if let Some(manifest_path) = &cli.manifest {
+        Manifest::new(&run, schema_version, output_path.as_deref())?.save(manifest_path)?;
+    }
+
+    if !quiet {
+        print_summary(
+            &summary,
+            &processed,
+            &run.filters,
+            &memory,
+            skips_not_logged,
+        );
+        if !progress_events.enabled() {
+            report_problems(&summary);
+        }
+    }
+    if cli.fail_on.is_triggered_by(&summary) {
+        std::process::exit(DATA_QUALITY_EXIT_CODE);
+    }
+
+    Ok(())
+}
+
+/// The languages of a run, resolved from the command line and the
+/// configuration file.
+struct RunLangs {
+    source_lang: Option<String>,
+    requested_langs: RequestedLangs,
+
+    /// Read from `--langs-file`, and checked strictly against the languages
+    /// found in the input.
+    langs_from_file: Vec<String>,
+
+    /// See `--match-lang`.
+    match_lang: Option<String>,
+
+    /// See `--score-alignment`.
+    alignment_langs: Option<Vec<String>>,
+}
+
+fn resolve_langs(cli: &Cli, config: &Config) -> Result<RunLangs> {
+    // Allows the user to restrict which languages are included in the output.
+    //
+    // By default, the output will contain texts in all languages. If language
+    // codes are specified, only the specified languages will be included in the
+    // output.
+    let source_lang: Option<String> = cli
+        .source_lang
+        .clone()
+        .map(|lang| coerce_lang_codes(vec![lang]).remove(0));
+    let mut langs_from_file: Vec<String> = Vec::new();
+    let langs: Option<Vec<String>> =
+        match (cli.langs.clone(), &cli.langs_file, cli.langs_preset.clone()) {
+            (None, None, None) => config.langs.clone(),
+            (langs, langs_file, langs_preset) => {
+                let mut langs = langs.unwrap_or_default();
+                if let Some(langs_file) = langs_file {
+                    langs_from_file = coerce_lang_codes(read_langs_file(langs_file)?);
+                    langs.extend(langs_from_file.clone());
                 }
-                if let RequestedLangs::Each(_) = &requested_langs {
-                    if !tu.contains_each_lang(&requested_langs) {
-                        continue;
-                    }
+                // Expanded by `coerce_lang_codes`.
+                langs.extend(langs_preset.unwrap_or_default());
+                Some(langs)
+            }
+        };
+    let requested_langs: RequestedLangs = match langs {
+        None => RequestedLangs::Unlimited,
+        Some(langs) => {
+            let mut langs = coerce_lang_codes(langs);
+            // The source language is always included in the output.
+            if let Some(source_lang) = &source_lang {
+                if !langs.contains(source_lang) {
+                    langs.insert(0, source_lang.clone());
                 }
-                handler.handle(tu, i as u32);
             }
+            // A language may be specified both with `-l` and in the file or
+            // the preset; duplicates are removed.
+            match cli.require_each_lang {
+                true => RequestedLangs::Each(langs.into_iter().collect()),
+                false => RequestedLangs::Some(langs.into_iter().collect()),
+            }
+        }
+    };
+
+    let match_lang: Option<String> = cli
+        .match_lang
+        .clone()
+        .map(|lang| coerce_lang_codes(vec![lang]).remove(0));
+
+    let alignment_langs: Option<Vec<String>> = cli.score_alignment.clone().map(coerce_lang_codes);
+
+    Ok(RunLangs {
+        source_lang,
+        requested_langs,
+        langs_from_file,
+        match_lang,
+        alignment_langs,
+    })
+}
+
+/// The effective settings of a run, recorded in its output and manifest.
+fn recorded_filters(cli: &Cli, input_source: &InputSource, langs: &RunLangs) -> Filters {
+    Filters {
+        input: Some(input_source.to_string()),
+        config_file: match cli.config.exists() {
+            true => Some(cli.config.clone()),
+            false => None,
+        },
+        langs: match &langs.requested_langs {
+            RequestedLangs::Unlimited => None,
+            RequestedLangs::Some(langs) | RequestedLangs::Each(langs) => Some(langs.to_vec()),
+        },
+        require_each_lang: cli.require_each_lang,
+        min_langs: cli.min_langs,
+        match_lang: langs.match_lang.clone(),
+        match_regex: cli.match_regex.as_ref().map(|regex| regex.to_string()),
+        require_doc_coverage: cli.require_doc_coverage,
+        source_lang: langs.source_lang.clone(),
+        tmx_glob: cli.tmx_glob.as_ref().map(|pattern| pattern.to_string()),
+        docs_file: cli.docs_file.clone(),
+        exclude_docs_file: cli.exclude_docs_file.clone(),
+        seen_files: cli.seen_files.clone(),
+        cluster_near_duplicates: cli.cluster_near_duplicates,
+        drop_near_duplicates: cli.drop_near_duplicates,
+        flag_artifacts: cli.flag_artifacts,
+        boilerplate_files: cli.boilerplate_file.clone(),
+        flag_boilerplate: cli.flag_boilerplate,
+        index_in_file: cli.index_in_file,
+        merged_corpora: cli
+            .merge_corpus
+            .iter()
+            .map(|(name, source)| (name.clone(), source.to_string()))
+            .collect(),
+        score_alignment: langs.alignment_langs.clone(),
+        max_units_per_lang: cli.max_units_per_lang,
+        max_chars_per_lang: cli.max_chars_per_lang,
+        multi_doc: cli.multi_doc,
+        missing_as: cli.missing_as,
+        max_units: cli.max_units,
+        max_duration: cli.max_duration.map(|duration| duration.as_secs()),
+        read_ahead: cli.read_ahead,
+    }
+}
+
+/// Build the filters of the translation units from the command line, reading
+/// the files given for them.
+fn unit_filters(
+    cli: &Cli,
+    source_lang: &Option<String>,
+    requested_langs: &RequestedLangs,
+    match_lang: &Option<String>,
+) -> Result<UnitFilters> {
+    // Optionally clusters near-duplicate translation units (or drops all but
+    // the first unit of each cluster).
+    let near_duplicates = cli
+        .cluster_near_duplicates
+        .or(cli.drop_near_duplicates)
+        .map(NearDuplicateDetector::new);
+
+    let artifacts = match cli.flag_artifacts {
+        true => Some(ArtifactDetector::new()),
+        false => None,
+    };
+
+    let boilerplate = match cli.boilerplate_file.is_empty() {
+        true => None,
+        false => {
+            let mut boilerplate = Boilerplate::default();
+            for (lang, path) in &cli.boilerplate_file {
+                boilerplate.add_file(lang, path)?;
+            }
+            Some(boilerplate)
+        }
+    };
 
-            Ok(())
-        })?;
+    // The only documents processed, e.g. a curated set of regulations.
+    let listed_docs = match &cli.docs_file {
+        Some(path) => Some(read_docs_file(path)?),
+        None => None,
+    };
+
+    // Documents skipped entirely, e.g. known to be misaligned.
+    let excluded_docs = match &cli.exclude_docs_file {
+        Some(path) => read_docs_file(path)?,
+        None => HashSet::new(),
+    };
 
+    Ok(UnitFilters {
+        listed_docs,
+        excluded_docs,
+        require_doc_coverage: cli.require_doc_coverage,
+        source_lang: source_lang.clone(),
+        requested_langs: requested_langs.clone(),
+        min_langs: cli.min_langs,
+        text_match: match_lang.clone().zip(cli.match_regex.clone()),
+        near_duplicates,
+        drop_near_duplicates: cli.drop_near_duplicates.is_some(),
+        boilerplate,
+        flag_boilerplate: cli.flag_boilerplate,
+        multi_doc: cli.multi_doc,
+        index_in_file: cli.index_in_file,
+        quotas: match cli.max_units_per_lang.is_some() || cli.max_chars_per_lang.is_some() {
+            true => Some(LangQuotas::new(
+                cli.max_units_per_lang,
+                cli.max_chars_per_lang,
+            )),
+            false => None,
+        },
+        // Trained separately, see `train_alignment_model`.
+        alignment_model: None,
+        artifacts,
+    })
+}
+
+/// Record the problems found once the input has been processed, e.g. the
+/// requested languages which do not occur in it.
+fn record_problems(
+    summary: &mut Summary,
+    processed: &Processed,
+    catalog: Option<&Path>,
+    requested_langs: &RequestedLangs,
+    strict_langs: bool,
+    langs_from_file: &[String],
+) {
+    // Otherwise nothing would have been verified against the catalog.
+    if let Some(path) = catalog {
+        if processed.cataloged_files == 0 && summary.tmx_files_parsed > 0 {
+            summary.errors.push(format!(
+                "None of the parsed TMX files is listed in the catalog {}",
+                path.display()
+            ));
+        }
+    }
+    if let RequestedLangs::Some(langs) | RequestedLangs::Each(langs) = requested_langs {
+        for lang in langs
+            .iter()
+            .filter(|lang| processed.requested_lang_hits[*lang] == 0)
+        {
+            let problem = format!("Language {} does not occur in the input", lang);
+            // A list of many languages is checked strictly, since a typo in
+            // it is easily missed.
+            match strict_langs || langs_from_file.contains(lang) {
+                true => summary.errors.push(problem),
+                false => summary.warnings.push(problem),
+            }
+        }
+    }
+    for doc in &processed.missing_listed_docs {
+        summary.warnings.push(format!(
+            "Document {} listed in --docs-file does not occur in the input",
+            doc
+        ));
+    }
+}
+
+/// Print what was done besides handling the translation units, e.g. the
+/// numbers of units dropped by the filters.
+fn print_summary(
+    summary: &Summary,
+    processed: &Processed,
+    filters: &Filters,
+    memory: &MemoryUsage,
+    skips_not_logged: u64,
+) {
+    if summary.tmx_files_excluded > 0 {
+        println!(
+            "\nExcluded {} TMX files not matching --tmx-glob.",
+            summary.tmx_files_excluded
+        );
+    }
+    if summary.tmx_files_seen_before > 0 {
+        println!(
+            "\nSkipped {} TMX files listed in --seen-files.",
+            summary.tmx_files_seen_before
+        );
+    }
+    if let Some(limit) = processed.stopped_by {
+        let hint = match filters.seen_files {
+            Some(_) => {
+                "run again with the same --seen-files (and e.g. `sqlite --append`) to continue"
+            }
+            None => "use --seen-files to be able to continue",
+        };
+        println!(
+            "\nStopped because {} was reached, leaving out {} TMX files; {}.",
+            limit, summary.tmx_files_left_out, hint
+        );
+    }
+    if summary.translation_units_not_listed > 0 {
+        println!(
+            "\nSkipped {} translation units of documents not listed in --docs-file.",
+            summary.translation_units_not_listed
+        );
+    }
+    if summary.translation_units_excluded > 0 {
+        println!(
+            "\nExcluded {} translation units of documents listed in --exclude-docs-file.",
+            summary.translation_units_excluded
+        );
+    }
+    if summary.translation_units_with_several_docs > 0 && filters.multi_doc == MultiDoc::First {
+        println!(
+            "\nAssigned {} translation units naming several documents to the first one (--multi-doc).",
+            summary.translation_units_with_several_docs
+        );
+    }
+    if summary.translation_units_with_too_few_langs > 0 {
+        println!(
+            "\nDropped {} translation units with texts in fewer than {} languages (--min-langs).",
+            summary.translation_units_with_too_few_langs,
+            filters.min_langs.unwrap_or_default()
+        );
+    }
+    if let (Some(match_lang), Some(match_regex)) = (&filters.match_lang, &filters.match_regex) {
+        println!(
+            "\nDropped {} translation units whose {} text does not match {} (--match-regex).",
+            summary.translation_units_not_matching, match_lang, match_regex
+        );
+    }
+    if summary.documents_with_low_coverage > 0 {
+        println!(
+            "\nDropped {} documents ({} translation units) with texts in fewer than {:.0}% of their translation units in some language (--require-doc-coverage).",
+            summary.documents_with_low_coverage,
+            summary.translation_units_with_low_doc_coverage,
+            filters.require_doc_coverage.unwrap_or_default() * 100_f64
+        );
+    }
+    if summary.near_duplicates_dropped > 0 {
+        println!(
+            "\nDropped {} near-duplicate translation units.",
+            summary.near_duplicates_dropped
+        );
+    }
+    if summary.boilerplate_dropped > 0 {
+        println!(
+            "\nDropped {} translation units with boilerplate texts (--boilerplate-file).",
+            summary.boilerplate_dropped
+        );
+    }
+    if summary.translation_units_over_quota > 0 {
+        println!(
+            "\nDropped {} translation units left without texts by the per-language quotas.",
+            summary.translation_units_over_quota
+        );
+    }
+    if let Some(limit) = memory.limit() {
+        println!(
+            "\nHeld at most {} of translation units in memory (--max-memory {}).",
+            format_bytes(memory.peak()),
+            format_bytes(limit)
+        );
+        if processed.largest_parsed_size > limit {
+            println!(
+                "The translation units of the largest TMX file alone took {}, so the cap could not be met while it was processed.",
+                format_bytes(processed.largest_parsed_size)
+            );
+        }
+    }
+    if summary.translation_units_revised + summary.translation_units_unchanged > 0 {
+        println!(
+            "\nUpdated {} translation units already in the database (see the revisions table); {} were unchanged.",
+            summary.translation_units_revised, summary.translation_units_unchanged
+        );
+    }
+    if skips_not_logged > 0 {
+        println!(
+            "\nOmitted {} skipped translation units from --skip-log (--skip-log-limit).",
+            skips_not_logged
+        );
+    }
+}
+
+/// Print an error which stopped the run, with its code and a hint (or emit it
+/// as an `error` event, see `--progress-json`).
+fn report_error(error: &anyhow::Error, progress_events: ProgressEvents) {
+    let dgt_error = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<DgtError>());
+    if progress_events.enabled() {
+        progress_events.emit(ProgressEvent::Error {
+            file: None,
+            code: dgt_error.map(DgtError::code),
+            message: format!("{:#}", error),
+            hint: dgt_error.and_then(DgtError::hint),
+        });
+        return;
+    }
+    match dgt_error {
+        Some(dgt_error) => {
+            eprintln!("Error[{}]: {:#}", dgt_error.code(), error);
+            if let Some(hint) = dgt_error.hint() {
+                eprintln!("Hint: {}", hint);
+            }
+        }
+        None => eprintln!("Error: {:#}", error),
+    }
+}
+
+/// Reports the progress of [process_corpus] and records the skipped
+/// translation units (see `--skip-log`).
+struct RunHooks<'a> {
+    progress_reporter: &'a ProgressReporter,
+    progress_events: ProgressEvents,
+    skip_log: Option<&'a mut SkipLog>,
+}
+
+impl Hooks for RunHooks<'_> {
+    fn file_started(&mut self, file: &str, bytes_read: u64) {
+        self.progress_reporter.file_started(bytes_read);
+        self.progress_events
+            .emit(ProgressEvent::FileStarted { file, bytes_read });
+    }
+
+    fn file_done(&mut self, file: &str, translation_units: u64) {
+        self.progress_events.emit(ProgressEvent::FileDone {
+            file,
+            translation_units,
+        });
+    }
+
+    fn file_failed(&mut self, file: &str, error: &DgtError) -> error::Result<()> {
+        self.progress_events.emit(ProgressEvent::Error {
+            file: Some(file),
+            code: Some(error.code()),
+            message: describe(error),
+            hint: None,
+        });
         Ok(())
-    })?;
+    }
 
-    Ok(())
+    fn unit_skipped(
+        &mut self,
+        reason: SkipReason,
+        file: &str,
+        document: Option<&str>,
+        sequential_number: u32,
+    ) -> error::Result<()> {
+        match &mut self.skip_log {
+            Some(skip_log) => skip_log.record(reason, file, document, sequential_number),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Print the warnings and errors collected while parsing the input.
+/// Emit the problems with the input data as events (see `--progress-json`)
+/// rather than printing them. The first `emitted_errors` errors, about the
+/// skipped TMX files, were emitted as they were found.
+fn emit_problems(summary: &Summary, progress_events: ProgressEvents, emitted_errors: usize) {
+    for error in &summary.errors[emitted_errors..] {
+        progress_events.emit(ProgressEvent::Error {
+            file: None,
+            code: None,
+            message: error.clone(),
+            hint: None,
+        });
+    }
+    for warning in &summary.warnings {
+        progress_events.emit(ProgressEvent::Warning {
+            message: warning.clone(),
+        });
+    }
+}
+
+fn report_problems(summary: &Summary) {
+    if summary.warnings.is_empty() && summary.errors.is_empty() {
+        return;
+    }
+    println!();
+    for error in &summary.errors {
+        eprintln!("Error: {}", error);
+    }
+    for warning in &summary.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+/// What the handler reports on while the run is in progress.
+#[cfg(feature = "sqlite")]
+struct Reporting {
+    /// See `--max-memory`.
+    memory: std::sync::Arc<MemoryUsage>,
+
+    /// See `--progress-json`.
+    progress_events: ProgressEvents,
 }
 
 fn init_handler(
     cli_command: Commands,
     requested_langs: RequestedLangs,
+    #[cfg(feature = "sqlite")] missing_as: types::MissingAs,
+    #[cfg(feature = "sqlite")] multi_doc: MultiDoc,
+    #[cfg(any(feature = "sqlite", feature = "xlsx"))] props: dgt_parser::props::PropColumns,
+    #[cfg(feature = "sqlite")] reporting: Reporting,
+    config_file: &Path,
 ) -> Result<Box<dyn types::TranslationUnitHandler>> {
     let handler: Box<dyn types::TranslationUnitHandler> = match cli_command {
-        Commands::Sqlite { output_file } => {
-            if Path::exists(&PathBuf::from(&output_file)) {
-                bail!("Error: {} already exists.", &output_file);
+        #[cfg(feature = "sqlite")]
+        Commands::Sqlite {
+            output_file,
+            max_batch_bytes,
+            dates,
+            search_columns,
+            srclang_column,
+            lang_storage,
+            strip_doc_name_suffixes,
+            metadata_only,
+            deterministic,
+            strict,
+            append,
+            update,
+            with_indexes,
+            pragmas,
+            extensions,
+        } => {
+            // Updating is appending with the existing units revised.
+            let append = append || update;
+            let options = handlers::sqlite_db::Options {
+                max_batch_bytes,
+                missing_as,
+                dates,
+                strip_doc_name_suffixes,
+                metadata_only,
+                search_columns,
+                srclang_column,
+                deterministic,
+                lang_storage,
+                strict,
+                link_all_documents: multi_doc == MultiDoc::All,
+                props,
+                memory: Some(reporting.memory),
+                progress_events: reporting.progress_events,
+                update_existing: update,
+                with_indexes,
+                pragmas,
+                extensions,
+            };
+            if output_file == "-" {
+                if append {
+                    bail!(DgtError::ConflictingOutputs(
+                        "cannot append to the standard output"
+                    ));
+                }
+                return Ok(Box::new(handlers::sqlite_db::Handler::to_stdout(
+                    requested_langs,
+                    options,
+                )?));
+            }
+            let exists = Path::exists(&PathBuf::from(&output_file));
+            if append {
+                if !exists {
+                    bail!(DgtError::DatabaseNotFound(PathBuf::from(output_file)));
+                }
+                let conn = rusqlite::Connection::open(output_file)?;
+                handlers::sqlite_db::configure_connection(&conn, &options)?;
+                return Ok(Box::new(handlers::sqlite_db::Handler::append(
+                    conn,
+                    requested_langs,
+                    options,
+                )?));
+            }
+            if exists {
+                bail!(DgtError::OutputExists(PathBuf::from(output_file)));
             }
             let conn = rusqlite::Connection::open(output_file)?;
-            let handler = Box::new(handlers::sqlite_db::Handler::new(conn, requested_langs));
-            handler
+            handlers::sqlite_db::configure_connection(&conn, &options)?;
+            Box::new(handlers::sqlite_db::Handler::new(
+                conn,
+                requested_langs,
+                options,
+            )?)
+        }
+        Commands::LangPairs { output_file } => {
+            if Path::exists(&PathBuf::from(&output_file)) {
+                bail!(DgtError::OutputExists(PathBuf::from(output_file)));
+            }
+            Box::new(handlers::lang_pairs::Handler::new(
+                output_file,
+                requested_langs,
+            ))
+        }
+        #[cfg(feature = "kv")]
+        Commands::Kv { output_dir } => {
+            if output_dir.exists() {
+                bail!(DgtError::OutputExists(output_dir));
+            }
+            Box::new(handlers::kv::Handler::new(
+                sled::open(output_dir)?,
+                requested_langs,
+            ))
+        }
+        Commands::ExtractAllPairs {
+            output_dir,
+            format,
+            newline_policy,
+            max_open_outputs,
+        } => {
+            if newline_policy == NewlinePolicy::Keep && format == PairFormat::Moses {
+                conflicting_args(
+                    "--newline-policy keep requires --format tsv, whose fields are quoted",
+                );
+            }
+            let langs = match requested_langs {
+                RequestedLangs::Some(langs) | RequestedLangs::Each(langs) if langs.len() > 1 => {
+                    langs.to_vec()
+                }
+                _ => bail!(DgtError::MissingLangs(2)),
+            };
+            if output_dir.exists() {
+                bail!(DgtError::OutputExists(output_dir));
+            }
+            Box::new(handlers::all_pairs::Handler::new(
+                &output_dir,
+                langs,
+                format,
+                newline_policy,
+                max_open_outputs,
+            ))
+        }
+        #[cfg(feature = "xlsx")]
+        Commands::Xlsx {
+            output_file,
+            rows_per_sheet,
+        } => {
+            let langs = match requested_langs {
+                RequestedLangs::Some(langs) | RequestedLangs::Each(langs) => langs.to_vec(),
+                RequestedLangs::Unlimited => bail!(DgtError::MissingLangs(1)),
+            };
+            if output_file.exists() {
+                bail!(DgtError::OutputExists(output_file));
+            }
+            Box::new(handlers::xlsx::Handler::new(
+                output_file,
+                langs,
+                rows_per_sheet,
+                props,
+            ))
+        }
+        Commands::HtmlReview { output_dir } => {
+            let langs = match requested_langs {
+                RequestedLangs::Some(langs) | RequestedLangs::Each(langs) if langs.len() > 1 => {
+                    langs.to_vec()
+                }
+                _ => bail!(DgtError::MissingLangs(2)),
+            };
+            if output_dir.exists() {
+                bail!(DgtError::OutputExists(output_dir));
+            }
+            Box::new(handlers::html_review::Handler::new(&output_dir, langs))
+        }
+        Commands::Classification {
+            output_file,
+            format,
+            newline_policy,
+        } => {
+            if newline_policy == NewlinePolicy::Keep && format == ClassificationFormat::LangId {
+                conflicting_args(
+                    "--newline-policy keep requires --format pairs, whose fields are quoted",
+                );
+            }
+            let langs = match requested_langs {
+                RequestedLangs::Some(langs) | RequestedLangs::Each(langs) => langs.to_vec(),
+                RequestedLangs::Unlimited => Vec::new(),
+            };
+            if let ClassificationFormat::Pairs = format {
+                if langs.len() < 2 {
+                    bail!(DgtError::MissingLangs(2));
+                }
+            }
+            if output_file.exists() {
+                bail!(DgtError::OutputExists(output_file));
+            }
+            let writer = BufWriter::new(File::create(output_file)?);
+            Box::new(handlers::classification::Handler::new(
+                Box::new(writer),
+                format,
+                newline_policy,
+                langs,
+            ))
+        }
+        Commands::External { name } => {
+            let config = Config::load(config_file)?;
+            Box::new(handlers::external::Handler::spawn(
+                &name,
+                config.handler(&name)?,
+                requested_langs,
+            )?)
+        }
+        Commands::Verify { min_coverage } => Box::new(handlers::verify::Handler::new(
+            requested_langs,
+            min_coverage,
+        )),
+        // Only the JSONL output is left once `convert` is resolved.
+        Commands::Convert { output_file } => {
+            if output_file.exists() {
+                bail!(DgtError::OutputExists(output_file));
+            }
+            Box::new(handlers::jsonl::Handler::new(
+                Box::new(BufWriter::new(File::create(output_file)?)),
+                requested_langs,
+            ))
+        }
+        #[cfg(feature = "sqlite")]
+        Commands::Migrate { .. } | Commands::Kwic { .. } | Commands::AlignPhrases { .. } => {
+            unreachable!("not an output format")
+        }
+        #[cfg(feature = "sqlite")]
+        Commands::Export { .. } => unreachable!("replaced with the subcommand saving the output"),
+        Commands::Catalog { .. } | Commands::Init | Commands::GenEdgeCases { .. } => {
+            unreachable!("not an output format")
         }
     };
 
     Ok(handler)
 }
 
-/// Determine the total number of TMX files across all ZIP archives in the
-/// target directory.
-fn count_tmx_files(path: &PathBuf) -> Result<u32> {
-    let mut counter = 0;
-    for_each_zip(path, &mut |zip_archive| {
-        let file_names = zip_archive.file_names();
-        for file_name in file_names {
-            if file_name.ends_with(".tmx") {
-                counter += 1;
+/// Verify the checksums of the input files listed in the checksum file,
+/// matched by their path relative to the input directory or by their file
+/// name. Returns the mismatches. Input files which are not listed, and listed
+/// files which are not found, are reported as warnings.
+fn verify_checksums(
+    input_dir: &Path,
+    checksums: &HashMap<String, String>,
+    summary: &mut Summary,
+) -> Result<Vec<String>> {
+    let mut files: Vec<(String, u64)> = Vec::new();
+    collect_files(input_dir, input_dir, &mut files)?;
+    files.sort();
+
+    let mut mismatches: Vec<String> = Vec::new();
+    let mut verified: HashSet<&String> = HashSet::new();
+    for (relative_path, _size) in &files {
+        let file_name = Path::new(relative_path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let (name, expected) = match checksums
+            .get_key_value(relative_path)
+            .or_else(|| checksums.get_key_value(&file_name))
+        {
+            Some(entry) => entry,
+            None => {
+                summary.warnings.push(format!(
+                    "{} is not listed in the checksum file",
+                    relative_path
+                ));
+                continue;
+            }
+        };
+        verified.insert(name);
+        let found = sha256_file(&input_dir.join(relative_path))?;
+        if &found != expected {
+            mismatches.push(format!(
+                "{}: expected {}, found {}",
+                relative_path, expected, found
+            ));
+        }
+    }
+    let mut missing: Vec<&String> = checksums
+        .keys()
+        .filter(|name| !verified.contains(name))
+        .collect();
+    missing.sort();
+    for name in missing {
+        summary.warnings.push(format!(
+            "{} is listed in the checksum file, but not found in the input",
+            name
+        ));
+    }
+
+    Ok(mismatches)
+}
+
+/// Determine the total number of TMX files across all archives in the
+/// target directory (matching the `--tmx-glob` pattern, if any).
+fn count_tmx_files(input: &Input, tmx_glob: Option<glob::Pattern>) -> Result<u32> {
+    Ok(input.count_tmx_files(&mut Walk::new(tmx_glob))?)
+}
+
+/// Train a model scoring the alignment of the texts in the two languages on
+/// the whole input (matching the `--tmx-glob` pattern, if any). Broken TMX
+/// files are skipped (they are reported by the main pass).
+fn train_alignment_model(
+    input: &Input,
+    tmx_glob: Option<glob::Pattern>,
+    langs: &[String],
+    iterations: usize,
+    quiet: bool,
+) -> Result<AlignmentModel> {
+    let mut trainer = AlignmentTrainer::new(langs[0].clone(), langs[1].clone());
+    input.for_each_tmx_file(&mut Walk::new(tmx_glob), &mut |tmx_file| {
+        if let Ok(parsed) = parse_tmx_stream(tmx_file.reader) {
+            for tu in &parsed.translation_units {
+                trainer.add(tu);
             }
         }
 
         Ok(())
     })?;
+    if !quiet {
+        println!(
+            "Training the alignment model on {} translation units...",
+            trainer.units()
+        );
+    }
+
+    Ok(trainer.train(iterations))
+}
 
-    Ok(counter)
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
 }
@@ -0,0 +1,146 @@
+use crate::error::Result;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use crate::functions::{collect_files, to_hex};
+use crate::types::{MissingAs, MultiDoc, RunInfo};
+
+/// Describes a finished run: the files produced, with their checksums, and
+/// the settings which determined their contents. Allows checking that an
+/// output was generated from a given input (and has not been modified since)
+/// before it is reused downstream.
+#[derive(Serialize)]
+pub struct Manifest {
+    pub tool_version: String,
+    pub arguments: Vec<String>,
+    pub input_hash: String,
+
+    /// See [RunInfo::attribution].
+    pub attribution: Option<String>,
+
+    /// Version of the database schema, for the outputs which have one.
+    pub schema_version: Option<u32>,
+
+    pub filters: Filters,
+    pub outputs: Vec<OutputFile>,
+}
+
+/// Settings which determine which translation units (and texts) are saved,
+/// as resolved from the command line and the configuration file (with the
+/// languages coerced to DGT codes). Recorded in the manifest and in the
+/// outputs which describe their runs (see [RunInfo::filters]).
+#[derive(Serialize, Clone, Default, Debug, PartialEq)]
+pub struct Filters {
+    /// The input, which may come from the configuration file.
+    pub input: Option<String>,
+
+    /// The configuration file, if one was read.
+    pub config_file: Option<PathBuf>,
+
+    /// Requested languages (after coercion), or `None` if all languages are
+    /// included.
+    pub langs: Option<Vec<String>>,
+    pub require_each_lang: bool,
+    pub min_langs: Option<usize>,
+    pub match_lang: Option<String>,
+    pub match_regex: Option<String>,
+    pub require_doc_coverage: Option<f64>,
+    pub source_lang: Option<String>,
+    pub tmx_glob: Option<String>,
+    pub docs_file: Option<PathBuf>,
+    pub exclude_docs_file: Option<PathBuf>,
+    pub seen_files: Option<PathBuf>,
+    pub cluster_near_duplicates: Option<f64>,
+    pub drop_near_duplicates: Option<f64>,
+    pub flag_artifacts: bool,
+
+    /// Language and path of each `--boilerplate-file`.
+    pub boilerplate_files: Vec<(String, PathBuf)>,
+    pub flag_boilerplate: bool,
+    pub index_in_file: bool,
+
+    /// Name and input of each `--merge-corpus`.
+    pub merged_corpora: Vec<(String, String)>,
+
+    /// Source and target language of `--score-alignment`.
+    pub score_alignment: Option<Vec<String>>,
+
+    pub max_units_per_lang: Option<u64>,
+    pub max_chars_per_lang: Option<u64>,
+    pub multi_doc: MultiDoc,
+    pub missing_as: MissingAs,
+    pub max_units: Option<u64>,
+
+    /// `--max-duration` in seconds.
+    pub max_duration: Option<u64>,
+    pub read_ahead: bool,
+}
+
+#[derive(Serialize)]
+pub struct OutputFile {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl Manifest {
+    /// Describe the files found at the output path: a single file or every
+    /// file in an output directory, in path order.
+    pub fn new(
+        run: &RunInfo,
+        schema_version: Option<u32>,
+        output_path: Option<&Path>,
+    ) -> Result<Manifest> {
+        let mut paths: Vec<PathBuf> = Vec::new();
+        if let Some(output_path) = output_path {
+            match output_path.is_dir() {
+                true => {
+                    let mut files: Vec<(String, u64)> = Vec::new();
+                    collect_files(output_path, output_path, &mut files)?;
+                    files.sort();
+                    paths.extend(
+                        files
+                            .into_iter()
+                            .map(|(path, _size)| output_path.join(path)),
+                    );
+                }
+                false => paths.push(output_path.to_path_buf()),
+            }
+        }
+
+        Ok(Manifest {
+            tool_version: run.tool_version.clone(),
+            arguments: run.arguments.clone(),
+            input_hash: run.input_hash.clone(),
+            attribution: run.attribution.clone(),
+            schema_version,
+            filters: run.filters.clone(),
+            outputs: paths
+                .iter()
+                .map(|path| OutputFile::new(path))
+                .collect::<Result<Vec<OutputFile>>>()?,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+}
+
+impl OutputFile {
+    fn new(path: &Path) -> Result<OutputFile> {
+        let mut hasher = Sha256::new();
+        let size = std::io::copy(&mut File::open(path)?, &mut hasher)?;
+
+        Ok(OutputFile {
+            path: path.display().to_string(),
+            size,
+            sha256: to_hex(&hasher.finalize()),
+        })
+    }
+}
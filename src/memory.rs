@@ -0,0 +1,130 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::tmx_parser::TranslationUnit;
+
+/// Approximate memory used by the translation units held at once: those of
+/// the TMX file being processed which have not been handled yet, and those
+/// in batches which the handler has not written yet. Shared by the main
+/// loop, the handler and the progress reporter, which shows it.
+///
+/// Only the texts and props are counted, not the allocator overhead nor
+/// the memory used by SQLite or the decompression of the input.
+#[derive(Debug, Default)]
+pub struct MemoryUsage {
+    parsed: AtomicUsize,
+    batches: AtomicUsize,
+    peak: AtomicUsize,
+
+    /// See `--max-memory`.
+    limit: Option<usize>,
+}
+
+impl MemoryUsage {
+    pub fn new(limit: Option<usize>) -> Arc<MemoryUsage> {
+        Arc::new(MemoryUsage {
+            limit,
+            ..Default::default()
+        })
+    }
+
+    /// Record the translation units of a TMX file which has been parsed.
+    pub fn add_parsed(&self, bytes: usize) {
+        self.parsed.fetch_add(bytes, Ordering::Relaxed);
+        self.update_peak();
+    }
+
+    /// Record that a parsed translation unit has been passed to the handler
+    /// (or dropped).
+    pub fn release_parsed(&self, bytes: usize) {
+        let _ = self
+            .parsed
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |parsed| {
+                Some(parsed.saturating_sub(bytes))
+            });
+    }
+
+    /// Record the size of the batch not yet written by the handler.
+    pub fn set_batches(&self, bytes: usize) {
+        self.batches.store(bytes, Ordering::Relaxed);
+        self.update_peak();
+    }
+
+    pub fn current(&self) -> usize {
+        self.parsed.load(Ordering::Relaxed) + self.batches.load(Ordering::Relaxed)
+    }
+
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Whether the handler should write its batch before it is full.
+    pub fn is_over_limit(&self) -> bool {
+        self.limit.is_some_and(|limit| self.current() > limit)
+    }
+
+    fn update_peak(&self) {
+        self.peak.fetch_max(self.current(), Ordering::Relaxed);
+    }
+}
+
+/// Approximate size of the texts and props of a translation unit.
+pub fn unit_size(tu: &TranslationUnit) -> usize {
+    let props: usize = tu
+        .props
+        .iter()
+        .map(|prop| prop.key.len() + prop.value.len())
+        .sum();
+    let segments: usize = tu
+        .segments
+        .iter()
+        .map(|segment| segment.lang.len() + segment.content.len())
+        .sum();
+    std::mem::size_of::<TranslationUnit>() + props + segments
+}
+
+/// Format a size in bytes for the user, e.g. `3.2 MiB`.
+pub fn format_bytes(bytes: usize) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", size, units[unit]),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_bytes, MemoryUsage};
+
+    #[test]
+    fn usage_is_tracked_against_limit() {
+        let memory = MemoryUsage::new(Some(100));
+        memory.add_parsed(80);
+        memory.set_batches(10);
+        assert!(!memory.is_over_limit());
+
+        memory.release_parsed(30);
+        memory.set_batches(60);
+        assert_eq!(memory.current(), 110);
+        assert!(memory.is_over_limit());
+
+        memory.set_batches(0);
+        memory.release_parsed(100);
+        assert_eq!(memory.current(), 0);
+        assert_eq!(memory.peak(), 110);
+        assert!(!MemoryUsage::new(None).is_over_limit());
+
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(3 * 1024 * 1024 + 200 * 1024), "3.2 MiB");
+    }
+}
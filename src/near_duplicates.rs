@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+
+/// Number of hash functions (i.e. the length of a MinHash signature).
+const NUM_HASHES: usize = 64;
+
+/// Texts are compared as sets of overlapping word n-grams of this length.
+const SHINGLE_SIZE: usize = 3;
+
+/// The cluster a text has been assigned to.
+#[derive(Debug, PartialEq)]
+pub struct Cluster {
+    pub id: u32,
+
+    /// Whether the text is the first one in the cluster (i.e. it is not a
+    /// near-duplicate of any text seen before).
+    pub is_new: bool,
+}
+
+/// Clusters near-duplicate texts, e.g. boilerplate sentences which differ
+/// only in a date or a number, using MinHash signatures and locality-sensitive
+/// hashing.
+///
+/// Texts are processed one by one: a text joins the cluster of the first
+/// text whose estimated Jaccard similarity (over word trigrams) is at least
+/// `threshold`, or starts a new cluster. Only the signature of the first text
+/// of each cluster is kept in memory.
+pub struct NearDuplicateDetector {
+    threshold: f64,
+
+    /// Rows per band; signatures are split into `NUM_HASHES / rows` bands.
+    rows: usize,
+
+    /// Signatures of the first text of each cluster, indexed by cluster ID.
+    representatives: Vec<[u32; NUM_HASHES]>,
+
+    /// Maps a band (its index and hash) to the clusters whose representatives
+    /// contain it. Only these clusters are compared with a new text.
+    buckets: HashMap<(usize, u64), Vec<u32>>,
+}
+
+impl NearDuplicateDetector {
+    /// `threshold` is the minimum estimated Jaccard similarity (0–1) of two
+    /// texts considered near-duplicates.
+    pub fn new(threshold: f64) -> NearDuplicateDetector {
+        NearDuplicateDetector {
+            threshold,
+            rows: rows_per_band(threshold),
+            representatives: Vec::new(),
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Assign the text to a cluster.
+    pub fn cluster(&mut self, text: &str) -> Cluster {
+        let signature = signature(text);
+        let bands: Vec<(usize, u64)> = signature
+            .chunks(self.rows)
+            .enumerate()
+            .map(|(i, band)| (i, hash_band(band)))
+            .collect();
+
+        for band in &bands {
+            if let Some(candidates) = self.buckets.get(band) {
+                for &id in candidates {
+                    if similarity(&signature, &self.representatives[id as usize]) >= self.threshold
+                    {
+                        return Cluster { id, is_new: false };
+                    }
+                }
+            }
+        }
+
+        let id = self.representatives.len() as u32;
+        self.representatives.push(signature);
+        for band in bands {
+            self.buckets.entry(band).or_default().push(id);
+        }
+
+        Cluster { id, is_new: true }
+    }
+}
+
+/// Choose the number of rows per band for which the LSH similarity threshold,
+/// approximately `(1 / bands) ^ (1 / rows)`, is the closest to `threshold`.
+fn rows_per_band(threshold: f64) -> usize {
+    (1..=NUM_HASHES)
+        .filter(|rows| NUM_HASHES.is_multiple_of(*rows))
+        .min_by(|a, b| {
+            let distance = |rows: &usize| {
+                let bands = (NUM_HASHES / rows) as f64;
+                ((1.0 / bands).powf(1.0 / *rows as f64) - threshold).abs()
+            };
+            distance(a).total_cmp(&distance(b))
+        })
+        .unwrap()
+}
+
+/// Compute the MinHash signature of the word trigrams of a text. Texts are
+/// compared case-insensitively and ignoring punctuation.
+fn signature(text: &str) -> [u32; NUM_HASHES] {
+    let text = text.to_lowercase();
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    let shingles: Vec<&[&str]> = match words.len() < SHINGLE_SIZE {
+        true => vec![&words[..]],
+        false => words.windows(SHINGLE_SIZE).collect(),
+    };
+
+    let mut signature = [u32::MAX; NUM_HASHES];
+    for shingle in shingles {
+        let hash = fnv1a(shingle.join(" ").as_bytes());
+        for (i, min) in signature.iter_mut().enumerate() {
+            let value = (splitmix64(hash ^ splitmix64(i as u64)) >> 32) as u32;
+            *min = (*min).min(value);
+        }
+    }
+
+    signature
+}
+
+/// Estimate the Jaccard similarity of two texts from their signatures.
+fn similarity(a: &[u32; NUM_HASHES], b: &[u32; NUM_HASHES]) -> f64 {
+    let equal = a.iter().zip(b).filter(|(a, b)| a == b).count();
+    equal as f64 / NUM_HASHES as f64
+}
+
+fn hash_band(band: &[u32]) -> u64 {
+    band.iter()
+        .fold(0, |hash, value| splitmix64(hash ^ *value as u64))
+}
+
+/// The hash functions are implemented by hand (rather than with the standard
+/// library's hasher), so that cluster IDs are stable across Rust versions.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn near_duplicates_are_clustered() {
+        let mut detector = NearDuplicateDetector::new(0.5);
+        let first = detector.cluster(
+            "This Regulation shall enter into force on the twentieth day following that of its publication in the Official Journal of the European Union.",
+        );
+        let other = detector.cluster("The Commission shall publish the list of approved bodies.");
+        let near_duplicate = detector.cluster(
+            "This Decision shall enter into force on the twentieth day following that of its publication in the Official Journal of the European Union.",
+        );
+
+        assert_eq!(
+            first,
+            Cluster {
+                id: 0,
+                is_new: true
+            }
+        );
+        assert_eq!(
+            other,
+            Cluster {
+                id: 1,
+                is_new: true
+            }
+        );
+        assert_eq!(
+            near_duplicate,
+            Cluster {
+                id: 0,
+                is_new: false
+            }
+        );
+    }
+
+    #[test]
+    fn rows_per_band_follow_threshold() {
+        assert!(rows_per_band(0.9) > rows_per_band(0.5));
+        assert!(NUM_HASHES.is_multiple_of(rows_per_band(0.8)));
+    }
+}
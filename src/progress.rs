@@ -0,0 +1,275 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::memory::{format_bytes, MemoryUsage};
+use crate::types::Progress;
+
+/// Version of the events emitted with `--progress-json`, increased whenever
+/// an event or field is changed or removed (but not when one is added).
+pub const PROGRESS_PROTOCOL_VERSION: u32 = 1;
+
+/// How often the progress line is redrawn.
+const REPORT_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Counters shared with the reporting thread.
+#[derive(Default)]
+struct Counters {
+    tmx_files_seen: AtomicU64,
+    bytes_read: AtomicU64,
+    stopped: AtomicBool,
+}
+
+/// Reports progress from a dedicated thread, redrawing the progress line at a
+/// fixed rate. Parsing only updates atomic counters, so that it is not slowed
+/// down by writing to a slow terminal (or a CI log) after every TMX file.
+pub struct ProgressReporter {
+    counters: Arc<Counters>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ProgressReporter {
+    /// `total` is the number of TMX files or the size of the input (see
+    /// [Progress]). The memory used by the translation units is shown as
+    /// well. Nothing is reported with [Progress::None].
+    pub fn start(progress: Progress, total: u64, memory: Arc<MemoryUsage>) -> ProgressReporter {
+        let counters = Arc::new(Counters::default());
+        let thread = match progress {
+            Progress::None => None,
+            _ => {
+                let counters = counters.clone();
+                Some(std::thread::spawn(move || {
+                    report_until_stopped(&counters, &memory, progress, total)
+                }))
+            }
+        };
+
+        ProgressReporter { counters, thread }
+    }
+
+    /// Record that a TMX file is being parsed, with the number of bytes read
+    /// from the input files so far.
+    pub fn file_started(&self, bytes_read: u64) {
+        self.counters.tmx_files_seen.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .bytes_read
+            .store(bytes_read, Ordering::Relaxed);
+    }
+
+    /// Report the final state and stop the reporting thread.
+    pub fn finish(&mut self) {
+        self.counters.stopped.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for ProgressReporter {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// An event of the machine-readable progress protocol (see
+/// `--progress-json`), written as a line of JSON with its name in `event`.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent<'a> {
+    Started {
+        protocol: u32,
+    },
+    FileStarted {
+        file: &'a str,
+        bytes_read: u64,
+    },
+    FileDone {
+        file: &'a str,
+        translation_units: u64,
+    },
+    /// A batch of translation units has been written to the database.
+    BatchCommitted {
+        translation_units: u64,
+    },
+    /// A TMX file has been skipped (with its name in `file`), or the run
+    /// has failed.
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        file: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        code: Option<&'static str>,
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        hint: Option<&'static str>,
+    },
+    /// A problem with the input data, reported once the run is finished.
+    Warning {
+        message: String,
+    },
+    Finished {
+        tmx_files_parsed: u32,
+        tmx_files_skipped: u32,
+        translation_units_handled: u64,
+    },
+}
+
+/// Emits [ProgressEvent]s to the standard error, one per line, if enabled.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ProgressEvents {
+    enabled: bool,
+}
+
+impl ProgressEvents {
+    pub fn new(enabled: bool) -> ProgressEvents {
+        ProgressEvents { enabled }
+    }
+
+    /// Whether the events are emitted, in which case the problems are not
+    /// printed as text.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn emit(&self, event: ProgressEvent) {
+        if self.enabled {
+            // Locked so that events are not interleaved with other output.
+            let mut stderr = std::io::stderr().lock();
+            let _ = writeln!(stderr, "{}", event_line(&event));
+        }
+    }
+}
+
+fn event_line(event: &ProgressEvent) -> String {
+    serde_json::to_string(event).expect("progress events are serializable")
+}
+
+/// Redraw the progress line whenever the counters have changed, until the
+/// reporter is stopped.
+fn report_until_stopped(counters: &Counters, memory: &MemoryUsage, progress: Progress, total: u64) {
+    let mut last_reported = (0, 0, 0);
+    loop {
+        // Read before checking the flag, so that the final state is always
+        // reported.
+        let stopped = counters.stopped.load(Ordering::Acquire);
+        let current = (
+            counters.tmx_files_seen.load(Ordering::Relaxed),
+            counters.bytes_read.load(Ordering::Relaxed),
+            memory.current(),
+        );
+        if current != last_reported {
+            if let Some(line) = progress_line(progress, current.0, current.1, total, current.2) {
+                let mut stdout = std::io::stdout().lock();
+                let _ = write!(stdout, "\r{}", line);
+                let _ = stdout.flush();
+            }
+            last_reported = current;
+        }
+        if stopped {
+            break;
+        }
+        std::thread::park_timeout(REPORT_INTERVAL);
+    }
+}
+
+fn progress_line(
+    progress: Progress,
+    tmx_files_seen: u64,
+    bytes_read: u64,
+    total: u64,
+    memory: usize,
+) -> Option<String> {
+    // Padded to the longest size (e.g. `1023.9 KiB`), so that a line with a
+    // shorter size fully covers the previous one.
+    let memory = format!("{:<22}", format!("{} in memory).", format_bytes(memory)));
+    match progress {
+        Progress::Files => {
+            let percentage: f32 = (tmx_files_seen as f32 / total as f32) * 100_f32;
+            Some(format!(
+                "Parsing {} out of {} documents ({:.0}%, {}",
+                tmx_files_seen, total, percentage, memory
+            ))
+        }
+        Progress::Bytes => {
+            let percentage: f32 = (bytes_read as f32 / total as f32 * 100_f32).min(100_f32);
+            Some(format!(
+                "Parsing document {} ({:.0}% of the input read, {}",
+                tmx_files_seen, percentage, memory
+            ))
+        }
+        Progress::None => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{event_line, progress_line, ProgressEvent, ProgressReporter};
+    use crate::memory::MemoryUsage;
+    use crate::types::Progress;
+
+    #[test]
+    fn progress_is_reported() {
+        assert_eq!(
+            progress_line(Progress::Files, 1, 0, 4, 2048)
+                .unwrap()
+                .trim_end(),
+            "Parsing 1 out of 4 documents (25%, 2.0 KiB in memory)."
+        );
+        assert_eq!(
+            progress_line(Progress::Bytes, 3, 150, 100, 0)
+                .unwrap()
+                .trim_end(),
+            "Parsing document 3 (100% of the input read, 0 B in memory)."
+        );
+        assert_eq!(
+            progress_line(Progress::Bytes, 3, 150, 100, 0)
+                .unwrap()
+                .len(),
+            progress_line(Progress::Bytes, 3, 150, 100, 1023 * 1024 + 900)
+                .unwrap()
+                .len()
+        );
+        assert_eq!(progress_line(Progress::None, 3, 150, 100, 0), None);
+
+        let mut reporter = ProgressReporter::start(Progress::None, 0, MemoryUsage::new(None));
+        reporter.file_started(10);
+        reporter.finish();
+        assert_eq!(
+            reporter
+                .counters
+                .tmx_files_seen
+                .load(std::sync::atomic::Ordering::Relaxed),
+            1
+        );
+    }
+
+    #[test]
+    fn progress_events_are_json_lines() {
+        assert_eq!(
+            event_line(&ProgressEvent::FileStarted {
+                file: "Vol_2011_1.zip/EUR-Lex_2011.tmx",
+                bytes_read: 42
+            }),
+            r#"{"event":"file_started","file":"Vol_2011_1.zip/EUR-Lex_2011.tmx","bytes_read":42}"#
+        );
+        assert_eq!(
+            event_line(&ProgressEvent::BatchCommitted {
+                translation_units: 3
+            }),
+            r#"{"event":"batch_committed","translation_units":3}"#
+        );
+        assert_eq!(
+            event_line(&ProgressEvent::Error {
+                file: None,
+                code: Some("E001"),
+                message: String::from("./input_dir does not exist"),
+                hint: None,
+            }),
+            r#"{"event":"error","code":"E001","message":"./input_dir does not exist"}"#
+        );
+    }
+}
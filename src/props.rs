@@ -0,0 +1,151 @@
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+use crate::tmx_parser::TranslationUnit;
+use crate::types::{PropColumn, PropType, Summary};
+
+/// Type of the prop holding the document name, which every output saves
+/// anyway.
+const DOC_NAME_KEY: &str = "Txt::Doc. No.";
+
+/// A value of a [PropColumn].
+#[derive(Debug, PartialEq)]
+pub enum PropValue<'a> {
+    Text(&'a str),
+    Integer(i64),
+}
+
+/// Extracts the props of the translation units saved by the tabular outputs
+/// (see `--prop-column` and `--unmapped-props-json`).
+#[derive(Clone, Debug, Default)]
+pub struct PropColumns {
+    pub columns: Vec<PropColumn>,
+
+    /// Also extract the props which are neither mapped to a column nor the
+    /// document name, as a JSON object mapping their types to their values.
+    pub unmapped_as_json: bool,
+
+    /// Number of values left out of integer columns, keyed by column.
+    invalid_values: BTreeMap<String, u64>,
+}
+
+impl PropColumns {
+    pub fn new(columns: Vec<PropColumn>, unmapped_as_json: bool) -> PropColumns {
+        PropColumns {
+            columns,
+            unmapped_as_json,
+            invalid_values: BTreeMap::new(),
+        }
+    }
+
+    /// Values of the columns, in their order (`None` if the translation unit
+    /// does not have the prop). The first value is used if a prop occurs more
+    /// than once.
+    pub fn values<'a>(&mut self, tu: &'a TranslationUnit) -> Vec<Option<PropValue<'a>>> {
+        let mut values = Vec::with_capacity(self.columns.len());
+        for column in &self.columns {
+            let value = tu
+                .props
+                .iter()
+                .find(|prop| prop.key == column.key)
+                .map(|prop| prop.value.as_str());
+            values.push(match (value, column.column_type) {
+                (None, _) => None,
+                (Some(value), PropType::Text) => Some(PropValue::Text(value)),
+                (Some(value), PropType::Integer) => match value.trim().parse() {
+                    Ok(value) => Some(PropValue::Integer(value)),
+                    Err(_) => {
+                        *self
+                            .invalid_values
+                            .entry(column.column.clone())
+                            .or_default() += 1;
+                        None
+                    }
+                },
+            });
+        }
+        values
+    }
+
+    /// The props which are not mapped to a column, as a JSON object (with the
+    /// first value of each type), or `None` if they are not extracted or the
+    /// translation unit has no such props.
+    pub fn unmapped_json(&self, tu: &TranslationUnit) -> Option<String> {
+        if !self.unmapped_as_json {
+            return None;
+        }
+        let mut unmapped = Map::new();
+        for prop in &tu.props {
+            if prop.key == DOC_NAME_KEY
+                || unmapped.contains_key(&prop.key)
+                || self.columns.iter().any(|column| column.key == prop.key)
+            {
+                continue;
+            }
+            unmapped.insert(prop.key.clone(), Value::String(prop.value.clone()));
+        }
+        match unmapped.is_empty() {
+            true => None,
+            false => Some(Value::Object(unmapped).to_string()),
+        }
+    }
+
+    /// Report the values which were left out of integer columns.
+    pub fn report(&self, summary: &mut Summary) {
+        for (column, count) in &self.invalid_values {
+            summary.warnings.push(format!(
+                "Left out {} values of the {} column which are not integers",
+                count, column
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PropColumns, PropValue};
+    use crate::functions::parse_prop_column;
+    use crate::tmx_parser::parse_tmx;
+    use crate::types::Summary;
+
+    #[test]
+    fn props_are_extracted_into_columns() {
+        let units = parse_tmx(String::from(
+            r#"<tmx><header></header><body>
+            <tu><prop type="Txt::Doc. No.">A</prop><prop type="Txt::Doc. Type">DEC</prop>
+            <prop type="Txt::Year">2019</prop><prop type="Txt::Note">x</prop><prop type="Txt::Note">y</prop>
+            <tuv lang="EN-GB"><seg>Text</seg></tuv></tu>
+            <tu><prop type="Txt::Doc. No.">B</prop><prop type="Txt::Year">n/a</prop>
+            <tuv lang="EN-GB"><seg>Text</seg></tuv></tu>
+            </body></tmx>"#,
+        ))
+        .unwrap()
+        .body
+        .translation_units;
+        let mut props = PropColumns::new(
+            vec![
+                parse_prop_column("Txt::Doc. Type=doc_type").unwrap(),
+                parse_prop_column("Txt::Year=year:integer").unwrap(),
+            ],
+            true,
+        );
+
+        assert_eq!(
+            props.values(&units[0]),
+            vec![Some(PropValue::Text("DEC")), Some(PropValue::Integer(2019))]
+        );
+        assert_eq!(
+            props.unmapped_json(&units[0]).unwrap(),
+            r#"{"Txt::Note":"x"}"#
+        );
+        assert_eq!(props.values(&units[1]), vec![None, None]);
+        assert_eq!(props.unmapped_json(&units[1]), None);
+
+        let mut summary = Summary::default();
+        props.report(&mut summary);
+        assert_eq!(
+            summary.warnings,
+            vec!["Left out 1 values of the year column which are not integers"]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use crate::tmx_parser::TranslationUnit;
+
+/// Caps the number of texts (and characters) saved in each language, e.g.
+/// to build a training set of a bounded size in a single pass. Texts are
+/// kept in the order of the input until a quota is reached.
+pub struct LangQuotas {
+    max_units: Option<u64>,
+    max_chars: Option<u64>,
+
+    /// Number of texts and characters kept so far, keyed by language.
+    used: HashMap<String, (u64, u64)>,
+}
+
+impl LangQuotas {
+    pub fn new(max_units: Option<u64>, max_chars: Option<u64>) -> LangQuotas {
+        LangQuotas {
+            max_units,
+            max_chars,
+            used: HashMap::new(),
+        }
+    }
+
+    /// Remove the texts which would exceed the quota of their language.
+    /// Empty texts do not count towards the quotas.
+    pub fn apply(&mut self, translation_unit: &mut TranslationUnit) {
+        translation_unit.segments.retain(|segment| {
+            if segment.content.is_empty() {
+                return true;
+            }
+            let (texts, chars) = self.used.entry(segment.lang.clone()).or_default();
+            let segment_chars = segment.content.chars().count() as u64;
+            let fits = self.max_units.is_none_or(|max| *texts < max)
+                && self
+                    .max_chars
+                    .is_none_or(|max| *chars + segment_chars <= max);
+            if fits {
+                *texts += 1;
+                *chars += segment_chars;
+            }
+            fits
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LangQuotas;
+    use crate::tmx_parser::parse_tmx;
+
+    #[test]
+    fn texts_over_quota_are_removed() {
+        let tmx = parse_tmx(String::from(
+            r#"<tmx><header></header><body>
+            <tu><tuv lang="EN-GB"><seg>One</seg></tuv><tuv lang="PL-01"><seg>Jeden</seg></tuv></tu>
+            <tu><tuv lang="EN-GB"><seg>Two</seg></tuv><tuv lang="PL-01"><seg>Dwa</seg></tuv></tu>
+            <tu><tuv lang="EN-GB"><seg>Three</seg></tuv><tuv lang="PL-01"><seg>Trzy</seg></tuv></tu>
+            </body></tmx>"#,
+        ))
+        .unwrap();
+        let mut quotas = LangQuotas::new(Some(2), Some(7));
+        let langs: Vec<Vec<String>> = tmx
+            .body
+            .translation_units
+            .into_iter()
+            .map(|mut tu| {
+                quotas.apply(&mut tu);
+                tu.segments
+                    .into_iter()
+                    .map(|segment| segment.lang)
+                    .collect()
+            })
+            .collect();
+
+        // `Jeden` and `Dwa` would exceed the 7 characters of Polish, and
+        // the third text exceeds the limit of 2 texts.
+        assert_eq!(langs, vec![vec!["EN-GB", "PL-01"], vec!["EN-GB"], vec![]]);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::error::Result;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::functions::to_hex;
+
+/// Records which TMX files have been processed, keyed by the SHA-256 hash of
+/// their contents, so that a run over an input which partially overlaps a
+/// previous run (e.g. a mirror synced with new releases) only processes the
+/// new files. The record is a text file in the format of `sha256sum`, with a
+/// line per TMX file (`<hash>  <name>`).
+pub struct SeenFiles {
+    path: PathBuf,
+
+    /// Hashes of the files processed by previous runs.
+    seen: HashSet<String>,
+
+    /// Files processed by this run, saved once the run has succeeded.
+    new: Vec<(String, String)>,
+}
+
+impl SeenFiles {
+    /// Read the record, which does not need to exist yet.
+    pub fn open(path: PathBuf) -> Result<SeenFiles> {
+        let seen = match path.exists() {
+            true => std::fs::read_to_string(&path)?
+                .lines()
+                .filter_map(|line| line.split_whitespace().next())
+                .map(|hash| hash.to_ascii_lowercase())
+                .collect(),
+            false => HashSet::new(),
+        };
+
+        Ok(SeenFiles {
+            path,
+            seen,
+            new: Vec::new(),
+        })
+    }
+
+    pub fn hash(contents: &[u8]) -> String {
+        to_hex(&Sha256::digest(contents))
+    }
+
+    /// Whether a file with this hash has been processed, by a previous run or
+    /// earlier in this run (e.g. the same file in two archives).
+    pub fn contains(&self, hash: &str) -> bool {
+        self.seen.contains(hash)
+    }
+
+    /// Mark a file as processed.
+    pub fn insert(&mut self, hash: String, name: &str) {
+        if self.seen.insert(hash.clone()) {
+            self.new.push((hash, name.to_string()));
+        }
+    }
+
+    /// Append the files processed by this run to the record.
+    pub fn save(&mut self) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let lines: String = self
+            .new
+            .drain(..)
+            .map(|(hash, name)| format!("{}  {}\n", hash, name))
+            .collect();
+        file.write_all(lines.as_bytes())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SeenFiles;
+
+    #[test]
+    fn seen_files_are_recorded() {
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("seen.txt");
+        let hash = SeenFiles::hash(b"<tmx/>");
+
+        let mut seen_files = SeenFiles::open(path.clone()).unwrap();
+        assert!(!seen_files.contains(&hash));
+        seen_files.insert(hash.clone(), "1.zip/a.tmx");
+        seen_files.insert(hash.clone(), "2.zip/a.tmx");
+        assert!(seen_files.contains(&hash));
+        seen_files.save().unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let seen_files = SeenFiles::open(path.clone()).unwrap();
+
+        assert_eq!(contents, format!("{}  1.zip/a.tmx\n", hash));
+        assert!(seen_files.contains(&hash));
+        assert!(!seen_files.contains(&SeenFiles::hash(b"<tmx></tmx>")));
+    }
+}
@@ -0,0 +1,208 @@
+use crate::error::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Why a translation unit was not passed to the handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    MissingDocId,
+    /// Not listed in `--docs-file`. Only the first translation unit of each
+    /// document is reported.
+    UnlistedDoc,
+    /// Listed in `--exclude-docs-file`.
+    ExcludedDoc,
+    /// See `--require-doc-coverage`.
+    LowDocCoverage,
+    MissingSourceLang,
+    /// None (or, with `--require-each-lang`, not all) of the requested
+    /// languages.
+    MissingLangs,
+    /// See `--min-langs`.
+    TooFewLangs,
+    /// See `--match-regex`.
+    NoMatch,
+    /// See `--drop-near-duplicates`.
+    NearDuplicate,
+    /// See `--boilerplate-file`.
+    Boilerplate,
+    /// Left without texts by `--max-units-per-lang` or
+    /// `--max-chars-per-lang`.
+    OverQuota,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    reason: SkipReason,
+    file: &'a str,
+    document: Option<&'a str>,
+    sequential_number: u32,
+}
+
+/// Records each skipped translation unit as a JSON object on a line of its
+/// own, e.g. `{"reason":"missing_langs","file":"1.zip/a.tmx","document":"22019D0391","sequential_number":3}`,
+/// so that the effect of the filters can be audited. The sequential number
+/// is the one the handler would have received.
+pub struct SkipLog {
+    writer: BufWriter<File>,
+
+    /// At most this many translation units are recorded for each reason,
+    /// which bounds the size of the log. They are sampled uniformly from all
+    /// the units skipped for the reason (and written once the log is
+    /// finished, in the order they were skipped).
+    max_per_reason: Option<u64>,
+
+    /// Number of translation units skipped for each reason, recorded or not.
+    skipped: HashMap<SkipReason, u64>,
+
+    /// The sampled entries of each reason, with the number of units skipped
+    /// before them (for any reason), if the number of entries is limited.
+    samples: HashMap<SkipReason, Vec<(u64, String)>>,
+
+    /// State of the pseudorandom generator of the samples, seeded with a
+    /// constant so that runs over the same input record the same units.
+    random_state: u64,
+}
+
+impl SkipLog {
+    pub fn create(path: &Path, max_per_reason: Option<u64>) -> Result<SkipLog> {
+        Ok(SkipLog {
+            writer: BufWriter::new(File::create(path)?),
+            max_per_reason,
+            skipped: HashMap::new(),
+            samples: HashMap::new(),
+            random_state: 0,
+        })
+    }
+
+    /// A pseudorandom number (SplitMix64).
+    fn next_random(&mut self) -> u64 {
+        self.random_state = self.random_state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.random_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    pub fn record(
+        &mut self,
+        reason: SkipReason,
+        file: &str,
+        document: Option<&str>,
+        sequential_number: u32,
+    ) -> Result<()> {
+        let order: u64 = self.skipped.values().sum();
+        let skipped = self.skipped.entry(reason).or_default();
+        *skipped += 1;
+        let skipped = *skipped;
+        let entry = Entry {
+            reason,
+            file,
+            document,
+            sequential_number,
+        };
+        let max = match self.max_per_reason {
+            Some(max) => max,
+            None => {
+                serde_json::to_writer(&mut self.writer, &entry)?;
+                self.writer.write_all(b"\n")?;
+                return Ok(());
+            }
+        };
+
+        // Reservoir sampling: the n-th unit replaces a random sampled one
+        // with a probability of max/n.
+        let slot = match skipped <= max {
+            true => None,
+            false => match self.next_random() % skipped {
+                slot if slot < max => Some(slot as usize),
+                _ => return Ok(()),
+            },
+        };
+        let line = (order, serde_json::to_string(&entry)?);
+        let samples = self.samples.entry(reason).or_default();
+        match slot {
+            Some(slot) => samples[slot] = line,
+            None => samples.push(line),
+        }
+
+        Ok(())
+    }
+
+    /// Write the sampled entries and flush the log, returning the number of
+    /// skipped translation units which were not recorded because of the
+    /// limit.
+    pub fn finish(&mut self) -> Result<u64> {
+        let mut samples: Vec<(u64, String)> = self.samples.drain().flat_map(|(_, s)| s).collect();
+        samples.sort();
+        for (_, line) in samples {
+            self.writer.write_all(line.as_bytes())?;
+            self.writer.write_all(b"\n")?;
+        }
+        self.writer.flush()?;
+
+        Ok(self
+            .skipped
+            .values()
+            .map(|skipped| skipped.saturating_sub(self.max_per_reason.unwrap_or(u64::MAX)))
+            .sum())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SkipLog, SkipReason};
+
+    #[test]
+    fn skipped_units_are_sampled_across_the_input() {
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("skipped.jsonl");
+
+        let mut skip_log = SkipLog::create(&path, Some(10)).unwrap();
+        for i in 0..1000 {
+            skip_log
+                .record(SkipReason::TooFewLangs, "a.tmx", Some("A"), i)
+                .unwrap();
+        }
+        assert_eq!(skip_log.finish().unwrap(), 990);
+
+        let numbers: Vec<u64> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let entry: serde_json::Value = serde_json::from_str(line).unwrap();
+                entry["sequential_number"].as_u64().unwrap()
+            })
+            .collect();
+        assert_eq!(numbers.len(), 10);
+        assert!(numbers.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(numbers.iter().any(|number| *number >= 500));
+    }
+
+    #[test]
+    fn skipped_units_are_recorded_up_to_limit() {
+        let tmp_dir = assert_fs::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("skipped.jsonl");
+
+        let mut skip_log = SkipLog::create(&path, Some(1)).unwrap();
+        skip_log
+            .record(SkipReason::MissingLangs, "a.tmx", Some("A"), 0)
+            .unwrap();
+        skip_log
+            .record(SkipReason::MissingLangs, "a.tmx", Some("A"), 1)
+            .unwrap();
+        skip_log
+            .record(SkipReason::MissingDocId, "a.tmx", None, 2)
+            .unwrap();
+        assert_eq!(skip_log.finish().unwrap(), 1);
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "{\"reason\":\"missing_langs\",\"file\":\"a.tmx\",\"document\":\"A\",\"sequential_number\":0}\n\
+            {\"reason\":\"missing_doc_id\",\"file\":\"a.tmx\",\"document\":null,\"sequential_number\":2}\n"
+        );
+    }
+}
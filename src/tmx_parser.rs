@@ -1,9 +1,10 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+use crate::artifacts::ArtifactFlags;
 use crate::types::RequestedLangs;
-use anyhow::Result;
-use quick_xml::de::{from_str, DeError};
-use serde::Deserialize;
+use quick_xml::de::{from_reader, from_str, DeError};
+use serde::{Deserialize, Serialize};
+use std::io::BufRead;
 
 #[derive(Debug, Deserialize, PartialEq)]
 pub struct Tmx {
@@ -27,12 +28,51 @@ pub struct Body {
 
 /// A translation unit contains the translations of a text in multiple
 /// languages.
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
 pub struct TranslationUnit {
     #[serde(rename = "prop", default)]
     pub props: Vec<Prop>,
     #[serde(rename = "tuv", default)]
     pub segments: Vec<Tuv>,
+
+    /// Language of the original text, if it differs from the `srclang` of
+    /// the header.
+    #[serde(rename = "srclang", default)]
+    pub source_lang: Option<String>,
+
+    /// Data derived while processing the translation unit. Not part of the
+    /// TMX file (nor of the cache).
+    #[serde(skip)]
+    pub annotations: Annotations,
+}
+
+/// See [TranslationUnit::annotations].
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Annotations {
+    /// ID of the cluster of near-duplicate texts the translation unit belongs
+    /// to, if near-duplicate detection is enabled.
+    pub near_duplicate_cluster: Option<u32>,
+
+    /// Whether the text is a formatting artifact, if detection is enabled.
+    pub artifact_flags: Option<ArtifactFlags>,
+
+    /// How likely the texts of the language pair of `--score-alignment` are
+    /// translations of each other (see [crate::alignment::AlignmentModel]).
+    pub alignment_score: Option<f64>,
+
+    /// Whether a text is a boilerplate phrase, if boilerplate is flagged
+    /// rather than dropped (see `--flag-boilerplate`).
+    pub is_boilerplate: Option<bool>,
+
+    /// Position of the translation unit in its TMX file (starting from 0),
+    /// if recorded (see `--index-in-file`). The sequential number passed to
+    /// the handler is its position in its document instead, which may span
+    /// several TMX files.
+    pub index_in_file: Option<u32>,
+
+    /// Name of the corpus the translation unit comes from, once other
+    /// corpora are merged with DGT-TM (see `--merge-corpus`).
+    pub corpus: Option<String>,
 }
 
 /// The `prop` element defines metadata. In the context of the DGT-TM, this
@@ -51,7 +91,7 @@ pub struct TranslationUnit {
 ///     ...
 /// </tu>
 /// ```
-#[derive(Debug, Deserialize, PartialEq, Clone)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
 pub struct Prop {
     #[serde(rename = "type")]
     pub key: String,
@@ -59,21 +99,123 @@ pub struct Prop {
     pub value: String,
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
 pub struct Tuv {
     #[serde(alias = "lang", default)]
     #[serde(alias = "xml:lang")]
     pub lang: String,
     #[serde(rename = "seg", default)]
     pub content: String,
+
+    /// Present in some releases, e.g. `20190827T102232Z`. See
+    /// [crate::functions::tmx_date_to_iso8601].
+    #[serde(rename = "creationdate", default)]
+    pub creation_date: Option<String>,
+    #[serde(rename = "changedate", default)]
+    pub change_date: Option<String>,
+
+    /// Position of the `tuv` element within its translation unit (starting
+    /// from 0), assigned by [parse_tmx]. Allows consumers to reconstruct the
+    /// original order, e.g. if a language occurs more than once.
+    #[serde(default)]
+    pub index: u32,
+
+    /// Whether this is the original text of the translation unit rather than
+    /// a translation, according to the `srclang` attribute of the translation
+    /// unit or the header. Assigned by [parse_tmx].
+    #[serde(default)]
+    pub is_source: bool,
 }
 
 /// Deserialize an XML string into a [Tmx] struct.
 pub fn parse_tmx(xml_string: String) -> Result<Tmx, DeError> {
-    from_str(&xml_string)
+    let tmx: Tmx = from_str(&xml_string)?;
+    Ok(index_segments(tmx))
+}
+
+/// Deserialize XML read from a (UTF-8) reader into a [Tmx] struct, without
+/// holding the whole document in memory.
+pub fn parse_tmx_reader<R: BufRead>(reader: R) -> Result<Tmx, DeError> {
+    let tmx: Tmx = from_reader(reader)?;
+    Ok(index_segments(tmx))
+}
+
+/// The language of a code without its region, e.g. `EN` for `EN-GB`.
+fn primary_lang(lang: &str) -> &str {
+    lang.split(['-', '_']).next().unwrap_or(lang)
+}
+
+/// Assign the data derived from the structure of the document:
+/// [Tuv::index] and [Tuv::is_source]. Only the first text in the source
+/// language is marked, and none if the source language is `*all*` (any).
+fn index_segments(mut tmx: Tmx) -> Tmx {
+    let header_source_lang = tmx.header.attributes.get("srclang").cloned();
+    for tu in &mut tmx.body.translation_units {
+        for (index, segment) in tu.segments.iter_mut().enumerate() {
+            segment.index = index as u32;
+        }
+        let source_lang = tu.source_lang.as_ref().or(header_source_lang.as_ref());
+        if let Some(source_lang) = source_lang.filter(|lang| *lang != "*all*") {
+            if let Some(segment) = tu
+                .segments
+                .iter_mut()
+                .find(|segment| LangMatch::IgnoreCase.matches(&segment.lang, source_lang))
+            {
+                segment.is_source = true;
+            }
+        }
+    }
+    tmx
+}
+
+/// How language codes are compared, e.g. by [TranslationUnit::get_lang].
+/// Codes are always compared case-insensitively, e.g. `EN-GB` matches
+/// `en-gb`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LangMatch {
+    /// The whole code has to match.
+    IgnoreCase,
+
+    /// Regional variants match each other, e.g. `EN-GB` matches `EN` and
+    /// `EN-US`, but a text in the same variant is preferred.
+    Variant,
+}
+
+impl LangMatch {
+    /// Whether the language code matches the other one.
+    pub fn matches(self, lang: &str, other: &str) -> bool {
+        match self {
+            LangMatch::IgnoreCase => lang.eq_ignore_ascii_case(other),
+            LangMatch::Variant => primary_lang(lang).eq_ignore_ascii_case(primary_lang(other)),
+        }
+    }
 }
 
 impl TranslationUnit {
+    /// The first non-empty text in the language, if any.
+    pub fn get_lang(&self, lang: &str, matching: LangMatch) -> Option<&Tuv> {
+        let texts = || {
+            self.segments
+                .iter()
+                .filter(|segment| !segment.content.is_empty())
+        };
+        let same_variant =
+            texts().find(|segment| LangMatch::IgnoreCase.matches(&segment.lang, lang));
+        match matching {
+            LangMatch::IgnoreCase => same_variant,
+            LangMatch::Variant => same_variant
+                .or_else(|| texts().find(|segment| matching.matches(&segment.lang, lang))),
+        }
+    }
+
+    /// Whether the translation unit has a text (even an empty one) in the
+    /// language, compared with [LangMatch::IgnoreCase].
+    pub fn has_lang(&self, lang: &str) -> bool {
+        self.segments
+            .iter()
+            .any(|segment| LangMatch::IgnoreCase.matches(&segment.lang, lang))
+    }
+
     /// Name/ID of EU legislation associated with the translation unit.
     pub fn doc_name(&self) -> Option<&String> {
         let name_props = &self
@@ -82,50 +224,271 @@ impl TranslationUnit {
             .filter(|el| el.key == "Txt::Doc. No.")
             .collect::<Vec<&Prop>>();
 
-        return match name_props.get(0) {
+        match name_props.first() {
             Some(name) => Some(&name.value),
             None => None,
+        }
+    }
+
+    /// Names/IDs of all documents associated with the translation unit, in
+    /// the order of their props and without duplicates. A few translation
+    /// units name several documents (see `--multi-doc`).
+    pub fn doc_names(&self) -> Vec<&String> {
+        let mut names: Vec<&String> = Vec::new();
+        for prop in self.props.iter().filter(|el| el.key == "Txt::Doc. No.") {
+            if !names.contains(&&prop.value) {
+                names.push(&prop.value);
+            }
+        }
+        names
+    }
+
+    /// Assign the translation unit to a document, e.g. one without a
+    /// document ID.
+    pub fn add_doc_name(&mut self, name: String) {
+        self.props.push(Prop {
+            key: String::from("Txt::Doc. No."),
+            value: name,
+        });
+    }
+
+    /// Rename each document of the translation unit.
+    pub fn rename_docs<F>(&mut self, rename: F)
+    where
+        F: Fn(&str) -> String,
+    {
+        for prop in self.props.iter_mut().filter(|el| el.key == "Txt::Doc. No.") {
+            prop.value = rename(&prop.value);
+        }
+    }
+
+    /// Replace the document props with a single one naming all documents,
+    /// joined with the separator (e.g. `32019D0557;32019D0558`), so that the
+    /// translation unit is assigned to a document of its own.
+    pub fn join_doc_names(&mut self, separator: &str) {
+        let names: Vec<String> = self.doc_names().into_iter().cloned().collect();
+        if names.len() < 2 {
+            return;
+        }
+        let position = self
+            .props
+            .iter()
+            .position(|el| el.key == "Txt::Doc. No.")
+            .unwrap_or_default();
+        self.props.retain(|el| el.key != "Txt::Doc. No.");
+        self.props.insert(
+            position,
+            Prop {
+                key: String::from("Txt::Doc. No."),
+                value: names.join(separator),
+            },
+        );
+    }
+
+    /// The text which represents the translation unit in checks of the
+    /// contents: the text in the source language or, if no source language
+    /// has been selected, the first text (English in DGT-TM).
+    pub fn source_text(&self, source_lang: Option<&String>) -> &str {
+        let segment = match source_lang {
+            Some(source_lang) => self.get_lang(source_lang, LangMatch::IgnoreCase),
+            None => self.segments.first(),
         };
+        segment.map(|s| s.content.as_str()).unwrap_or("")
+    }
+
+    /// Number of languages with a non-empty text in the translation unit,
+    /// counting only the specified languages (if any). Language codes are
+    /// compared case-insensitively.
+    pub fn lang_count(&self, langs: &RequestedLangs) -> usize {
+        let texts = self
+            .segments
+            .iter()
+            .filter(|segment| !segment.content.is_empty());
+        match langs {
+            RequestedLangs::Unlimited => {
+                let mut found: Vec<String> = texts
+                    .map(|segment| segment.lang.to_ascii_uppercase())
+                    .collect();
+                found.sort();
+                found.dedup();
+                found.len()
+            }
+            RequestedLangs::Each(langs) | RequestedLangs::Some(langs) => {
+                let mut found: Vec<usize> = texts
+                    .filter_map(|segment| langs.position(&segment.lang))
+                    .collect();
+                found.sort();
+                found.dedup();
+                found.len()
+            }
+        }
     }
 
     /// Checks whether the translation unit contains texts in **each** of the
     /// specified languages.
     pub fn contains_each_lang(&self, langs: &RequestedLangs) -> bool {
-        return match langs {
+        match langs {
             RequestedLangs::Unlimited => true,
             RequestedLangs::Each(langs) | RequestedLangs::Some(langs) => {
-                langs.iter().fold(true, |acc, lang| {
-                    if !acc {
-                        return false;
+                // Positions of the languages found, as a bit set if there are
+                // few enough of them (which is always the case in DGT-TM).
+                let positions = self
+                    .segments
+                    .iter()
+                    .filter_map(|segment| langs.position(&segment.lang));
+                match langs.len() <= u64::BITS as usize {
+                    true => {
+                        let found = positions.fold(0_u64, |found, i| found | 1 << i);
+                        found.count_ones() as usize == langs.len()
                     }
-
-                    for segment in &self.segments {
-                        if &segment.lang == lang {
-                            return true;
-                        }
-                    }
-
-                    false
-                })
+                    false => positions.collect::<HashSet<usize>>().len() == langs.len(),
+                }
             }
-        };
+        }
     }
 
     /// Checks whether the translation unit contains texts in **any** of the
     /// specified languages.
     pub fn contains_any_lang(&self, langs: &RequestedLangs) -> bool {
-        return match langs {
+        match langs {
             RequestedLangs::Unlimited => true,
-            RequestedLangs::Each(langs) | RequestedLangs::Some(langs) => {
-                for lang in langs {
-                    for segment in &self.segments {
-                        if &segment.lang == lang {
-                            return true;
-                        }
-                    }
-                }
-                return false;
-            }
-        };
+            RequestedLangs::Each(langs) | RequestedLangs::Some(langs) => self
+                .segments
+                .iter()
+                .any(|segment| langs.contains(&segment.lang)),
+        }
     }
 }
+
+#[test]
+fn several_doc_names_are_joined() {
+    let mut tu = parse_tmx(String::from(
+        r#"<tmx><header></header><body><tu>
+        <prop type="Txt::Doc. No.">A</prop><prop type="Txt::Other">X</prop>
+        <prop type="Txt::Doc. No.">B</prop><prop type="Txt::Doc. No.">A</prop>
+        <tuv lang="EN-GB"><seg>Text</seg></tuv></tu></body></tmx>"#,
+    ))
+    .unwrap()
+    .body
+    .translation_units
+    .remove(0);
+
+    assert_eq!(tu.doc_names(), vec!["A", "B"]);
+    assert_eq!(tu.doc_name().unwrap(), "A");
+    tu.join_doc_names(";");
+    assert_eq!(tu.doc_names(), vec!["A;B"]);
+    assert_eq!(tu.props.len(), 2);
+    assert_eq!(tu.props[1].key, "Txt::Other");
+}
+
+#[test]
+fn segments_are_indexed_in_document_order() {
+    let tmx = parse_tmx(String::from(
+        r#"<tmx><header></header><body><tu>
+        <tuv lang="EN-GB"><seg>A</seg></tuv><tuv lang="PL-01"><seg>B</seg></tuv><tuv lang="EN-GB"><seg>C</seg></tuv>
+        </tu></body></tmx>"#,
+    ))
+    .unwrap();
+    let segments = &tmx.body.translation_units[0].segments;
+
+    let indexes: Vec<(u32, &str)> = segments
+        .iter()
+        .map(|segment| (segment.index, segment.content.as_str()))
+        .collect();
+    assert_eq!(indexes, vec![(0, "A"), (1, "B"), (2, "C")]);
+}
+
+#[test]
+fn source_segments_are_marked() {
+    let tmx = parse_tmx(String::from(
+        r#"<tmx><header srclang="EN-GB"></header><body>
+        <tu><tuv lang="PL-01"><seg>A</seg></tuv><tuv lang="en-gb"><seg>B</seg></tuv><tuv lang="EN-GB"><seg>C</seg></tuv></tu>
+        <tu srclang="PL-01"><tuv lang="EN-GB"><seg>D</seg></tuv><tuv lang="PL-01"><seg>E</seg></tuv></tu>
+        </body></tmx>"#,
+    ))
+    .unwrap();
+    let sources: Vec<Vec<bool>> = tmx
+        .body
+        .translation_units
+        .iter()
+        .map(|tu| {
+            tu.segments
+                .iter()
+                .map(|segment| segment.is_source)
+                .collect()
+        })
+        .collect();
+
+    assert_eq!(sources, vec![vec![false, true, false], vec![false, true]]);
+}
+
+#[test]
+fn languages_with_texts_are_counted() {
+    let tmx = parse_tmx(String::from(
+        r#"<tmx><header></header><body><tu>
+        <tuv lang="EN-GB"><seg>A</seg></tuv><tuv lang="PL-01"><seg></seg></tuv><tuv lang="DE-DE"><seg>B</seg></tuv><tuv lang="EN-GB"><seg>C</seg></tuv><tuv lang="en-gb"><seg>D</seg></tuv>
+        </tu></body></tmx>"#,
+    ))
+    .unwrap();
+    let tu = &tmx.body.translation_units[0];
+    let langs =
+        |langs: &[&str]| RequestedLangs::Some(langs.iter().map(|l| l.to_string()).collect());
+
+    assert_eq!(tu.lang_count(&RequestedLangs::Unlimited), 2);
+    assert_eq!(tu.lang_count(&langs(&["EN-GB", "PL-01"])), 1);
+    assert_eq!(tu.lang_count(&langs(&["en-gb", "de-de"])), 2);
+}
+
+#[test]
+fn segment_dates_are_parsed() {
+    let tmx = parse_tmx(String::from(
+        r#"<tmx><header></header><body><tu>
+        <tuv lang="EN-GB" creationdate="20190827T102232Z" changedate="20200101T000000Z"><seg>A</seg></tuv><tuv lang="PL-01"><seg>B</seg></tuv>
+        </tu></body></tmx>"#,
+    ))
+    .unwrap();
+    let segments = &tmx.body.translation_units[0].segments;
+
+    assert_eq!(
+        segments[0].creation_date.as_deref(),
+        Some("20190827T102232Z")
+    );
+    assert_eq!(segments[0].change_date.as_deref(), Some("20200101T000000Z"));
+    assert_eq!(segments[1].creation_date, None);
+}
+
+#[test]
+fn requested_langs_are_matched() {
+    let tmx = parse_tmx(String::from(
+        r#"<tmx><header></header><body><tu>
+        <tuv lang="EN-GB"><seg>A</seg></tuv><tuv lang="PL-01"><seg>B</seg></tuv><tuv lang="EN-GB"><seg>C</seg></tuv>
+        </tu></body></tmx>"#,
+    ))
+    .unwrap();
+    let tu = &tmx.body.translation_units[0];
+    let langs = |langs: &[&str]| langs.iter().map(|lang| lang.to_string()).collect();
+
+    assert!(tu.contains_each_lang(&RequestedLangs::Each(langs(&["PL-01", "EN-GB"]))));
+    assert!(!tu.contains_each_lang(&RequestedLangs::Each(langs(&["EN-GB", "DE-DE"]))));
+    assert!(tu.contains_any_lang(&RequestedLangs::Some(langs(&["DE-DE", "PL-01"]))));
+    assert!(!tu.contains_any_lang(&RequestedLangs::Some(langs(&["DE-DE"]))));
+    assert!(tu.contains_each_lang(&RequestedLangs::Unlimited));
+}
+
+#[test]
+fn texts_are_looked_up_by_lang() {
+    let tmx = parse_tmx(String::from(
+        r#"<tmx><header></header><body><tu>
+        <tuv lang="en-us"><seg>Color</seg></tuv><tuv lang="PL-01"><seg></seg></tuv><tuv lang="EN-GB"><seg>Colour</seg></tuv>
+        </tu></body></tmx>"#,
+    ))
+    .unwrap();
+    let tu = &tmx.body.translation_units[0];
+    let text = |lang: &str, matching| tu.get_lang(lang, matching).map(|s| s.content.as_str());
+
+    assert_eq!(text("en-gb", LangMatch::IgnoreCase), Some("Colour"));
+    assert_eq!(text("EN-US", LangMatch::Variant), Some("Color"));
+    assert_eq!(text("EN", LangMatch::IgnoreCase), None);
+    assert_eq!(text("EN", LangMatch::Variant), Some("Color"));
+    assert_eq!(text("pl_01", LangMatch::Variant), None);
+}
@@ -1,24 +1,399 @@
-use crate::tmx_parser::TranslationUnit;
+use crate::error::Result;
+use serde::Serialize;
+use std::ops::Deref;
+use std::path::PathBuf;
+
+use crate::manifest::Filters;
+use crate::tmx_parser::{LangMatch, TranslationUnit};
 
 /// Passed to the handler to specify which languages should be included in the
 /// output. Language codes are in the same format as in the source TMX files,
 /// i.e. `EN-GB`, `PL-01`.
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub enum RequestedLangs {
     /// 1. Include all languages.
     /// 2. Don’t skip any translation units.
+    #[default]
     Unlimited,
 
     /// 1. Include only the specified languages.
     /// 2. Include translation units that contain **at least one** of the specified languages.
-    Some(Vec<String>),
+    Some(LangSet),
 
     /// 1. Include only the specified languages.
     /// 2. Include translation units that contain **each** of the specified languages.
-    Each(Vec<String>),
+    Each(LangSet),
+}
+
+/// Language codes in the order in which they were specified, without
+/// duplicates. Codes are compared with [LangMatch::IgnoreCase], like the
+/// other lookups of languages. Languages are looked up for every text of
+/// every translation unit, so each code is also packed into an integer: for
+/// a few short codes, scanning the integers is faster than hashing or
+/// comparing the strings.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LangSet {
+    langs: Vec<String>,
+
+    /// `keys[i]` is [lang_key] of `langs[i]`.
+    keys: Vec<u64>,
+}
+
+impl LangSet {
+    pub fn contains(&self, lang: &str) -> bool {
+        self.position(lang).is_some()
+    }
+
+    /// Index of the language in the order in which it was specified.
+    pub fn position(&self, lang: &str) -> Option<usize> {
+        let key = lang_key(lang);
+        // Codes longer than 8 bytes may share a key, hence the comparison.
+        self.keys
+            .iter()
+            .zip(&self.langs)
+            .position(|(other_key, other)| {
+                *other_key == key && LangMatch::IgnoreCase.matches(other, lang)
+            })
+    }
+}
+
+/// The first 8 bytes of a language code (e.g. `EN-GB`) in upper case, as an
+/// integer.
+fn lang_key(lang: &str) -> u64 {
+    lang.bytes()
+        .map(|byte| byte.to_ascii_uppercase())
+        .take(8)
+        .enumerate()
+        .fold(0, |key, (i, byte)| key | (byte as u64) << (8 * i))
+}
+
+impl FromIterator<String> for LangSet {
+    fn from_iter<I: IntoIterator<Item = String>>(iter: I) -> LangSet {
+        let mut set = LangSet::default();
+        for lang in iter {
+            if !set.contains(&lang) {
+                set.keys.push(lang_key(&lang));
+                set.langs.push(lang);
+            }
+        }
+        set
+    }
+}
+
+impl Deref for LangSet {
+    type Target = [String];
+
+    fn deref(&self) -> &[String] {
+        &self.langs
+    }
+}
+
+/// Describes how the output is being produced. Passed to the handler before
+/// the first translation unit, so that outputs can be made self-describing.
+pub struct RunInfo {
+    pub tool_version: String,
+
+    /// Command-line arguments, including the program name.
+    pub arguments: Vec<String>,
+
+    /// See [crate::functions::hash_inputs].
+    pub input_hash: String,
+
+    /// The pivot language selected by the user, e.g. `EN-GB`. Each handled
+    /// translation unit contains a text in this language.
+    pub source_lang: Option<String>,
+
+    /// Unix timestamp (seconds).
+    pub started_at: u64,
+
+    /// Reuse notice embedded in the outputs which can hold one (see
+    /// `--attribution`), or `None` if disabled.
+    pub attribution: Option<String>,
+
+    /// The effective settings of the run, so that the filters which
+    /// produced an output are never ambiguous.
+    pub filters: Filters,
+}
+
+/// Acknowledgement of the source required for reuse of the DGT-TM, embedded
+/// in the outputs by default.
+pub const DGT_ATTRIBUTION: &str = "Source: DGT-Translation Memory (DGT-TM), \
+    https://joint-research-centre.ec.europa.eu/language-technology-resources/dgt-translation-memory_en. \
+    © European Union. Reuse is authorised provided the source is acknowledged \
+    (Commission Decision 2011/833/EU).";
+
+/// Counters collected while parsing the input.
+#[derive(Default)]
+pub struct Summary {
+    pub tmx_files_parsed: u32,
+
+    /// TMX files which could not be read, decoded or parsed.
+    pub tmx_files_skipped: u32,
+
+    /// TMX files in archives excluded by `--tmx-glob`.
+    pub tmx_files_excluded: u32,
+
+    /// TMX files skipped because they are listed in `--seen-files`.
+    pub tmx_files_seen_before: u32,
+
+    /// TMX files left out because `--max-duration` or `--max-units` was
+    /// reached.
+    pub tmx_files_left_out: u32,
+
+    /// Translation units passed to the handler.
+    pub translation_units_handled: u64,
+
+    /// Translation units skipped because of the language requirements.
+    pub translation_units_skipped: u64,
+
+    /// Translation units dropped because of invalid data, e.g. a missing
+    /// document ID.
+    pub translation_units_dropped: u64,
+
+    /// Translation units dropped because of `--min-langs`.
+    pub translation_units_with_too_few_langs: u64,
+
+    /// Translation units dropped because their text did not match
+    /// `--match-regex`.
+    pub translation_units_not_matching: u64,
+
+    /// Documents (and their translation units) dropped because of
+    /// `--require-doc-coverage`.
+    pub documents_with_low_coverage: u64,
+    pub translation_units_with_low_doc_coverage: u64,
+
+    /// Translation units dropped because of `--drop-near-duplicates`.
+    pub near_duplicates_dropped: u64,
+
+    /// Translation units dropped because one of their texts is listed in
+    /// `--boilerplate-file`.
+    pub boilerplate_dropped: u64,
+
+    /// Translation units left without texts by `--max-units-per-lang` or
+    /// `--max-chars-per-lang`.
+    pub translation_units_over_quota: u64,
+
+    /// Translation units of the documents not listed in `--docs-file`.
+    pub translation_units_not_listed: u64,
+
+    /// Translation units of the documents listed in `--exclude-docs-file`.
+    pub translation_units_excluded: u64,
+
+    /// Translation units handled which name several documents (see
+    /// `--multi-doc`).
+    pub translation_units_with_several_docs: u64,
+
+    /// Translation units already in the database whose texts were updated
+    /// (or were the same), with `sqlite --update`.
+    pub translation_units_revised: u64,
+    pub translation_units_unchanged: u64,
+
+    /// Problems with the input data which did not prevent the file from being
+    /// parsed.
+    pub warnings: Vec<String>,
+
+    /// Problems with the input data which caused a file to be skipped.
+    pub errors: Vec<String>,
+
+    /// Unix timestamp (seconds).
+    pub finished_at: u64,
+}
+
+#[test]
+fn lang_set_keeps_order_without_duplicates() {
+    let langs: LangSet = [
+        "PL-01",
+        "EN-GB",
+        "PL-01",
+        "EN-GB-X-LONG-CODE",
+        "EN-GB-X-LONG-CODF",
+    ]
+    .iter()
+    .map(|lang| lang.to_string())
+    .collect();
+
+    assert_eq!(
+        langs.to_vec(),
+        vec!["PL-01", "EN-GB", "EN-GB-X-LONG-CODE", "EN-GB-X-LONG-CODF"]
+    );
+    assert_eq!(langs.position("EN-GB"), Some(1));
+    assert_eq!(langs.position("EN-GB-X-LONG-CODF"), Some(3));
+    assert_eq!(langs.position("en-gb"), Some(1));
+    assert!(!langs.contains("DE-DE"));
+}
+
+/// Secondary output (`--tee`) receiving the same translation units as the
+/// main output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TeeTarget {
+    /// The only format so far is JSONL.
+    pub format: TeeFormat,
+
+    /// `None` for the standard output.
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TeeFormat {
+    Jsonl,
+}
+
+/// A prop of the translation units saved in a column of its own by the
+/// tabular outputs (see `--prop-column`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct PropColumn {
+    /// Type of the prop, e.g. `Txt::Doc. Type`.
+    pub key: String,
+
+    /// Name of the column, e.g. `doc_type`.
+    pub column: String,
+
+    pub column_type: PropType,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PropType {
+    Text,
+
+    /// Values which are not integers are left out (and reported).
+    Integer,
+}
+
+/// How output formats with a fixed set of language fields represent texts
+/// which are missing from a translation unit (or empty).
+#[derive(Clone, Copy, Default, PartialEq, Debug, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingAs {
+    /// Missing and empty texts are `NULL`.
+    Null,
+
+    /// Missing and empty texts are empty strings.
+    #[default]
+    Empty,
+}
+
+/// How line breaks in the texts are written by the outputs with a text (or a
+/// tab-separated field) per line (see `--newline-policy`).
+#[derive(Clone, Copy, Default, PartialEq, Debug, clap::ValueEnum)]
+pub enum NewlinePolicy {
+    /// Line breaks and tabs are replaced with spaces.
+    #[default]
+    Replace,
+
+    /// Line breaks, tabs and backslashes are escaped as `\n`, `\r`, `\t`
+    /// and `\\`, so that the texts can be restored.
+    Escape,
+
+    /// Line breaks are kept, and the fields which contain one (or a tab or a
+    /// quote) are quoted as in RFC 4180, e.g. `"Article 1\nScope"`. Only
+    /// for the tab-separated formats.
+    Keep,
+}
+
+/// What is done with translation units which name several documents (i.e.
+/// have several `Txt::Doc. No.` props).
+#[derive(Clone, Copy, Default, PartialEq, Debug, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MultiDoc {
+    /// The translation unit is assigned to the first document only.
+    #[default]
+    First,
+
+    /// The translation unit is assigned to the first document, and outputs
+    /// with a table of documents (e.g. SQLite) link it to all of them.
+    All,
+
+    /// The translation unit is assigned to a document named after all of its
+    /// documents, joined with [JOINED_DOC_NAMES_SEPARATOR].
+    Join,
+}
+
+pub const JOINED_DOC_NAMES_SEPARATOR: &str = ";";
+
+/// How progress is reported while parsing the input.
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum Progress {
+    /// Number of parsed TMX files out of the total, which is determined by an
+    /// extra pass over the input before parsing.
+    Files,
+
+    /// Share of the input read so far, based on the size of the input files.
+    Bytes,
+
+    None,
+}
+
+/// Determines which problems with the input data cause the program to exit
+/// with a non-zero status code.
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum FailOn {
+    Warnings,
+    Errors,
+    None,
+}
+
+impl FailOn {
+    pub fn is_triggered_by(&self, summary: &Summary) -> bool {
+        match self {
+            FailOn::Warnings => !summary.warnings.is_empty() || !summary.errors.is_empty(),
+            FailOn::Errors => !summary.errors.is_empty(),
+            FailOn::None => false,
+        }
+    }
 }
 
 pub trait TranslationUnitHandler {
-    /// Process a [TranslationUnit], e.g. insert it into a database.
-    fn handle(&mut self, translation_unit: TranslationUnit, sequential_number_in_doc: u32);
+    /// Called once before the first translation unit is handled.
+    fn start(&mut self, _run: &RunInfo) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once after [TranslationUnitHandler::start] with the languages
+    /// known to be included in the output: the source language (if any)
+    /// followed by the requested languages. Handlers with a field per
+    /// language can create them up front, in this order, rather than when
+    /// each language first appears in the input. Empty if all languages are
+    /// included and no source language has been selected.
+    fn declare_langs(&mut self, _langs: &[String]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Process a [TranslationUnit], e.g. insert it into a database. The
+    /// sequential number is the position of the unit among those of its
+    /// document (starting from 0), counting the units skipped by the filters,
+    /// even if the document spans several TMX files.
+    fn handle(
+        &mut self,
+        translation_unit: TranslationUnit,
+        sequential_number_in_doc: u32,
+    ) -> Result<()>;
+
+    /// Called once after all translation units have been handled, e.g. to
+    /// commit the last batch or write the output file. Handlers which check
+    /// the input data may add warnings to the summary.
+    fn finish(&mut self, _summary: &mut Summary) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<H: TranslationUnitHandler + ?Sized> TranslationUnitHandler for Box<H> {
+    fn start(&mut self, run: &RunInfo) -> Result<()> {
+        (**self).start(run)
+    }
+
+    fn declare_langs(&mut self, langs: &[String]) -> Result<()> {
+        (**self).declare_langs(langs)
+    }
+
+    fn handle(
+        &mut self,
+        translation_unit: TranslationUnit,
+        sequential_number_in_doc: u32,
+    ) -> Result<()> {
+        (**self).handle(translation_unit, sequential_number_in_doc)
+    }
+
+    fn finish(&mut self, summary: &mut Summary) -> Result<()> {
+        (**self).finish(summary)
+    }
 }
@@ -0,0 +1,101 @@
+use assert_cmd::prelude::CommandCargoExt;
+use std::process::Command;
+
+#[test]
+fn verify_reports_documents_with_missing_texts() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args(["--fail-on", "warnings", "-i", "test_data/zipped", "verify"]);
+    let output = cmd.output()?;
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8(output.stderr)?
+        .contains("Document 22019D0391 has missing texts: ET-01 18/25"));
+
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "--fail-on",
+        "warnings",
+        "-i",
+        "test_data/zipped",
+        "verify",
+        "--min-coverage",
+        "0.5",
+    ]);
+    assert_eq!(cmd.output()?.status.code(), Some(0));
+
+    Ok(())
+}
+
+#[test]
+fn documents_are_saved_as_html_pages() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp_dir = assert_fs::TempDir::new()?;
+    let output_dir = tmp_dir.path().join("review");
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-l",
+        "en",
+        "-l",
+        "pl",
+        "-i",
+        "test_data/zipped",
+        "html-review",
+        "-o",
+        output_dir.display().to_string().as_str(),
+    ]);
+    assert!(cmd.output()?.status.success());
+
+    let pages = std::fs::read_dir(&output_dir)?.count();
+    assert_eq!(pages, 7 + 1);
+    let page = std::fs::read_to_string(output_dir.join("22019D0391.html"))?;
+    assert_eq!(page.matches("<tr id=").count(), 25);
+    assert!(page.contains("<tr id=\"24\">"));
+
+    Ok(())
+}
+
+#[test]
+fn external_handler_receives_translation_units() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp_dir = assert_fs::TempDir::new()?;
+    let config_path = tmp_dir.path().join("dgt_parser.toml");
+    let output_path = tmp_dir.path().join("received.jsonl");
+    std::fs::write(
+        &config_path,
+        format!(
+            "[handlers.custom]\ncommand = \"sh\"\nargs = [\"-c\", \"cat > \\\"$0\\\"\", {:?}]\n",
+            output_path.display().to_string()
+        ),
+    )?;
+    let run = |name: &str| -> Result<std::process::Output, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+        cmd.args([
+            "--config",
+            config_path.display().to_string().as_str(),
+            "-i",
+            "test_data/zipped",
+            "external",
+            name,
+        ]);
+        Ok(cmd.output()?)
+    };
+
+    assert!(run("custom")?.status.success());
+    let messages: Vec<serde_json::Value> = std::fs::read_to_string(&output_path)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    assert_eq!(messages[0]["type"], "start");
+    assert_eq!(messages[0]["protocol"], 1);
+    assert_eq!(
+        messages
+            .iter()
+            .filter(|message| message["type"] == "unit")
+            .count(),
+        462
+    );
+    assert_eq!(messages.last().unwrap()["translation_units"], 462);
+
+    let output = run("missing")?;
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)?.starts_with("Error[E020]: unknown handler: missing"));
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+#![cfg(feature = "kv")]
+
+use assert_cmd::prelude::CommandCargoExt;
+use std::process::Command;
+
+#[test]
+fn units_are_saved_in_key_value_store() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp_dir = assert_fs::TempDir::new()?;
+    let output_dir = tmp_dir.path().join("kv");
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-l",
+        "en",
+        "-i",
+        "test_data/zipped",
+        "kv",
+        "-o",
+        output_dir.display().to_string().as_str(),
+    ]);
+    assert!(cmd.output()?.status.success());
+
+    let db = sled::open(&output_dir)?;
+    assert_eq!(db.len(), 462);
+    assert_eq!(db.scan_prefix(b"22019D0391\0").count(), 25);
+    let mut key = b"22019D0391\0".to_vec();
+    key.extend_from_slice(&0u32.to_be_bytes());
+    let value: serde_json::Value = serde_json::from_slice(&db.get(key)?.unwrap())?;
+    assert_eq!(value["document"], "22019D0391");
+    assert_eq!(value["texts"][0]["lang"], "EN-GB");
+
+    Ok(())
+}
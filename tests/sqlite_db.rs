@@ -1,7 +1,13 @@
+#![cfg(feature = "sqlite")]
+
 use assert_cmd::prelude::CommandCargoExt;
 use assert_fs::{self, TempDir};
 use rusqlite::{self, Connection};
-use std::{path::PathBuf, process::Command};
+use sha2::Digest;
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
 
 fn setup() -> Result<(TempDir, PathBuf), Box<dyn std::error::Error>> {
     let tmp_dir = assert_fs::TempDir::new().unwrap();
@@ -77,3 +83,2094 @@ fn require_each_lang_test() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn zip_files_nested_in_tar_gz_are_parsed() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-i",
+        "test_data/tar_gz",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    let _output = cmd.output();
+
+    let conn = rusqlite::Connection::open(db_file_path.display().to_string().as_str()).unwrap();
+
+    let count = query_number(&conn, "select count(*) from translation_units").unwrap();
+    let doc_count = query_number(&conn, "select count(*) from documents").unwrap();
+
+    assert_eq!(count, 462);
+    assert_eq!(doc_count, 7);
+
+    Ok(())
+}
+
+#[test]
+fn plain_tmx_files_in_directory_are_parsed() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-i",
+        "test_data/test_dgt_docs",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    let _output = cmd.output();
+
+    let conn = rusqlite::Connection::open(db_file_path.display().to_string().as_str()).unwrap();
+
+    let doc_count = query_number(&conn, "select count(*) from documents").unwrap();
+
+    assert_eq!(doc_count, 34);
+
+    Ok(())
+}
+
+#[test]
+fn run_metadata_is_recorded() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-l",
+        "pl",
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    let _output = cmd.output();
+
+    let conn = rusqlite::Connection::open(db_file_path.display().to_string().as_str()).unwrap();
+
+    let (version, arguments, finished_at): (String, String, Option<String>) = conn.query_row(
+        "select tool_version, arguments, finished_at from runs",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    let tmx_files = query_number(&conn, "select tmx_files from runs").unwrap();
+    let handled = query_number(&conn, "select translation_units from runs").unwrap();
+    let skipped = query_number(&conn, "select skipped_translation_units from runs").unwrap();
+
+    assert_eq!(version, env!("CARGO_PKG_VERSION"));
+    assert!(arguments.contains("\"test_data/zipped\""));
+    assert!(finished_at.is_some());
+    assert_eq!(tmx_files, 7);
+    assert_eq!(handled, 440);
+    assert_eq!(skipped, 22);
+
+    Ok(())
+}
+
+#[test]
+fn broken_tmx_files_are_skipped_and_reported_in_exit_code() -> Result<(), Box<dyn std::error::Error>>
+{
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let input_dir = tmp_dir.path().join("input");
+    std::fs::create_dir(&input_dir)?;
+    std::fs::copy(
+        "test_data/test_dgt_docs/22019D1360.tmx",
+        input_dir.join("22019D1360.tmx"),
+    )?;
+    std::fs::write(input_dir.join("broken.tmx"), b"\xff\xfe<\x00t\x00")?;
+
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-q",
+        "-i",
+        input_dir.display().to_string().as_str(),
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    let output = cmd.output()?;
+    assert_eq!(output.status.code(), Some(3));
+
+    let conn = rusqlite::Connection::open(db_file_path.display().to_string().as_str()).unwrap();
+    let doc_count = query_number(&conn, "select count(*) from documents").unwrap();
+    assert_eq!(doc_count, 1);
+
+    let other_db_file_path = tmp_dir.path().join("other.sqlite");
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-q",
+        "--fail-on",
+        "none",
+        "-i",
+        input_dir.display().to_string().as_str(),
+        "sqlite",
+        "-o",
+        other_db_file_path.display().to_string().as_str(),
+    ]);
+    let output = cmd.output()?;
+    assert_eq!(output.status.code(), Some(0));
+
+    Ok(())
+}
+
+#[test]
+fn truncated_archives_are_skipped_and_reported() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let input_dir = tmp_dir.path().join("input");
+    std::fs::create_dir(&input_dir)?;
+    std::fs::copy(
+        "test_data/test_dgt_docs/22019D1360.tmx",
+        input_dir.join("22019D1360.tmx"),
+    )?;
+    let zip = std::fs::read("test_data/zipped/1.zip")?;
+    std::fs::write(input_dir.join("1.zip"), &zip[..3000])?;
+
+    let output = Command::cargo_bin("dgt_parser")?
+        .args(["--progress-json", "--fail-on", "warnings", "-i"])
+        .arg(&input_dir)
+        .args(["sqlite", "-o"])
+        .arg(&db_file_path)
+        .output()?;
+    assert_eq!(output.status.code(), Some(3));
+
+    let events: Vec<serde_json::Value> = String::from_utf8(output.stderr)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    let errors: Vec<&serde_json::Value> = events.iter().filter(|e| e["event"] == "error").collect();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0]["file"].as_str().unwrap().ends_with("1.zip"));
+    assert_eq!(errors[0]["code"], "E016");
+
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    let doc_count = query_number(&conn, "select count(*) from documents").unwrap();
+    assert_eq!(doc_count, 1);
+
+    Ok(())
+}
+
+#[test]
+fn source_lang_is_the_first_language_column() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "--source-lang",
+        "pl",
+        "-l",
+        "en",
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    assert!(cmd.output()?.status.success());
+
+    let conn = rusqlite::Connection::open(db_file_path.display().to_string().as_str()).unwrap();
+
+    let first_lang_column: String = conn.query_row(
+        "SELECT name FROM pragma_table_info('translation_units') WHERE cid = 3",
+        [],
+        |row| row.get(0),
+    )?;
+    assert_eq!(first_lang_column, "pl_01");
+    let without_source = query_number(
+        &conn,
+        "select count(*) from translation_units where pl_01 is null",
+    )
+    .unwrap();
+    assert_eq!(without_source, 0);
+    let source_lang: String =
+        conn.query_row("SELECT source_lang FROM runs", [], |row| row.get(0))?;
+    assert_eq!(source_lang, "PL-01");
+
+    Ok(())
+}
+
+#[test]
+fn near_duplicates_are_clustered_or_dropped() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, clustered_db_path) = setup().unwrap();
+    let deduplicated_db_path = tmp_dir.path().join("deduplicated.sqlite");
+    for (flag, db_file_path) in [
+        ("--cluster-near-duplicates", &clustered_db_path),
+        ("--drop-near-duplicates", &deduplicated_db_path),
+    ] {
+        let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+        cmd.args([
+            flag,
+            "0.8",
+            "-l",
+            "en",
+            "-i",
+            "test_data/zipped",
+            "sqlite",
+            "-o",
+            db_file_path.display().to_string().as_str(),
+        ]);
+        assert!(cmd.output()?.status.success());
+    }
+
+    let clustered = rusqlite::Connection::open(&clustered_db_path).unwrap();
+    let units = query_number(&clustered, "select count(*) from translation_units").unwrap();
+    let clusters = query_number(
+        &clustered,
+        "select count(distinct near_duplicate_cluster) from translation_units",
+    )
+    .unwrap();
+    assert_eq!(units, 462);
+    assert!(clusters < units);
+
+    let deduplicated = rusqlite::Connection::open(&deduplicated_db_path).unwrap();
+    let units = query_number(&deduplicated, "select count(*) from translation_units").unwrap();
+    assert_eq!(units, clusters);
+
+    Ok(())
+}
+
+#[test]
+fn requested_lang_columns_are_declared_in_order() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-l",
+        "pl",
+        "-l",
+        "en",
+        "-l",
+        "ga",
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    assert!(cmd.output()?.status.success());
+
+    let conn = rusqlite::Connection::open(db_file_path.display().to_string().as_str()).unwrap();
+    let mut query = conn.prepare("SELECT name FROM pragma_table_info('translation_units')")?;
+    let columns: Vec<String> = query
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+
+    assert_eq!(
+        columns,
+        vec![
+            "id",
+            "document_id",
+            "sequential_number",
+            "pl_01",
+            "en_gb",
+            "ga_ie"
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn formatting_artifacts_are_flagged() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "--flag-artifacts",
+        "-l",
+        "en",
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    assert!(cmd.output()?.status.success());
+
+    let conn = rusqlite::Connection::open(db_file_path.display().to_string().as_str()).unwrap();
+    let uppercase = query_number(
+        &conn,
+        "select count(*) from translation_units where is_uppercase",
+    )
+    .unwrap();
+    let heading = query_number(
+        &conn,
+        "select is_uppercase from translation_units where en_gb = 'HAS DECIDED AS FOLLOWS:' limit 1",
+    )
+    .unwrap();
+    assert_eq!(uppercase, 36);
+    assert_eq!(heading, 1);
+
+    Ok(())
+}
+
+#[test]
+fn manifest_lists_output_with_checksum() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let manifest_path = tmp_dir.path().join("manifest.json");
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-l",
+        "en",
+        "--manifest",
+        manifest_path.display().to_string().as_str(),
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    assert!(cmd.output()?.status.success());
+
+    let manifest: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(&manifest_path)?)?;
+    let db = std::fs::read(&db_file_path)?;
+    let sha256: String = sha2::Sha256::digest(&db)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    assert_eq!(manifest["filters"]["langs"], serde_json::json!(["EN-GB"]));
+    assert_eq!(manifest["outputs"][0]["size"], db.len());
+    assert_eq!(manifest["outputs"][0]["sha256"], sha256);
+    assert!(manifest["schema_version"].is_u64());
+
+    Ok(())
+}
+
+#[test]
+fn requested_langs_missing_from_input_are_reported() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let run = |strict: bool| {
+        let _ = std::fs::remove_file(&db_file_path);
+        let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+        if strict {
+            cmd.arg("--strict-langs");
+        }
+        cmd.args([
+            "-l",
+            "en",
+            "-l",
+            "sw-ke",
+            "-i",
+            "test_data/zipped",
+            "sqlite",
+            "-o",
+            db_file_path.display().to_string().as_str(),
+        ]);
+        cmd.output().unwrap()
+    };
+
+    let output = run(false);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8(output.stderr)?
+        .contains("Warning: Language sw-ke does not occur in the input"));
+
+    let output = run(true);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8(output.stderr)?
+        .contains("Error: Language sw-ke does not occur in the input"));
+
+    // The languages of a file are always checked strictly.
+    let langs_file = tmp_dir.path().join("langs.txt");
+    std::fs::write(&langs_file, "pl\nsw-tz\n")?;
+    let _ = std::fs::remove_file(&db_file_path);
+    let output = Command::cargo_bin("dgt_parser")?
+        .args(["-l", "sw-ke", "--langs-file"])
+        .arg(&langs_file)
+        .args(["-i", "test_data/zipped", "sqlite", "-o"])
+        .arg(&db_file_path)
+        .output()?;
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("Warning: Language sw-ke does not occur in the input"));
+    assert!(stderr.contains("Error: Language sw-tz does not occur in the input"));
+
+    Ok(())
+}
+
+#[test]
+fn database_is_written_to_stdout() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    // The database is built in a temporary file, removed once written.
+    let temp_dir = tmp_dir.path().join("temp");
+    std::fs::create_dir(&temp_dir)?;
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.env("TMPDIR", &temp_dir)
+        .args(["-l", "en", "-i", "test_data/zipped", "sqlite", "-o", "-"]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert_eq!(std::fs::read_dir(&temp_dir)?.count(), 0);
+    std::fs::write(&db_file_path, output.stdout)?;
+
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    let count = query_number(&conn, "select count(en_gb) from translation_units")?;
+    assert_eq!(count, 462);
+
+    // The transactions still in the write-ahead log are written too.
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args(["-l", "en", "-i", "test_data/zipped", "sqlite"])
+        .args(["--pragma", "journal_mode=WAL", "-o", "-"]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    std::fs::write(&db_file_path, output.stdout)?;
+
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    let count = query_number(&conn, "select count(en_gb) from translation_units")?;
+    assert_eq!(count, 462);
+
+    Ok(())
+}
+
+#[test]
+fn edge_cases_are_handled() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let edge_cases_dir = tmp_dir.path().join("edge_cases");
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "gen-edge-cases",
+        "-o",
+        edge_cases_dir.display().to_string().as_str(),
+    ]);
+    assert!(cmd.output()?.status.success());
+
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-l",
+        "en",
+        "-l",
+        "pl",
+        "-i",
+        edge_cases_dir.display().to_string().as_str(),
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)?
+        .contains("Dropped 2 translation units without a document ID"));
+
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    let count_in = |doc: &str| {
+        query_number(
+            &conn,
+            &format!(
+                "select count(*) from translation_units tu join documents d on d.id = tu.document_id where d.name = '{}'",
+                doc
+            ),
+        )
+        .unwrap()
+    };
+    assert_eq!(count_in("EMPTY"), 3);
+    assert_eq!(count_in("MISSING"), 1);
+    assert_eq!(count_in("INVALID"), 1);
+    assert_eq!(count_in("MULTI_A"), 2);
+    assert_eq!(count_in("MULTI_B"), 0);
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from translation_units where en_gb = ''"
+        )?,
+        2
+    );
+    assert_eq!(
+        query_number(&conn, "select length(en_gb) > 900000 from translation_units join documents d on d.id = document_id where d.name = 'HUGE'")?,
+        1
+    );
+    let duplicate: String = conn.query_row(
+        "select en_gb from translation_units join documents d on d.id = document_id where d.name = 'DUPLICATE'",
+        [],
+        |row| row.get(0),
+    )?;
+    assert_eq!(duplicate, "First");
+
+    Ok(())
+}
+
+#[test]
+fn appended_translation_units_keep_document_ids() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let run = |input: &str, append: bool| {
+        let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+        cmd.args(["-l", "en", "-l", "pl", "-i", input, "sqlite", "-o"]);
+        cmd.arg(&db_file_path);
+        if append {
+            cmd.arg("--append");
+        }
+        assert!(cmd.output().unwrap().status.success());
+    };
+    let doc_ids = |conn: &Connection| -> Vec<(String, u32)> {
+        let mut query = conn
+            .prepare("select name, id from documents order by name")
+            .unwrap();
+        let rows = query
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        rows.map(|row| row.unwrap()).collect()
+    };
+
+    run("test_data/zipped", false);
+    let doc_ids_before = doc_ids(&Connection::open(&db_file_path)?);
+    run("test_data/zipped", true);
+    let conn = Connection::open(&db_file_path)?;
+
+    assert_eq!(doc_ids(&conn), doc_ids_before);
+    assert_eq!(
+        query_number(&conn, "select count(*) from translation_units")?,
+        2 * 462
+    );
+    assert_eq!(query_number(&conn, "select count(*) from runs")?, 2);
+    // The numbers of the appended units continue those of their documents.
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from (select distinct document_id, sequential_number from translation_units)"
+        )?,
+        2 * 462
+    );
+
+    Ok(())
+}
+
+#[test]
+fn units_are_streamed_as_jsonl_with_tee() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "--tee",
+        "jsonl:-",
+        "-l",
+        "en",
+        "-l",
+        "pl",
+        "--require-each-lang",
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let units: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    assert_eq!(
+        units.len() as u32,
+        query_number(&conn, "select count(*) from translation_units")?
+    );
+    assert_eq!(units[0]["texts"].as_array().unwrap().len(), 2);
+    assert!(units[0]["document"].is_string());
+
+    // Documents are linked to EUR-Lex in both outputs.
+    let document = units[0]["document"].as_str().unwrap();
+    let url: String = conn.query_row(
+        "select url from documents where name = ?",
+        [document],
+        |row| row.get(0),
+    )?;
+    assert_eq!(
+        url,
+        format!(
+            "https://eur-lex.europa.eu/legal-content/AUTO/?uri=CELEX:{}",
+            document
+        )
+    );
+    assert_eq!(units[0]["document_url"].as_str(), Some(url.as_str()));
+
+    Ok(())
+}
+
+#[test]
+fn listed_documents_are_excluded() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let docs_file_path = tmp_dir.path().join("docs.txt");
+    std::fs::write(
+        &docs_file_path,
+        "# Misaligned\n22019d0391\n22019D0556 # corrigendum\n",
+    )?;
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-l",
+        "en",
+        "--exclude-docs-file",
+        docs_file_path.display().to_string().as_str(),
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains("Excluded 44 translation units"));
+
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    assert_eq!(
+        query_number(&conn, "select count(*) from translation_units")?,
+        462 - 25 - 19
+    );
+    assert_eq!(query_number(&conn, "select count(*) from documents")?, 5);
+
+    Ok(())
+}
+
+#[test]
+fn database_is_exported_with_another_subcommand() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let db_file = db_file_path.display().to_string();
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-q",
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "--srclang-column",
+        "-o",
+        db_file.as_str(),
+    ]);
+    assert!(cmd.output()?.status.success());
+
+    let extract = |output_dir: &Path, input: &[&str]| {
+        let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+        cmd.args(["-q", "-l", "en", "-l", "pl"])
+            .args(input)
+            .args(["extract-all-pairs", "-o"])
+            .arg(output_dir);
+        assert!(cmd.output().unwrap().status.success());
+        std::fs::read_to_string(output_dir.join("en-pl").join("corpus.pl")).unwrap()
+    };
+    let exported = extract(&tmp_dir.path().join("exported"), &["export", &db_file]);
+    let parsed = extract(&tmp_dir.path().join("parsed"), &["-i", "test_data/zipped"]);
+    assert_eq!(exported.lines().count(), 440);
+    assert_eq!(exported, parsed);
+
+    // The languages of the original texts are read back.
+    let copy_path = tmp_dir.path().join("copy.sqlite");
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-q",
+        "export",
+        db_file.as_str(),
+        "sqlite",
+        "--srclang-column",
+        "-o",
+    ])
+    .arg(&copy_path);
+    assert!(cmd.output()?.status.success());
+    let source_langs = "select count(*) from translation_units where srclang = 'EN-GB'";
+    let original = query_number(&Connection::open(&db_file_path)?, source_langs)?;
+    assert!(original > 0);
+    assert_eq!(
+        query_number(&Connection::open(&copy_path)?, source_langs)?,
+        original
+    );
+
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args(["export", db_file.as_str(), "kwic"]);
+    assert!(!cmd.output()?.status.success());
+
+    Ok(())
+}
+
+#[test]
+fn other_corpora_are_merged() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let europarl_dir = tmp_dir.path().join("europarl");
+    std::fs::create_dir(&europarl_dir)?;
+    std::fs::write(
+        europarl_dir.join("en-pl.tmx"),
+        r#"<?xml version="1.0" encoding="utf-8"?><tmx version="1.4"><header srclang="en"/><body>
+        <tu><tuv xml:lang="en"><seg>Resumption of the session</seg></tuv><tuv xml:lang="pl"><seg>Wznowienie sesji</seg></tuv></tu>
+        <tu><tuv xml:lang="en"><seg>Approval of the minutes</seg></tuv><tuv xml:lang="pl"><seg>Zatwierdzenie protokołu</seg></tuv></tu>
+        <tu><prop type="Txt::Doc. No.">22019D0557</prop><tuv xml:lang="en"><seg>Same name</seg></tuv></tu>
+        </body></tmx>"#,
+    )?;
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-l",
+        "en",
+        "-l",
+        "pl",
+        "-i",
+        "test_data/zipped",
+        "--merge-corpus",
+        format!("europarl={}", europarl_dir.display()).as_str(),
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    assert!(cmd.output()?.status.success());
+
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from translation_units where corpus = 'dgt'"
+        )?,
+        462
+    );
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from translation_units t join documents d on d.id = t.document_id
+            where corpus = 'europarl' and d.name = 'EUROPARL:EN-PL' and en_gb is not null and pl_01 is not null"
+        )?,
+        2
+    );
+    // Documents of other corpora never join those of DGT-TM.
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from translation_units t join documents d on d.id = t.document_id
+            where corpus = 'europarl' and d.name = 'EUROPARL:22019D0557'"
+        )?,
+        1
+    );
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from translation_units t join documents d on d.id = t.document_id
+            where d.name = '22019D0557' and corpus <> 'dgt'"
+        )?,
+        0
+    );
+
+    Ok(())
+}
+
+#[test]
+fn only_listed_documents_are_processed() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let docs_file_path = tmp_dir.path().join("docs.txt");
+    std::fs::write(
+        &docs_file_path,
+        "22019d0391
+22019D0556
+32019R9999
+",
+    )?;
+    let skip_log_path = tmp_dir.path().join("skipped.jsonl");
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-l",
+        "en",
+        "--docs-file",
+        docs_file_path.display().to_string().as_str(),
+        "--skip-log",
+        skip_log_path.display().to_string().as_str(),
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Skipped 418 translation units of documents not listed in --docs-file"));
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains("Document 32019R9999 listed in --docs-file does not occur in the input")
+    );
+    // Once for each of the 5 documents not listed.
+    let skip_log = std::fs::read_to_string(&skip_log_path)?;
+    assert_eq!(skip_log.matches("\"unlisted_doc\"").count(), 5);
+
+    // The documents may occur in the files left out.
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "--docs-file",
+        docs_file_path.display().to_string().as_str(),
+        "--max-units",
+        "1",
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "-o",
+        tmp_dir
+            .path()
+            .join("stopped.sqlite")
+            .display()
+            .to_string()
+            .as_str(),
+    ]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains("Stopped because --max-units was reached"));
+    assert!(!String::from_utf8(output.stderr)?.contains("does not occur in the input"));
+
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    assert_eq!(
+        query_number(&conn, "select count(*) from translation_units")?,
+        25 + 19
+    );
+    assert_eq!(query_number(&conn, "select count(*) from documents")?, 2);
+
+    Ok(())
+}
+
+#[test]
+fn input_is_verified_against_checksums() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let checksums_path = tmp_dir.path().join("SHA256SUMS");
+    let run = |checksums: &str| -> Result<std::process::Output, Box<dyn std::error::Error>> {
+        std::fs::write(&checksums_path, checksums)?;
+        let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+        cmd.args([
+            "--checksums",
+            checksums_path.display().to_string().as_str(),
+            "-i",
+            "test_data/zipped",
+            "sqlite",
+            "-o",
+            db_file_path.display().to_string().as_str(),
+        ]);
+        Ok(cmd.output()?)
+    };
+
+    let output = run(
+        "bf2bce61698621ec1867c6425d2b4205074f586a285220443fedf4c391620605  1.zip\n\
+         0000000000000000000000000000000000000000000000000000000000000000  2.zip\n",
+    )?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("2.zip: expected 0000"));
+    assert!(!db_file_path.exists());
+
+    let output = run(
+        "bf2bce61698621ec1867c6425d2b4205074f586a285220443fedf4c391620605  1.zip\n\
+         775f7d047707aea8e46889c2733672acf50011f12ffe58cb47bb5e2bf22c38e3  2.zip\n",
+    )?;
+    assert!(output.status.success());
+    assert!(!String::from_utf8(output.stdout)?.contains("checksum file"));
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    assert_eq!(
+        query_number(&conn, "select count(*) from translation_units")?,
+        462
+    );
+
+    Ok(())
+}
+
+#[test]
+fn texts_are_stored_as_json_objects() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-l",
+        "en",
+        "-l",
+        "pl",
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "--lang-storage",
+        "json",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    assert!(cmd.output()?.status.success());
+
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from translation_units where json_extract(texts, '$.\"EN-GB\"') is not null"
+        )?,
+        462
+    );
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from pragma_table_info('translation_units')"
+        )?,
+        4
+    );
+
+    Ok(())
+}
+
+#[test]
+fn languages_of_original_texts_are_saved_on_request() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-l",
+        "en",
+        "-l",
+        "pl",
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "--srclang-column",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    assert!(cmd.output()?.status.success());
+
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from translation_units where srclang = 'EN-GB'"
+        )?,
+        462
+    );
+
+    Ok(())
+}
+
+#[test]
+fn unknown_langs_are_rejected_with_suggestion() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-l",
+        "en",
+        "-l",
+        "polish",
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    let output = cmd.output()?;
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("did you mean `pl`?"));
+    assert!(!db_file_path.exists());
+
+    Ok(())
+}
+
+#[test]
+fn seen_files_are_not_processed_again() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let seen_files_path = tmp_dir.path().join("seen.txt");
+    let run = |append: bool| {
+        let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+        cmd.args(["-i", "test_data/zipped", "--seen-files"]);
+        cmd.arg(&seen_files_path);
+        cmd.args(["sqlite", "-o"]);
+        cmd.arg(&db_file_path);
+        if append {
+            cmd.arg("--append");
+        }
+        let output = cmd.output().unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    run(false);
+    let seen_files = std::fs::read_to_string(&seen_files_path)?;
+    let stdout = run(true);
+    let conn = Connection::open(&db_file_path)?;
+
+    assert!(!seen_files.is_empty());
+    assert_eq!(std::fs::read_to_string(&seen_files_path)?, seen_files);
+    assert!(stdout.contains(&format!(
+        "Skipped {} TMX files listed in --seen-files.",
+        seen_files.lines().count()
+    )));
+    assert_eq!(
+        query_number(&conn, "select count(*) from translation_units")?,
+        462
+    );
+
+    Ok(())
+}
+
+#[test]
+fn units_with_too_few_langs_are_dropped() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-l",
+        "en",
+        "-l",
+        "pl",
+        "--min-langs",
+        "2",
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?
+        .contains("Dropped 22 translation units with texts in fewer than 2 languages"));
+
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    assert_eq!(
+        query_number(&conn, "select count(*) from translation_units")?,
+        440
+    );
+
+    Ok(())
+}
+
+#[test]
+fn input_is_read_from_stdin() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-i",
+        "-",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    cmd.stdin(std::fs::File::open("test_data/zipped/1.zip")?);
+    assert!(cmd.output()?.status.success());
+
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    assert_eq!(
+        query_number(&conn, "select count(*) from translation_units")?,
+        63
+    );
+
+    Ok(())
+}
+
+#[test]
+fn attribution_is_embedded() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-i",
+        "test_data/zipped/1.zip",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    assert!(cmd.output()?.status.success());
+
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    let notice: String = conn.query_row("select notice from license", [], |row| row.get(0))?;
+    assert!(notice.contains("© European Union"));
+
+    // An empty attribution disables it, leaving the table empty.
+    let other_db_file_path = tmp_dir.path().join("other.db");
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "--attribution",
+        "",
+        "-i",
+        "test_data/zipped/1.zip",
+        "sqlite",
+        "-o",
+        other_db_file_path.display().to_string().as_str(),
+    ]);
+    assert!(cmd.output()?.status.success());
+
+    let conn = rusqlite::Connection::open(&other_db_file_path)?;
+    assert_eq!(query_number(&conn, "select count(*) from license")?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn errors_are_reported_with_codes() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    std::fs::write(&db_file_path, "")?;
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    let output = cmd.output()?;
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.starts_with(&format!(
+        "Error[E002]: {} already exists\nHint: ",
+        db_file_path.display()
+    )));
+
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args(["-i", "test_data/missing", "verify"]);
+    let output = cmd.output()?;
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)?
+        .starts_with("Error[E001]: test_data/missing does not exist"));
+
+    Ok(())
+}
+
+#[test]
+fn documents_with_low_coverage_are_dropped() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-l",
+        "en",
+        "-l",
+        "et",
+        "--require-doc-coverage",
+        "0.9",
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?
+        .contains("Dropped 2 documents (46 translation units) with texts in fewer than 90%"));
+
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    assert_eq!(
+        query_number(&conn, "select count(*) from translation_units")?,
+        416
+    );
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from documents where name = '22019D0391'"
+        )?,
+        0
+    );
+
+    Ok(())
+}
+
+#[test]
+fn skipped_units_are_logged() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let skip_log_path = tmp_dir.path().join("skipped.jsonl");
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-l",
+        "en",
+        "-l",
+        "pl",
+        "--min-langs",
+        "2",
+        "--skip-log",
+        skip_log_path.display().to_string().as_str(),
+        "--skip-log-limit",
+        "5",
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?
+        .contains("Omitted 17 skipped translation units from --skip-log"));
+
+    let skip_log = std::fs::read_to_string(&skip_log_path)?;
+    assert_eq!(skip_log.lines().count(), 5);
+    for line in skip_log.lines() {
+        let entry: serde_json::Value = serde_json::from_str(line)?;
+        assert_eq!(entry["reason"], "too_few_langs");
+        assert!(entry["document"].is_string());
+        assert!(entry["sequential_number"].is_u64());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn catalog_is_used_for_progress() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let catalog_path = tmp_dir.path().join("catalog.json");
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-i",
+        "test_data/zipped",
+        "catalog",
+        "-o",
+        catalog_path.display().to_string().as_str(),
+    ]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?
+        .contains("Cataloged 7 TMX files with 462 translation units."));
+    let catalog: serde_json::Value = serde_json::from_reader(std::fs::File::open(&catalog_path)?)?;
+    assert_eq!(catalog["files"].as_array().unwrap().len(), 7);
+    assert_eq!(catalog["files"][0]["sha256"].as_str().unwrap().len(), 64);
+
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "--catalog",
+        catalog_path.display().to_string().as_str(),
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains("Parsing 7 out of 7 documents (100%, "));
+    assert!(String::from_utf8(output.stderr)?.is_empty());
+
+    // The TMX files are matched by their names relative to the input, so a
+    // copy of the input is verified against the catalog too.
+    let copy_dir = tmp_dir.path().join("copy");
+    std::fs::create_dir(&copy_dir)?;
+    for entry in std::fs::read_dir("test_data/zipped")? {
+        let entry = entry?;
+        std::fs::copy(entry.path(), copy_dir.join(entry.file_name()))?;
+    }
+    let mut catalog = catalog;
+    for file in catalog["files"].as_array_mut().unwrap() {
+        if file["name"] == "1.zip/22019D0558.tmx" {
+            file["translation_units"] = 1000.into();
+        }
+    }
+    std::fs::write(&catalog_path, catalog.to_string())?;
+    let verify = |catalog_path: &Path| {
+        Command::cargo_bin("dgt_parser")
+            .unwrap()
+            .arg("--catalog")
+            .arg(catalog_path)
+            .arg("-i")
+            .arg(&copy_dir)
+            .arg("verify")
+            .output()
+            .unwrap()
+    };
+    let output = verify(&catalog_path);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains(
+        "1.zip/22019D0558.tmx: parsed 22 translation units, but the catalog declares 1000"
+    ));
+
+    // A catalog which lists none of the files verifies nothing.
+    catalog["files"] = serde_json::json!([
+        {"name": "other.tmx", "size": 1, "translation_units": 1}
+    ]);
+    std::fs::write(&catalog_path, catalog.to_string())?;
+    let output = verify(&catalog_path);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8(output.stderr)?
+        .contains("None of the parsed TMX files is listed in the catalog"));
+
+    Ok(())
+}
+
+#[test]
+fn units_naming_several_documents_are_linked_or_joined() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, _db_file_path) = setup().unwrap();
+    let edge_cases_dir = tmp_dir.path().join("edge_cases");
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "gen-edge-cases",
+        "-o",
+        edge_cases_dir.display().to_string().as_str(),
+    ]);
+    assert!(cmd.output()?.status.success());
+    let run = |policy: &str| {
+        let db_file_path = tmp_dir.path().join(format!("{}.sqlite", policy));
+        let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+        cmd.args([
+            "--multi-doc",
+            policy,
+            "-l",
+            "en",
+            "-i",
+            edge_cases_dir.display().to_string().as_str(),
+            "sqlite",
+            "--deterministic",
+            "-o",
+            db_file_path.display().to_string().as_str(),
+        ]);
+        assert!(cmd.output().unwrap().status.success());
+        rusqlite::Connection::open(&db_file_path).unwrap()
+    };
+
+    let conn = run("all");
+    let linked_units = |doc: &str| {
+        query_number(
+            &conn,
+            &format!(
+                "select count(*) from unit_documents ud join documents d on d.id = ud.document_id where d.name = '{}'",
+                doc
+            ),
+        )
+        .unwrap()
+    };
+    assert_eq!(linked_units("MULTI_A"), 2);
+    assert_eq!(linked_units("MULTI_B"), 1);
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from unit_documents ud join translation_units tu on tu.id = ud.translation_unit_id"
+        )?,
+        query_number(&conn, "select count(*) from unit_documents")?
+    );
+
+    let conn = run("join");
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from translation_units tu join documents d on d.id = tu.document_id where d.name = 'MULTI_A;MULTI_B'"
+        )?,
+        1
+    );
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from sqlite_master where name = 'unit_documents'"
+        )?,
+        0
+    );
+
+    Ok(())
+}
+
+#[test]
+fn linked_documents_are_renumbered() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let input_dir = tmp_dir.path().join("input");
+    std::fs::create_dir(&input_dir)?;
+    // ZETA is found first, but numbered after ALPHA.
+    std::fs::write(
+        input_dir.join("1.tmx"),
+        r#"<?xml version="1.0" encoding="utf-8"?><tmx><header></header><body>
+        <tu><prop type="Txt::Doc. No.">ZETA</prop><tuv lang="EN-GB"><seg>Own</seg></tuv></tu>
+        <tu><prop type="Txt::Doc. No.">ZETA</prop><prop type="Txt::Doc. No.">ALPHA</prop><tuv lang="EN-GB"><seg>Shared</seg></tuv></tu>
+        </body></tmx>"#,
+    )?;
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "--multi-doc",
+        "all",
+        "-i",
+        input_dir.display().to_string().as_str(),
+        "sqlite",
+        "--deterministic",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    let output = cmd.output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8(output.stderr)?
+    );
+
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    assert_eq!(
+        query_number(&conn, "select id from documents where name = 'ALPHA'")?,
+        1
+    );
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from unit_documents ud join documents d on d.id = ud.document_id
+            join translation_units tu on tu.id = ud.translation_unit_id where tu.en_gb = 'Shared'"
+        )?,
+        2
+    );
+
+    Ok(())
+}
+
+#[test]
+fn props_are_saved_in_columns() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let edge_cases_dir = tmp_dir.path().join("edge_cases");
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "gen-edge-cases",
+        "-o",
+        edge_cases_dir.display().to_string().as_str(),
+    ]);
+    assert!(cmd.output()?.status.success());
+
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "--prop-column",
+        "Txt::Doc. Type=doc_type",
+        "--prop-column",
+        "Txt::Year=year:integer",
+        "--unmapped-props-json",
+        "-l",
+        "en",
+        "-i",
+        edge_cases_dir.display().to_string().as_str(),
+        "sqlite",
+        "--strict",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)?
+        .contains("Left out 1 values of the year column which are not integers"));
+
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    let (doc_type, year, props): (String, i64, String) = conn.query_row(
+        "select doc_type, year, props from translation_units where en_gb = 'With props'",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    assert_eq!(doc_type, "DEC");
+    assert_eq!(year, 2019);
+    assert_eq!(props, r#"{"Txt::Note":"Corrigendum"}"#);
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from translation_units where en_gb = 'Invalid year' and year is null"
+        )?,
+        1
+    );
+
+    Ok(())
+}
+
+#[test]
+fn init_saves_config_used_by_later_runs() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, _db_file_path) = setup().unwrap();
+    let input_dir = std::fs::canonicalize("test_data/zipped")?;
+    let answers_path = tmp_dir.path().join("answers.txt");
+    std::fs::write(
+        &answers_path,
+        format!("{}\nen pl\nsqlite\n\n", input_dir.display()),
+    )?;
+
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.current_dir(tmp_dir.path())
+        .arg("init")
+        .stdin(std::fs::File::open(&answers_path)?);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains(&format!(
+        "dgt_parser -i {} -l en -l pl sqlite -o dgt.sqlite",
+        input_dir.display()
+    )));
+    let config = std::fs::read_to_string(tmp_dir.path().join("dgt_parser.toml"))?;
+    assert!(config.contains(r#"langs = ["en", "pl"]"#));
+
+    // The settings found in the current directory are not applied silently.
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.current_dir(tmp_dir.path())
+        .args(["--progress", "none", "sqlite", "-o", "dgt.sqlite"]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains(
+        "Warning: using the input and the languages from dgt_parser.toml in the current directory"
+    ));
+
+    let conn = rusqlite::Connection::open(tmp_dir.path().join("dgt.sqlite"))?;
+    assert_eq!(
+        query_number(&conn, "select count(*) from translation_units")?,
+        462
+    );
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from pragma_table_info('translation_units') where name in ('en_gb', 'pl_01', 'de_de')"
+        )?,
+        2
+    );
+
+    Ok(())
+}
+
+#[test]
+fn alignment_is_scored() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let jsonl_path = tmp_dir.path().join("units.jsonl");
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "--score-alignment",
+        "en",
+        "pl",
+        "--tee",
+        format!("jsonl:{}", jsonl_path.display()).as_str(),
+        "-i",
+        "test_data/zipped",
+        "sqlite",
+        "-o",
+        db_file_path.display().to_string().as_str(),
+    ]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains("Training the alignment model on"));
+
+    let conn = rusqlite::Connection::open(&db_file_path)?;
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from translation_units where alignment_score is not null"
+        )?,
+        query_number(
+            &conn,
+            "select count(*) from translation_units where en_gb <> '' and pl_01 <> ''"
+        )?
+    );
+    assert_eq!(
+        query_number(
+            &conn,
+            "select count(*) from translation_units where alignment_score > 0"
+        )?,
+        0
+    );
+    let scored_lines = std::fs::read_to_string(&jsonl_path)?
+        .lines()
+        .filter(|line| line.contains("\"alignment_score\":"))
+        .count();
+    assert_eq!(
+        scored_lines as u32,
+        query_number(
+            &conn,
+            "select count(*) from translation_units where alignment_score is not null"
+        )?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn stopped_run_is_continued_with_seen_files() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let seen_files_path = tmp_dir.path().join("seen.txt");
+    let run = |limit: &[&str], append: bool| {
+        let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+        cmd.args(limit);
+        cmd.args(["-i", "test_data/zipped", "--seen-files"]);
+        cmd.arg(&seen_files_path);
+        cmd.args(["sqlite", "-o"]);
+        cmd.arg(&db_file_path);
+        if append {
+            cmd.arg("--append");
+        }
+        let output = cmd.output().unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let stdout = run(&["--max-units", "1"], false);
+    assert!(stdout.contains("Stopped because --max-units was reached, leaving out 6 TMX files"));
+    let conn = Connection::open(&db_file_path)?;
+    let first_run_units = query_number(&conn, "select count(*) from translation_units")?;
+    assert!(first_run_units > 0 && first_run_units < 462);
+    assert_eq!(
+        std::fs::read_to_string(&seen_files_path)?.lines().count(),
+        1
+    );
+
+    let stdout = run(&["--max-duration", "1h"], true);
+    assert!(!stdout.contains("Stopped because"));
+    assert_eq!(
+        query_number(&conn, "select count(*) from translation_units")?,
+        462
+    );
+
+    Ok(())
+}
+
+#[test]
+fn kwic_matches_are_exported_in_pages() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let status = Command::cargo_bin("dgt_parser")?
+        .args([
+            "-q",
+            "-l",
+            "en",
+            "-l",
+            "pl",
+            "-i",
+            "test_data/zipped",
+            "sqlite",
+            "-o",
+        ])
+        .arg(&db_file_path)
+        .status()?;
+    assert!(status.success());
+    let kwic_term = |term: &str, lang: &str, args: &[&str]| {
+        let output = Command::cargo_bin("dgt_parser")
+            .unwrap()
+            .arg("kwic")
+            .arg(&db_file_path)
+            .args(["--term", term, "--lang", lang])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let kwic = |args: &[&str]| kwic_term("joint committee", "en", args);
+
+    let all: Vec<serde_json::Value> = kwic(&["--format", "jsonl", "--limit", "0"])
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert!(all.len() > 2);
+    assert!(all[0]["keyword"]
+        .as_str()
+        .unwrap()
+        .eq_ignore_ascii_case("joint committee"));
+    assert!(all[0].get("translation").is_none());
+
+    let page = kwic(&[
+        "-T", "pl", "--format", "csv", "--limit", "1", "--offset", "1",
+    ]);
+    let rows: Vec<&str> = page.lines().collect();
+    assert_eq!(
+        rows[0],
+        "document,sequential_number,left,keyword,right,translation"
+    );
+    assert!(rows[1].starts_with(&format!(
+        "{},{},",
+        all[1]["document"].as_str().unwrap(),
+        all[1]["sequential_number"]
+    )));
+
+    // Wildcards are taken literally, and non-ASCII letters match in any case.
+    assert_eq!(kwic_term("joint%committee", "en", &["--limit", "0"]), "");
+    let polish = kwic_term("WSPÓLNEGO KOMITETU", "pl", &["--format", "jsonl"]);
+    assert!(!polish.is_empty());
+    assert_eq!(
+        polish,
+        kwic_term("wspólnego komitetu", "pl", &["--format", "jsonl"])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn views_are_created_for_lang_pairs() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let status = Command::cargo_bin("dgt_parser")?
+        .args(["-q", "-l", "pl", "-l", "de", "--source-lang", "en"])
+        .args(["-i", "test_data/zipped", "sqlite", "-o"])
+        .arg(&db_file_path)
+        .status()?;
+    assert!(status.success());
+
+    let conn = Connection::open(&db_file_path)?;
+    let views: Vec<String> = conn
+        .prepare("select name from sqlite_master where type = 'view' order by name")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    assert_eq!(views, vec!["v_en_de", "v_en_pl", "v_pl_de"]);
+    assert_eq!(
+        query_number(&conn, "select count(*) from v_en_pl")?,
+        query_number(
+            &conn,
+            "select count(*) from translation_units where en_gb <> '' and pl_01 <> ''"
+        )?
+    );
+    let columns: Vec<String> = conn
+        .prepare("select name from pragma_table_info('v_en_pl')")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    assert_eq!(
+        columns,
+        vec!["document_id", "sequential_number", "en_gb", "pl_01"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn memory_is_capped() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let output = Command::cargo_bin("dgt_parser")?
+        .args(["--max-memory", "64K", "-i", "test_data/zipped", "sqlite", "-o"])
+        .arg(&db_file_path)
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("of translation units in memory (--max-memory 64.0 KiB)."));
+    assert!(stdout.contains("the cap could not be met"));
+    assert!(String::from_utf8(output.stderr)?.is_empty());
+
+    let conn = Connection::open(&db_file_path)?;
+    assert_eq!(
+        query_number(&conn, "select count(*) from translation_units")?,
+        462
+    );
+
+    Ok(())
+}
+
+#[test]
+fn changed_texts_are_recorded_as_revisions() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let run = |update: bool| {
+        let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+        cmd.args([
+            "-l",
+            "en",
+            "-l",
+            "pl",
+            "-i",
+            "test_data/zipped",
+            "sqlite",
+            "--search-columns",
+            "-o",
+        ]);
+        cmd.arg(&db_file_path);
+        if update {
+            cmd.arg("--update");
+        }
+        let output = cmd.output().unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    run(false);
+    // As if the text had changed between releases.
+    let conn = Connection::open(&db_file_path)?;
+    let (unit_id, old_text, old_search_text): (i64, String, String) = conn.query_row(
+        "select id, en_gb, en_gb_search from translation_units where en_gb <> '' order by id limit 1",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    conn.execute(
+        "update translation_units set en_gb = 'Outdated text', en_gb_search = 'outdated text'
+        where id = ?",
+        [unit_id],
+    )?;
+
+    let stdout = run(true);
+    assert!(stdout.contains("Updated 1 translation units already in the database"));
+    assert!(stdout.contains("461 were unchanged"));
+    assert_eq!(
+        query_number(&conn, "select count(*) from translation_units")?,
+        462
+    );
+    let (revised_unit, lang, old_text_hash, new_text): (i64, String, String, String) = conn
+        .query_row(
+            "select translation_unit_id, lang, old_text_hash, new_text from revisions",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+    assert_eq!(revised_unit, unit_id);
+    assert_eq!(lang, "EN-GB");
+    assert_eq!(
+        old_text_hash,
+        format!("{:x}", sha2::Sha256::digest("Outdated text"))
+    );
+    assert_eq!(new_text, old_text);
+    // The columns derived from the text are updated too.
+    let (current_text, current_search_text): (String, String) = conn.query_row(
+        "select en_gb, en_gb_search from translation_units where id = ?",
+        [unit_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    assert_eq!(current_text, old_text);
+    assert_eq!(current_search_text, old_search_text);
+
+    // The units are looked up by their numbers, which depend on the files
+    // processed before them.
+    let output = Command::cargo_bin("dgt_parser")?
+        .args([
+            "--tmx-glob",
+            "*D0557.tmx",
+            "-i",
+            "test_data/zipped",
+            "sqlite",
+        ])
+        .args(["--update", "-o"])
+        .arg(&db_file_path)
+        .output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("[E024]"));
+
+    Ok(())
+}
+
+#[test]
+fn pragmas_and_extensions_are_passed_to_sqlite() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let status = Command::cargo_bin("dgt_parser")?
+        .args(["-q", "-l", "pl", "-i", "test_data/zipped", "sqlite"])
+        .args(["--pragma", "page_size=8192", "--pragma", "journal_mode=WAL"])
+        .arg("-o")
+        .arg(&db_file_path)
+        .status()?;
+    assert!(status.success());
+
+    let conn = Connection::open(&db_file_path)?;
+    assert_eq!(query_number(&conn, "pragma page_size")?, 8192);
+    let journal_mode: String = conn.query_row("pragma journal_mode", [], |row| row.get(0))?;
+    assert_eq!(journal_mode, "wal");
+
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let output = Command::cargo_bin("dgt_parser")?
+        .args(["-q", "-l", "pl", "-i", "test_data/zipped", "sqlite"])
+        .args(["--load-extension", "test_data/no_such_extension"])
+        .arg("-o")
+        .arg(&db_file_path)
+        .output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("no_such_extension"));
+
+    Ok(())
+}
+
+#[test]
+fn units_are_filtered_by_regex_on_text_in_language() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let output = Command::cargo_bin("dgt_parser")?
+        .args([
+            "-l",
+            "pl",
+            "--match-lang",
+            "en",
+            "--match-regex",
+            "(?i)joint committee",
+        ])
+        .args(["-i", "test_data/zipped", "sqlite", "-o"])
+        .arg(&db_file_path)
+        .output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?
+        .contains("Dropped 424 translation units whose EN-GB text does not match"));
+
+    // The English texts are matched even though only Polish is saved.
+    let conn = Connection::open(&db_file_path)?;
+    assert_eq!(
+        query_number(&conn, "select count(*) from translation_units")?,
+        16
+    );
+
+    Ok(())
+}
+
+#[test]
+fn effective_settings_are_recorded() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let config_path = tmp_dir.path().join("dgt_parser.toml");
+    std::fs::write(
+        &config_path,
+        "input_dir = \"test_data/zipped\"\nlangs = [\"pl\", \"en\"]\n",
+    )?;
+    let manifest_path = tmp_dir.path().join("manifest.json");
+    let status = Command::cargo_bin("dgt_parser")?
+        .args(["-q", "--min-langs", "2", "--multi-doc", "all"])
+        .args(["--max-duration", "1h", "--read-ahead", "--config"])
+        .arg(&config_path)
+        .arg("--manifest")
+        .arg(&manifest_path)
+        .args(["sqlite", "-o"])
+        .arg(&db_file_path)
+        .status()?;
+    assert!(status.success());
+
+    let manifest: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(&manifest_path)?)?;
+    let filters = &manifest["filters"];
+    assert_eq!(filters["input"], "test_data/zipped");
+    assert_eq!(filters["config_file"], config_path.display().to_string());
+    assert_eq!(filters["langs"], serde_json::json!(["PL-01", "EN-GB"]));
+    assert_eq!(filters["min_langs"], 2);
+    assert_eq!(filters["multi_doc"], "all");
+    assert_eq!(filters["missing_as"], "empty");
+    assert_eq!(filters["max_units"], serde_json::Value::Null);
+    assert_eq!(filters["max_duration"], 3600);
+    assert_eq!(filters["read_ahead"], true);
+
+    let conn = Connection::open(&db_file_path)?;
+    let recorded: String = conn.query_row("select filters from runs", [], |row| row.get(0))?;
+    assert_eq!(
+        &serde_json::from_str::<serde_json::Value>(&recorded)?,
+        filters
+    );
+
+    Ok(())
+}
+
+#[test]
+fn indexes_are_created_at_the_end() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    let status = Command::cargo_bin("dgt_parser")?
+        .args([
+            "-q",
+            "--multi-doc",
+            "all",
+            "-i",
+            "test_data/zipped",
+            "sqlite",
+        ])
+        .arg("--with-indexes")
+        .arg("-o")
+        .arg(&db_file_path)
+        .status()?;
+    assert!(status.success());
+
+    let conn = Connection::open(&db_file_path)?;
+    let indexes: Vec<String> = conn
+        .prepare(
+            "select name from sqlite_master where type = 'index' and sql is not null order by name",
+        )?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    assert_eq!(
+        indexes,
+        vec![
+            "documents_name",
+            "translation_units_position",
+            "unit_documents_document"
+        ]
+    );
+    // Statistics gathered by `ANALYZE`.
+    assert!(query_number(&conn, "select count(*) from sqlite_stat1")? > 0);
+
+    Ok(())
+}
+
+#[test]
+fn progress_is_reported_as_json_events() -> Result<(), Box<dyn std::error::Error>> {
+    let (_tmp_dir, db_file_path) = setup().unwrap();
+    // Without `-q`, so that nothing but the events is written to the
+    // standard error.
+    let output = Command::cargo_bin("dgt_parser")?
+        .args(["--progress-json", "-l", "en", "-l", "ga"])
+        .args(["-i", "test_data/zipped", "sqlite", "-o"])
+        .arg(&db_file_path)
+        .output()?;
+    assert!(output.status.success());
+
+    let events: Vec<serde_json::Value> = String::from_utf8(output.stderr)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    let count = |name: &str| events.iter().filter(|e| e["event"] == name).count();
+    assert_eq!(events.first().unwrap()["event"], "started");
+    assert_eq!(events.first().unwrap()["protocol"], 1);
+    assert_eq!(count("file_started"), 7);
+    assert_eq!(count("file_done"), 7);
+    let batches: Vec<u64> = events
+        .iter()
+        .filter(|e| e["event"] == "batch_committed")
+        .map(|e| e["translation_units"].as_u64().unwrap())
+        .collect();
+    assert_eq!(batches, vec![462]);
+    let warnings: Vec<&serde_json::Value> = events
+        .iter()
+        .filter(|e| e["event"] == "warning")
+        .map(|e| &e["message"])
+        .collect();
+    assert_eq!(warnings, vec!["Language GA-IE does not occur in the input"]);
+    let units_done: u64 = events
+        .iter()
+        .filter(|e| e["event"] == "file_done")
+        .map(|e| e["translation_units"].as_u64().unwrap())
+        .sum();
+    assert_eq!(units_done, 462);
+    let finished = events.last().unwrap();
+    assert_eq!(finished["event"], "finished");
+    assert_eq!(finished["translation_units_handled"], 462);
+
+    // An error which stops the run is an event too.
+    let output = Command::cargo_bin("dgt_parser")?
+        .args(["--progress-json", "-i", "test_data/missing", "sqlite", "-o"])
+        .arg(&db_file_path)
+        .output()?;
+    assert!(!output.status.success());
+    let event: serde_json::Value = serde_json::from_slice(&output.stderr)?;
+    assert_eq!(event["event"], "error");
+    assert_eq!(event["code"], "E001");
+
+    Ok(())
+}
+
+#[test]
+fn boilerplate_is_dropped_or_flagged() -> Result<(), Box<dyn std::error::Error>> {
+    let (tmp_dir, db_file_path) = setup().unwrap();
+    let boilerplate_path = tmp_dir.path().join("boilerplate_en.txt");
+    std::fs::write(
+        &boilerplate_path,
+        "# Final provisions\nThis Decision shall be effective from the date of the later of these signatures.\nTHE JOINT COMMITTEE,\n",
+    )?;
+    let boilerplate_arg = format!("en={}", boilerplate_path.display());
+
+    let status = Command::cargo_bin("dgt_parser")?
+        .args(["-q", "--boilerplate-file", &boilerplate_arg])
+        .args(["-i", "test_data/zipped", "sqlite", "-o"])
+        .arg(&db_file_path)
+        .status()?;
+    assert!(status.success());
+    let conn = Connection::open(&db_file_path)?;
+    assert_eq!(
+        query_number(&conn, "select count(*) from translation_units")?,
+        462 - 8
+    );
+    drop(conn);
+
+    std::fs::remove_file(&db_file_path)?;
+    let status = Command::cargo_bin("dgt_parser")?
+        .args([
+            "-q",
+            "--flag-boilerplate",
+            "--boilerplate-file",
+            &boilerplate_arg,
+        ])
+        .args(["-i", "test_data/zipped", "sqlite", "-o"])
+        .arg(&db_file_path)
+        .status()?;
+    assert!(status.success());
+    let conn = Connection::open(&db_file_path)?;
+    assert_eq!(
+        query_number(&conn, "select count(*) from translation_units")?,
+        462
+    );
+    assert_eq!(
+        query_number(&conn, "select sum(is_boilerplate) from translation_units")?,
+        8
+    );
+
+    Ok(())
+}
+
+#[test]
+fn output_format_is_inferred_from_extension() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp_dir = assert_fs::TempDir::new()?;
+    let convert = |output_file: &PathBuf| {
+        Command::cargo_bin("dgt_parser")
+            .unwrap()
+            .args(["-q", "-i", "test_data/zipped", "convert", "-o"])
+            .arg(output_file)
+            .output()
+            .unwrap()
+    };
+
+    let db_file_path = tmp_dir.path().join("units.db");
+    assert!(convert(&db_file_path).status.success());
+    let conn = Connection::open(&db_file_path)?;
+    assert_eq!(
+        query_number(&conn, "select count(*) from translation_units")?,
+        462
+    );
+
+    let jsonl_path = tmp_dir.path().join("units.jsonl");
+    assert!(convert(&jsonl_path).status.success());
+    assert_eq!(std::fs::read_to_string(&jsonl_path)?.lines().count(), 462);
+
+    let output = convert(&tmp_dir.path().join("units.parquet"));
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.starts_with("Error[E023]"));
+
+    // Paths starting with `-` are not taken for options.
+    let input_dir = std::env::current_dir()?.join("test_data/zipped");
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.current_dir(tmp_dir.path())
+        .args(["-q", "-i"])
+        .arg(&input_dir)
+        .args(["convert", "--output=-units.db"]);
+    assert!(cmd.output()?.status.success());
+    let conn = Connection::open(tmp_dir.path().join("-units.db"))?;
+    assert_eq!(
+        query_number(&conn, "select count(*) from translation_units")?,
+        462
+    );
+
+    Ok(())
+}
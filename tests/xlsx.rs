@@ -0,0 +1,31 @@
+#![cfg(feature = "xlsx")]
+
+use assert_cmd::prelude::CommandCargoExt;
+use std::process::Command;
+
+#[test]
+fn units_are_saved_in_workbook() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp_dir = assert_fs::TempDir::new()?;
+    let output_file = tmp_dir.path().join("units.xlsx");
+    let mut cmd = Command::cargo_bin("dgt_parser").unwrap();
+    cmd.args([
+        "-l",
+        "en",
+        "-l",
+        "pl",
+        "-i",
+        "test_data/zipped",
+        "xlsx",
+        "-o",
+        output_file.display().to_string().as_str(),
+    ]);
+    assert!(cmd.output()?.status.success());
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(&output_file)?)?;
+    let mut workbook = String::new();
+    std::io::Read::read_to_string(&mut archive.by_name("xl/workbook.xml")?, &mut workbook)?;
+    assert_eq!(workbook.matches("<sheet ").count(), 7);
+    assert!(workbook.contains(r#"<sheet name="22019D0391""#));
+
+    Ok(())
+}